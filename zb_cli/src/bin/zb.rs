@@ -8,16 +8,35 @@ use zb_cli::{
     ui::Ui,
     utils::get_root_path,
 };
-use zb_io::create_installer;
+use zb_core::{DependencyPolicy, LoggerHandle, Paths};
+use zb_io::{MethodPolicy, PackageKind, TlsOptions, create_installer};
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
     logging::init(cli.verbose, cli.quiet);
 
-    if let Err(e) = run(cli).await {
-        eprintln!("{} {}", style("error:").red().bold(), e);
-        std::process::exit(1);
+    // Source builds run in their own process group, out of reach of the
+    // terminal's SIGINT. Dropping `run` on Ctrl-C kills them before we exit.
+    let result = if matches!(
+        cli.command,
+        Commands::Install { .. } | Commands::Bundle { .. }
+    ) {
+        tokio::select! {
+            result = run(cli) => Some(result),
+            _ = tokio::signal::ctrl_c() => None,
+        }
+    } else {
+        Some(run(cli).await)
+    };
+
+    match result {
+        Some(Ok(())) => {}
+        Some(Err(e)) => {
+            eprintln!("{} {}", style("error:").red().bold(), e);
+            std::process::exit(1);
+        }
+        None => std::process::exit(130),
     }
 }
 
@@ -29,16 +48,8 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
     }
 
     let root = get_root_path(cli.root);
-    let prefix = cli.prefix.unwrap_or_else(|| {
-        // On macOS, Mach-O binaries have fixed-size path fields so the prefix
-        // must be no longer than the original Homebrew prefix (/opt/homebrew = 13 chars).
-        // Using root directly (/opt/zerobrew = 13 chars) keeps us within that limit.
-        if cfg!(target_os = "macos") {
-            root.clone()
-        } else {
-            root.join("prefix")
-        }
-    });
+    let prefix = cli.prefix.unwrap_or_else(|| Paths::default_prefix(&root));
+    let paths = Paths::new(root.clone(), prefix.clone());
 
     if let Commands::Init { no_modify_path } = cli.command {
         return commands::init::execute(&root, &prefix, no_modify_path, &mut ui);
@@ -48,21 +59,97 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
         ensure_init(&root, &prefix, cli.auto_init, &mut ui)?;
     }
 
-    let mut installer = create_installer(&root, &prefix, cli.concurrency)?;
+    let installer = create_installer(&paths, cli.concurrency, cli.cache_dir.as_deref())?;
+    let config = installer.config().clone();
+    let mut installer = installer
+        .with_logger(LoggerHandle::from_verbosity(cli.verbose, cli.quiet))
+        .with_offline(cli.offline || config.offline)
+        .with_tls_options(&TlsOptions {
+            ca_bundle: cli.ca_bundle.clone(),
+            insecure: cli.insecure_tls,
+            prefix: None,
+        })?
+        .with_bottle_corruption_source_fallback(matches!(
+            cli.command,
+            Commands::Install {
+                source_fallback: true,
+                ..
+            }
+        ))
+        .with_force_reinstall(matches!(cli.command, Commands::Install { force: true, .. }))
+        .with_keep_going(matches!(
+            cli.command,
+            Commands::Install {
+                keep_going: true,
+                ..
+            }
+        ))
+        .with_build_phase_timeout(match &cli.command {
+            Commands::Install { build_timeout, .. } => {
+                build_timeout.map(std::time::Duration::from_secs)
+            }
+            _ => None,
+        })
+        .with_bottle_tag(match &cli.command {
+            Commands::Install { target, .. } => target.clone(),
+            _ => None,
+        })
+        .with_cask_binaries(match &cli.command {
+            Commands::Install { only_binaries, .. } if !only_binaries.is_empty() => {
+                Some(only_binaries.clone())
+            }
+            _ => None,
+        })
+        .with_dependency_policy(match &cli.command {
+            Commands::Install {
+                include_optional,
+                without_recommended,
+                ..
+            } => DependencyPolicy {
+                include_recommended: !without_recommended,
+                include_optional: *include_optional,
+            },
+            _ => DependencyPolicy::default(),
+        });
 
     match cli.command {
         Commands::Init { .. } => unreachable!(),
         Commands::Completion { .. } => unreachable!(),
+        Commands::Install {
+            formulas,
+            build_from_source,
+            force_bottle,
+            dry_run: true,
+            cask,
+            formula,
+            ..
+        } => {
+            commands::install::dry_run(
+                &mut installer,
+                formulas,
+                method_policy(build_from_source, force_bottle),
+                package_kind(cask, formula),
+                &mut ui,
+            )
+            .await
+        }
         Commands::Install {
             formulas,
             no_link,
+            link,
             build_from_source,
+            force_bottle,
+            cask,
+            formula,
+            ..
         } => {
             commands::install::execute(
                 &mut installer,
                 formulas,
-                no_link,
-                build_from_source,
+                (no_link || config.no_link) && !link,
+                method_policy(build_from_source, force_bottle),
+                package_kind(cask, formula),
+                cli.quiet,
                 &mut ui,
             )
             .await
@@ -70,17 +157,36 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
         Commands::Bundle { command } => {
             commands::bundle::execute(&mut installer, command, &mut ui).await
         }
-        Commands::Uninstall { formulas, all } => {
-            commands::uninstall::execute(&mut installer, formulas, all, &mut ui)
-        }
+        Commands::Uninstall {
+            formulas,
+            all,
+            zap,
+            force,
+        } => commands::uninstall::execute(&mut installer, formulas, all, zap, force, &mut ui).await,
         Commands::Migrate { yes, force } => {
             commands::migrate::execute(&mut installer, yes, force, &mut ui).await
         }
-        Commands::Doctor { repair } => commands::doctor::execute(&mut installer, repair, &mut ui),
-        Commands::List => commands::list::execute(&mut installer),
+        Commands::Doctor { recover: true, .. } => {
+            commands::doctor::recover(&mut installer, &mut ui)
+        }
+        Commands::Doctor { repair, deep, .. } => {
+            commands::doctor::execute(&mut installer, repair, deep, &mut ui)
+        }
+        Commands::List {
+            pattern,
+            versions,
+            size,
+            json,
+        } => commands::list::execute(&mut installer, pattern.as_deref(), versions, size, json),
         Commands::Info { formula } => commands::info::execute(&mut installer, formula),
+        Commands::Link { formula, overwrite } => {
+            commands::link::execute(&mut installer, formula, overwrite)
+        }
         Commands::Gc => commands::gc::execute(&mut installer),
+        Commands::Autoremove => commands::autoremove::execute(&mut installer).await,
+        Commands::Cache { command } => commands::cache::execute(&mut installer, command),
         Commands::Update => commands::update::execute(&mut installer),
+        Commands::Config => commands::config::execute(&root, &config),
         Commands::Outdated { json } => {
             commands::outdated::execute(&mut installer, cli.quiet, cli.verbose > 0, json).await
         }
@@ -90,3 +196,23 @@ async fn run(cli: Cli) -> Result<(), zb_core::Error> {
         }
     }
 }
+
+fn method_policy(build_from_source: bool, force_bottle: bool) -> MethodPolicy {
+    if build_from_source {
+        MethodPolicy::ForceSource
+    } else if force_bottle {
+        MethodPolicy::ForceBottle
+    } else {
+        MethodPolicy::PreferBottle
+    }
+}
+
+fn package_kind(cask: bool, formula: bool) -> Option<PackageKind> {
+    if cask {
+        Some(PackageKind::Cask)
+    } else if formula {
+        Some(PackageKind::Formula)
+    } else {
+        None
+    }
+}
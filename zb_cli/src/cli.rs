@@ -1,3 +1,4 @@
+use clap::builder::FalseyValueParser;
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
@@ -12,12 +13,15 @@ pub struct Cli {
     #[arg(long, env = "ZEROBREW_PREFIX")]
     pub prefix: Option<PathBuf>,
 
-    #[arg(
-        long,
-        default_value = "20",
-        value_parser = parse_concurrency
-    )]
-    pub concurrency: usize,
+    /// Directory for downloaded bottles instead of `<root>/cache`. Several
+    /// roots can share one; pruning from any of them may evict blobs the
+    /// others would have reused.
+    #[arg(long, env = "ZEROBREW_CACHE_DIR")]
+    pub cache_dir: Option<PathBuf>,
+
+    /// Bottles downloaded at once [default: `concurrency` in config.toml, or 20]
+    #[arg(long, value_parser = parse_concurrency)]
+    pub concurrency: Option<usize>,
 
     #[arg(long = "auto-init", global = true, env = "ZEROBREW_AUTO_INIT")]
     pub auto_init: bool,
@@ -28,6 +32,19 @@ pub struct Cli {
     #[arg(long, short = 'q', global = true, conflicts_with = "verbose")]
     pub quiet: bool,
 
+    /// Never touch the network; use only cached formula metadata and bottles.
+    #[arg(long, global = true, env = "ZEROBREW_OFFLINE")]
+    pub offline: bool,
+
+    /// Extra CA bundle (PEM file or directory) to trust for downloads.
+    #[arg(long, global = true, env = "ZEROBREW_CA_BUNDLE")]
+    pub ca_bundle: Option<PathBuf>,
+
+    /// Disable TLS certificate verification for downloads. Dangerous; only for
+    /// testing against a self-signed mirror.
+    #[arg(long, global = true)]
+    pub insecure_tls: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -46,11 +63,18 @@ fn parse_concurrency(value: &str) -> Result<usize, String> {
 mod tests {
     use super::Cli;
     use clap::Parser;
+    use std::path::PathBuf;
 
     #[test]
     fn accepts_positive_concurrency() {
         let cli = Cli::try_parse_from(["zb", "--concurrency", "4", "list"]).unwrap();
-        assert_eq!(cli.concurrency, 4);
+        assert_eq!(cli.concurrency, Some(4));
+    }
+
+    #[test]
+    fn concurrency_defers_to_config_when_not_given() {
+        let cli = Cli::try_parse_from(["zb", "list"]).unwrap();
+        assert_eq!(cli.concurrency, None);
     }
 
     #[test]
@@ -68,6 +92,108 @@ mod tests {
         assert!(!cli.quiet);
     }
 
+    #[test]
+    fn accepts_offline_after_subcommand() {
+        let cli = Cli::try_parse_from(["zb", "install", "--offline", "jq"]).unwrap();
+        assert!(cli.offline);
+    }
+
+    #[test]
+    fn accepts_tls_overrides() {
+        let cli = Cli::try_parse_from([
+            "zb",
+            "install",
+            "--ca-bundle",
+            "/etc/corp-ca.pem",
+            "--insecure-tls",
+            "jq",
+        ])
+        .unwrap();
+        assert_eq!(cli.ca_bundle, Some(PathBuf::from("/etc/corp-ca.pem")));
+        assert!(cli.insecure_tls);
+    }
+
+    #[test]
+    fn parses_cache_prune_limits() {
+        use super::{CacheCommands, Commands};
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "cache",
+            "prune",
+            "--max-size",
+            "512",
+            "--max-age",
+            "30",
+        ])
+        .unwrap();
+        let Commands::Cache {
+            command: CacheCommands::Prune { max_size, max_age },
+        } = cli.command
+        else {
+            panic!("expected cache prune command");
+        };
+        assert_eq!(max_size, Some(512));
+        assert_eq!(max_age, Some(30));
+    }
+
+    #[test]
+    fn parses_cache_verify() {
+        use super::{CacheCommands, Commands};
+
+        let cli = Cli::try_parse_from(["zb", "cache", "verify", "--remove"]).unwrap();
+        let Commands::Cache {
+            command: CacheCommands::Verify { remove },
+        } = cli.command
+        else {
+            panic!("expected cache verify command");
+        };
+        assert!(remove);
+    }
+
+    #[test]
+    fn install_target_requires_dry_run() {
+        use super::Commands;
+
+        assert!(Cli::try_parse_from(["zb", "install", "jq", "--target", "x86_64_linux"]).is_err());
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "install",
+            "jq",
+            "--dry-run",
+            "--target",
+            "x86_64_linux",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Install {
+                dry_run, target, ..
+            } => {
+                assert!(dry_run);
+                assert_eq!(target.as_deref(), Some("x86_64_linux"));
+            }
+            _ => panic!("expected install command"),
+        }
+    }
+
+    #[test]
+    fn install_cask_and_formula_selectors_conflict() {
+        use super::Commands;
+
+        let result = Cli::try_parse_from(["zb", "install", "--cask", "--formula", "docker"]);
+        assert!(result.is_err());
+
+        let cli = Cli::try_parse_from(["zb", "install", "--cask", "docker"]).unwrap();
+        match cli.command {
+            Commands::Install { cask, formula, .. } => {
+                assert!(cask);
+                assert!(!formula);
+            }
+            _ => panic!("expected install command"),
+        }
+    }
+
     #[test]
     fn rejects_quiet_with_verbose() {
         let result = Cli::try_parse_from(["zb", "-v", "-q", "list"]);
@@ -91,6 +217,106 @@ mod tests {
         let result = Cli::try_parse_from(["zb", "outdated", "--verbose", "--json"]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn list_json_conflicts_with_versions_and_size() {
+        assert!(Cli::try_parse_from(["zb", "list", "--json", "--versions"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "list", "--json", "--size"]).is_err());
+        assert!(Cli::try_parse_from(["zb", "list", "--json"]).is_ok());
+    }
+
+    #[test]
+    fn install_dependency_policy_flags() {
+        use super::Commands;
+
+        let cli = Cli::try_parse_from([
+            "zb",
+            "install",
+            "--include-optional",
+            "--without-recommended",
+            "ffmpeg",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Install {
+                include_optional,
+                without_recommended,
+                ..
+            } => {
+                assert!(include_optional);
+                assert!(without_recommended);
+            }
+            _ => panic!("expected install command"),
+        }
+    }
+
+    #[test]
+    fn install_link_overrides_no_link() {
+        use super::Commands;
+
+        let cli = Cli::try_parse_from(["zb", "install", "--no-link", "--link", "jq"]).unwrap();
+        match cli.command {
+            Commands::Install { no_link, link, .. } => {
+                assert!(no_link);
+                assert!(link);
+            }
+            _ => panic!("expected install command"),
+        }
+    }
+
+    #[test]
+    fn list_accepts_a_pattern() {
+        use super::Commands;
+
+        let cli = Cli::try_parse_from(["zb", "list", "py*", "--size"]).unwrap();
+        match cli.command {
+            Commands::List { pattern, size, .. } => {
+                assert_eq!(pattern.as_deref(), Some("py*"));
+                assert!(size);
+            }
+            _ => panic!("expected list command"),
+        }
+    }
+
+    #[test]
+    fn force_bottle_conflicts_with_build_from_source() {
+        use super::Commands;
+
+        let cli = Cli::try_parse_from(["zb", "install", "--force-bottle", "jq"]).unwrap();
+        match cli.command {
+            Commands::Install { force_bottle, .. } => assert!(force_bottle),
+            _ => panic!("expected install command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "install", "--force-bottle", "-s", "jq"]).is_err());
+    }
+
+    #[test]
+    fn link_accepts_overwrite() {
+        use super::Commands;
+
+        let cli = Cli::try_parse_from(["zb", "link", "node@20", "--overwrite"]).unwrap();
+        match cli.command {
+            Commands::Link { formula, overwrite } => {
+                assert_eq!(formula, "node@20");
+                assert!(overwrite);
+            }
+            _ => panic!("expected link command"),
+        }
+    }
+
+    #[test]
+    fn doctor_recover_is_a_separate_mode() {
+        use super::Commands;
+
+        let cli = Cli::try_parse_from(["zb", "doctor", "--recover"]).unwrap();
+        match cli.command {
+            Commands::Doctor { recover, .. } => assert!(recover),
+            _ => panic!("expected doctor command"),
+        }
+
+        assert!(Cli::try_parse_from(["zb", "doctor", "--recover", "--repair"]).is_err());
+    }
 }
 
 #[derive(Subcommand)]
@@ -98,20 +324,72 @@ pub enum Commands {
     Install {
         #[arg(required = true, num_args = 1..)]
         formulas: Vec<String>,
-        #[arg(long)]
+        /// Don't link into the prefix; `opt/<name>` is still created, and
+        /// keg-only formulae are never linked either way
+        #[arg(long, env = "ZEROBREW_NO_LINK", value_parser = FalseyValueParser::new())]
         no_link: bool,
-        #[arg(long, short = 's')]
+        /// Link into the prefix even when ZEROBREW_NO_LINK is set
+        #[arg(long)]
+        link: bool,
+        /// Build from source; fail rather than pour a bottle for formulae
+        /// without one
+        #[arg(long, short = 's', conflicts_with = "force_bottle")]
         build_from_source: bool,
+        /// Pour bottles only; fail rather than build formulae without one
+        #[arg(long)]
+        force_bottle: bool,
+        /// Build from source if a bottle is still corrupt after re-downloading
+        #[arg(long)]
+        source_fallback: bool,
+        /// Kill a source build if one phase (configure, compile, ...) runs
+        /// longer than this many seconds
+        #[arg(long, value_name = "SECS", env = "ZEROBREW_BUILD_TIMEOUT")]
+        build_timeout: Option<u64>,
+        /// Reinstall requested formulae even if already installed at the latest version
+        #[arg(long, short = 'f')]
+        force: bool,
+        /// Keep installing the remaining formulae after one fails
+        #[arg(long, short = 'k')]
+        keep_going: bool,
+        /// Resolve and print the install plan without installing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Plan bottles for another platform tag (e.g. x86_64_linux)
+        #[arg(long, value_name = "TAG", requires = "dry_run")]
+        target: Option<String>,
+        /// Treat names as casks when they exist as both a formula and a cask
+        #[arg(long, conflicts_with = "formula")]
+        cask: bool,
+        /// Treat names as formulae when they exist as both a formula and a cask
+        #[arg(long)]
+        formula: bool,
+        /// Stage only this cask binary (by target name); repeat for several
+        #[arg(long = "only-binary", value_name = "TARGET")]
+        only_binaries: Vec<String>,
+        /// Also install dependencies formulae mark as optional
+        #[arg(long)]
+        include_optional: bool,
+        /// Skip dependencies formulae mark as recommended
+        #[arg(long)]
+        without_recommended: bool,
     },
     Bundle {
         #[command(subcommand)]
         command: Option<BundleCommands>,
     },
     Uninstall {
+        /// Names to uninstall. A partial name or glob works too, as long as
+        /// it matches only one installed package
         #[arg(required_unless_present = "all", num_args = 1..)]
         formulas: Vec<String>,
         #[arg(long)]
         all: bool,
+        /// Also remove the data files a cask's zap stanza lists
+        #[arg(long)]
+        zap: bool,
+        /// Remove formulae even if installed formulae still depend on them
+        #[arg(long)]
+        force: bool,
     },
     Migrate {
         #[arg(long, short = 'y')]
@@ -119,15 +397,49 @@ pub enum Commands {
         #[arg(long)]
         force: bool,
     },
-    List,
+    List {
+        /// Only list packages whose name contains this, ignoring case; `*`
+        /// and `?` make it a glob over the whole name
+        pattern: Option<String>,
+        /// Show every recorded version, not just the active one
+        #[arg(long)]
+        versions: bool,
+        /// Show how much disk space each formula uses, largest first
+        #[arg(long, conflicts_with = "versions")]
+        size: bool,
+        /// Print installed formulae as JSON for scripts
+        #[arg(long, conflicts_with_all = ["versions", "size"])]
+        json: bool,
+    },
     Info {
         formula: String,
     },
+    /// Link an installed formula into the prefix
+    Link {
+        formula: String,
+        /// Take over links owned by another installed formula, e.g. to
+        /// switch which version provides a shared binary
+        #[arg(long)]
+        overwrite: bool,
+    },
     Doctor {
         #[arg(long)]
         repair: bool,
+        /// Re-hash every store entry against its extraction manifest
+        #[arg(long)]
+        deep: bool,
+        /// Clean up after an interrupted install: remove unrecorded kegs
+        /// and report records whose keg is missing
+        #[arg(long, conflicts_with_all = ["repair", "deep"])]
+        recover: bool,
     },
     Gc,
+    /// Uninstall dependencies no longer needed by any requested formula
+    Autoremove,
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
     Reset {
         #[arg(long, short = 'y')]
         yes: bool,
@@ -147,6 +459,8 @@ pub enum Commands {
         args: Vec<String>,
     },
     Update,
+    /// Show the settings in effect and where config.toml is read from
+    Config,
     Outdated {
         /// Output as JSON
         #[arg(long, conflicts_with_all = ["quiet", "verbose"])]
@@ -154,13 +468,38 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Remove cached bottle downloads that are not backing an installed formula
+    Prune {
+        /// Evict least-recently-used bottles until the cache is at most this many MiB
+        #[arg(long, value_name = "MIB")]
+        max_size: Option<u64>,
+        /// Remove bottles downloaded more than this many days ago
+        #[arg(long, value_name = "DAYS")]
+        max_age: Option<u64>,
+    },
+    /// Show each store entry's refcount and the installed formulae holding it
+    Info,
+    /// Re-hash cached bottle downloads and report any that are corrupt
+    Verify {
+        /// Delete corrupt bottles so they are downloaded again
+        #[arg(long)]
+        remove: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum BundleCommands {
     Install {
         #[arg(long, short = 'f', value_name = "FILE", default_value = "Brewfile")]
         file: PathBuf,
-        #[arg(long)]
+        /// Don't link into the prefix; see `zb install --no-link`
+        #[arg(long, env = "ZEROBREW_NO_LINK", value_parser = FalseyValueParser::new())]
         no_link: bool,
+        /// Link into the prefix even when ZEROBREW_NO_LINK is set
+        #[arg(long)]
+        link: bool,
     },
     Dump {
         #[arg(long, short = 'f', value_name = "FILE", default_value = "Brewfile")]
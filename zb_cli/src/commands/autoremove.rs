@@ -0,0 +1,29 @@
+use console::style;
+
+pub async fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
+    println!(
+        "{} Removing unused dependencies...",
+        style("==>").cyan().bold()
+    );
+    let removed = installer.autoremove().await?;
+
+    if removed.is_empty() {
+        println!("No unused dependencies to remove.");
+    } else {
+        for name in &removed {
+            println!("    {} Uninstalled {}", style("✓").green(), name);
+        }
+        println!(
+            "{} Removed {} unused {}",
+            style("==>").cyan().bold(),
+            style(removed.len()).green().bold(),
+            if removed.len() == 1 {
+                "dependency"
+            } else {
+                "dependencies"
+            }
+        );
+    }
+
+    Ok(())
+}
@@ -12,11 +12,18 @@ pub async fn execute(
     command: Option<BundleCommands>,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
-    match command.unwrap_or(BundleCommands::Install {
+    let no_link_by_default = installer.config().no_link;
+    match command.unwrap_or_else(|| BundleCommands::Install {
         file: PathBuf::from("Brewfile"),
         no_link: false,
+        link: false,
     }) {
-        BundleCommands::Install { file, no_link } => {
+        BundleCommands::Install {
+            file,
+            no_link,
+            link,
+        } => {
+            let no_link = (no_link || no_link_by_default) && !link;
             install_from_file(installer, &file, no_link, ui).await
         }
         BundleCommands::Dump { file, force } => dump_to_file(installer, &file, force),
@@ -39,7 +46,17 @@ async fn install_from_file(
 
     let start = Instant::now();
     for formula in formulas {
-        install::execute(installer, vec![formula], no_link, false, ui).await?;
+        // Manifest entries are formulae unless written as `cask:<token>`.
+        install::execute(
+            installer,
+            vec![formula],
+            no_link,
+            zb_io::MethodPolicy::PreferBottle,
+            Some(zb_io::PackageKind::Formula),
+            false,
+            ui,
+        )
+        .await?;
     }
 
     println!(
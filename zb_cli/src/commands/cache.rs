@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use console::style;
+
+use crate::cli::CacheCommands;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    command: CacheCommands,
+) -> Result<(), zb_core::Error> {
+    match command {
+        CacheCommands::Prune { max_size, max_age } => prune(installer, max_size, max_age),
+        CacheCommands::Info => info(installer),
+        CacheCommands::Verify { remove } => verify(installer, remove),
+    }
+}
+
+fn info(installer: &zb_io::Installer) -> Result<(), zb_core::Error> {
+    let references = installer.store_references()?;
+    if references.is_empty() {
+        println!("No store entries.");
+        return Ok(());
+    }
+
+    for (store_ref, kegs) in &references {
+        let key = &store_ref.store_key[..store_ref.store_key.len().min(12)];
+        let refcount = if store_ref.refcount > 0 {
+            style(store_ref.refcount).green()
+        } else {
+            style(store_ref.refcount).yellow()
+        };
+        println!("{} refs={}", style(key).bold(), refcount);
+        if store_ref.refcount <= 0 {
+            println!("    {}", style("(unreferenced; removed by `zb gc`)").dim());
+        } else if kegs.is_empty() {
+            println!(
+                "    {}",
+                style("(no installed formula; fix with `zb doctor --repair`)").dim()
+            );
+        }
+        for keg in kegs {
+            println!("    {keg}");
+        }
+    }
+
+    Ok(())
+}
+
+fn prune(
+    installer: &mut zb_io::Installer,
+    max_size_mib: Option<u64>,
+    max_age_days: Option<u64>,
+) -> Result<(), zb_core::Error> {
+    println!("{} Pruning download cache...", style("==>").cyan().bold());
+
+    let max_total_bytes = max_size_mib.map(|mib| mib.saturating_mul(1024 * 1024));
+    let max_age = max_age_days.map(|days| Duration::from_secs(days.saturating_mul(24 * 60 * 60)));
+    let removed = installer.prune_cache(max_total_bytes, max_age)?;
+
+    if removed.is_empty() {
+        println!("No cached bottles to remove.");
+    } else {
+        for sha in &removed {
+            println!(
+                "    {} Removed {}",
+                style("✓").green(),
+                &sha[..sha.len().min(12)]
+            );
+        }
+        println!(
+            "{} Removed {} cached bottles",
+            style("==>").cyan().bold(),
+            style(removed.len()).green().bold()
+        );
+    }
+
+    Ok(())
+}
+
+fn verify(installer: &zb_io::Installer, remove: bool) -> Result<(), zb_core::Error> {
+    println!("{} Verifying download cache...", style("==>").cyan().bold());
+
+    let corrupt = installer.verify_cache(remove)?;
+    if corrupt.is_empty() {
+        println!("All cached bottles match their checksums.");
+        return Ok(());
+    }
+
+    let action = if remove { "Removed" } else { "Corrupt" };
+    for sha in &corrupt {
+        println!(
+            "    {} {} {}",
+            style("✗").red(),
+            action,
+            &sha[..sha.len().min(12)]
+        );
+    }
+    println!(
+        "{} {} corrupt cached bottles",
+        style("==>").cyan().bold(),
+        style(corrupt.len()).red().bold()
+    );
+    if !remove {
+        println!("Run `zb cache verify --remove` to delete them.");
+    }
+
+    Ok(())
+}
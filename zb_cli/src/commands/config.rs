@@ -0,0 +1,82 @@
+use console::style;
+use std::path::Path;
+
+use zb_io::Config;
+
+/// Print the settings in effect, in `config.toml` syntax so any line can be
+/// copied into the file. Values already reflect environment overrides.
+pub fn execute(root: &Path, config: &Config) -> Result<(), zb_core::Error> {
+    let path = Config::path(root);
+    let status = if path.exists() { "" } else { " (not found)" };
+    println!(
+        "{} Settings from {}{}",
+        style("==>").cyan().bold(),
+        path.display(),
+        style(status).dim()
+    );
+
+    for line in render(config) {
+        println!("{line}");
+    }
+
+    Ok(())
+}
+
+fn render(config: &Config) -> Vec<String> {
+    let optional = |key: &str, value: &Option<String>| match value {
+        Some(value) => format!("{key} = {value:?}"),
+        None => format!("# {key} is not set"),
+    };
+    let download = &config.download;
+
+    vec![
+        format!("concurrency = {}", config.concurrency),
+        format!("no_link = {}", config.no_link),
+        format!("offline = {}", config.offline),
+        optional("api_url", &config.api_url),
+        optional("ghcr_url", &config.ghcr_url),
+        format!("mirrors = {:?}", config.mirrors),
+        String::new(),
+        "[download]".to_string(),
+        format!("racing_connections = {}", download.racing_connections),
+        format!(
+            "racing_stagger_ms = {}",
+            download.racing_stagger.as_millis()
+        ),
+        format!(
+            "max_buffered_chunk_bytes = {}",
+            download.max_buffered_chunk_bytes
+        ),
+        format!("reject_html_responses = {}", download.reject_html_responses),
+        format!(
+            "connect_timeout_secs = {}",
+            download.connect_timeout.as_secs()
+        ),
+        format!(
+            "request_timeout_secs = {}",
+            download.request_timeout.as_secs()
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_uses_config_file_syntax() {
+        let config = Config {
+            api_url: Some("https://api.example.com".to_string()),
+            mirrors: vec!["primary!fast.example.com".to_string()],
+            ..Config::default()
+        };
+
+        let lines = render(&config);
+
+        assert!(lines.contains(&"concurrency = 20".to_string()));
+        assert!(lines.contains(&"api_url = \"https://api.example.com\"".to_string()));
+        assert!(lines.contains(&"# ghcr_url is not set".to_string()));
+        assert!(lines.contains(&"mirrors = [\"primary!fast.example.com\"]".to_string()));
+        assert!(lines.contains(&"[download]".to_string()));
+    }
+}
@@ -5,11 +5,16 @@ use crate::ui::StdUi;
 pub fn execute(
     installer: &mut zb_io::Installer,
     repair: bool,
+    deep: bool,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
     ui.heading("Running diagnostics...").map_err(ui_error)?;
 
-    let report = installer.doctor()?;
+    let report = if deep {
+        installer.doctor_deep()?
+    } else {
+        installer.doctor()?
+    };
 
     if report.is_healthy() {
         ui.println(format!("    {} No issues found", style("✓").green()))
@@ -17,68 +22,17 @@ pub fn execute(
         return Ok(());
     }
 
-    for orphan in &report.orphaned_cellar_kegs {
-        ui.warn(format!(
-            "Orphaned cellar keg: {}/{} (no DB record)",
-            orphan.name, orphan.version
-        ))
-        .map_err(ui_error)?;
-    }
-
-    for missing in &report.missing_cellar_kegs {
-        ui.warn(format!(
-            "Missing cellar keg: {}/{} (DB record exists but {} is gone)",
-            missing.name,
-            missing.version,
-            missing.expected_path.display()
-        ))
-        .map_err(ui_error)?;
-    }
-
-    for key in &report.orphaned_store_entries {
-        ui.warn(format!(
-            "Orphaned store entry: {} (no DB reference)",
-            &key[..key.len().min(12)]
-        ))
-        .map_err(ui_error)?;
-    }
-
-    for stale in &report.stale_store_refs {
-        let status = if !stale.on_disk {
-            "not on disk"
-        } else if !stale.referenced_by_any_keg {
-            "unreferenced"
+    let findings = report.findings();
+    for finding in &findings {
+        let suffix = if finding.is_auto_fixable() {
+            ""
         } else {
-            "refcount mismatch"
+            " (needs manual fix)"
         };
-        ui.warn(format!(
-            "Stale store ref: {} (refcount={}, {})",
-            &stale.store_key[..stale.store_key.len().min(12)],
-            stale.refcount,
-            status
-        ))
-        .map_err(ui_error)?;
-    }
-
-    for link in &report.broken_symlinks {
-        ui.warn(format!("Broken symlink: {}", link.display()))
+        ui.warn(format!("{}{suffix}", finding.message()))
             .map_err(ui_error)?;
     }
-
-    if report.stale_keg_file_records > 0 {
-        ui.warn(format!(
-            "{} stale keg_files records (referencing uninstalled kegs)",
-            report.stale_keg_file_records
-        ))
-        .map_err(ui_error)?;
-    }
-
-    let issue_count = report.orphaned_cellar_kegs.len()
-        + report.missing_cellar_kegs.len()
-        + report.orphaned_store_entries.len()
-        + report.stale_store_refs.len()
-        + report.broken_symlinks.len()
-        + usize::from(report.stale_keg_file_records > 0);
+    let issue_count = findings.len();
 
     ui.blank_line().map_err(ui_error)?;
     ui.heading(format!(
@@ -150,6 +104,14 @@ pub fn execute(
         ))
         .map_err(ui_error)?;
     }
+    if summary.reextracted_store_entries > 0 {
+        ui.bullet(format!(
+            "Re-extracted {} corrupted store {}",
+            summary.reextracted_store_entries,
+            pluralize("entry", summary.reextracted_store_entries)
+        ))
+        .map_err(ui_error)?;
+    }
 
     ui.blank_line().map_err(ui_error)?;
     ui.println(format!(
@@ -163,6 +125,51 @@ pub fn execute(
     Ok(())
 }
 
+/// Run [`zb_io::Installer::recover`] and say exactly what it changed and
+/// what it left for the user.
+pub fn recover(installer: &mut zb_io::Installer, ui: &mut StdUi) -> Result<(), zb_core::Error> {
+    ui.heading("Checking for interrupted installs...")
+        .map_err(ui_error)?;
+
+    let report = installer.recover()?;
+
+    if report.is_clean() {
+        ui.println(format!("    {} Nothing to recover", style("✓").green()))
+            .map_err(ui_error)?;
+        return Ok(());
+    }
+
+    for keg in &report.removed_kegs {
+        ui.bullet(format!(
+            "Removed unrecorded keg {}/{}",
+            keg.name, keg.version
+        ))
+        .map_err(ui_error)?;
+    }
+    for dir in &report.removed_staging_dirs {
+        ui.bullet(format!("Removed staging directory {}", dir.display()))
+            .map_err(ui_error)?;
+    }
+    for keg in &report.skipped_kegs {
+        ui.warn(format!(
+            "Left {}/{} in place: it has no DB record but is linked into the prefix",
+            keg.name, keg.version
+        ))
+        .map_err(ui_error)?;
+    }
+    for missing in &report.needs_reinstall {
+        ui.warn(format!(
+            "{} {} is recorded but its keg is missing; run {} to restore it",
+            missing.name,
+            missing.version,
+            style(format!("zb install {}", missing.name)).bold()
+        ))
+        .map_err(ui_error)?;
+    }
+
+    Ok(())
+}
+
 fn pluralize(word: &str, count: usize) -> &str {
     if count == 1 {
         word
@@ -173,6 +180,7 @@ fn pluralize(word: &str, count: usize) -> &str {
             "ref" => "refs",
             "entry" => "entries",
             "symlink" => "symlinks",
+            "file" => "files",
             "fix" => "fixes",
             "issue" => "issues",
             _ => word,
@@ -1,9 +1,10 @@
 use console::style;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar, ProgressStyle};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use zb_io::{InstallProgress, ProgressCallback};
+use zb_core::InstallMethod;
+use zb_io::{BuildPhase, InstallProgress, MethodPolicy, PackageKind, ProgressCallback};
 
 use crate::ui::StdUi;
 use crate::utils::{normalize_formula_name, suggest_homebrew, suggest_missing_formula_matches};
@@ -12,27 +13,24 @@ pub async fn execute(
     installer: &mut zb_io::Installer,
     formulas: Vec<String>,
     no_link: bool,
-    build_from_source: bool,
+    method: MethodPolicy,
+    kind: Option<PackageKind>,
+    quiet: bool,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
     let start = Instant::now();
-    ui.heading(format!(
-        "Installing {}...",
-        style(formulas.join(", ")).bold()
-    ))
-    .map_err(ui_error)?;
+    if !quiet {
+        ui.heading(format!(
+            "Installing {}...",
+            style(formulas.join(", ")).bold()
+        ))
+        .map_err(ui_error)?;
+    }
 
-    let mut normalized_names = Vec::new();
-    let mut cask_names = Vec::new();
+    let mut names = Vec::new();
     for formula in &formulas {
         match normalize_formula_name(formula) {
-            Ok(name) => {
-                if name.starts_with("cask:") {
-                    cask_names.push(name);
-                } else {
-                    normalized_names.push(name);
-                }
-            }
+            Ok(name) => names.push(name),
             Err(e) => {
                 suggest_homebrew(formula, &e);
                 return Err(e);
@@ -40,11 +38,18 @@ pub async fn execute(
         }
     }
 
+    let (cask_names, normalized_names): (Vec<_>, Vec<_>) = installer
+        .resolve_package_kinds(&names, kind)
+        .await?
+        .into_iter()
+        .partition(|name| name.starts_with("cask:"));
+
     let mut installed_count = 0usize;
+    let mut download_summary = None;
 
     if !normalized_names.is_empty() {
         let plan = match installer
-            .plan_with_options(&normalized_names, build_from_source)
+            .plan_with_options(&normalized_names, method, &installer.installed_versions()?)
             .await
         {
             Ok(p) => p,
@@ -60,25 +65,29 @@ pub async fn execute(
             }
         };
 
-        ui.heading(format!(
-            "Resolving dependencies ({} packages)...",
-            plan.items.len()
-        ))
-        .map_err(ui_error)?;
-        for item in &plan.items {
-            ui.bullet(format!(
-                "{} {}",
-                style(&item.formula.name).green(),
-                style(&item.formula.versions.stable).dim()
+        if !quiet {
+            ui.heading(format!(
+                "Resolving dependencies ({} packages)...",
+                plan.items.len()
             ))
             .map_err(ui_error)?;
+            for item in &plan.items {
+                ui.bullet(format!(
+                    "{} {}",
+                    style(&item.formula.name).green(),
+                    style(item.formula.effective_version()).dim()
+                ))
+                .map_err(ui_error)?;
+            }
         }
 
         let multi = MultiProgress::new();
         let bars: Arc<Mutex<HashMap<String, ProgressBar>>> = Arc::new(Mutex::new(HashMap::new()));
 
         let download_style = ProgressStyle::default_bar()
-            .template("    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6}")
+            .template(
+                "    {prefix:<16} {bar:25.cyan/dim} {bytes:>10}/{total_bytes:<10} {eta:>6} {msg}",
+            )
             .unwrap()
             .progress_chars("━━╸");
 
@@ -91,8 +100,10 @@ pub async fn execute(
             .template("    {prefix:<16} {msg}")
             .unwrap();
 
-        ui.heading("Downloading and installing formulas...")
-            .map_err(ui_error)?;
+        if !quiet {
+            ui.heading("Downloading and installing formulas...")
+                .map_err(ui_error)?;
+        }
 
         let bars_clone = bars.clone();
         let multi_clone = multi.clone();
@@ -139,6 +150,58 @@ pub async fn execute(
                         pb.enable_steady_tick(std::time::Duration::from_millis(80));
                     }
                 }
+                InstallProgress::DownloadRaceWon {
+                    name,
+                    url,
+                    attempt,
+                    aborted,
+                } => {
+                    tracing::debug!(
+                        formula = %name,
+                        url = %url,
+                        attempt,
+                        aborted,
+                        "download race won"
+                    );
+                }
+                InstallProgress::DownloadRetrying {
+                    name,
+                    attempt,
+                    max,
+                    reason,
+                } => {
+                    tracing::debug!(
+                        formula = %name,
+                        attempt,
+                        max,
+                        reason = %reason,
+                        "retrying download"
+                    );
+                    if let Some(pb) = bars.get(&name) {
+                        pb.set_message(format!("retrying ({attempt}/{max})..."));
+                    }
+                }
+                InstallProgress::ExtractRetrying {
+                    name,
+                    attempt,
+                    max,
+                    reason,
+                } => {
+                    tracing::debug!(
+                        formula = %name,
+                        attempt,
+                        max,
+                        reason = %reason,
+                        "retrying corrupt bottle"
+                    );
+                    if let Some(pb) = bars.get(&name) {
+                        pb.set_style(spinner_style_clone.clone());
+                        pb.set_message(format!(
+                            "{} bottle corrupt, retrying ({attempt}/{max})...",
+                            style("!").yellow()
+                        ));
+                    }
+                }
                 InstallProgress::UnpackStarted { name } => {
                     if let Some(pb) = bars.get(&name) {
                         pb.set_message("unpacking...");
@@ -159,6 +222,30 @@ pub async fn execute(
                         pb.set_message("linked");
                     }
                 }
+                InstallProgress::SourceFallback { name, .. } => {
+                    if let Some(pb) = bars.get(&name) {
+                        pb.set_style(spinner_style_clone.clone());
+                        pb.set_message(format!(
+                            "{} bottle corrupt, building from source...",
+                            style("!").yellow()
+                        ));
+                    }
+                }
+                InstallProgress::BuildPhase { name, phase } => {
+                    let pb = bars.entry(name.clone()).or_insert_with(|| {
+                        let pb = multi_clone.add(ProgressBar::new_spinner());
+                        pb.set_style(spinner_style_clone.clone());
+                        pb.set_prefix(name);
+                        pb.enable_steady_tick(std::time::Duration::from_millis(80));
+                        pb
+                    });
+                    pb.set_message(format!(
+                        "{}... ({}/{})",
+                        phase.label(),
+                        phase.step(),
+                        BuildPhase::COUNT
+                    ));
+                }
                 InstallProgress::LinkSkipped { name, reason } => {
                     if let Some(pb) = bars.get(&name) {
                         pb.set_message(format!("keg-only ({})", reason));
@@ -171,6 +258,14 @@ pub async fn execute(
                         pb.finish();
                     }
                 }
+                InstallProgress::AlreadyInstalled { name, version } => {
+                    let _ = multi_clone.println(format!(
+                        "    {:<16} {} {} already installed",
+                        name,
+                        style("✓").green(),
+                        style(version).dim()
+                    ));
+                }
             }
         }));
 
@@ -214,6 +309,16 @@ pub async fn execute(
                 ui.blank_line().map_err(ui_error)?;
                 return Err(e.clone());
             }
+            Err(ref e @ zb_core::Error::InstallFailed { ref failed }) => {
+                let names: Vec<&str> = failed.iter().map(|(name, _)| name.as_str()).collect();
+                ui.blank_line().map_err(ui_error)?;
+                ui.info(format!(
+                    "Retry just the failed packages with: {}",
+                    style(format!("zb install {}", names.join(" "))).cyan()
+                ))
+                .map_err(ui_error)?;
+                return Err(e.clone());
+            }
             Err(e) => {
                 let handled_missing = suggest_missing_formula_matches(installer, &e).await;
 
@@ -226,18 +331,25 @@ pub async fn execute(
             }
         };
         installed_count += result.installed;
+        download_summary = download_stats(&result);
     }
 
     if !cask_names.is_empty() {
-        ui.heading(format!(
-            "Installing casks ({} packages)...",
-            cask_names.len()
-        ))
-        .map_err(ui_error)?;
+        if !quiet {
+            ui.heading(format!(
+                "Installing casks ({} packages)...",
+                cask_names.len()
+            ))
+            .map_err(ui_error)?;
+        }
         let result = installer.install_casks(&cask_names, !no_link).await?;
         installed_count += result.installed;
     }
 
+    if quiet {
+        return Ok(());
+    }
+
     let elapsed = start.elapsed();
     ui.blank_line().map_err(ui_error)?;
     ui.heading(format!(
@@ -246,6 +358,101 @@ pub async fn execute(
         elapsed.as_secs_f64()
     ))
     .map_err(ui_error)?;
+    if let Some(summary) = download_summary {
+        ui.note(summary).map_err(ui_error)?;
+    }
+
+    Ok(())
+}
+
+/// One line on how the bottles were obtained, or `None` when none were
+/// needed.
+fn download_stats(result: &zb_io::ExecuteResult) -> Option<String> {
+    if result.downloaded == 0 && result.cache_hits == 0 {
+        return None;
+    }
+
+    let mut line = format!(
+        "Downloaded {} bottles ({})",
+        result.downloaded,
+        HumanBytes(result.downloaded_bytes)
+    );
+    let secs = result.download_time.as_secs_f64();
+    if result.downloaded_bytes > 0 && secs > 0.0 {
+        let rate = (result.downloaded_bytes as f64 / secs) as u64;
+        line.push_str(&format!(" at {}/s", HumanBytes(rate)));
+    }
+    if result.cache_hits > 0 {
+        line.push_str(&format!(", {} from cache", result.cache_hits));
+    }
+    Some(line)
+}
+
+/// Resolve the install plan and print what would be installed, without
+/// downloading or touching the prefix.
+pub async fn dry_run(
+    installer: &mut zb_io::Installer,
+    formulas: Vec<String>,
+    method: MethodPolicy,
+    kind: Option<PackageKind>,
+    ui: &mut StdUi,
+) -> Result<(), zb_core::Error> {
+    let mut names = Vec::new();
+    for formula in &formulas {
+        let name = normalize_formula_name(formula).inspect_err(|e| suggest_homebrew(formula, e))?;
+        names.push(name);
+    }
+
+    let (cask_names, normalized_names): (Vec<_>, Vec<_>) = installer
+        .resolve_package_kinds(&names, kind)
+        .await?
+        .into_iter()
+        .partition(|name| name.starts_with("cask:"));
+
+    if !normalized_names.is_empty() {
+        let plan = match installer
+            .plan_with_options(&normalized_names, method, &installer.installed_versions()?)
+            .await
+        {
+            Ok(p) => p,
+            Err(e) => {
+                if !suggest_missing_formula_matches(installer, &e).await {
+                    for formula in &formulas {
+                        suggest_homebrew(formula, &e);
+                    }
+                }
+                return Err(e);
+            }
+        };
+
+        ui.heading(format!(
+            "Would install {} packages:",
+            style(plan.items.len()).bold()
+        ))
+        .map_err(ui_error)?;
+        for item in &plan.items {
+            let method = match &item.method {
+                InstallMethod::Bottle(bottle) => format!("{} bottle", bottle.tag),
+                InstallMethod::Source(_) => "from source".to_string(),
+            };
+            ui.bullet(format!(
+                "{} {} {}",
+                style(&item.formula.name).green(),
+                style(item.formula.effective_version()).dim(),
+                style(format!("({method})")).dim()
+            ))
+            .map_err(ui_error)?;
+        }
+    }
+
+    if !cask_names.is_empty() {
+        ui.heading(format!("Would install {} casks:", cask_names.len()))
+            .map_err(ui_error)?;
+        for cask in &cask_names {
+            ui.bullet(cask.trim_start_matches("cask:"))
+                .map_err(ui_error)?;
+        }
+    }
 
     Ok(())
 }
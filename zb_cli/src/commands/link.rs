@@ -0,0 +1,23 @@
+use console::style;
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    formula: String,
+    overwrite: bool,
+) -> Result<(), zb_core::Error> {
+    let linked = if overwrite {
+        installer.overwrite_link(&formula)?
+    } else {
+        installer.link(&formula)?
+    };
+
+    println!(
+        "{} Linked {} ({} {})",
+        style("==>").cyan().bold(),
+        style(&formula).bold(),
+        linked.len(),
+        if linked.len() == 1 { "file" } else { "files" }
+    );
+
+    Ok(())
+}
@@ -1,10 +1,63 @@
 use console::style;
+use indicatif::HumanBytes;
+use std::collections::HashSet;
 
-pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
-    let installed = installer.list_installed()?;
+/// Names `zb list <pattern>` keeps; `None` keeps everything.
+struct Filter(Option<HashSet<String>>);
+
+impl Filter {
+    fn new(installer: &zb_io::Installer, pattern: Option<&str>) -> Result<Self, zb_core::Error> {
+        let Some(pattern) = pattern else {
+            return Ok(Self(None));
+        };
+        let names = installer
+            .search_installed(pattern)?
+            .into_iter()
+            .map(|keg| keg.name)
+            .collect();
+        Ok(Self(Some(names)))
+    }
+
+    fn keeps(&self, name: &str) -> bool {
+        self.0.as_ref().is_none_or(|names| names.contains(name))
+    }
+
+    fn nothing_listed(&self) {
+        if self.0.is_some() {
+            println!("No installed formulas match.");
+        } else {
+            println!("No formulas installed.");
+        }
+    }
+}
+
+pub fn execute(
+    installer: &mut zb_io::Installer,
+    pattern: Option<&str>,
+    versions: bool,
+    size: bool,
+    json: bool,
+) -> Result<(), zb_core::Error> {
+    let filter = Filter::new(installer, pattern)?;
+
+    if json {
+        let mut entries = installer.list_installed_detailed()?;
+        entries.retain(|entry| filter.keeps(&entry.name));
+        println!("{}", serde_json::to_string_pretty(&entries).unwrap());
+        return Ok(());
+    }
+    if versions {
+        return list_versions(installer, &filter);
+    }
+    if size {
+        return list_sizes(installer, &filter);
+    }
+
+    let mut installed = installer.list_installed()?;
+    installed.retain(|keg| filter.keeps(&keg.name));
 
     if installed.is_empty() {
-        println!("No formulas installed.");
+        filter.nothing_listed();
     } else {
         for keg in installed {
             println!("{} {}", style(&keg.name).bold(), style(&keg.version).dim());
@@ -13,3 +66,63 @@ pub fn execute(installer: &mut zb_io::Installer) -> Result<(), zb_core::Error> {
 
     Ok(())
 }
+
+fn list_versions(installer: &mut zb_io::Installer, filter: &Filter) -> Result<(), zb_core::Error> {
+    let mut versions = installer.list_installed_versions()?;
+    versions.retain(|keg| filter.keeps(&keg.name));
+
+    if versions.is_empty() {
+        filter.nothing_listed();
+        return Ok(());
+    }
+
+    let mut current: Option<&str> = None;
+    let mut line = String::new();
+    for keg in &versions {
+        if current != Some(keg.name.as_str()) {
+            if !line.is_empty() {
+                println!("{line}");
+            }
+            line = style(&keg.name).bold().to_string();
+            current = Some(keg.name.as_str());
+        }
+        let version = if keg.is_active {
+            style(&keg.version).green().to_string()
+        } else {
+            style(&keg.version).dim().to_string()
+        };
+        line.push(' ');
+        line.push_str(&version);
+    }
+    println!("{line}");
+
+    Ok(())
+}
+
+fn list_sizes(installer: &mut zb_io::Installer, filter: &Filter) -> Result<(), zb_core::Error> {
+    let mut sized = installer.list_installed_with_sizes()?;
+    sized.retain(|(keg, _)| filter.keeps(&keg.name));
+
+    if sized.is_empty() {
+        filter.nothing_listed();
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for (keg, size) in &sized {
+        total += size;
+        println!(
+            "{:>10}  {} {}",
+            HumanBytes(*size).to_string(),
+            style(&keg.name).bold(),
+            style(&keg.version).dim()
+        );
+    }
+    println!(
+        "{:>10}  {}",
+        HumanBytes(total).to_string(),
+        style("total").dim()
+    );
+
+    Ok(())
+}
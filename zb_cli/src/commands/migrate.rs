@@ -88,7 +88,9 @@ pub async fn execute(
         installer,
         formula_names.clone(),
         false, // no_link
-        false, // build_from_source
+        zb_io::MethodPolicy::PreferBottle,
+        Some(zb_io::PackageKind::Formula),
+        false, // quiet
         ui,
     )
     .await
@@ -1,10 +1,14 @@
+pub mod autoremove;
 pub mod bundle;
+pub mod cache;
 pub mod completion;
+pub mod config;
 pub mod doctor;
 pub mod gc;
 pub mod info;
 pub mod init;
 pub mod install;
+pub mod link;
 pub mod list;
 pub mod migrate;
 pub mod outdated;
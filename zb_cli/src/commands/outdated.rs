@@ -39,6 +39,15 @@ pub async fn execute(
     }
 
     for pkg in &outdated {
+        let current_version = if pkg.is_rebuild() {
+            format!(
+                "{} (bottle rebuild {})",
+                pkg.current_version, pkg.current_rebuild
+            )
+        } else {
+            pkg.current_version.clone()
+        };
+
         if quiet {
             println!("{}", pkg.name);
         } else if verbose {
@@ -47,12 +56,12 @@ pub async fn execute(
                 pkg.name,
                 style(&pkg.installed_version).red(),
                 style("→").dim(),
-                style(&pkg.current_version).green(),
+                style(&current_version).green(),
             );
         } else {
             println!(
                 "{} ({}) < {}",
-                pkg.name, pkg.installed_version, pkg.current_version
+                pkg.name, pkg.installed_version, current_version
             );
         }
     }
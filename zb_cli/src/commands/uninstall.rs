@@ -2,10 +2,12 @@ use crate::ui::StdUi;
 use crate::utils::normalize_formula_name;
 use console::style;
 
-pub fn execute(
+pub async fn execute(
     installer: &mut zb_io::Installer,
     formulas: Vec<String>,
     all: bool,
+    zap: bool,
+    force: bool,
     ui: &mut StdUi,
 ) -> Result<(), zb_core::Error> {
     let formulas = if all {
@@ -16,11 +18,18 @@ pub fn execute(
         }
         installed.into_iter().map(|k| k.name).collect()
     } else {
-        let mut normalized = Vec::with_capacity(formulas.len());
+        let mut resolved = Vec::with_capacity(formulas.len());
         for formula in formulas {
-            normalized.push(normalize_formula_name(&formula)?);
+            let name = normalize_formula_name(&formula)?;
+            // Nothing is removed if any pattern is ambiguous. Names that
+            // match nothing go through as typed and are reported below.
+            match installer.resolve_installed(&name) {
+                Ok(installed) => resolved.push(installed),
+                Err(zb_core::Error::NotInstalled { .. }) => resolved.push(name),
+                Err(e) => return Err(e),
+            }
         }
-        normalized
+        resolved
     };
 
     ui.heading(format!(
@@ -30,20 +39,45 @@ pub fn execute(
     .map_err(ui_error)?;
 
     let mut errors: Vec<(String, zb_core::Error)> = Vec::new();
+    let mut zapped: Vec<zb_io::ZapReport> = Vec::new();
 
-    if formulas.len() > 1 {
+    if !zap {
+        let report = installer.uninstall_many(&formulas, force).await?;
+        for name in &report.removed {
+            ui.step_start(name).map_err(ui_error)?;
+            ui.step_ok().map_err(ui_error)?;
+        }
+        errors.extend(report.skipped);
+    } else if formulas.len() > 1 {
         for name in &formulas {
             ui.step_start(name).map_err(ui_error)?;
-            match installer.uninstall(name) {
-                Ok(()) => ui.step_ok().map_err(ui_error)?,
+            match installer.uninstall_zap(name).await {
+                Ok(report) => {
+                    ui.step_ok().map_err(ui_error)?;
+                    zapped.push(report);
+                }
                 Err(e) => {
                     ui.step_fail().map_err(ui_error)?;
                     errors.push((name.clone(), e));
                 }
             }
         }
-    } else if let Err(e) = installer.uninstall(&formulas[0]) {
-        errors.push((formulas[0].clone(), e));
+    } else {
+        match installer.uninstall_zap(&formulas[0]).await {
+            Ok(report) => zapped.push(report),
+            Err(e) => errors.push((formulas[0].clone(), e)),
+        }
+    }
+
+    for report in &zapped {
+        for path in &report.removed {
+            ui.bullet(format!("Zapped {}", path.display()))
+                .map_err(ui_error)?;
+        }
+        for (path, err) in &report.skipped {
+            ui.warn(format!("Skipped zap path {path}: {err}"))
+                .map_err(ui_error)?;
+        }
     }
 
     if errors.is_empty() {
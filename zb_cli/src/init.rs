@@ -1,9 +1,10 @@
 use console::style;
 use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::process::Command;
 
 use crate::ui::{PromptDefault, StdUi};
+use zb_core::Paths;
 use zb_io::validate_privileged_path;
 
 #[derive(Debug)]
@@ -31,19 +32,7 @@ pub fn needs_init(root: &Path, prefix: &Path) -> bool {
     !(root_ok && prefix_ok)
 }
 
-pub fn is_writable(path: &Path) -> bool {
-    if !path.exists() {
-        return false;
-    }
-    let test_file = path.join(".zb_write_test");
-    match std::fs::write(&test_file, b"test") {
-        Ok(_) => {
-            let _ = std::fs::remove_file(&test_file);
-            true
-        }
-        Err(_) => false,
-    }
-}
+pub use zb_io::is_writable;
 
 /// Longest Homebrew prefix we may need to replace in Mach-O binaries.
 /// On macOS, paths inside Mach-O headers are fixed-size, so the replacement
@@ -92,16 +81,7 @@ pub fn run_init(
     };
     let zerobrew_bin = format!("{}/bin", zerobrew_dir);
 
-    let dirs_to_create: Vec<PathBuf> = vec![
-        root.to_path_buf(),
-        root.join("store"),
-        root.join("db"),
-        root.join("cache"),
-        root.join("locks"),
-        prefix.to_path_buf(),
-        prefix.join("bin"),
-        prefix.join("Cellar"),
-    ];
+    let dirs_to_create = Paths::new(root.to_path_buf(), prefix.to_path_buf()).init_dirs();
 
     let need_sudo = dirs_to_create.iter().any(|d| {
         if d.exists() {
@@ -1,6 +1,7 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-use crate::Formula;
+use crate::{Checksum, Formula};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum BuildSystem {
@@ -22,13 +23,19 @@ pub struct BuildPlan {
     pub formula_name: String,
     pub version: String,
     pub source_url: String,
-    pub source_checksum: Option<String>,
+    pub source_checksum: Checksum,
     pub ruby_source_path: Option<String>,
     pub build_dependencies: Vec<String>,
     pub runtime_dependencies: Vec<String>,
     pub detected_system: BuildSystem,
     pub prefix: PathBuf,
     pub cellar_path: PathBuf,
+    /// Extra variables for the build process, applied over the standard
+    /// build environment (so e.g. `CFLAGS` here replaces the default).
+    pub env: BTreeMap<String, String>,
+    /// Appended to `std_configure_args` for formulae that need flags such as
+    /// `--with-feature`.
+    pub configure_args: Vec<String>,
 }
 
 impl BuildPlan {
@@ -44,13 +51,17 @@ impl BuildPlan {
             formula_name: formula.name.clone(),
             version,
             source_url: source.url.clone(),
-            source_checksum: source.checksum.clone(),
+            source_checksum: source.parsed_checksum(),
             ruby_source_path: formula.ruby_source_path.clone(),
             build_dependencies: all_build_deps,
             runtime_dependencies: formula.dependencies.clone(),
             detected_system,
             prefix: prefix.to_path_buf(),
             cellar_path,
+            // The formula API carries no build flags; callers fill these in
+            // on the planned item before executing it.
+            env: BTreeMap::new(),
+            configure_args: Vec::new(),
         })
     }
 }
@@ -86,6 +97,8 @@ mod tests {
             BottleFile {
                 url: format!("https://example.com/{name}.tar.gz"),
                 sha256: "deadbeef".repeat(8),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -102,6 +115,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: build_deps.iter().map(|s| s.to_string()).collect(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: Some(FormulaUrls {
                 stable: Some(SourceUrl {
                     url: source_url.to_string(),
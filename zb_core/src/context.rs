@@ -1,9 +1,16 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::Error;
+
+/// Where zerobrew keeps its state (`root`) and where packages are exposed
+/// (`prefix`). Both `zb init` and the installer derive every directory from
+/// here, so the layout is defined in one place.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Paths {
     pub root: PathBuf,
+    pub prefix: PathBuf,
     pub store: PathBuf,
+    /// Lives under the prefix so bottles' hardcoded rpaths resolve.
     pub cellar: PathBuf,
     pub cache: PathBuf,
     pub db: PathBuf,
@@ -11,15 +18,16 @@ pub struct Paths {
 }
 
 impl Paths {
-    pub fn from_root(root: PathBuf) -> Self {
+    pub fn new(root: PathBuf, prefix: PathBuf) -> Self {
         let store = root.join("store");
-        let cellar = root.join("cellar");
+        let cellar = prefix.join("Cellar");
         let cache = root.join("cache");
         let db = root.join("db").join("zb.sqlite3");
         let locks = root.join("locks");
 
         Self {
             root,
+            prefix,
             store,
             cellar,
             cache,
@@ -27,6 +35,97 @@ impl Paths {
             locks,
         }
     }
+
+    pub fn from_root(root: PathBuf) -> Self {
+        let prefix = Self::default_prefix(&root);
+        Self::new(root, prefix)
+    }
+
+    /// On macOS, Mach-O binaries have fixed-size path fields so the prefix
+    /// must be no longer than the original Homebrew prefix (/opt/homebrew =
+    /// 13 chars). Using root directly (/opt/zerobrew = 13 chars) keeps us
+    /// within that limit.
+    pub fn default_prefix(root: &Path) -> PathBuf {
+        if cfg!(target_os = "macos") {
+            root.to_path_buf()
+        } else {
+            root.join("prefix")
+        }
+    }
+
+    pub fn bin(&self) -> PathBuf {
+        self.prefix.join("bin")
+    }
+
+    pub fn api_cache(&self) -> PathBuf {
+        self.cache.join("api-cache.sqlite")
+    }
+
+    /// Output of each source build, kept after the build so failures can be
+    /// diagnosed.
+    pub fn build_logs(&self) -> PathBuf {
+        self.root.join("tmp").join("build-logs")
+    }
+
+    /// Reject layouts that would make zerobrew trample its own state: relative
+    /// paths, a prefix inside one of the root's state directories, or a root
+    /// inside the cellar. A prefix equal to the root is fine (it is the macOS
+    /// default); the state directories and prefix directories don't overlap.
+    pub fn validate(&self) -> Result<(), Error> {
+        for (label, path) in [("root", &self.root), ("prefix", &self.prefix)] {
+            if !path.is_absolute() {
+                return Err(Error::InvalidArgument {
+                    message: format!("{label} must be an absolute path, got '{}'", path.display()),
+                });
+            }
+        }
+
+        let db_dir = self.db.parent().unwrap_or(&self.root);
+        for state_dir in [&self.store, &self.cache, &db_dir.to_path_buf(), &self.locks] {
+            if self.prefix.starts_with(state_dir) {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "prefix '{}' is inside zerobrew's state directory '{}'",
+                        self.prefix.display(),
+                        state_dir.display()
+                    ),
+                });
+            }
+        }
+
+        if self.root.starts_with(&self.cellar) {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "root '{}' is inside the cellar '{}'",
+                    self.root.display(),
+                    self.cellar.display()
+                ),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Bottles hardcode rpaths relative to the prefix, so a cellar anywhere
+    /// else installs fine but breaks at load time.
+    pub fn cellar_outside_prefix(&self) -> bool {
+        !self.cellar.starts_with(&self.prefix)
+    }
+
+    /// Directories `zb init` creates, parents first.
+    pub fn init_dirs(&self) -> Vec<PathBuf> {
+        let db_dir = self.db.parent().unwrap_or(&self.root).to_path_buf();
+        vec![
+            self.root.clone(),
+            self.store.clone(),
+            db_dir,
+            self.cache.clone(),
+            self.locks.clone(),
+            self.prefix.clone(),
+            self.bin(),
+            self.cellar.clone(),
+        ]
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -34,6 +133,8 @@ pub struct ConcurrencyLimits {
     pub download: usize,
     pub unpack: usize,
     pub materialize: usize,
+    /// Formula API requests in flight at once while resolving dependencies.
+    pub metadata: usize,
 }
 
 impl Default for ConcurrencyLimits {
@@ -42,12 +143,16 @@ impl Default for ConcurrencyLimits {
             download: 20,
             unpack: 4,
             materialize: 4,
+            metadata: 8,
         }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Ordered from most to least verbose; a handle at a given level emits that
+/// level and everything more severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
+    Debug,
     Info,
     Warn,
     Error,
@@ -58,6 +163,33 @@ pub struct LoggerHandle {
     pub level: LogLevel,
 }
 
+impl LoggerHandle {
+    pub fn new(level: LogLevel) -> Self {
+        Self { level }
+    }
+
+    /// Map the CLI's `-v`/`-q` flags onto a level: quiet keeps only errors,
+    /// the default is `Info`, and any `-v` enables debug detail.
+    pub fn from_verbosity(verbose: u8, quiet: bool) -> Self {
+        let level = if quiet {
+            LogLevel::Error
+        } else if verbose > 0 {
+            LogLevel::Debug
+        } else {
+            LogLevel::Info
+        };
+        Self { level }
+    }
+
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.level == LogLevel::Error
+    }
+}
+
 impl Default for LoggerHandle {
     fn default() -> Self {
         Self {
@@ -96,10 +228,7 @@ mod tests {
             context.paths.store,
             PathBuf::from("/opt/zerobrew").join("store")
         );
-        assert_eq!(
-            context.paths.cellar,
-            PathBuf::from("/opt/zerobrew").join("cellar")
-        );
+        assert_eq!(context.paths.cellar, context.paths.prefix.join("Cellar"));
         assert_eq!(
             context.paths.cache,
             PathBuf::from("/opt/zerobrew").join("cache")
@@ -113,4 +242,86 @@ mod tests {
             PathBuf::from("/opt/zerobrew").join("locks")
         );
     }
+
+    #[test]
+    fn init_dirs_cover_the_installer_layout() {
+        let paths = Paths::new(PathBuf::from("/zb"), PathBuf::from("/zb/prefix"));
+
+        assert_eq!(paths.cellar, PathBuf::from("/zb/prefix/Cellar"));
+        assert_eq!(
+            paths.api_cache(),
+            PathBuf::from("/zb/cache/api-cache.sqlite")
+        );
+        let dirs = paths.init_dirs();
+        assert!(dirs.contains(&PathBuf::from("/zb/db")));
+        assert!(dirs.contains(&paths.cellar));
+        assert!(dirs.contains(&paths.bin()));
+    }
+
+    #[test]
+    fn logger_handle_filters_by_severity() {
+        let quiet = LoggerHandle::from_verbosity(0, true);
+        assert!(quiet.is_quiet());
+        assert!(quiet.enabled(LogLevel::Error));
+        assert!(!quiet.enabled(LogLevel::Warn));
+
+        let normal = LoggerHandle::from_verbosity(0, false);
+        assert_eq!(normal, LoggerHandle::default());
+        assert!(normal.enabled(LogLevel::Warn));
+        assert!(!normal.enabled(LogLevel::Debug));
+
+        let verbose = LoggerHandle::from_verbosity(2, false);
+        assert!(verbose.enabled(LogLevel::Debug));
+    }
+
+    #[test]
+    fn validate_accepts_default_layouts() {
+        let linux = Paths::new(PathBuf::from("/zb"), PathBuf::from("/zb/prefix"));
+        assert!(linux.validate().is_ok());
+
+        let macos = Paths::new(
+            PathBuf::from("/opt/zerobrew"),
+            PathBuf::from("/opt/zerobrew"),
+        );
+        assert!(macos.validate().is_ok());
+        assert!(!macos.cellar_outside_prefix());
+    }
+
+    #[test]
+    fn validate_rejects_overlapping_or_relative_paths() {
+        let relative = Paths::new(PathBuf::from("zb"), PathBuf::from("/zb/prefix"));
+        assert!(
+            relative
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("absolute")
+        );
+
+        let in_store = Paths::new(PathBuf::from("/zb"), PathBuf::from("/zb/store/prefix"));
+        assert!(
+            in_store
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("state directory")
+        );
+
+        let root_in_cellar = Paths::new(PathBuf::from("/p/Cellar/zb"), PathBuf::from("/p"));
+        assert!(
+            root_in_cellar
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("cellar")
+        );
+    }
+
+    #[test]
+    fn cellar_outside_prefix_is_detected() {
+        let mut paths = Paths::new(PathBuf::from("/zb"), PathBuf::from("/zb/prefix"));
+        paths.cellar = PathBuf::from("/elsewhere/Cellar");
+        assert!(paths.cellar_outside_prefix());
+        assert!(paths.validate().is_ok());
+    }
 }
@@ -1,5 +1,6 @@
 use std::fmt;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ConflictedLink {
@@ -9,19 +10,76 @@ pub struct ConflictedLink {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Error {
-    UnsupportedBottle { name: String },
-    ChecksumMismatch { expected: String, actual: String },
-    LinkConflict { conflicts: Vec<ConflictedLink> },
-    StoreCorruption { message: String },
-    NetworkFailure { message: String },
-    MissingFormula { name: String },
-    UnsupportedTap { name: String },
-    UnsupportedFormula { name: String, reason: String },
-    DependencyCycle { cycle: Vec<String> },
-    NotInstalled { name: String },
-    FileError { message: String },
-    InvalidArgument { message: String },
-    ExecutionError { message: String },
+    UnsupportedBottle {
+        name: String,
+    },
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    LinkConflict {
+        conflicts: Vec<ConflictedLink>,
+    },
+    StoreCorruption {
+        message: String,
+    },
+    NetworkFailure {
+        message: String,
+    },
+    NetworkTimeout {
+        url: String,
+        after: Duration,
+    },
+    MissingFormula {
+        name: String,
+    },
+    UnsupportedTap {
+        name: String,
+    },
+    UnsupportedFormula {
+        name: String,
+        reason: String,
+    },
+    DependencyCycle {
+        cycle: Vec<String>,
+    },
+    NotInstalled {
+        name: String,
+    },
+    FileError {
+        message: String,
+    },
+    InvalidArgument {
+        message: String,
+    },
+    ExecutionError {
+        message: String,
+    },
+    OfflineCacheMiss {
+        name: String,
+    },
+    UnknownArchiveFormat {
+        magic: String,
+        is_text: bool,
+    },
+    UnsupportedArchiveFormat {
+        format: String,
+    },
+    /// `candidates` lists the installed packages `name` could mean; empty
+    /// when it is both a formula and a cask.
+    AmbiguousName {
+        name: String,
+        candidates: Vec<String>,
+    },
+    InstallFailed {
+        failed: Vec<(String, Error)>,
+    },
+    RequiredBy {
+        name: String,
+        dependents: Vec<String>,
+    },
+    /// Stopped on request, for instance by Ctrl-C, before it finished.
+    Cancelled,
 }
 
 impl fmt::Display for Error {
@@ -53,6 +111,11 @@ impl fmt::Display for Error {
             }
             Error::StoreCorruption { message } => write!(f, "store corruption: {message}"),
             Error::NetworkFailure { message } => write!(f, "network failure: {message}"),
+            Error::NetworkTimeout { url, after } => write!(
+                f,
+                "timed out after {:.1}s waiting on {url}; check your network connection",
+                after.as_secs_f64()
+            ),
             Error::MissingFormula { name } => write!(f, "missing formula '{name}'"),
             Error::UnsupportedTap { name } => {
                 write!(
@@ -71,6 +134,46 @@ impl fmt::Display for Error {
             Error::FileError { message } => write!(f, "file error: {message}"),
             Error::InvalidArgument { message } => write!(f, "invalid argument: {message}"),
             Error::ExecutionError { message } => write!(f, "{message}"),
+            Error::OfflineCacheMiss { name } => {
+                write!(f, "offline mode: not available in local cache: {name}")
+            }
+            Error::UnknownArchiveFormat {
+                magic,
+                is_text: true,
+            } => write!(
+                f,
+                "downloaded content is not an archive (looks like HTML/text; leading bytes: {magic})"
+            ),
+            Error::UnknownArchiveFormat {
+                magic,
+                is_text: false,
+            } => write!(f, "unrecognized archive format (leading bytes: {magic})"),
+            Error::UnsupportedArchiveFormat { format } => {
+                write!(f, "{format} archives are not supported")
+            }
+            Error::AmbiguousName { name, candidates } if candidates.is_empty() => write!(
+                f,
+                "'{name}' is both a formula and a cask; choose one with --formula or --cask (or install cask:{name})"
+            ),
+            Error::AmbiguousName { name, candidates } => write!(
+                f,
+                "'{name}' matches {} installed packages: {}; use the full name",
+                candidates.len(),
+                candidates.join(", ")
+            ),
+            Error::RequiredBy { name, dependents } => write!(
+                f,
+                "'{name}' is required by {}; uninstall them first or use --force",
+                dependents.join(", ")
+            ),
+            Error::Cancelled => write!(f, "cancelled"),
+            Error::InstallFailed { failed } => {
+                write!(f, "{} packages failed to install:", failed.len())?;
+                for (name, err) in failed {
+                    write!(f, "\n  {name}: {err}")?;
+                }
+                Ok(())
+            }
         }
     }
 }
@@ -108,4 +211,86 @@ mod tests {
 
         assert!(err.to_string().contains("libheif"));
     }
+
+    #[test]
+    fn offline_cache_miss_display_includes_name() {
+        let err = Error::OfflineCacheMiss {
+            name: "jq".to_string(),
+        };
+
+        assert!(err.to_string().contains("offline"));
+        assert!(err.to_string().contains("jq"));
+    }
+
+    #[test]
+    fn install_failed_display_lists_each_package() {
+        let err = Error::InstallFailed {
+            failed: vec![
+                (
+                    "jq".to_string(),
+                    Error::MissingFormula {
+                        name: "oniguruma".to_string(),
+                    },
+                ),
+                (
+                    "wget".to_string(),
+                    Error::NetworkFailure {
+                        message: "timed out".to_string(),
+                    },
+                ),
+            ],
+        };
+
+        let rendered = err.to_string();
+        assert!(rendered.starts_with("2 packages failed"));
+        assert!(rendered.contains("\n  jq: missing formula 'oniguruma'"));
+        assert!(rendered.contains("\n  wget: network failure: timed out"));
+    }
+
+    #[test]
+    fn unknown_archive_format_display_flags_text_content() {
+        let err = Error::UnknownArchiveFormat {
+            magic: "3c 21 44 4f".to_string(),
+            is_text: true,
+        };
+
+        assert!(err.to_string().contains("not an archive"));
+        assert!(err.to_string().contains("3c 21 44 4f"));
+    }
+
+    #[test]
+    fn required_by_display_lists_dependents() {
+        let err = Error::RequiredBy {
+            name: "openssl@3".to_string(),
+            dependents: vec!["curl".to_string(), "wget".to_string()],
+        };
+
+        let rendered = err.to_string();
+        assert!(rendered.contains("'openssl@3'"));
+        assert!(rendered.contains("curl, wget"));
+    }
+
+    #[test]
+    fn network_timeout_display_names_url_and_duration() {
+        let err = Error::NetworkTimeout {
+            url: "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc".to_string(),
+            after: Duration::from_millis(30_300),
+        };
+
+        let rendered = err.to_string();
+        assert!(rendered.contains("timed out after 30.3s"));
+        assert!(rendered.contains("ghcr.io/v2/homebrew/core/jq"));
+    }
+
+    #[test]
+    fn ambiguous_name_display_lists_candidates() {
+        let err = Error::AmbiguousName {
+            name: "python".to_string(),
+            candidates: vec!["python@3.11".to_string(), "python@3.12".to_string()],
+        };
+        assert_eq!(
+            err.to_string(),
+            "'python' matches 2 installed packages: python@3.11, python@3.12; use the full name"
+        );
+    }
 }
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use crate::{Error, Formula};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -5,6 +7,45 @@ pub struct SelectedBottle {
     pub tag: String,
     pub url: String,
     pub sha256: String,
+    /// Bottle rebuild number. A rebuild keeps the version but changes sha256.
+    pub rebuild: u32,
+    /// Formula-declared mirrors of `url`, tried as download fallbacks.
+    pub mirror_urls: Vec<String>,
+    /// Where the bottle may be poured.
+    pub cellar: CellarRequirement,
+}
+
+/// What a bottle's `cellar` field says about where it can be installed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CellarRequirement {
+    /// `:any`: paths inside are placeholders rewritten when poured. Also
+    /// assumed when the field is missing.
+    #[default]
+    Any,
+    /// `:any_skip_relocation`: contains no paths that need rewriting.
+    AnySkipRelocation,
+    /// Built with this cellar path baked in; only usable from that cellar.
+    Path(String),
+}
+
+impl CellarRequirement {
+    /// Read a `cellar` value as it appears in formula JSON or a tap's
+    /// `bottle do` block, with or without the leading colon on symbols.
+    pub fn parse(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            None | Some("") | Some(":any") | Some("any") => Self::Any,
+            Some(":any_skip_relocation") | Some("any_skip_relocation") => Self::AnySkipRelocation,
+            Some(path) => Self::Path(path.trim_end_matches('/').to_string()),
+        }
+    }
+
+    /// Whether a bottle with this requirement can be poured into `cellar`.
+    pub fn allows(&self, cellar: &Path) -> bool {
+        match self {
+            Self::Any | Self::AnySkipRelocation => true,
+            Self::Path(path) => Path::new(path) == cellar,
+        }
+    }
 }
 
 const MACOS_CODENAMES_NEWEST_FIRST: &[&str] = &["tahoe", "sequoia", "sonoma", "ventura"];
@@ -43,7 +84,17 @@ pub fn compatible_codenames(major_version: Option<u32>) -> Vec<&'static str> {
     MACOS_CODENAMES_NEWEST_FIRST[pos..].to_vec()
 }
 
-pub fn select_bottle(formula: &Formula) -> Result<SelectedBottle, Error> {
+/// Pick the bottle to install for `formula`.
+///
+/// With `target_tag` set (e.g. `arm64_sonoma`, `x86_64_linux`) the choice is
+/// made for that platform instead of the host, so installs can be planned for
+/// another machine. macOS tags also accept bottles for older releases of the
+/// same architecture, mirroring host selection.
+pub fn select_bottle(formula: &Formula, target_tag: Option<&str>) -> Result<SelectedBottle, Error> {
+    if let Some(tag) = target_tag {
+        return select_bottle_for_tag(formula, tag);
+    }
+
     #[cfg(target_os = "macos")]
     let macos_version = macos_major_version();
     #[cfg(not(target_os = "macos"))]
@@ -52,6 +103,48 @@ pub fn select_bottle(formula: &Formula) -> Result<SelectedBottle, Error> {
     select_bottle_with_version(formula, macos_version)
 }
 
+fn select_bottle_for_tag(formula: &Formula, target_tag: &str) -> Result<SelectedBottle, Error> {
+    for tag in candidate_tags(target_tag)
+        .iter()
+        .map(String::as_str)
+        .chain(["all"])
+    {
+        if let Some(file) = formula.bottle.stable.files.get(tag) {
+            return Ok(SelectedBottle {
+                tag: tag.to_string(),
+                url: file.url.clone(),
+                sha256: file.sha256.clone(),
+                rebuild: formula.bottle.stable.rebuild,
+                mirror_urls: file.mirrors.clone(),
+                cellar: CellarRequirement::parse(file.cellar.as_deref()),
+            });
+        }
+    }
+
+    Err(Error::UnsupportedBottle {
+        name: formula.name.clone(),
+    })
+}
+
+/// Tags acceptable for `target_tag`, most preferred first.
+fn candidate_tags(target_tag: &str) -> Vec<String> {
+    let (arch_prefix, codename) = match target_tag.strip_prefix("arm64_") {
+        Some(codename) => ("arm64_", codename),
+        None => ("", target_tag),
+    };
+
+    match MACOS_CODENAMES_NEWEST_FIRST
+        .iter()
+        .position(|&c| c == codename)
+    {
+        Some(pos) => MACOS_CODENAMES_NEWEST_FIRST[pos..]
+            .iter()
+            .map(|c| format!("{arch_prefix}{c}"))
+            .collect(),
+        None => vec![target_tag.to_string()],
+    }
+}
+
 fn select_bottle_with_version(
     formula: &Formula,
     macos_version: Option<u32>,
@@ -70,6 +163,9 @@ fn select_bottle_with_version(
                     tag: tag.clone(),
                     url: file.url.clone(),
                     sha256: file.sha256.clone(),
+                    rebuild: formula.bottle.stable.rebuild,
+                    mirror_urls: file.mirrors.clone(),
+                    cellar: CellarRequirement::parse(file.cellar.as_deref()),
                 });
             }
         }
@@ -85,6 +181,9 @@ fn select_bottle_with_version(
                     tag: tag.to_string(),
                     url: file.url.clone(),
                     sha256: file.sha256.clone(),
+                    rebuild: formula.bottle.stable.rebuild,
+                    mirror_urls: file.mirrors.clone(),
+                    cellar: CellarRequirement::parse(file.cellar.as_deref()),
                 });
             }
         }
@@ -99,6 +198,9 @@ fn select_bottle_with_version(
                     tag: preferred_tag.to_string(),
                     url: file.url.clone(),
                     sha256: file.sha256.clone(),
+                    rebuild: formula.bottle.stable.rebuild,
+                    mirror_urls: file.mirrors.clone(),
+                    cellar: CellarRequirement::parse(file.cellar.as_deref()),
                 });
             }
         }
@@ -109,6 +211,9 @@ fn select_bottle_with_version(
             tag: "all".to_string(),
             url: file.url.clone(),
             sha256: file.sha256.clone(),
+            rebuild: formula.bottle.stable.rebuild,
+            mirror_urls: file.mirrors.clone(),
+            cellar: CellarRequirement::parse(file.cellar.as_deref()),
         });
     }
 
@@ -123,6 +228,9 @@ fn select_bottle_with_version(
                         tag: tag.clone(),
                         url: file.url.clone(),
                         sha256: file.sha256.clone(),
+                        rebuild: formula.bottle.stable.rebuild,
+                        mirror_urls: file.mirrors.clone(),
+                        cellar: CellarRequirement::parse(file.cellar.as_deref()),
                     });
                 }
             }
@@ -139,6 +247,9 @@ fn select_bottle_with_version(
                         tag: tag.clone(),
                         url: file.url.clone(),
                         sha256: file.sha256.clone(),
+                        rebuild: formula.bottle.stable.rebuild,
+                        mirror_urls: file.mirrors.clone(),
+                        cellar: CellarRequirement::parse(file.cellar.as_deref()),
                     });
                 }
             }
@@ -152,6 +263,9 @@ fn select_bottle_with_version(
                 tag: tag.clone(),
                 url: file.url.clone(),
                 sha256: file.sha256.clone(),
+                rebuild: formula.bottle.stable.rebuild,
+                mirror_urls: file.mirrors.clone(),
+                cellar: CellarRequirement::parse(file.cellar.as_deref()),
             });
         }
     }
@@ -172,7 +286,7 @@ mod tests {
         let fixture = include_str!("../../fixtures/formula_foo.json");
         let formula: Formula = serde_json::from_str(fixture).unwrap();
 
-        let selected = select_bottle(&formula).unwrap();
+        let selected = select_bottle(&formula, None).unwrap();
 
         #[cfg(all(target_os = "macos", target_arch = "aarch64"))]
         {
@@ -223,6 +337,8 @@ mod tests {
                 url: "https://ghcr.io/v2/homebrew/core/ca-certificates/blobs/sha256:abc123"
                     .to_string(),
                 sha256: "abc123".to_string(),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -239,6 +355,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
@@ -247,7 +365,7 @@ mod tests {
             variations: None,
         };
 
-        let selected = select_bottle(&formula).unwrap();
+        let selected = select_bottle(&formula, None).unwrap();
         assert_eq!(selected.tag, "all");
         assert!(selected.url.contains("ca-certificates"));
     }
@@ -262,6 +380,8 @@ mod tests {
                 url: "https://example.com/legacy.tar.gz".to_string(),
                 sha256: "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc"
                     .to_string(),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -278,6 +398,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
@@ -286,7 +408,7 @@ mod tests {
             variations: None,
         };
 
-        let err = select_bottle(&formula).unwrap_err();
+        let err = select_bottle(&formula, None).unwrap_err();
         assert!(matches!(
             err,
             Error::UnsupportedBottle { name } if name == "legacy"
@@ -303,6 +425,8 @@ mod tests {
                 url: "https://example.com/legacy.tar.gz".to_string(),
                 sha256: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
                     .to_string(),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -319,6 +443,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
@@ -327,7 +453,7 @@ mod tests {
             variations: None,
         };
 
-        let err = select_bottle(&formula).unwrap_err();
+        let err = select_bottle(&formula, None).unwrap_err();
         assert!(matches!(
             err,
             Error::UnsupportedBottle { name } if name == "legacy"
@@ -379,6 +505,8 @@ mod tests {
             BottleFile {
                 url: "https://example.com/tahoe.tar.gz".to_string(),
                 sha256: "aaaa".repeat(16),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
         files.insert(
@@ -386,6 +514,8 @@ mod tests {
             BottleFile {
                 url: "https://example.com/sequoia.tar.gz".to_string(),
                 sha256: "bbbb".repeat(16),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -402,6 +532,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
@@ -428,6 +560,8 @@ mod tests {
             BottleFile {
                 url: "https://example.com/tahoe.tar.gz".to_string(),
                 sha256: "aaaa".repeat(16),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
         files.insert(
@@ -435,6 +569,8 @@ mod tests {
             BottleFile {
                 url: "https://example.com/sequoia.tar.gz".to_string(),
                 sha256: "bbbb".repeat(16),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -451,6 +587,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
@@ -467,4 +605,99 @@ mod tests {
         #[cfg(target_arch = "x86_64")]
         assert_eq!(selected.tag, "all");
     }
+
+    #[test]
+    fn explicit_target_tag_overrides_host() {
+        let fixture = include_str!("../../fixtures/formula_foo.json");
+        let formula: Formula = serde_json::from_str(fixture).unwrap();
+
+        let linux = select_bottle(&formula, Some("x86_64_linux")).unwrap();
+        assert_eq!(linux.tag, "x86_64_linux");
+
+        let intel = select_bottle(&formula, Some("sonoma")).unwrap();
+        assert_eq!(intel.tag, "sonoma");
+        assert_eq!(
+            intel.url,
+            "https://example.com/foo-1.2.3.sonoma.bottle.tar.gz"
+        );
+    }
+
+    #[test]
+    fn explicit_macos_target_falls_back_to_older_release() {
+        let fixture = include_str!("../../fixtures/formula_foo.json");
+        let formula: Formula = serde_json::from_str(fixture).unwrap();
+
+        let selected = select_bottle(&formula, Some("arm64_tahoe")).unwrap();
+        assert_eq!(selected.tag, "arm64_sonoma");
+
+        let err = select_bottle(&formula, Some("arm64_ventura")).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedBottle { name } if name == "foo"));
+    }
+
+    #[test]
+    fn selected_bottle_carries_declared_mirrors() {
+        let formula: Formula = serde_json::from_value(serde_json::json!({
+            "name": "tool",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": { "all": {
+                "url": "https://github.com/o/r/releases/download/v1/tool.tar.gz",
+                "sha256": "abc123",
+                "mirrors": ["https://cdn.example.com/tool.tar.gz"]
+            } } } }
+        }))
+        .unwrap();
+
+        let selected = select_bottle(&formula, Some("all")).unwrap();
+        assert_eq!(
+            selected.mirror_urls,
+            vec!["https://cdn.example.com/tool.tar.gz".to_string()]
+        );
+    }
+
+    #[test]
+    fn cellar_requirement_parses_symbols_and_paths() {
+        assert_eq!(CellarRequirement::parse(None), CellarRequirement::Any);
+        assert_eq!(
+            CellarRequirement::parse(Some(":any")),
+            CellarRequirement::Any
+        );
+        assert_eq!(
+            CellarRequirement::parse(Some(":any_skip_relocation")),
+            CellarRequirement::AnySkipRelocation
+        );
+
+        let fixed = CellarRequirement::parse(Some("/opt/homebrew/Cellar/"));
+        assert_eq!(
+            fixed,
+            CellarRequirement::Path("/opt/homebrew/Cellar".to_string())
+        );
+        assert!(fixed.allows(Path::new("/opt/homebrew/Cellar")));
+        assert!(!fixed.allows(Path::new("/opt/zerobrew/prefix/Cellar")));
+        assert!(CellarRequirement::Any.allows(Path::new("/anywhere")));
+    }
+
+    #[test]
+    fn selected_bottle_carries_cellar_requirement() {
+        let mut files = BTreeMap::new();
+        files.insert(
+            "x86_64_linux".to_string(),
+            BottleFile {
+                url: "https://example.com/fixed.tar.gz".to_string(),
+                sha256: "dddd".repeat(16),
+                mirrors: Vec::new(),
+                cellar: Some("/home/linuxbrew/.linuxbrew/Cellar".to_string()),
+            },
+        );
+        let mut formula: Formula =
+            serde_json::from_str(include_str!("../../fixtures/formula_foo.json")).unwrap();
+        formula.bottle.stable.files = files;
+
+        let selected = select_bottle(&formula, Some("x86_64_linux")).unwrap();
+
+        assert_eq!(
+            selected.cellar,
+            CellarRequirement::Path("/home/linuxbrew/.linuxbrew/Cellar".to_string())
+        );
+    }
 }
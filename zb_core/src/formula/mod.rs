@@ -1,16 +1,19 @@
 pub mod bottle;
 pub mod resolve;
 pub mod types;
+pub mod version;
 
-pub use bottle::{SelectedBottle, compatible_codenames, select_bottle};
+pub use bottle::{CellarRequirement, SelectedBottle, compatible_codenames, select_bottle};
 
 #[cfg(target_os = "macos")]
 pub use bottle::macos_major_version;
-pub use resolve::resolve_closure;
+pub use resolve::{resolve_closure, resolve_closure_pruned, resolve_closure_with_policy};
 pub use types::{
-    Bottle, BottleFile, BottleStable, Formula, FormulaUrls, KegOnly, KegOnlyReason,
-    RubySourceChecksum, SourceUrl, UsesFromMacos, Versions,
+    Bottle, BottleFile, BottleStable, Checksum, Dependency, DependencyKind, DependencyPolicy,
+    Formula, FormulaUrls, KegOnly, KegOnlyReason, RubySourceChecksum, SourceUrl, UsesFromMacos,
+    Versions,
 };
+pub use version::{compare_versions, revisioned_version};
 
 /// Extract the formula token from an install key.
 /// Examples:
@@ -1,9 +1,35 @@
-use crate::{Error, Formula};
+use crate::{DependencyPolicy, Error, Formula};
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 pub fn resolve_closure(
     roots: &[String],
     formulas: &BTreeMap<String, Formula>,
+) -> Result<Vec<String>, Error> {
+    resolve_closure_with_policy(roots, formulas, DependencyPolicy::default())
+}
+
+/// Like [`resolve_closure`], following only the dependency kinds `policy`
+/// includes.
+pub fn resolve_closure_with_policy(
+    roots: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    policy: DependencyPolicy,
+) -> Result<Vec<String>, Error> {
+    resolve_closure_pruned(roots, formulas, policy, &BTreeMap::new())
+}
+
+/// Like [`resolve_closure_with_policy`], leaving out dependencies already in
+/// `installed` (name to installed version) and anything only they pull in.
+/// Roots are always kept, so a formula requested by name is still ordered.
+///
+/// Pruning is by presence alone: any installed version satisfies a
+/// dependency. Homebrew dependencies carry no version constraints today;
+/// honoring them would mean comparing the recorded version here.
+pub fn resolve_closure_pruned(
+    roots: &[String],
+    formulas: &BTreeMap<String, Formula>,
+    policy: DependencyPolicy,
+    installed: &BTreeMap<String, String>,
 ) -> Result<Vec<String>, Error> {
     let name_to_idx: HashMap<&str, usize> = formulas
         .keys()
@@ -13,26 +39,28 @@ pub fn resolve_closure(
     let idx_to_name: Vec<&str> = formulas.keys().map(|k| k.as_str()).collect();
     let n = idx_to_name.len();
 
-    let closure = compute_closure(roots, formulas, &name_to_idx)?;
+    let closure = compute_closure(roots, formulas, &name_to_idx, policy, installed)?;
 
     let mut indegree = vec![0u32; n];
     let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); n];
 
     for &idx in &closure {
         let formula = &formulas[idx_to_name[idx]];
         let mut dep_indices: Vec<usize> = formula
-            .dependencies
-            .iter()
+            .runtime_dependencies(policy)
+            .into_iter()
             .filter_map(|dep| {
-                let &di = name_to_idx.get(dep.as_str())?;
+                let &di = name_to_idx.get(dep)?;
                 closure.contains(&di).then_some(di)
             })
             .collect();
         dep_indices.sort_unstable();
-        for di in dep_indices {
+        for &di in &dep_indices {
             indegree[idx] += 1;
             adjacency[di].push(idx);
         }
+        dependencies[idx] = dep_indices;
     }
 
     let mut ready: BTreeSet<usize> = closure
@@ -54,10 +82,9 @@ pub fn resolve_closure(
     }
 
     if ordered.len() != closure.len() {
-        let cycle: Vec<String> = closure
-            .iter()
-            .filter(|&&i| indegree[i] > 0)
-            .map(|&i| idx_to_name[i].to_string())
+        let cycle = find_cycle(&closure, &indegree, &dependencies)
+            .into_iter()
+            .map(|i| idx_to_name[i].to_string())
             .collect();
         return Err(Error::DependencyCycle { cycle });
     }
@@ -68,10 +95,43 @@ pub fn resolve_closure(
         .collect())
 }
 
+/// Pull one concrete cycle out of the nodes the topological sort could not
+/// place, as a closed chain (`a -> b -> a`). Every such node still waits on at
+/// least one dependency that is itself unplaced, so following those edges from
+/// any of them must revisit a node.
+fn find_cycle(
+    closure: &BTreeSet<usize>,
+    indegree: &[u32],
+    dependencies: &[Vec<usize>],
+) -> Vec<usize> {
+    let Some(&start) = closure.iter().find(|&&i| indegree[i] > 0) else {
+        return Vec::new();
+    };
+
+    let mut chain = vec![start];
+    let mut position: HashMap<usize, usize> = HashMap::from([(start, 0)]);
+    let mut current = start;
+    loop {
+        let Some(&next) = dependencies[current].iter().find(|&&d| indegree[d] > 0) else {
+            return chain;
+        };
+        if let Some(&at) = position.get(&next) {
+            let mut cycle = chain.split_off(at);
+            cycle.push(next);
+            return cycle;
+        }
+        position.insert(next, chain.len());
+        chain.push(next);
+        current = next;
+    }
+}
+
 fn compute_closure(
     roots: &[String],
     formulas: &BTreeMap<String, Formula>,
     name_to_idx: &HashMap<&str, usize>,
+    policy: DependencyPolicy,
+    installed: &BTreeMap<String, String>,
 ) -> Result<BTreeSet<usize>, Error> {
     let mut closure = BTreeSet::new();
     let mut stack: Vec<usize> = Vec::with_capacity(roots.len());
@@ -91,8 +151,11 @@ fn compute_closure(
         }
 
         let formula = &formulas[idx_to_name[idx]];
-        for dep in &formula.dependencies {
-            if let Some(&di) = name_to_idx.get(dep.as_str())
+        for dep in formula.runtime_dependencies(policy) {
+            if installed.contains_key(dep) && !roots.iter().any(|root| root == dep) {
+                continue;
+            }
+            if let Some(&di) = name_to_idx.get(dep)
                 && !closure.contains(&di)
             {
                 stack.push(di);
@@ -116,6 +179,8 @@ mod tests {
             BottleFile {
                 url: format!("https://example.com/{name}.tar.gz"),
                 sha256: "deadbeef".repeat(8),
+                mirrors: Vec::new(),
+                cellar: None,
             },
         );
 
@@ -132,6 +197,8 @@ mod tests {
             keg_only: KegOnly::default(),
             keg_only_reason: None,
             build_dependencies: Vec::new(),
+            recommended_dependencies: Vec::new(),
+            optional_dependencies: Vec::new(),
             urls: None,
             ruby_source_path: None,
             ruby_source_checksum: None,
@@ -176,6 +243,21 @@ mod tests {
         assert!(matches!(err, Error::DependencyCycle { .. }));
     }
 
+    #[test]
+    fn cycle_error_names_only_the_cycle() {
+        let mut formulas = BTreeMap::new();
+        formulas.insert("app".to_string(), formula("app", &["ping"]));
+        formulas.insert("ping".to_string(), formula("ping", &["pong"]));
+        formulas.insert("pong".to_string(), formula("pong", &["ping"]));
+
+        let err = resolve_closure(&["app".to_string()], &formulas).unwrap_err();
+        let Error::DependencyCycle { cycle } = &err else {
+            panic!("expected a dependency cycle, got {err:?}");
+        };
+        assert_eq!(cycle, &["ping", "pong", "ping"]);
+        assert!(err.to_string().contains("ping -> pong -> ping"));
+    }
+
     #[test]
     fn skips_missing_dependencies() {
         // Test that dependencies not in the formulas map are skipped
@@ -189,4 +271,29 @@ mod tests {
         // Should successfully resolve with just git and gettext
         assert_eq!(order, vec!["gettext", "git"]);
     }
+
+    #[test]
+    fn installed_dependencies_are_pruned_with_their_subtrees() {
+        let mut formulas = BTreeMap::new();
+        formulas.insert("foo".to_string(), formula("foo", &["bar", "baz"]));
+        formulas.insert("bar".to_string(), formula("bar", &["qux"]));
+        formulas.insert("baz".to_string(), formula("baz", &[]));
+        formulas.insert("qux".to_string(), formula("qux", &[]));
+        let installed = BTreeMap::from([("bar".to_string(), "0.9.0".to_string())]);
+        let policy = DependencyPolicy::default();
+
+        let order =
+            resolve_closure_pruned(&["foo".to_string()], &formulas, policy, &installed).unwrap();
+        assert_eq!(order, vec!["baz", "foo"]);
+
+        // Naming an installed formula keeps it and its dependencies.
+        let order = resolve_closure_pruned(
+            &["foo".to_string(), "bar".to_string()],
+            &formulas,
+            policy,
+            &installed,
+        )
+        .unwrap();
+        assert_eq!(order, vec!["baz", "qux", "bar", "foo"]);
+    }
 }
@@ -48,6 +48,45 @@ pub struct SourceUrl {
     pub revision: Option<String>,
 }
 
+impl SourceUrl {
+    /// The source checksum with its algorithm worked out. See [`Checksum`].
+    pub fn parsed_checksum(&self) -> Checksum {
+        Checksum::parse(self.checksum.as_deref())
+    }
+}
+
+/// Expected digest of a source archive. The formula API does not say which
+/// algorithm a checksum uses, so an untagged value is classified by length:
+/// 40 hex characters is SHA-1 (older formulae), anything else is treated as
+/// SHA-256 and validated as such. A `sha1:`/`sha256:` tag is also accepted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    Sha256(String),
+    Sha1(String),
+    None,
+}
+
+impl Checksum {
+    pub fn parse(value: Option<&str>) -> Self {
+        let Some(value) = value.map(str::trim).filter(|v| !v.is_empty()) else {
+            return Self::None;
+        };
+
+        if let Some(hex) = value.strip_prefix("sha256:") {
+            return Self::Sha256(hex.to_string());
+        }
+        if let Some(hex) = value.strip_prefix("sha1:") {
+            return Self::Sha1(hex.to_string());
+        }
+
+        if value.len() == 40 && value.bytes().all(|b| b.is_ascii_hexdigit()) {
+            Self::Sha1(value.to_string())
+        } else {
+            Self::Sha256(value.to_string())
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct FormulaUrls {
     #[serde(default)]
@@ -94,6 +133,51 @@ impl UsesFromMacos {
     }
 }
 
+/// How a formula declares a dependency, mirroring Homebrew's `depends_on`
+/// options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyKind {
+    Required,
+    Recommended,
+    Optional,
+    Build,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dependency {
+    pub name: String,
+    pub kind: DependencyKind,
+}
+
+/// Which runtime dependencies an install pulls in. Required dependencies
+/// always are; by default so are recommended ones, like `brew install`
+/// without `--without-*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DependencyPolicy {
+    pub include_recommended: bool,
+    pub include_optional: bool,
+}
+
+impl Default for DependencyPolicy {
+    fn default() -> Self {
+        Self {
+            include_recommended: true,
+            include_optional: false,
+        }
+    }
+}
+
+impl DependencyPolicy {
+    pub fn includes(&self, kind: DependencyKind) -> bool {
+        match kind {
+            DependencyKind::Required => true,
+            DependencyKind::Recommended => self.include_recommended,
+            DependencyKind::Optional => self.include_optional,
+            DependencyKind::Build => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
 pub struct Formula {
     pub name: String,
@@ -109,6 +193,10 @@ pub struct Formula {
     #[serde(default)]
     pub build_dependencies: Vec<String>,
     #[serde(default)]
+    pub recommended_dependencies: Vec<String>,
+    #[serde(default)]
+    pub optional_dependencies: Vec<String>,
+    #[serde(default)]
     pub urls: Option<FormulaUrls>,
     #[serde(default)]
     pub ruby_source_path: Option<String>,
@@ -124,11 +212,7 @@ pub struct Formula {
 
 impl Formula {
     pub fn effective_version(&self) -> String {
-        if self.revision > 0 {
-            format!("{}_{}", self.versions.stable, self.revision)
-        } else {
-            self.versions.stable.clone()
-        }
+        super::revisioned_version(&self.versions.stable, self.revision)
     }
 
     pub fn is_keg_only(&self) -> bool {
@@ -155,6 +239,48 @@ impl Formula {
         self.source_url().is_some()
     }
 
+    /// Every declared dependency tagged with its kind: required first, then
+    /// recommended, optional and build-only.
+    pub fn typed_dependencies(&self) -> Vec<Dependency> {
+        let kinds = [
+            (&self.dependencies, DependencyKind::Required),
+            (&self.recommended_dependencies, DependencyKind::Recommended),
+            (&self.optional_dependencies, DependencyKind::Optional),
+            (&self.build_dependencies, DependencyKind::Build),
+        ];
+        kinds
+            .into_iter()
+            .flat_map(|(names, kind)| {
+                names.iter().map(move |name| Dependency {
+                    name: name.clone(),
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// Names of the runtime dependencies `policy` installs, without
+    /// duplicates.
+    pub fn runtime_dependencies(&self, policy: DependencyPolicy) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        let kinds = [
+            (&self.dependencies, DependencyKind::Required),
+            (&self.recommended_dependencies, DependencyKind::Recommended),
+            (&self.optional_dependencies, DependencyKind::Optional),
+        ];
+        for (deps, kind) in kinds {
+            if !policy.includes(kind) {
+                continue;
+            }
+            for dep in deps {
+                if !names.contains(&dep.as_str()) {
+                    names.push(dep);
+                }
+            }
+        }
+        names
+    }
+
     pub fn all_build_dependencies(&self) -> Vec<String> {
         let deps = self.build_dependencies.clone();
         #[cfg(not(target_os = "macos"))]
@@ -192,6 +318,16 @@ pub struct BottleStable {
 pub struct BottleFile {
     pub url: String,
     pub sha256: String,
+    /// Other hosts serving the identical bottle, e.g. a CDN copy of a
+    /// GitHub release asset.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// Homebrew's `cellar` field: `:any`, `:any_skip_relocation`, or the
+    /// cellar path the bottle was built for. See [`CellarRequirement`].
+    ///
+    /// [`CellarRequirement`]: crate::CellarRequirement
+    #[serde(default)]
+    pub cellar: Option<String>,
 }
 
 #[cfg(test)]
@@ -213,6 +349,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn checksum_algorithm_is_inferred_from_length_or_tag() {
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        let sha1 = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+
+        assert_eq!(
+            Checksum::parse(Some(sha256)),
+            Checksum::Sha256(sha256.to_string())
+        );
+        assert_eq!(
+            Checksum::parse(Some(sha1)),
+            Checksum::Sha1(sha1.to_string())
+        );
+        assert_eq!(
+            Checksum::parse(Some(&format!("sha1:{sha1}"))),
+            Checksum::Sha1(sha1.to_string())
+        );
+        assert_eq!(
+            Checksum::parse(Some("abc123")),
+            Checksum::Sha256("abc123".to_string())
+        );
+        assert_eq!(Checksum::parse(Some("  ")), Checksum::None);
+        assert_eq!(Checksum::parse(None), Checksum::None);
+    }
+
     #[test]
     fn effective_version_without_revision() {
         let fixture = include_str!("../../fixtures/formula_foo.json");
@@ -411,4 +572,74 @@ mod tests {
         assert!(formula.keg_only_reason.is_none());
         assert!(formula.is_keg_only());
     }
+
+    fn formula_with_optional_deps() -> Formula {
+        let json = r#"{
+            "name": "ffmpeg",
+            "versions": { "stable": "7.1" },
+            "dependencies": ["x264"],
+            "recommended_dependencies": ["lame"],
+            "optional_dependencies": ["rav1e"],
+            "build_dependencies": ["pkgconf"],
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn typed_dependencies_parse_each_api_array() {
+        let formula = formula_with_optional_deps();
+        assert_eq!(
+            formula.typed_dependencies(),
+            vec![
+                Dependency {
+                    name: "x264".into(),
+                    kind: DependencyKind::Required
+                },
+                Dependency {
+                    name: "lame".into(),
+                    kind: DependencyKind::Recommended
+                },
+                Dependency {
+                    name: "rav1e".into(),
+                    kind: DependencyKind::Optional
+                },
+                Dependency {
+                    name: "pkgconf".into(),
+                    kind: DependencyKind::Build
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn default_policy_installs_required_and_recommended() {
+        let formula = formula_with_optional_deps();
+        assert_eq!(
+            formula.runtime_dependencies(DependencyPolicy::default()),
+            vec!["x264", "lame"]
+        );
+    }
+
+    #[test]
+    fn policy_can_add_optional_or_drop_recommended() {
+        let formula = formula_with_optional_deps();
+        let with_optional = DependencyPolicy {
+            include_optional: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            formula.runtime_dependencies(with_optional),
+            vec!["x264", "lame", "rav1e"]
+        );
+
+        let without_recommended = DependencyPolicy {
+            include_recommended: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            formula.runtime_dependencies(without_recommended),
+            vec!["x264"]
+        );
+    }
 }
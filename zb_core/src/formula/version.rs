@@ -0,0 +1,111 @@
+use std::cmp::Ordering;
+
+/// Compare two formula versions the way Homebrew orders them: numeric
+/// components numerically, everything else lexically, with a `_N` revision
+/// suffix breaking ties. `1.10` sorts after `1.9`, and `1.2.3_1` after `1.2.3`.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (a_version, a_revision) = split_revision(a);
+    let (b_version, b_revision) = split_revision(b);
+
+    let mut a_parts = version_components(a_version);
+    let mut b_parts = version_components(b_version);
+
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => break,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(x), Some(y)) => {
+                let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    // A numeric component is newer than a pre-release tag
+                    // such as `rc` or `beta` in the same position.
+                    (Ok(_), Err(_)) => Ordering::Greater,
+                    (Err(_), Ok(_)) => Ordering::Less,
+                    (Err(_), Err(_)) => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+
+    a_revision.cmp(&b_revision)
+}
+
+/// The installed version string Homebrew uses for `stable` at `revision`:
+/// `1.10.0` at revision 0, `1.10.0_2` at revision 2. Cellar paths, the
+/// install database and bottle filenames all use this form.
+pub fn revisioned_version(stable: &str, revision: u32) -> String {
+    if revision > 0 {
+        format!("{stable}_{revision}")
+    } else {
+        stable.to_string()
+    }
+}
+
+fn split_revision(version: &str) -> (&str, u32) {
+    match version.rsplit_once('_') {
+        Some((base, revision)) => match revision.parse() {
+            Ok(revision) => (base, revision),
+            Err(_) => (version, 0),
+        },
+        None => (version, 0),
+    }
+}
+
+/// Split on separators and at digit/non-digit boundaries, so `1.2rc1`
+/// yields `1`, `2`, `rc`, `1`.
+fn version_components(version: &str) -> impl Iterator<Item = &str> {
+    version
+        .split(['.', '-', '+'])
+        .filter(|part| !part.is_empty())
+        .flat_map(|part| {
+            let mut pieces = Vec::new();
+            let mut start = 0;
+            let bytes = part.as_bytes();
+            for i in 1..bytes.len() {
+                if bytes[i].is_ascii_digit() != bytes[i - 1].is_ascii_digit() {
+                    pieces.push(&part[start..i]);
+                    start = i;
+                }
+            }
+            pieces.push(&part[start..]);
+            pieces
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_numeric_components_numerically() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("2.0.0", "2.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.7.1", "1.7.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn revision_suffix_breaks_ties() {
+        assert_eq!(compare_versions("1.2.3_1", "1.2.3"), Ordering::Greater);
+        assert_eq!(compare_versions("1.2.3_1", "1.2.4"), Ordering::Less);
+    }
+
+    #[test]
+    fn revisioned_version_round_trips_through_split() {
+        assert_eq!(revisioned_version("1.10.0", 0), "1.10.0");
+        assert_eq!(revisioned_version("1.10.0", 2), "1.10.0_2");
+        assert_eq!(split_revision("1.10.0_2"), ("1.10.0", 2));
+    }
+
+    #[test]
+    fn prerelease_sorts_before_release_component() {
+        assert_eq!(compare_versions("1.2rc1", "1.2.1"), Ordering::Less);
+        assert_eq!(
+            compare_versions("3.0.0-beta", "3.0.0-alpha"),
+            Ordering::Greater
+        );
+    }
+}
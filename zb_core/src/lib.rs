@@ -7,8 +7,10 @@ pub use build::{BuildPlan, BuildSystem, InstallMethod};
 pub use context::{ConcurrencyLimits, Context, LogLevel, LoggerHandle, Paths};
 pub use errors::{ConflictedLink, Error};
 pub use formula::{
-    Formula, KegOnly, KegOnlyReason, SelectedBottle, compatible_codenames, formula_token,
-    resolve_closure, select_bottle,
+    CellarRequirement, Checksum, Dependency, DependencyKind, DependencyPolicy, Formula, KegOnly,
+    KegOnlyReason, SelectedBottle, compare_versions, compatible_codenames, formula_token,
+    resolve_closure, resolve_closure_pruned, resolve_closure_with_policy, revisioned_version,
+    select_bottle,
 };
 
 #[cfg(target_os = "macos")]
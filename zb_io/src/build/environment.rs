@@ -72,6 +72,14 @@ pub fn build_env(plan: &BuildPlan, prefix: &Path) -> HashMap<String, String> {
         env.insert("MACOSX_DEPLOYMENT_TARGET".into(), target);
     }
 
+    if !plan.configure_args.is_empty() {
+        env.insert(
+            "ZEROBREW_CONFIGURE_ARGS".into(),
+            serde_json::to_string(&plan.configure_args).unwrap_or_else(|_| "[]".into()),
+        );
+    }
+    env.extend(plan.env.clone());
+
     env
 }
 
@@ -85,20 +93,22 @@ fn num_cpus() -> usize {
 mod tests {
     use super::*;
     use std::path::PathBuf;
-    use zb_core::{BuildPlan, BuildSystem};
+    use zb_core::{BuildPlan, BuildSystem, Checksum};
 
     fn test_plan() -> BuildPlan {
         BuildPlan {
             formula_name: "test".to_string(),
             version: "1.0.0".to_string(),
             source_url: "https://example.com/test.tar.gz".to_string(),
-            source_checksum: None,
+            source_checksum: Checksum::None,
             ruby_source_path: None,
             build_dependencies: Vec::new(),
             runtime_dependencies: Vec::new(),
             detected_system: BuildSystem::Autoconf,
             prefix: PathBuf::from("/opt/zerobrew/prefix"),
             cellar_path: PathBuf::from("/opt/zerobrew/cellar/test/1.0.0"),
+            env: Default::default(),
+            configure_args: Vec::new(),
         }
     }
 
@@ -123,4 +133,18 @@ mod tests {
         assert!(env.contains_key("ZEROBREW_FORMULA_NAME"));
         assert!(env.contains_key("MAKEFLAGS"));
     }
+
+    #[test]
+    fn build_env_applies_plan_env_and_configure_args_last() {
+        let mut plan = test_plan();
+        plan.env.insert("CFLAGS".to_string(), "-O1".to_string());
+        plan.env.insert("FOO_FEATURE".to_string(), "1".to_string());
+        plan.configure_args = vec!["--with-feature".to_string()];
+
+        let env = build_env(&plan, &PathBuf::from("/opt/zerobrew/prefix"));
+        assert_eq!(env["CFLAGS"], "-O1");
+        assert_eq!(env["FOO_FEATURE"], "1");
+        assert_eq!(env["ZEROBREW_CONFIGURE_ARGS"], r#"["--with-feature"]"#);
+        assert!(env["PKG_CONFIG_PATH"].starts_with("/opt/zerobrew/prefix/lib/pkgconfig"));
+    }
 }
@@ -1,26 +1,143 @@
 use std::collections::{HashMap, VecDeque};
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
 
 use tokio::fs;
 use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Command;
+use tokio::sync::watch;
+use tokio::time::Instant;
 use zb_core::{BuildPlan, Error};
 
 use super::environment::build_env;
 use super::source::download_and_extract_source;
+use crate::storage::blob::BlobCache;
 
 const SHIM_RUBY: &str = include_str!("shim.rb");
 
+/// Callback invoked each time a source build moves into a new phase
+pub type BuildProgressCallback = Arc<dyn Fn(BuildPhase) + Send + Sync>;
+
+/// Coarse phases of a source build, inferred from the commands the formula runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildPhase {
+    DownloadSource,
+    Configure,
+    Compile,
+    Install,
+}
+
+impl BuildPhase {
+    /// Number of phases, for "compiling foo (3/4)" style displays
+    pub const COUNT: usize = 4;
+
+    /// 1-based position of this phase within a build
+    pub fn step(self) -> usize {
+        match self {
+            BuildPhase::DownloadSource => 1,
+            BuildPhase::Configure => 2,
+            BuildPhase::Compile => 3,
+            BuildPhase::Install => 4,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BuildPhase::DownloadSource => "downloading source",
+            BuildPhase::Configure => "configuring",
+            BuildPhase::Compile => "compiling",
+            BuildPhase::Install => "installing",
+        }
+    }
+
+    /// Classify a command echoed by the shim's `system` helper. Returns `None`
+    /// for commands that don't clearly belong to a phase (e.g. `mkdir`).
+    fn from_command(command: &str) -> Option<Self> {
+        let mut words = command.split_whitespace();
+        let program = words.next()?;
+        let program = program.rsplit('/').next().unwrap_or(program);
+        let args: Vec<&str> = words.collect();
+        let has = |arg: &str| args.contains(&arg);
+
+        match program {
+            "configure" | "autoreconf" | "autogen.sh" | "bootstrap" => Some(BuildPhase::Configure),
+            "make" | "gmake" | "ninja" => Some(if has("install") {
+                BuildPhase::Install
+            } else {
+                BuildPhase::Compile
+            }),
+            "cmake" if has("--install") => Some(BuildPhase::Install),
+            "cmake" if has("--build") => Some(BuildPhase::Compile),
+            "cmake" => Some(BuildPhase::Configure),
+            "meson" => match args.first().copied() {
+                Some("install") => Some(BuildPhase::Install),
+                Some("compile") => Some(BuildPhase::Compile),
+                _ => Some(BuildPhase::Configure),
+            },
+            "cargo" | "go" | "swift" => match args.first().copied() {
+                Some("install") => Some(BuildPhase::Install),
+                Some("build") => Some(BuildPhase::Compile),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
 pub struct BuildExecutor {
     prefix: PathBuf,
     work_root: PathBuf,
+    log_dir: PathBuf,
+    echo_output: bool,
+    phase_timeout: Option<Duration>,
+    source_cache: Option<BlobCache>,
 }
 
 impl BuildExecutor {
     pub fn new(prefix: PathBuf) -> Self {
         let work_root = prefix.join("tmp").join("build");
-        Self { prefix, work_root }
+        let log_dir = prefix.join("tmp").join("build-logs");
+        Self {
+            prefix,
+            work_root,
+            log_dir,
+            echo_output: true,
+            phase_timeout: None,
+            source_cache: None,
+        }
+    }
+
+    /// Controls whether build stdout/stderr is streamed to the terminal. The
+    /// output is still written to the build log and its tail captured for
+    /// error messages either way.
+    pub fn with_echo_output(mut self, echo_output: bool) -> Self {
+        self.echo_output = echo_output;
+        self
+    }
+
+    /// Write each build's stdout and stderr to
+    /// `<dir>/<formula>-<version>.log`, replacing the log of any earlier
+    /// build of that version.
+    pub fn with_log_dir(mut self, dir: PathBuf) -> Self {
+        self.log_dir = dir;
+        self
+    }
+
+    /// Kill the build if a single phase (configure, compile, ...) runs longer
+    /// than `timeout`. `None`, the default, lets builds run indefinitely.
+    pub fn with_phase_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.phase_timeout = timeout;
+        self
+    }
+
+    /// Keep downloaded source archives in `cache` and reuse them on later
+    /// builds instead of fetching the source again.
+    pub fn with_source_cache(mut self, cache: BlobCache) -> Self {
+        self.source_cache = Some(cache);
+        self
     }
 
     pub async fn execute(
@@ -28,14 +145,30 @@ impl BuildExecutor {
         plan: &BuildPlan,
         formula_rb_path: &Path,
         installed_deps: &HashMap<String, DepInfo>,
+    ) -> Result<(), Error> {
+        self.execute_with_progress(plan, formula_rb_path, installed_deps, None)
+            .await
+    }
+
+    pub async fn execute_with_progress(
+        &self,
+        plan: &BuildPlan,
+        formula_rb_path: &Path,
+        installed_deps: &HashMap<String, DepInfo>,
+        progress: Option<BuildProgressCallback>,
     ) -> Result<(), Error> {
         let work_dir = self.work_root.join(&plan.formula_name);
         self.prepare_work_dir(&work_dir).await?;
 
+        if let Some(ref cb) = progress {
+            cb(BuildPhase::DownloadSource);
+        }
+
         let source_root = download_and_extract_source(
             &plan.source_url,
-            plan.source_checksum.as_deref(),
+            &plan.source_checksum,
             &work_dir,
+            self.source_cache.as_ref(),
         )
         .await?;
 
@@ -57,8 +190,28 @@ impl BuildExecutor {
         let deps_json = serde_json::to_string(installed_deps).unwrap_or_else(|_| "{}".into());
         env.insert("ZEROBREW_INSTALLED_DEPS".into(), deps_json);
 
+        fs::create_dir_all(&self.log_dir)
+            .await
+            .map_err(Error::file("failed to create build log directory"))?;
+        let output = BuildOutput {
+            echo: self.echo_output,
+            log_path: Some(
+                self.log_dir
+                    .join(format!("{}-{}.log", plan.formula_name, plan.version)),
+            ),
+        };
+
         let ruby = find_ruby().await?;
-        run_build(&ruby, &shim_path, &source_root, &env).await?;
+        run_build(
+            &ruby,
+            &shim_path,
+            &source_root,
+            &env,
+            &output,
+            progress,
+            self.phase_timeout,
+        )
+        .await?;
 
         self.cleanup_work_dir(&work_dir).await;
         Ok(())
@@ -99,20 +252,90 @@ async fn find_ruby() -> Result<PathBuf, Error> {
     })
 }
 
+/// Where a build's stdout and stderr go besides the tail kept for errors.
+#[derive(Debug, Clone, Default)]
+struct BuildOutput {
+    /// Stream every line to the terminal.
+    echo: bool,
+    /// Write every line to this file, created before the build starts so it
+    /// exists even when the build is killed.
+    log_path: Option<PathBuf>,
+}
+
+impl BuildOutput {
+    fn open_log(&self) -> Result<Option<Arc<std::fs::File>>, Error> {
+        self.log_path
+            .as_ref()
+            .map(|path| {
+                std::fs::File::create(path)
+                    .map(Arc::new)
+                    .map_err(Error::file("failed to create build log"))
+            })
+            .transpose()
+    }
+
+    /// Point at the full log from an error message that only quotes its tail.
+    fn log_hint(&self) -> String {
+        match &self.log_path {
+            Some(path) => format!("\nfull build log: {}", path.display()),
+            None => String::new(),
+        }
+    }
+}
+
+/// Kills the build's process group on drop unless disarmed, so a build that
+/// times out or whose future is dropped (e.g. on Ctrl-C) doesn't leave
+/// compilers running behind it.
+struct ProcessGroupGuard {
+    pgid: Option<i32>,
+}
+
+impl ProcessGroupGuard {
+    fn kill(&mut self) {
+        if let Some(pgid) = self.pgid.take() {
+            unsafe {
+                libc::killpg(pgid, libc::SIGKILL);
+            }
+        }
+    }
+
+    fn disarm(&mut self) {
+        self.pgid = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
 async fn run_build(
     ruby: &Path,
     shim_path: &Path,
     source_root: &Path,
     env: &HashMap<String, String>,
+    output: &BuildOutput,
+    progress: Option<BuildProgressCallback>,
+    phase_timeout: Option<Duration>,
 ) -> Result<(), Error> {
+    let log = output.open_log()?;
+
+    // The shim leads its own process group so the whole tree (make, cc, ...)
+    // can be killed at once.
     let mut child = Command::new(ruby)
         .arg(shim_path)
         .current_dir(source_root)
         .envs(env)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true)
         .spawn()
         .map_err(Error::exec("failed to execute ruby shim"))?;
+    let mut group = ProcessGroupGuard {
+        pgid: child.id().map(|pid| pid as i32),
+    };
 
     let stdout = child.stdout.take().ok_or_else(|| Error::ExecutionError {
         message: "failed to capture ruby shim stdout".to_string(),
@@ -121,13 +344,57 @@ async fn run_build(
         message: "failed to capture ruby shim stderr".to_string(),
     })?;
 
-    let stdout_task = tokio::spawn(stream_output_and_capture_tail(stdout, false));
-    let stderr_task = tokio::spawn(stream_output_and_capture_tail(stderr, true));
-
-    let status = child
-        .wait()
-        .await
-        .map_err(Error::exec("failed waiting for ruby shim"))?;
+    let (phase_tx, mut phase_rx) = watch::channel(BuildPhase::DownloadSource);
+    let stdout_task = tokio::spawn(stream_output_and_capture_tail(
+        stdout,
+        false,
+        output.echo,
+        log.clone(),
+        Some(phase_tx),
+    ));
+    let stderr_task = tokio::spawn(stream_output_and_capture_tail(
+        stderr,
+        true,
+        output.echo,
+        log,
+        None,
+    ));
+
+    // The timeout applies per phase: each phase change restarts the clock.
+    let mut phase = BuildPhase::DownloadSource;
+    let mut phases_open = true;
+    let mut deadline = phase_timeout.map(|timeout| Instant::now() + timeout);
+    let status = loop {
+        tokio::select! {
+            status = child.wait() => {
+                break status.map_err(Error::exec("failed waiting for ruby shim"))?;
+            }
+            changed = phase_rx.changed(), if phases_open => {
+                if changed.is_err() {
+                    phases_open = false;
+                    continue;
+                }
+                phase = *phase_rx.borrow_and_update();
+                deadline = phase_timeout.map(|timeout| Instant::now() + timeout);
+                if let Some(ref cb) = progress {
+                    cb(phase);
+                }
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                group.kill();
+                let _ = child.wait().await;
+                return Err(Error::ExecutionError {
+                    message: format!(
+                        "source build timed out after {}s while {}{}",
+                        phase_timeout.unwrap_or_default().as_secs(),
+                        phase.label(),
+                        output.log_hint()
+                    ),
+                });
+            }
+        }
+    };
+    group.disarm();
 
     let stdout_tail = stdout_task
         .await
@@ -149,6 +416,7 @@ async fn run_build(
             msg.push('\n');
             msg.push_str(&tail.join("\n"));
         }
+        msg.push_str(&output.log_hint());
         return Err(Error::ExecutionError { message: msg });
     }
 
@@ -158,6 +426,9 @@ async fn run_build(
 async fn stream_output_and_capture_tail<R>(
     reader: R,
     stderr: bool,
+    echo: bool,
+    log: Option<Arc<std::fs::File>>,
+    phases: Option<watch::Sender<BuildPhase>>,
 ) -> Result<Vec<String>, std::io::Error>
 where
     R: AsyncRead + Unpin,
@@ -167,10 +438,25 @@ where
     let mut lines = BufReader::new(reader).lines();
 
     while let Some(line) = lines.next_line().await? {
-        if stderr {
-            eprintln!("{line}");
-        } else {
-            println!("{line}");
+        // The shim echoes every `system` call as "==> <command>".
+        if let Some(ref tx) = phases
+            && let Some(command) = line.strip_prefix("==> ")
+            && let Some(phase) = BuildPhase::from_command(command)
+        {
+            tx.send_if_modified(|current| std::mem::replace(current, phase) != phase);
+        }
+
+        // A log that can't be written shouldn't fail the build itself.
+        if let Some(ref log) = log {
+            let _ = writeln!(&**log, "{line}");
+        }
+
+        if echo {
+            if stderr {
+                eprintln!("{line}");
+            } else {
+                println!("{line}");
+            }
         }
 
         if tail.len() == TAIL_LINES {
@@ -184,6 +470,8 @@ where
 
 #[cfg(test)]
 mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
     use super::*;
 
     #[tokio::test]
@@ -228,9 +516,17 @@ end
         );
         env.insert("ZEROBREW_INSTALLED_DEPS".to_string(), "{}".to_string());
 
-        run_build(&ruby, &shim_path, &source_root, &env)
-            .await
-            .unwrap();
+        run_build(
+            &ruby,
+            &shim_path,
+            &source_root,
+            &env,
+            &BuildOutput::default(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
 
         assert!(
             prefix
@@ -243,6 +539,73 @@ end
         );
     }
 
+    #[tokio::test]
+    async fn plan_env_and_configure_args_reach_the_build_command() {
+        let Some(ruby) = find_ruby().await.ok() else {
+            return;
+        };
+
+        let tmp = tempfile::tempdir().unwrap();
+        let source_root = tmp.path().join("source");
+        std::fs::create_dir_all(&source_root).unwrap();
+
+        let shim_path = tmp.path().join("shim.rb");
+        std::fs::write(&shim_path, SHIM_RUBY).unwrap();
+
+        let formula_path = tmp.path().join("foo.rb");
+        std::fs::write(
+            &formula_path,
+            r#"
+class Foo < Formula
+  def install
+    system "sh", "-c", "mkdir -p #{prefix} && printf '%s %s' \"$FOO_FEATURE\" '#{std_configure_args.last}' > #{prefix}/seen"
+  end
+end
+"#,
+        )
+        .unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        let plan = BuildPlan {
+            formula_name: "foo".to_string(),
+            version: "1.0.0".to_string(),
+            source_url: String::new(),
+            source_checksum: zb_core::Checksum::None,
+            ruby_source_path: None,
+            build_dependencies: Vec::new(),
+            runtime_dependencies: Vec::new(),
+            detected_system: zb_core::BuildSystem::RubyFormula,
+            prefix: prefix.clone(),
+            cellar_path: prefix.join("Cellar/foo/1.0.0"),
+            env: [("FOO_FEATURE".to_string(), "enabled".to_string())].into(),
+            configure_args: vec!["--with-feature".to_string()],
+        };
+
+        let mut env = build_env(&plan, &prefix);
+        env.insert(
+            "ZEROBREW_FORMULA_FILE".to_string(),
+            formula_path.display().to_string(),
+        );
+        env.insert("ZEROBREW_INSTALLED_DEPS".to_string(), "{}".to_string());
+
+        run_build(
+            &ruby,
+            &shim_path,
+            &source_root,
+            &env,
+            &BuildOutput::default(),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(plan.cellar_path.join("seen")).unwrap(),
+            "enabled --with-feature"
+        );
+    }
+
     #[tokio::test]
     async fn run_build_includes_stderr_tail_in_error() {
         let Some(ruby) = find_ruby().await.ok() else {
@@ -284,12 +647,215 @@ end
         );
         env.insert("ZEROBREW_INSTALLED_DEPS".to_string(), "{}".to_string());
 
-        let err = run_build(&ruby, &shim_path, &source_root, &env)
+        let log_path = tmp.path().join("foo-1.0.0.log");
+        let output = BuildOutput {
+            echo: false,
+            log_path: Some(log_path.clone()),
+        };
+
+        let err = run_build(&ruby, &shim_path, &source_root, &env, &output, None, None)
             .await
             .unwrap_err();
 
         let message = err.to_string();
         assert!(message.contains("source build failed"));
         assert!(message.contains("boom-from-stderr"));
+        assert!(message.contains(&format!("full build log: {}", log_path.display())));
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        assert!(log.contains("boom-from-stderr"), "{log}");
+    }
+
+    #[test]
+    fn build_phase_classifies_common_build_commands() {
+        let cases = [
+            ("./configure --prefix=/opt/zb", Some(BuildPhase::Configure)),
+            ("cmake -S . -B build", Some(BuildPhase::Configure)),
+            ("cmake --build build", Some(BuildPhase::Compile)),
+            ("cmake --install build", Some(BuildPhase::Install)),
+            ("make", Some(BuildPhase::Compile)),
+            ("make install", Some(BuildPhase::Install)),
+            ("meson setup build", Some(BuildPhase::Configure)),
+            ("meson compile -C build", Some(BuildPhase::Compile)),
+            ("ninja -C build install", Some(BuildPhase::Install)),
+            ("go build -o bin/foo", Some(BuildPhase::Compile)),
+            ("cargo install --root /opt/zb", Some(BuildPhase::Install)),
+            ("Building foo 1.0.0", None),
+            ("mkdir -p build", None),
+        ];
+
+        for (command, expected) in cases {
+            assert_eq!(BuildPhase::from_command(command), expected, "{command}");
+        }
+    }
+
+    #[tokio::test]
+    async fn run_build_reports_phase_changes() {
+        let Some(ruby) = find_ruby().await.ok() else {
+            return;
+        };
+
+        let tmp = tempfile::tempdir().unwrap();
+        let source_root = tmp.path().join("source");
+        std::fs::create_dir_all(&source_root).unwrap();
+        let configure = source_root.join("configure");
+        std::fs::write(&configure, "#!/bin/sh\nexit 0\n").unwrap();
+        std::fs::set_permissions(&configure, std::fs::Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(
+            source_root.join("Makefile"),
+            "all:\n\ttrue\ninstall:\n\ttrue\n",
+        )
+        .unwrap();
+
+        let shim_path = tmp.path().join("shim.rb");
+        std::fs::write(&shim_path, SHIM_RUBY).unwrap();
+
+        let formula_path = tmp.path().join("foo.rb");
+        std::fs::write(
+            &formula_path,
+            r#"
+class Foo < Formula
+  def install
+    system "./configure"
+    system "make"
+    system "make", "install"
+  end
+end
+"#,
+        )
+        .unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        let cellar = prefix.join("Cellar");
+        std::fs::create_dir_all(&cellar).unwrap();
+
+        let mut env = HashMap::new();
+        env.insert("ZEROBREW_PREFIX".to_string(), prefix.display().to_string());
+        env.insert("ZEROBREW_CELLAR".to_string(), cellar.display().to_string());
+        env.insert("ZEROBREW_FORMULA_NAME".to_string(), "foo".to_string());
+        env.insert("ZEROBREW_FORMULA_VERSION".to_string(), "1.0.0".to_string());
+        env.insert(
+            "ZEROBREW_FORMULA_FILE".to_string(),
+            formula_path.display().to_string(),
+        );
+        env.insert("ZEROBREW_INSTALLED_DEPS".to_string(), "{}".to_string());
+
+        let phases = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = phases.clone();
+        let progress: BuildProgressCallback = Arc::new(move |phase| {
+            seen.lock().unwrap().push(phase);
+        });
+
+        if run_build(
+            &ruby,
+            &shim_path,
+            &source_root,
+            &env,
+            &BuildOutput::default(),
+            Some(progress),
+            None,
+        )
+        .await
+        .is_err()
+        {
+            // No `make` on this host.
+            return;
+        }
+
+        assert_eq!(
+            *phases.lock().unwrap(),
+            vec![
+                BuildPhase::Configure,
+                BuildPhase::Compile,
+                BuildPhase::Install
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn run_build_kills_process_group_on_phase_timeout() {
+        let Some(ruby) = find_ruby().await.ok() else {
+            return;
+        };
+
+        let tmp = tempfile::tempdir().unwrap();
+        let source_root = tmp.path().join("source");
+        std::fs::create_dir_all(&source_root).unwrap();
+        let pid_file = tmp.path().join("sleep.pid");
+
+        let shim_path = tmp.path().join("shim.rb");
+        std::fs::write(&shim_path, SHIM_RUBY).unwrap();
+
+        let formula_path = tmp.path().join("foo.rb");
+        std::fs::write(
+            &formula_path,
+            format!(
+                r#"
+class Foo < Formula
+  def install
+    system "sh", "-c", "sleep 30 & echo $! > {}; wait"
+  end
+end
+"#,
+                pid_file.display()
+            ),
+        )
+        .unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        let cellar = prefix.join("Cellar");
+        std::fs::create_dir_all(&cellar).unwrap();
+
+        let mut env = HashMap::new();
+        env.insert("ZEROBREW_PREFIX".to_string(), prefix.display().to_string());
+        env.insert("ZEROBREW_CELLAR".to_string(), cellar.display().to_string());
+        env.insert("ZEROBREW_FORMULA_NAME".to_string(), "foo".to_string());
+        env.insert("ZEROBREW_FORMULA_VERSION".to_string(), "1.0.0".to_string());
+        env.insert(
+            "ZEROBREW_FORMULA_FILE".to_string(),
+            formula_path.display().to_string(),
+        );
+        env.insert("ZEROBREW_INSTALLED_DEPS".to_string(), "{}".to_string());
+
+        let log_path = tmp.path().join("foo-1.0.0.log");
+        let output = BuildOutput {
+            echo: false,
+            log_path: Some(log_path.clone()),
+        };
+
+        let started = std::time::Instant::now();
+        let err = run_build(
+            &ruby,
+            &shim_path,
+            &source_root,
+            &env,
+            &output,
+            None,
+            Some(Duration::from_secs(1)),
+        )
+        .await
+        .unwrap_err();
+
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(err.to_string().contains(&log_path.display().to_string()));
+        assert!(log_path.exists());
+
+        let pid: i32 = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        let mut alive = true;
+        for _ in 0..50 {
+            // A killed process nobody has reaped yet still answers kill(0).
+            alive = unsafe { libc::kill(pid, 0) } == 0
+                && !std::fs::read_to_string(format!("/proc/{pid}/stat"))
+                    .is_ok_and(|stat| stat.contains(") Z "));
+            if !alive {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        assert!(!alive, "grandchild sleep survived the timeout");
     }
 }
@@ -2,4 +2,4 @@ pub mod environment;
 pub mod executor;
 pub mod source;
 
-pub use executor::{BuildExecutor, DepInfo};
+pub use executor::{BuildExecutor, BuildPhase, BuildProgressCallback, DepInfo};
@@ -23,6 +23,7 @@ FORMULA_NAME = ENV.fetch("ZEROBREW_FORMULA_NAME")
 FORMULA_VERSION = ENV.fetch("ZEROBREW_FORMULA_VERSION")
 FORMULA_FILE = ENV.fetch("ZEROBREW_FORMULA_FILE")
 INSTALLED_DEPS = JSON.parse(ENV.fetch("ZEROBREW_INSTALLED_DEPS", "{}"))
+EXTRA_CONFIGURE_ARGS = JSON.parse(ENV.fetch("ZEROBREW_CONFIGURE_ARGS", "[]"))
 
 module OS
   def self.mac?
@@ -455,7 +456,8 @@ class Formula
   end
 
   def std_configure_args
-    ["--disable-debug", "--disable-dependency-tracking", "--prefix=#{prefix}", "--libdir=#{lib}"]
+    ["--disable-debug", "--disable-dependency-tracking", "--prefix=#{prefix}", "--libdir=#{lib}"] +
+      EXTRA_CONFIGURE_ARGS
   end
 
   def std_cmake_args
@@ -1,20 +1,35 @@
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
+use sha2::{Digest, Sha256};
 use tokio::fs;
-use zb_core::Error;
+use tracing::warn;
+use zb_core::{Checksum, Error};
 
-use crate::checksum::verify_sha256_bytes;
+use crate::checksum::verify_checksum_bytes;
 use crate::extraction::extract_tarball;
+use crate::storage::blob::BlobCache;
 
+/// Download, verify and unpack a source archive into `work_dir/src`. With a
+/// `cache`, the archive is kept in the blob cache and reused by later builds.
 pub async fn download_and_extract_source(
     url: &str,
-    expected_checksum: Option<&str>,
+    expected_checksum: &Checksum,
     work_dir: &Path,
+    cache: Option<&BlobCache>,
 ) -> Result<PathBuf, Error> {
-    let tarball_path = work_dir.join("source.tar.gz");
-    download_source(url, &tarball_path).await?;
-
-    verify_checksum(&tarball_path, expected_checksum, url).await?;
+    let tarball_path = match cache {
+        Some(cache) => fetch_cached_source(url, expected_checksum, cache).await?,
+        None => {
+            let tarball_path = work_dir.join("source.tar.gz");
+            let bytes = download_source(url).await?;
+            verify_source_bytes(&bytes, expected_checksum, url)?;
+            fs::write(&tarball_path, &bytes)
+                .await
+                .map_err(Error::file("failed to write source tarball"))?;
+            tarball_path
+        }
+    };
 
     let src_dir = work_dir.join("src");
     fs::create_dir_all(&src_dir)
@@ -26,8 +41,48 @@ pub async fn download_and_extract_source(
     find_source_root(&src_dir).await
 }
 
-async fn download_source(url: &str, dest: &Path) -> Result<(), Error> {
+/// Blob cache key for a source archive: its sha256 when the formula declares
+/// one, otherwise a hash of the URL. URL-keyed entries are only as good as
+/// the checksum (if any) verified on each use.
+fn source_cache_key(url: &str, expected: &Checksum) -> String {
+    match expected {
+        Checksum::Sha256(hex) if hex.len() == 64 => hex.to_ascii_lowercase(),
+        _ => format!("source-url-{:x}", Sha256::digest(url.as_bytes())),
+    }
+}
+
+async fn fetch_cached_source(
+    url: &str,
+    expected: &Checksum,
+    cache: &BlobCache,
+) -> Result<PathBuf, Error> {
+    let key = source_cache_key(url, expected);
+    let cached = cache.blob_path(&key);
+    if cache.has_blob(&key) {
+        match verify_checksum(&cached, expected, url).await {
+            Ok(()) => return Ok(cached),
+            Err(e) => {
+                warn!(url = %url, error = %e, "cached source archive is corrupt; downloading again");
+                let _ = cache.remove_blob(&key);
+            }
+        }
+    }
+
+    let bytes = download_source(url).await?;
+    verify_source_bytes(&bytes, expected, url)?;
+
+    let mut writer = cache
+        .start_write(&key)
+        .map_err(Error::store("failed to cache source tarball"))?;
+    writer
+        .write_all(&bytes)
+        .map_err(Error::store("failed to cache source tarball"))?;
+    writer.commit()
+}
+
+async fn download_source(url: &str) -> Result<Vec<u8>, Error> {
     let client = reqwest::Client::builder()
+        .gzip(false)
         .timeout(std::time::Duration::from_secs(300))
         .build()
         .map_err(Error::network("failed to create HTTP client"))?;
@@ -49,18 +104,29 @@ async fn download_source(url: &str, dest: &Path) -> Result<(), Error> {
         .bytes()
         .await
         .map_err(Error::network("failed to read source response"))?;
-
-    fs::write(dest, &bytes)
-        .await
-        .map_err(Error::file("failed to write source tarball"))
+    Ok(bytes.to_vec())
 }
 
-async fn verify_checksum(path: &Path, expected: Option<&str>, url: &str) -> Result<(), Error> {
+async fn verify_checksum(path: &Path, expected: &Checksum, url: &str) -> Result<(), Error> {
+    if *expected == Checksum::None {
+        warn!(url = %url, "formula has no source checksum; skipping verification");
+        return Ok(());
+    }
+
     let bytes = fs::read(path)
         .await
         .map_err(Error::file("failed to read tarball for checksum"))?;
 
-    verify_sha256_bytes(&bytes, expected).map_err(|e| match e {
+    verify_source_bytes(&bytes, expected, url)
+}
+
+fn verify_source_bytes(bytes: &[u8], expected: &Checksum, url: &str) -> Result<(), Error> {
+    if *expected == Checksum::None {
+        warn!(url = %url, "formula has no source checksum; skipping verification");
+        return Ok(());
+    }
+
+    verify_checksum_bytes(bytes, expected).map_err(|e| match e {
         Error::ChecksumMismatch { .. } => e,
         Error::InvalidArgument { message } => Error::InvalidArgument {
             message: format!("invalid source checksum for '{url}': {message}"),
@@ -99,3 +165,117 @@ async fn find_source_root(src_dir: &Path) -> Result<PathBuf, Error> {
 
     Ok(src_dir.to_path_buf())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const URL: &str = "https://example.com/hello-1.0.tar.gz";
+
+    #[tokio::test]
+    async fn verifies_sha256_source_archive() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = tmp.path().join("source.tar.gz");
+        fs::write(&tarball, b"hello").await.unwrap();
+
+        let good = Checksum::Sha256(
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824".to_string(),
+        );
+        verify_checksum(&tarball, &good, URL).await.unwrap();
+
+        let bad = Checksum::Sha256("0".repeat(64));
+        let err = verify_checksum(&tarball, &bad, URL).await.unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn verifies_sha1_source_archive() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = tmp.path().join("source.tar.gz");
+        fs::write(&tarball, b"hello").await.unwrap();
+
+        let good = Checksum::parse(Some("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"));
+        verify_checksum(&tarball, &good, URL).await.unwrap();
+
+        let bad = Checksum::Sha1("0".repeat(40));
+        let err = verify_checksum(&tarball, &bad, URL).await.unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+    }
+
+    #[tokio::test]
+    async fn missing_checksum_skips_verification() {
+        let tmp = TempDir::new().unwrap();
+
+        // Nothing is read when there is nothing to compare against.
+        verify_checksum(&tmp.path().join("absent.tar.gz"), &Checksum::None, URL)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn malformed_checksum_names_the_url() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = tmp.path().join("source.tar.gz");
+        fs::write(&tarball, b"hello").await.unwrap();
+
+        let err = verify_checksum(&tarball, &Checksum::Sha256("abc123".to_string()), URL)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains(URL));
+    }
+
+    fn source_tarball() -> Vec<u8> {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        let content = b"int main(void) { return 0; }\n";
+        let mut header = tar::Header::new_gnu();
+        header.set_path("hello-1.0/hello.c").unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, &content[..]).unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn repeated_builds_reuse_the_cached_source_archive() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let mock_server = MockServer::start().await;
+        let tarball = source_tarball();
+        Mock::given(method("GET"))
+            .and(path("/hello-1.0.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(tarball.clone()))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(&tmp.path().join("cache")).unwrap();
+        let url = format!("{}/hello-1.0.tar.gz", mock_server.uri());
+        let checksums = [
+            Checksum::Sha256(format!("{:x}", Sha256::digest(&tarball))),
+            Checksum::None,
+        ];
+
+        // Once keyed by checksum, once by URL: each archive is fetched once
+        // across two builds.
+        for (i, checksum) in checksums.iter().enumerate() {
+            for build in 0..2 {
+                let work_dir = tmp.path().join(format!("work-{i}-{build}"));
+                std::fs::create_dir_all(&work_dir).unwrap();
+                let root = download_and_extract_source(&url, checksum, &work_dir, Some(&cache))
+                    .await
+                    .unwrap();
+                assert!(root.join("hello.c").exists());
+            }
+        }
+    }
+}
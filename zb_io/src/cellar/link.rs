@@ -4,7 +4,9 @@ use std::path::{Component, Path, PathBuf};
 
 use zb_core::{ConflictedLink, Error};
 
-const LINK_DIRS: &[&str] = &["bin", "lib", "libexec", "include", "share", "etc"];
+const LINK_DIRS: &[&str] = &["bin", "lib", "libexec", "include", "share", "etc", "var"];
+/// Link directories only created in the prefix once a keg ships one.
+const ON_DEMAND_LINK_DIRS: &[&str] = &["var"];
 const LIBEXEC_SKIP_FILES: &[&str] = &[".gitignore", "pyvenv.cfg"];
 
 fn should_skip_link_entry(src_dir: &Path, entry_name: &std::ffi::OsStr) -> bool {
@@ -22,12 +24,97 @@ pub struct Linker {
     prefix: PathBuf,
     bin_dir: PathBuf,
     opt_dir: PathBuf,
+    versioned_opt: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct LinkedFile {
     pub link_path: PathBuf,
     pub target_path: PathBuf,
+    /// Where a pre-existing file at `link_path` was moved before linking.
+    pub backup_path: Option<PathBuf>,
+}
+
+/// Whether a recorded link is still what zerobrew left in the prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// The link exists and points at its recorded target.
+    Present,
+    /// Nothing is at the link path any more.
+    Missing,
+    /// Something else occupies the link path: a regular file, or a symlink
+    /// to a different target.
+    Hijacked,
+}
+
+/// A link a keg owns in the prefix, with its current state on disk.
+#[derive(Debug, Clone)]
+pub struct OwnedLink {
+    pub link_path: PathBuf,
+    pub target_path: PathBuf,
+    pub status: LinkStatus,
+}
+
+/// A `link_keg` that failed partway through. Everything it had created was
+/// removed again; `rolled_back` lists the links that were taken down.
+#[derive(Debug)]
+pub struct PartialLink {
+    pub error: Error,
+    pub rolled_back: Vec<PathBuf>,
+}
+
+/// One filesystem change made while linking a keg, journaled so a failure can
+/// undo exactly what this keg did and nothing that was already there.
+enum LinkChange {
+    Symlink {
+        path: PathBuf,
+        target: PathBuf,
+    },
+    Dir(PathBuf),
+    /// `path` was a symlink to `target` (another keg's directory, or a stale
+    /// opt link) and was removed to make room.
+    Replaced {
+        path: PathBuf,
+        target: PathBuf,
+    },
+}
+
+fn rollback(changes: Vec<LinkChange>) -> Vec<PathBuf> {
+    let mut removed = Vec::new();
+    for change in changes.into_iter().rev() {
+        match change {
+            LinkChange::Symlink { path, target } => {
+                // Leave it alone if something else has replaced it since.
+                if fs::read_link(&path).ok().as_ref() == Some(&target)
+                    && fs::remove_file(&path).is_ok()
+                {
+                    removed.push(path);
+                }
+            }
+            LinkChange::Dir(path) => {
+                let _ = fs::remove_dir(&path);
+            }
+            LinkChange::Replaced { path, target } => {
+                if path.symlink_metadata().is_err() {
+                    #[cfg(unix)]
+                    let _ = std::os::unix::fs::symlink(&target, &path);
+                }
+            }
+        }
+    }
+    removed
+}
+
+/// `create_dir_all`, journaling every directory it actually had to create.
+fn create_dir_tracked(dir: &Path, changes: &mut Vec<LinkChange>) -> Result<(), Error> {
+    let missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|p| p.symlink_metadata().is_err())
+        .map(Path::to_path_buf)
+        .collect();
+    fs::create_dir_all(dir).map_err(Error::store("failed to create directory"))?;
+    changes.extend(missing.into_iter().rev().map(LinkChange::Dir));
+    Ok(())
 }
 
 fn keg_name_from_path(path: &Path) -> Option<String> {
@@ -43,37 +130,100 @@ fn keg_name_from_path(path: &Path) -> Option<String> {
     None
 }
 
-fn keg_name_from_symlink(dst: &Path) -> Option<String> {
-    let target = fs::read_link(dst).ok()?;
-    let resolved = if target.is_relative() {
-        dst.parent().unwrap_or(Path::new("")).join(&target)
+/// Where the symlink at `link` points, given its raw `target`. Relative
+/// targets are joined onto the link's directory; the result is not
+/// canonicalized.
+fn resolve_link_target(link: &Path, target: &Path) -> PathBuf {
+    if target.is_relative() {
+        link.parent().unwrap_or(Path::new("")).join(target)
     } else {
-        target
-    };
+        target.to_path_buf()
+    }
+}
+
+/// Compare two paths by what they resolve to on disk, so a link made through
+/// a symlinked prefix or cellar still matches its real target.
+fn same_real_path(a: &Path, b: &Path) -> bool {
+    fs::canonicalize(a).ok() == fs::canonicalize(b).ok()
+}
+
+pub(crate) fn keg_name_from_symlink(dst: &Path) -> Option<String> {
+    let target = fs::read_link(dst).ok()?;
+    let resolved = resolve_link_target(dst, &target);
     let canonical = fs::canonicalize(&resolved).ok()?;
     keg_name_from_path(&canonical)
 }
 
+fn unique_backup_path(path: &Path) -> PathBuf {
+    if path.symlink_metadata().is_err() {
+        return path.to_path_buf();
+    }
+    let mut n = 1;
+    loop {
+        let mut candidate = path.as_os_str().to_owned();
+        candidate.push(format!(".{n}"));
+        let candidate = PathBuf::from(candidate);
+        if candidate.symlink_metadata().is_err() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+/// Rename, falling back to copy-and-delete when the backup area lives on a
+/// different filesystem from the prefix.
+fn move_path(src: &Path, dst: &Path) -> io::Result<()> {
+    if fs::rename(src, dst).is_ok() {
+        return Ok(());
+    }
+
+    let metadata = src.symlink_metadata()?;
+    if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+    } else {
+        fs::copy(src, dst)?;
+    }
+    fs::remove_file(src)
+}
+
 impl Linker {
+    /// Create the link directories under `prefix` and resolve it to its real
+    /// path, so a prefix that is itself a symlink (say `/opt/homebrew ->
+    /// /usr/local/homebrew`) links and unlinks exactly like the directory it
+    /// points to.
     pub fn new(prefix: &Path) -> io::Result<Self> {
+        fs::create_dir_all(prefix)?;
+        let prefix = fs::canonicalize(prefix)?;
         let bin_dir = prefix.join("bin");
         let opt_dir = prefix.join("opt");
         fs::create_dir_all(&bin_dir)?;
         fs::create_dir_all(&opt_dir)?;
 
         for dir in LINK_DIRS {
-            if *dir != "bin" {
+            if *dir != "bin" && !ON_DEMAND_LINK_DIRS.contains(dir) {
                 fs::create_dir_all(prefix.join(dir))?;
             }
         }
 
         Ok(Self {
-            prefix: prefix.to_path_buf(),
+            prefix,
             bin_dir,
             opt_dir,
+            versioned_opt: false,
         })
     }
 
+    /// Also link `opt/<name>@<version>` to the keg, for dependents whose
+    /// rpaths or configs were written against the versioned path. The
+    /// unversioned `opt/<name>` is still linked; a nested
+    /// `opt/<name>/<version>` is not possible because `opt/<name>` is itself
+    /// a link into the keg.
+    pub fn with_versioned_opt(mut self, enabled: bool) -> Self {
+        self.versioned_opt = enabled;
+        self
+    }
+
     /// Pre-flight check: scan all destinations for conflicts without creating any symlinks.
     /// Returns Ok(()) if no conflicts, or Err(LinkConflict) with all conflicts collected.
     pub fn check_conflicts(&self, keg_path: &Path) -> Result<(), Error> {
@@ -115,11 +265,7 @@ impl Linker {
                     && dst_path.is_symlink()
                     && let Ok(old_target) = fs::read_link(&dst_path)
                 {
-                    let resolved = if old_target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&old_target)
-                    } else {
-                        old_target
-                    };
+                    let resolved = resolve_link_target(&dst_path, &old_target);
                     Self::collect_conflicts_merged(&src_path, &resolved, &dst_path, conflicts);
                     continue;
                 }
@@ -129,12 +275,8 @@ impl Linker {
 
             if dst_path.symlink_metadata().is_ok() {
                 if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
+                    let resolved = resolve_link_target(&dst_path, &target);
+                    if same_real_path(&resolved, &src_path) {
                         continue;
                     }
                 }
@@ -178,9 +320,7 @@ impl Linker {
                 continue;
             }
 
-            if matching_old.exists()
-                && fs::canonicalize(&matching_old).ok() != fs::canonicalize(&src_path).ok()
-            {
+            if matching_old.exists() && !same_real_path(&matching_old, &src_path) {
                 conflicts.push(ConflictedLink {
                     path: dst_path,
                     owned_by: keg_name_from_symlink(dst).or_else(|| keg_name_from_path(old_target)),
@@ -190,23 +330,189 @@ impl Linker {
     }
 
     pub fn link_keg(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
-        self.check_conflicts(keg_path)?;
-        self.link_opt(keg_path)?;
+        self.try_link_keg(keg_path).map_err(|partial| partial.error)
+    }
+
+    /// Like [`Linker::link_keg`], but reports what was rolled back when
+    /// linking fails after some links were already made (for instance because
+    /// another process created a conflicting file after the pre-flight check).
+    /// Only this call's own links and directories are removed.
+    pub fn try_link_keg(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, PartialLink> {
+        self.check_conflicts(keg_path)
+            .map_err(|error| PartialLink {
+                error,
+                rolled_back: Vec::new(),
+            })?;
+        self.link_unchecked(keg_path)
+    }
+
+    fn link_unchecked(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, PartialLink> {
+        let mut changes = Vec::new();
+        self.link_all(keg_path, &mut changes)
+            .map_err(|error| PartialLink {
+                error,
+                rolled_back: rollback(changes),
+            })
+    }
+
+    fn link_all(
+        &self,
+        keg_path: &Path,
+        changes: &mut Vec<LinkChange>,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        self.link_opt_tracked(keg_path, changes)?;
         let mut linked = Vec::new();
         for dir_name in LINK_DIRS {
             let src_dir = keg_path.join(dir_name);
             let dst_dir = self.prefix.join(dir_name);
             if src_dir.exists() {
-                linked.extend(Self::link_recursive(&src_dir, &dst_dir)?);
+                linked.extend(Self::link_recursive(&src_dir, &dst_dir, changes)?);
             }
         }
         Ok(linked)
     }
 
-    fn link_recursive(src: &Path, dst: &Path) -> Result<Vec<LinkedFile>, Error> {
+    /// Link a keg, first moving any conflicting files that zerobrew does not
+    /// own into `backup_dir` so they can be restored on uninstall. Conflicts
+    /// with links owned by other kegs are still reported as errors.
+    pub fn link_keg_with_backups(
+        &self,
+        keg_path: &Path,
+        backup_dir: &Path,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        let conflicts = match self.check_conflicts(keg_path) {
+            Ok(()) => Vec::new(),
+            Err(Error::LinkConflict { conflicts }) => conflicts,
+            Err(e) => return Err(e),
+        };
+
+        let (foreign, owned): (Vec<_>, Vec<_>) = conflicts
+            .into_iter()
+            .partition(|c| c.owned_by.is_none() && (c.path.is_symlink() || !c.path.is_dir()));
+        if !owned.is_empty() {
+            return Err(Error::LinkConflict { conflicts: owned });
+        }
+
+        let mut displaced = Vec::with_capacity(foreign.len());
+        for conflict in foreign {
+            let relative =
+                conflict
+                    .path
+                    .strip_prefix(&self.prefix)
+                    .map_err(|_| Error::StoreCorruption {
+                        message: format!(
+                            "conflicting path {} is outside the prefix",
+                            conflict.path.display()
+                        ),
+                    })?;
+            let backup_path = unique_backup_path(&backup_dir.join(relative));
+            if let Some(parent) = backup_path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(Error::store("failed to create backup directory"))?;
+            }
+            move_path(&conflict.path, &backup_path)
+                .map_err(Error::store("failed to back up conflicting file"))?;
+            displaced.push((conflict.path, backup_path));
+        }
+
+        let mut linked = match self.link_keg(keg_path) {
+            Ok(linked) => linked,
+            Err(e) => {
+                for (link_path, backup_path) in &displaced {
+                    let _ = self.restore_backup(link_path, backup_path);
+                }
+                return Err(e);
+            }
+        };
+
+        for file in &mut linked {
+            if let Some((_, backup_path)) = displaced.iter().find(|(p, _)| *p == file.link_path) {
+                file.backup_path = Some(backup_path.clone());
+            }
+        }
+
+        Ok(linked)
+    }
+
+    /// Link a keg, taking over links that another keg owns at the same
+    /// paths. This is how the active provider of a shared file such as
+    /// `bin/node` is switched. Files zerobrew does not own still conflict
+    /// unless `backup_dir` is given, in which case they are moved aside as in
+    /// [`Linker::link_keg_with_backups`]. If linking fails, the other kegs'
+    /// links are put back.
+    pub fn link_keg_overwriting(
+        &self,
+        keg_path: &Path,
+        backup_dir: Option<&Path>,
+    ) -> Result<Vec<LinkedFile>, Error> {
+        let conflicts = match self.check_conflicts(keg_path) {
+            Ok(()) => Vec::new(),
+            Err(Error::LinkConflict { conflicts }) => conflicts,
+            Err(e) => return Err(e),
+        };
+
+        let (claimable, others): (Vec<_>, Vec<_>) = conflicts
+            .into_iter()
+            .partition(|c| c.owned_by.is_some() && c.path.is_symlink());
+        if backup_dir.is_none() && !others.is_empty() {
+            return Err(Error::LinkConflict { conflicts: others });
+        }
+
+        let mut released = Vec::with_capacity(claimable.len());
+        for conflict in claimable {
+            let result = fs::read_link(&conflict.path)
+                .and_then(|target| fs::remove_file(&conflict.path).map(|()| target));
+            match result {
+                Ok(target) => released.push(LinkChange::Replaced {
+                    path: conflict.path,
+                    target,
+                }),
+                Err(e) => {
+                    rollback(released);
+                    return Err(Error::store("failed to release conflicting link")(e));
+                }
+            }
+        }
+
+        let result = match backup_dir {
+            Some(backup_dir) => self.link_keg_with_backups(keg_path, backup_dir),
+            None => self.link_keg(keg_path),
+        };
+        if result.is_err() {
+            rollback(released);
+        }
+        result
+    }
+
+    /// Move a displaced file back to `link_path` once its link is gone.
+    ///
+    /// Returns `Ok(false)` and leaves the backup in place if something now
+    /// occupies `link_path`, since that is a change the user made after
+    /// linking and must not be clobbered.
+    pub fn restore_backup(&self, link_path: &Path, backup_path: &Path) -> Result<bool, Error> {
+        if link_path.symlink_metadata().is_ok() {
+            return Ok(false);
+        }
+        if backup_path.symlink_metadata().is_err() {
+            return Ok(false);
+        }
+
+        if let Some(parent) = link_path.parent() {
+            fs::create_dir_all(parent).map_err(Error::store("failed to create directory"))?;
+        }
+        move_path(backup_path, link_path).map_err(Error::store("failed to restore backup"))?;
+
+        Ok(true)
+    }
+
+    fn link_recursive(
+        src: &Path,
+        dst: &Path,
+        changes: &mut Vec<LinkChange>,
+    ) -> Result<Vec<LinkedFile>, Error> {
         let mut linked = Vec::new();
         if !dst.exists() {
-            fs::create_dir_all(dst).map_err(Error::store("failed to create directory"))?;
+            create_dir_tracked(dst, changes)?;
         }
 
         for entry in fs::read_dir(src).map_err(Error::store("failed to read directory"))? {
@@ -227,24 +533,25 @@ impl Linker {
                     let old_target = fs::read_link(&dst_path)
                         .map_err(Error::store("failed to read symlink target"))?;
                     let _ = fs::remove_file(&dst_path);
-                    Self::link_recursive(&old_target, &dst_path)?;
+                    changes.push(LinkChange::Replaced {
+                        path: dst_path.clone(),
+                        target: old_target.clone(),
+                    });
+                    Self::link_recursive(&old_target, &dst_path, changes)?;
                 }
-                linked.extend(Self::link_recursive(&src_path, &dst_path)?);
+                linked.extend(Self::link_recursive(&src_path, &dst_path, changes)?);
                 continue;
             }
 
             if dst_path.symlink_metadata().is_ok() {
                 if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
+                    let resolved = resolve_link_target(&dst_path, &target);
+                    if same_real_path(&resolved, &src_path) {
                         if resolved.exists() {
                             linked.push(LinkedFile {
                                 link_path: dst_path,
                                 target_path: src_path,
+                                backup_path: None,
                             });
                             continue;
                         } else {
@@ -278,9 +585,14 @@ impl Linker {
             #[cfg(unix)]
             std::os::unix::fs::symlink(&src_path, &dst_path)
                 .map_err(Error::store("failed to create symlink"))?;
+            changes.push(LinkChange::Symlink {
+                path: dst_path.clone(),
+                target: src_path.clone(),
+            });
             linked.push(LinkedFile {
                 link_path: dst_path,
                 target_path: src_path,
+                backup_path: None,
             });
         }
         Ok(linked)
@@ -299,6 +611,44 @@ impl Linker {
         Ok(unlinked)
     }
 
+    /// Symlinks under the prefix that point into a cellar keg that no longer
+    /// exists. These are safe to prune; nothing is removed here.
+    pub fn find_dangling_links(&self) -> Vec<PathBuf> {
+        let mut dangling = Vec::new();
+        for dir_name in LINK_DIRS {
+            Self::collect_dangling_recursive(&self.prefix.join(dir_name), &mut dangling);
+        }
+        dangling
+    }
+
+    fn collect_dangling_recursive(dir: &Path, dangling: &mut Vec<PathBuf>) {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+
+            if file_type.is_dir() {
+                Self::collect_dangling_recursive(&path, dangling);
+                continue;
+            }
+
+            if !file_type.is_symlink() || path.exists() {
+                continue;
+            }
+
+            if let Ok(target) = fs::read_link(&path) {
+                let resolved = resolve_link_target(&path, &target);
+                if keg_name_from_path(&resolved).is_some() {
+                    dangling.push(path);
+                }
+            }
+        }
+    }
+
     pub fn collect_linked_files(&self, keg_path: &Path) -> Result<Vec<LinkedFile>, Error> {
         let mut linked = Vec::new();
         for dir_name in LINK_DIRS {
@@ -332,12 +682,8 @@ impl Linker {
             }
 
             if let Ok(target) = fs::read_link(&dst_path) {
-                let resolved = if target.is_relative() {
-                    dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
+                let resolved = resolve_link_target(&dst_path, &target);
+                if same_real_path(&resolved, &src_path) {
                     let _ = fs::remove_file(&dst_path);
                     unlinked.push(dst_path);
                 }
@@ -362,15 +708,12 @@ impl Linker {
             }
 
             if let Ok(target) = fs::read_link(&dst_path) {
-                let resolved = if target.is_relative() {
-                    dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(&src_path).ok() {
+                let resolved = resolve_link_target(&dst_path, &target);
+                if same_real_path(&resolved, &src_path) {
                     linked.push(LinkedFile {
                         link_path: dst_path,
                         target_path: src_path,
+                        backup_path: None,
                     });
                 }
             }
@@ -378,20 +721,29 @@ impl Linker {
         Ok(linked)
     }
 
-    fn unlink_opt(&self, keg_path: &Path) -> Result<(), Error> {
+    /// `opt/<name>` and `opt/<name>@<version>` for a keg at
+    /// `<cellar>/<name>/<version>`. The versioned link is listed whether or
+    /// not it is enabled, so unlinking cleans it up either way.
+    fn opt_links(&self, keg_path: &Path) -> Option<(PathBuf, Option<PathBuf>)> {
         let name = keg_path
             .parent()
             .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str());
-        if let Some(name) = name {
-            let opt_link = self.opt_dir.join(name);
+            .and_then(|n| n.to_str())?;
+        let versioned = keg_path
+            .file_name()
+            .and_then(|v| v.to_str())
+            .map(|version| self.opt_dir.join(format!("{name}@{version}")));
+        Some((self.opt_dir.join(name), versioned))
+    }
+
+    fn unlink_opt(&self, keg_path: &Path) -> Result<(), Error> {
+        let Some((opt_link, versioned)) = self.opt_links(keg_path) else {
+            return Ok(());
+        };
+        for opt_link in std::iter::once(opt_link).chain(versioned) {
             if let Ok(target) = fs::read_link(&opt_link) {
-                let resolved = if target.is_relative() {
-                    opt_link.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(keg_path).ok() {
+                let resolved = resolve_link_target(&opt_link, &target);
+                if same_real_path(&resolved, keg_path) {
                     let _ = fs::remove_file(&opt_link);
                 }
             }
@@ -400,33 +752,89 @@ impl Linker {
     }
 
     pub fn link_opt(&self, keg_path: &Path) -> Result<(), Error> {
-        let name = keg_path
-            .parent()
-            .and_then(|p| p.file_name())
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| Error::StoreCorruption {
-                message: "invalid keg path".into(),
-            })?;
-        let opt_link = self.opt_dir.join(name);
+        self.link_opt_tracked(keg_path, &mut Vec::new())
+    }
+
+    fn link_opt_tracked(
+        &self,
+        keg_path: &Path,
+        changes: &mut Vec<LinkChange>,
+    ) -> Result<(), Error> {
+        let (opt_link, versioned) =
+            self.opt_links(keg_path)
+                .ok_or_else(|| Error::StoreCorruption {
+                    message: "invalid keg path".into(),
+                })?;
+        Self::link_opt_at(opt_link, keg_path, changes)?;
+        if self.versioned_opt
+            && let Some(versioned) = versioned
+        {
+            Self::link_opt_at(versioned, keg_path, changes)?;
+        }
+        Ok(())
+    }
+
+    fn link_opt_at(
+        opt_link: PathBuf,
+        keg_path: &Path,
+        changes: &mut Vec<LinkChange>,
+    ) -> Result<(), Error> {
         if opt_link.symlink_metadata().is_ok() {
             if let Ok(target) = fs::read_link(&opt_link) {
-                let resolved = if target.is_relative() {
-                    opt_link.parent().unwrap_or(Path::new("")).join(&target)
-                } else {
-                    target
-                };
-                if fs::canonicalize(&resolved).ok() == fs::canonicalize(keg_path).ok() {
+                let resolved = resolve_link_target(&opt_link, &target);
+                if same_real_path(&resolved, keg_path) {
                     return Ok(());
                 }
+                changes.push(LinkChange::Replaced {
+                    path: opt_link.clone(),
+                    target,
+                });
             }
             let _ = fs::remove_file(&opt_link);
         }
         #[cfg(unix)]
         std::os::unix::fs::symlink(keg_path, &opt_link)
             .map_err(Error::store("failed to create opt symlink"))?;
+        changes.push(LinkChange::Symlink {
+            path: opt_link,
+            target: keg_path.to_path_buf(),
+        });
         Ok(())
     }
 
+    /// Check a recorded link against the prefix. A link whose target has
+    /// since disappeared still counts as present if it points at the
+    /// recorded path.
+    pub fn link_status(link_path: &Path, target_path: &Path) -> LinkStatus {
+        if link_path.symlink_metadata().is_err() {
+            return LinkStatus::Missing;
+        }
+        let Ok(target) = fs::read_link(link_path) else {
+            return LinkStatus::Hijacked;
+        };
+        let resolved = resolve_link_target(link_path, &target);
+
+        if resolved == target_path {
+            return LinkStatus::Present;
+        }
+        match (fs::canonicalize(&resolved), fs::canonicalize(target_path)) {
+            (Ok(a), Ok(b)) if a == b => LinkStatus::Present,
+            _ => LinkStatus::Hijacked,
+        }
+    }
+
+    /// Pair each recorded `(link, target)` with its [`LinkStatus`].
+    pub fn linked_files_for(&self, recorded: &[(PathBuf, PathBuf)]) -> Vec<OwnedLink> {
+        recorded
+            .iter()
+            .map(|(link_path, target_path)| OwnedLink {
+                link_path: link_path.clone(),
+                target_path: target_path.clone(),
+                status: Self::link_status(link_path, target_path),
+            })
+            .collect()
+    }
+
     pub fn is_linked(&self, keg_path: &Path) -> bool {
         let keg_bin = keg_path.join("bin");
         if !keg_bin.exists() {
@@ -436,12 +844,8 @@ impl Linker {
             for entry in entries.flatten() {
                 let dst_path = self.bin_dir.join(entry.file_name());
                 if let Ok(target) = fs::read_link(&dst_path) {
-                    let resolved = if target.is_relative() {
-                        dst_path.parent().unwrap_or(Path::new("")).join(&target)
-                    } else {
-                        target
-                    };
-                    if fs::canonicalize(&resolved).ok() == fs::canonicalize(entry.path()).ok() {
+                    let resolved = resolve_link_target(&dst_path, &target);
+                    if same_real_path(&resolved, &entry.path()) {
                         return true;
                     }
                 }
@@ -449,6 +853,16 @@ impl Linker {
         }
         false
     }
+
+    /// Whether `opt/<name>` points at `keg_path`.
+    pub fn has_opt_link(&self, keg_path: &Path) -> bool {
+        let Some(name) = keg_path.parent().and_then(|p| p.file_name()) else {
+            return false;
+        };
+        let opt_link = self.opt_dir.join(name);
+        fs::read_link(&opt_link)
+            .is_ok_and(|target| same_real_path(&resolve_link_target(&opt_link, &target), keg_path))
+    }
 }
 
 #[cfg(test)]
@@ -699,4 +1113,237 @@ mod tests {
         // Pre-flight check should pass since the files don't overlap
         assert!(linker.check_conflicts(&keg2).is_ok());
     }
+
+    #[test]
+    fn link_with_backups_displaces_and_restores_foreign_file() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        let prefix = tmp.path().join("prefix");
+        let backups = tmp.path().join("backups");
+        let linker = Linker::new(&prefix).unwrap();
+        fs::write(prefix.join("bin/foo"), b"system foo").unwrap();
+
+        let linked = linker.link_keg_with_backups(&keg, &backups).unwrap();
+        let backup = linked[0].backup_path.clone().unwrap();
+        assert_eq!(backup, backups.join("bin/foo"));
+        assert_eq!(fs::read(&backup).unwrap(), b"system foo");
+        assert!(prefix.join("bin/foo").is_symlink());
+
+        linker.unlink_keg(&keg).unwrap();
+        assert!(
+            linker
+                .restore_backup(&prefix.join("bin/foo"), &backup)
+                .unwrap()
+        );
+        assert_eq!(fs::read(prefix.join("bin/foo")).unwrap(), b"system foo");
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn restore_backup_does_not_clobber_replaced_file() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        let prefix = tmp.path().join("prefix");
+        let backups = tmp.path().join("backups");
+        let linker = Linker::new(&prefix).unwrap();
+        fs::write(prefix.join("bin/foo"), b"system foo").unwrap();
+
+        let linked = linker.link_keg_with_backups(&keg, &backups).unwrap();
+        let backup = linked[0].backup_path.clone().unwrap();
+
+        fs::remove_file(prefix.join("bin/foo")).unwrap();
+        fs::write(prefix.join("bin/foo"), b"user edit").unwrap();
+
+        assert!(
+            !linker
+                .restore_backup(&prefix.join("bin/foo"), &backup)
+                .unwrap()
+        );
+        assert_eq!(fs::read(prefix.join("bin/foo")).unwrap(), b"user edit");
+        assert!(backup.exists());
+    }
+
+    #[test]
+    fn link_with_backups_still_rejects_links_owned_by_other_kegs() {
+        let tmp = TempDir::new().unwrap();
+        let foo = setup_keg(&tmp, "foo");
+        let other = tmp.path().join("cellar/bar/1.0.0");
+        fs::create_dir_all(other.join("bin")).unwrap();
+        fs::write(other.join("bin/foo"), b"bar's foo").unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&other).unwrap();
+
+        let err = linker
+            .link_keg_with_backups(&foo, &tmp.path().join("backups"))
+            .unwrap_err();
+        assert!(matches!(err, Error::LinkConflict { .. }));
+    }
+
+    #[test]
+    fn find_dangling_links_reports_only_links_into_missing_kegs() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        let prefix = tmp.path().join("prefix");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&keg).unwrap();
+
+        std::os::unix::fs::symlink("/nonexistent/tool", prefix.join("bin/tool")).unwrap();
+        assert!(linker.find_dangling_links().is_empty());
+
+        fs::remove_dir_all(&keg).unwrap();
+        assert_eq!(linker.find_dangling_links(), vec![prefix.join("bin/foo")]);
+    }
+
+    #[test]
+    fn failed_link_rolls_back_only_its_own_links() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::create_dir_all(keg.join("share/foo/doc")).unwrap();
+        fs::write(keg.join("share/foo/doc/README"), b"docs").unwrap();
+        fs::create_dir_all(keg.join("etc")).unwrap();
+        fs::write(keg.join("etc/foo.conf"), b"keg config").unwrap();
+
+        let prefix = tmp.path().join("prefix");
+        let linker = Linker::new(&prefix).unwrap();
+
+        // Another keg's link in a shared directory must survive the rollback.
+        let other = setup_keg(&tmp, "bar");
+        linker.link_keg(&other).unwrap();
+
+        // Appears after the pre-flight check, so linking fails on `etc` once
+        // `bin` and `share` are already linked.
+        fs::write(prefix.join("etc/foo.conf"), b"user config").unwrap();
+        let partial = linker.link_unchecked(&keg).unwrap_err();
+
+        assert!(matches!(partial.error, Error::LinkConflict { .. }));
+        assert!(partial.rolled_back.contains(&prefix.join("bin/foo")));
+        assert!(partial.rolled_back.contains(&prefix.join("opt/foo")));
+        assert!(prefix.join("bin/foo").symlink_metadata().is_err());
+        assert!(prefix.join("opt/foo").symlink_metadata().is_err());
+        assert!(!prefix.join("share/foo").exists());
+        assert!(prefix.join("share").is_dir());
+        assert!(prefix.join("bin/bar").is_symlink());
+        assert_eq!(
+            fs::read(prefix.join("etc/foo.conf")).unwrap(),
+            b"user config"
+        );
+    }
+
+    #[test]
+    fn link_status_detects_missing_and_hijacked_links() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::write(keg.join("bin/foo-config"), b"cfg").unwrap();
+        fs::write(keg.join("bin/foo-tool"), b"tool").unwrap();
+        let prefix = tmp.path().join("prefix");
+        let linker = Linker::new(&prefix).unwrap();
+        linker.link_keg(&keg).unwrap();
+
+        fs::remove_file(prefix.join("bin/foo-config")).unwrap();
+        fs::remove_file(prefix.join("bin/foo-tool")).unwrap();
+        std::os::unix::fs::symlink("/usr/bin/true", prefix.join("bin/foo-tool")).unwrap();
+
+        let recorded: Vec<(PathBuf, PathBuf)> = ["foo", "foo-config", "foo-tool"]
+            .iter()
+            .map(|name| (prefix.join("bin").join(name), keg.join("bin").join(name)))
+            .collect();
+        let statuses: Vec<LinkStatus> = linker
+            .linked_files_for(&recorded)
+            .into_iter()
+            .map(|link| link.status)
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                LinkStatus::Present,
+                LinkStatus::Missing,
+                LinkStatus::Hijacked
+            ]
+        );
+    }
+
+    #[test]
+    fn symlinked_prefix_links_and_unlinks_cleanly() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        fs::create_dir_all(keg.join("share/man/man1")).unwrap();
+        fs::write(keg.join("share/man/man1/foo.1"), b"manpage").unwrap();
+
+        let real_prefix = tmp.path().join("usr-local-homebrew");
+        fs::create_dir_all(&real_prefix).unwrap();
+        let prefix = tmp.path().join("opt-homebrew");
+        std::os::unix::fs::symlink(&real_prefix, &prefix).unwrap();
+
+        let linker = Linker::new(&prefix).unwrap();
+        let linked = linker.link_keg(&keg).unwrap();
+        assert_eq!(linked.len(), 2);
+        assert!(prefix.join("bin/foo").exists());
+        assert!(real_prefix.join("share/man/man1/foo.1").exists());
+        assert!(linker.is_linked(&keg));
+
+        // Links already in place are recognized through the symlink rather
+        // than reported as conflicts.
+        linker.check_conflicts(&keg).unwrap();
+        assert_eq!(linker.link_keg(&keg).unwrap().len(), 2);
+        assert_eq!(linker.collect_linked_files(&keg).unwrap().len(), 2);
+
+        let unlinked = linker.unlink_keg(&keg).unwrap();
+        assert_eq!(unlinked.len(), 2);
+        assert!(!real_prefix.join("bin/foo").exists());
+        assert!(!real_prefix.join("share/man").exists());
+        assert!(real_prefix.join("opt/foo").symlink_metadata().is_err());
+        assert!(!linker.is_linked(&keg));
+    }
+
+    #[test]
+    fn versioned_opt_link_is_created_alongside_and_removed_on_unlink() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        let linker = Linker::new(tmp.path()).unwrap().with_versioned_opt(true);
+
+        linker.link_keg(&keg).unwrap();
+
+        let opt = tmp.path().join("opt/foo");
+        let versioned = tmp.path().join("opt/foo@1.0.0");
+        assert_eq!(fs::read_link(&opt).unwrap(), keg);
+        assert_eq!(fs::read_link(&versioned).unwrap(), keg);
+        assert!(versioned.join("bin/foo").exists());
+
+        linker.unlink_keg(&keg).unwrap();
+
+        assert!(opt.symlink_metadata().is_err());
+        assert!(versioned.symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn versioned_opt_link_is_opt_in() {
+        let tmp = TempDir::new().unwrap();
+        let keg = setup_keg(&tmp, "foo");
+        let linker = Linker::new(tmp.path()).unwrap();
+
+        linker.link_keg(&keg).unwrap();
+
+        assert!(tmp.path().join("opt/foo").is_symlink());
+        assert!(tmp.path().join("opt/foo@1.0.0").symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn var_is_linked_only_when_a_keg_ships_it() {
+        let tmp = TempDir::new().unwrap();
+        let linker = Linker::new(tmp.path()).unwrap();
+        assert!(!tmp.path().join("var").exists());
+
+        let keg = setup_keg(&tmp, "foo");
+        fs::create_dir_all(keg.join("var/foo")).unwrap();
+        fs::write(keg.join("var/foo/state.db"), b"").unwrap();
+
+        linker.link_keg(&keg).unwrap();
+        let linked = tmp.path().join("var/foo/state.db");
+        assert!(linked.is_symlink());
+
+        linker.unlink_keg(&keg).unwrap();
+        assert!(linked.symlink_metadata().is_err());
+    }
 }
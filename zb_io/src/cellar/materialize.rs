@@ -1,7 +1,7 @@
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
-use zb_core::Error;
+use zb_core::{Error, formula_token};
 
 #[cfg(target_os = "linux")]
 use crate::extraction::patch::linux::patch_placeholders;
@@ -9,6 +9,13 @@ use crate::extraction::patch::linux::patch_placeholders;
 #[cfg(target_os = "macos")]
 use crate::extraction::patch::macos::{codesign_and_strip_xattrs, patch_homebrew_placeholders};
 
+/// Sits between the version and the owning pid in a keg staging directory
+/// name: `.<version>.partial-<pid>`.
+const STAGING_MARKER: &str = ".partial-";
+
+/// How `Cellar::materialize` lays store files out in a keg. Each strategy
+/// falls back to the next one when it can't apply: clonefile only works on
+/// APFS, and hardlinks only when the store and cellar share a device.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CopyStrategy {
     Clonefile,
@@ -16,8 +23,20 @@ pub enum CopyStrategy {
     Copy,
 }
 
+impl Default for CopyStrategy {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            Self::Clonefile
+        } else {
+            Self::Hardlink
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Cellar {
     cellar_dir: PathBuf,
+    copy_strategy: CopyStrategy,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -34,7 +53,22 @@ impl Cellar {
 
     pub fn new_at(cellar_dir: PathBuf) -> io::Result<Self> {
         fs::create_dir_all(&cellar_dir)?;
-        Ok(Self { cellar_dir })
+        Ok(Self {
+            cellar_dir,
+            copy_strategy: CopyStrategy::default(),
+        })
+    }
+
+    /// Choose how kegs are populated from the store. Hardlinked kegs share
+    /// inodes with the store, so the patching passes detach any file before
+    /// rewriting it.
+    pub fn with_copy_strategy(mut self, copy_strategy: CopyStrategy) -> Self {
+        self.copy_strategy = copy_strategy;
+        self
+    }
+
+    pub fn cellar_dir(&self) -> &Path {
+        &self.cellar_dir
     }
 
     pub fn keg_path(&self, name: &str, version: &str) -> PathBuf {
@@ -78,6 +112,9 @@ impl Cellar {
                 let Some(version) = version_entry.file_name().to_str().map(str::to_owned) else {
                     continue;
                 };
+                if version.starts_with('.') {
+                    continue;
+                }
 
                 kegs.push(MaterializedKeg {
                     name: name.clone(),
@@ -90,6 +127,55 @@ impl Cellar {
         Ok(kegs)
     }
 
+    /// Staging directories left behind by a materialization that never
+    /// finished, e.g. because the process was killed mid-copy. Only safe to
+    /// remove while no install is running.
+    pub fn list_staging_dirs(&self) -> Result<Vec<PathBuf>, Error> {
+        let mut staging = Vec::new();
+
+        for name_entry in fs::read_dir(&self.cellar_dir)
+            .map_err(Error::store("failed to read cellar directory"))?
+        {
+            let name_entry = name_entry.map_err(Error::store("failed to read cellar entry"))?;
+            if !name_entry.path().is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(name_entry.path())
+                .map_err(Error::store("failed to read keg directory"))?
+            {
+                let entry = entry.map_err(Error::store("failed to read keg entry"))?;
+                let file_name = entry.file_name();
+                let file_name = file_name.to_string_lossy();
+                if file_name.starts_with('.') && file_name.contains(STAGING_MARKER) {
+                    staging.push(entry.path());
+                }
+            }
+        }
+
+        staging.sort();
+        Ok(staging)
+    }
+
+    /// Bytes a keg occupies on disk. Symlinks count as the link itself, so a
+    /// link pointing out of the keg never pulls in its target's size.
+    pub fn disk_usage(&self, name: &str, version: &str) -> Result<u64, Error> {
+        let keg_path = self.keg_path(name, version);
+        if !keg_path.exists() {
+            return Err(Error::NotInstalled {
+                name: name.to_string(),
+            });
+        }
+        tree_size(&keg_path)
+    }
+
+    /// Bytes used by every keg in the cellar.
+    pub fn total_usage(&self) -> Result<u64, Error> {
+        if !self.cellar_dir.exists() {
+            return Ok(0);
+        }
+        tree_size(&self.cellar_dir)
+    }
+
     pub fn materialize(
         &self,
         name: &str,
@@ -112,96 +198,308 @@ impl Cellar {
         // Find the source directory to copy from
         let src_path = find_bottle_content(store_entry, name, version)?;
 
-        // Copy the content to the cellar using best available strategy
-        copy_dir_with_fallback(&src_path, &keg_path)?;
+        stage_and_swap(&keg_path, |staging| {
+            // Copy the content to the cellar using best available strategy
+            copy_dir_with_fallback(&src_path, staging, self.copy_strategy)?;
 
-        // Patch Homebrew placeholders in Mach-O binaries
-        #[cfg(target_os = "macos")]
-        patch_homebrew_placeholders(&keg_path, &self.cellar_dir, name, version)?;
+            // Patch Homebrew placeholders in Mach-O binaries
+            #[cfg(target_os = "macos")]
+            patch_homebrew_placeholders(staging, &self.cellar_dir, name, version)?;
 
-        // Patch Homebrew placeholders in ELF binaries
-        #[cfg(target_os = "linux")]
-        {
-            // Derive prefix from cellar_dir directly without hardcoded fallback
-            let prefix = self
-                .cellar_dir
-                .parent()
-                .ok_or_else(|| Error::StoreCorruption {
-                    message: format!(
-                        "Invalid cellar directory (no parent): {}",
-                        self.cellar_dir.display()
-                    ),
-                })?;
-            patch_placeholders(&keg_path, prefix, name, version)?;
-        }
+            // Patch Homebrew placeholders in ELF binaries
+            #[cfg(target_os = "linux")]
+            {
+                // Derive prefix from cellar_dir directly without hardcoded fallback
+                let prefix = self
+                    .cellar_dir
+                    .parent()
+                    .ok_or_else(|| Error::StoreCorruption {
+                        message: format!(
+                            "Invalid cellar directory (no parent): {}",
+                            self.cellar_dir.display()
+                        ),
+                    })?;
+                patch_placeholders(staging, prefix, name, version)?;
+            }
 
-        // Strip quarantine xattrs and ad-hoc sign Mach-O binaries
-        #[cfg(target_os = "macos")]
-        codesign_and_strip_xattrs(&keg_path)?;
+            // Strip quarantine xattrs and ad-hoc sign Mach-O binaries
+            #[cfg(target_os = "macos")]
+            codesign_and_strip_xattrs(staging)?;
+
+            Ok(())
+        })?;
 
         Ok(keg_path)
     }
 
+    /// Remove one version of a keg, and the formula directory with it once
+    /// no versions are left. Tap-qualified names are reduced to their token;
+    /// anything that could resolve outside `<cellar>/<name>/<version>` is
+    /// refused rather than removed.
     pub fn remove_keg(&self, name: &str, version: &str) -> Result<(), Error> {
-        let keg_path = self.keg_path(name, version);
+        let keg_path = self.removable_keg_path(name, version)?;
 
-        if !keg_path.exists() {
+        if keg_path.symlink_metadata().is_err() {
             return Ok(());
         }
 
         fs::remove_dir_all(&keg_path).map_err(Error::store("failed to remove keg"))?;
 
-        // Also try to remove the parent (name) directory if it's now empty
+        // Fails harmlessly while other versions remain.
         if let Some(parent) = keg_path.parent() {
-            let _ = fs::remove_dir(parent); // Ignore error if not empty
+            let _ = fs::remove_dir(parent);
+        }
+
+        Ok(())
+    }
+
+    fn removable_keg_path(&self, name: &str, version: &str) -> Result<PathBuf, Error> {
+        let name = formula_token(name);
+        for (what, component) in [("formula name", name), ("version", version)] {
+            if component.is_empty()
+                || component == "."
+                || component == ".."
+                || component.contains(['/', '\\'])
+            {
+                return Err(Error::InvalidArgument {
+                    message: format!("refusing to remove keg with invalid {what} '{component}'"),
+                });
+            }
+        }
+
+        // The formula directory itself could be a symlink to anywhere.
+        let keg_path = self.keg_path(name, version);
+        let formula_dir = self.cellar_dir.join(name);
+        if let (Ok(real_cellar), Ok(real_formula_dir)) = (
+            fs::canonicalize(&self.cellar_dir),
+            fs::canonicalize(&formula_dir),
+        ) && real_formula_dir.parent() != Some(real_cellar.as_path())
+        {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "refusing to remove {}: it resolves outside the cellar",
+                    keg_path.display()
+                ),
+            });
+        }
+
+        Ok(keg_path)
+    }
+}
+
+/// Build a keg in a hidden sibling of `keg_path` and rename it into place
+/// only once `populate` succeeds, so a crash mid-materialization never
+/// leaves a half-written keg that looks installed. The staging directory is
+/// removed on failure.
+fn stage_and_swap(
+    keg_path: &Path,
+    populate: impl FnOnce(&Path) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let staging = staging_path(keg_path);
+    if staging.symlink_metadata().is_ok() {
+        fs::remove_dir_all(&staging)
+            .map_err(Error::store("failed to remove stale keg staging directory"))?;
+    }
+
+    let result = populate(&staging).and_then(|()| swap_into_place(&staging, keg_path));
+    if result.is_err() && staging.symlink_metadata().is_ok() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    result
+}
+
+fn staging_path(keg_path: &Path) -> PathBuf {
+    let version = keg_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    keg_path.with_file_name(format!(".{version}{STAGING_MARKER}{}", std::process::id()))
+}
+
+/// Move a fully built keg to `keg_path`. If another keg already sits there
+/// (a reinstall, or a racing materialization) the two are exchanged
+/// atomically where the platform supports it, then the old one is removed.
+fn swap_into_place(staging: &Path, keg_path: &Path) -> Result<(), Error> {
+    match fs::rename(staging, keg_path) {
+        Ok(()) => return Ok(()),
+        Err(e) if !keg_path.is_dir() => {
+            return Err(Error::store("failed to move keg into place")(e));
+        }
+        Err(_) => {}
+    }
+
+    if exchange_paths(staging, keg_path).is_err() {
+        // No atomic exchange here: move the old keg aside first so the
+        // window without a keg is a single rename wide.
+        let mut old = staging.as_os_str().to_owned();
+        old.push(".old");
+        let old = PathBuf::from(old);
+        fs::rename(keg_path, &old).map_err(Error::store("failed to move old keg aside"))?;
+        if let Err(e) = fs::rename(staging, keg_path) {
+            let _ = fs::rename(&old, keg_path);
+            return Err(Error::store("failed to move keg into place")(e));
         }
+        let _ = fs::remove_dir_all(&old);
+        return Ok(());
+    }
 
+    // After the exchange the staging path holds the previous keg.
+    fs::remove_dir_all(staging).map_err(Error::store("failed to remove replaced keg"))
+}
+
+#[cfg(target_os = "linux")]
+fn exchange_paths(a: &Path, b: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+
+    if result == 0 {
         Ok(())
+    } else {
+        Err(io::Error::last_os_error())
     }
 }
 
+#[cfg(not(target_os = "linux"))]
+fn exchange_paths(_a: &Path, _b: &Path) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
 /// Find the bottle content directory inside a store entry.
 /// Homebrew bottles have structure {name}/{version}/ inside the tarball.
 /// This function finds that directory, falling back to the store_entry root
 /// if the expected structure isn't found.
+fn tree_size(root: &Path) -> Result<u64, Error> {
+    let mut total = 0;
+    for entry in walkdir::WalkDir::new(root).follow_links(false) {
+        let entry = entry.map_err(Error::store("failed to walk keg"))?;
+        let file_type = entry.file_type();
+        if file_type.is_file() || file_type.is_symlink() {
+            total += entry
+                .metadata()
+                .map_err(Error::store("failed to read keg entry metadata"))?
+                .len();
+        }
+    }
+    Ok(total)
+}
+
 fn find_bottle_content(store_entry: &Path, name: &str, version: &str) -> Result<PathBuf, Error> {
     // Try the expected Homebrew structure: {name}/{version}/
     let expected_path = store_entry.join(name).join(version);
-    if expected_path.exists() && expected_path.is_dir() {
+    if expected_path.is_dir() {
         return Ok(expected_path);
     }
 
-    // Try just {name}/ (some bottles may have different versioning)
+    // Bottles for tap or renamed formulae can be laid out under a different
+    // top-level name than the keg, e.g. `terraform/1.10.0/` installed as
+    // `terraform@1`. A lone top-level directory holding version directories
+    // is taken to be that.
     let name_path = store_entry.join(name);
-    if name_path.exists() && name_path.is_dir() {
-        // Check if there's a single version directory inside
-        if let Ok(entries) = fs::read_dir(&name_path) {
-            let dirs: Vec<_> = entries
-                .filter_map(|e| e.ok())
-                .filter(|e| e.path().is_dir())
+    let top = if name_path.is_dir() {
+        name_path
+    } else {
+        match child_entries(store_entry)?.as_slice() {
+            [only] if only.is_dir() && looks_like_version_dirs(only)? => only.clone(),
+            // Flat tarballs (and tests) keep the keg contents at the root.
+            _ => return Ok(store_entry.to_path_buf()),
+        }
+    };
+
+    let top_version = top.join(version);
+    if top_version.is_dir() {
+        return Ok(top_version);
+    }
+
+    // Some bottles carry a different version directory than the formula
+    // reports; accept it when it is the only one.
+    let children = child_entries(&top)?;
+    match children.as_slice() {
+        [only] if only.is_dir() => Ok(only.clone()),
+        _ => {
+            let mut found: Vec<String> = children
+                .iter()
+                .filter_map(|p| p.file_name()?.to_str().map(str::to_string))
                 .collect();
-            if dirs.len() == 1 {
-                return Ok(dirs[0].path());
-            }
+            found.sort();
+            Err(Error::StoreCorruption {
+                message: format!(
+                    "bottle for {name} {version} does not have the expected <name>/<version> layout: \
+                     '{}' contains [{}]",
+                    top.display(),
+                    found.join(", ")
+                ),
+            })
         }
-        return Ok(name_path);
     }
+}
 
-    // Fall back to store entry root (for flat tarballs or tests)
-    Ok(store_entry.to_path_buf())
+fn child_entries(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    fs::read_dir(dir)
+        .map_err(Error::store("failed to read bottle contents"))?
+        .map(|entry| {
+            entry
+                .map(|e| e.path())
+                .map_err(Error::store("failed to read bottle contents"))
+        })
+        .collect()
 }
 
-fn copy_dir_with_fallback(src: &Path, dst: &Path) -> Result<(), Error> {
+/// Whether `dir` holds nothing but version-named directories (`1.10.0`,
+/// `HEAD-abc1234`), as the top-level directory of a bottle does.
+fn looks_like_version_dirs(dir: &Path) -> Result<bool, Error> {
+    let children = child_entries(dir)?;
+    Ok(!children.is_empty()
+        && children.iter().all(|child| {
+            child.is_dir()
+                && child.file_name().and_then(|n| n.to_str()).is_some_and(|n| {
+                    n.starts_with(|c: char| c.is_ascii_digit()) || n.starts_with("HEAD")
+                })
+        }))
+}
+
+fn copy_dir_with_fallback(src: &Path, dst: &Path, strategy: CopyStrategy) -> Result<(), Error> {
     // Try clonefile first (APFS), then hardlink, then copy
     #[cfg(target_os = "macos")]
     {
-        if try_clonefile_dir(src, dst).is_ok() {
+        if strategy == CopyStrategy::Clonefile && try_clonefile_dir(src, dst).is_ok() {
             return Ok(());
         }
     }
 
+    // Hardlinking across devices always fails with EXDEV; check once up front
+    // instead of paying for a failed link(2) per file.
+    let try_hardlink = strategy != CopyStrategy::Copy
+        && dst.parent().is_some_and(|parent| same_device(src, parent));
+
     // Fall back to recursive copy with hardlink/copy per file
-    copy_dir_recursive(src, dst, true)
+    copy_dir_recursive(src, dst, try_hardlink)
+}
+
+#[cfg(unix)]
+fn same_device(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (fs::metadata(a), fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_device(_a: &Path, _b: &Path) -> bool {
+    false
 }
 
 #[cfg(target_os = "macos")]
@@ -395,6 +693,74 @@ mod tests {
         assert!(!cellar.has_keg("foo", "1.2.3"));
     }
 
+    #[test]
+    fn removing_the_last_version_removes_the_formula_directory() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+        cellar.materialize("foo", "2.0.0", &store_entry).unwrap();
+        let formula_dir = cellar.cellar_dir().join("foo");
+
+        cellar.remove_keg("foo", "1.2.3").unwrap();
+        assert!(formula_dir.exists());
+
+        cellar.remove_keg("user/tap/foo", "2.0.0").unwrap();
+        assert!(!formula_dir.exists());
+    }
+
+    #[test]
+    fn remove_keg_refuses_paths_outside_the_cellar() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let victim = tmp.path().join("victim");
+        fs::create_dir_all(victim.join("1.0")).unwrap();
+
+        for (name, version) in [
+            ("..", "victim"),
+            ("foo", ".."),
+            ("foo", "a/../../victim"),
+            ("", "1.0"),
+        ] {
+            let err = cellar.remove_keg(name, version).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidArgument { .. }),
+                "{name}/{version}: {err}"
+            );
+        }
+
+        std::os::unix::fs::symlink(&victim, cellar.cellar_dir().join("escape")).unwrap();
+        let err = cellar.remove_keg("escape", "1.0").unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument { .. }));
+
+        assert!(victim.join("1.0").exists());
+    }
+
+    #[test]
+    fn disk_usage_sums_files_and_symlinks_without_following() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        // A link out of the keg must count as the link, not the 1 MiB target.
+        let big = tmp.path().join("big");
+        fs::write(&big, vec![0u8; 1 << 20]).unwrap();
+        std::os::unix::fs::symlink(&big, keg_path.join("lib/outside")).unwrap();
+        let outside_link_len = big.as_os_str().len() as u64;
+
+        // bin/foo (18) + lib/libfoo.dylib (10) + lib/libfoo.1.dylib -> "libfoo.dylib" (12)
+        let expected = 18 + 10 + 12 + outside_link_len;
+        assert_eq!(cellar.disk_usage("foo", "1.2.3").unwrap(), expected);
+        assert_eq!(cellar.total_usage().unwrap(), expected);
+        assert!(matches!(
+            cellar.disk_usage("foo", "9.9.9"),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+
     #[test]
     fn keg_path_format() {
         let tmp = TempDir::new().unwrap();
@@ -426,6 +792,65 @@ mod tests {
         );
     }
 
+    #[test]
+    fn hardlink_strategy_shares_inodes_with_store() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+
+        let cellar = Cellar::new(tmp.path())
+            .unwrap()
+            .with_copy_strategy(CopyStrategy::Hardlink);
+        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        let keg_file = fs::metadata(keg_path.join("lib/libfoo.dylib")).unwrap();
+        let store_file = fs::metadata(store_entry.join("lib/libfoo.dylib")).unwrap();
+        assert!(keg_file.nlink() > 1);
+        assert_eq!(keg_file.ino(), store_file.ino());
+    }
+
+    #[test]
+    fn copy_strategy_never_hardlinks() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+
+        let cellar = Cellar::new(tmp.path())
+            .unwrap()
+            .with_copy_strategy(CopyStrategy::Copy);
+        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        assert_eq!(
+            fs::metadata(keg_path.join("lib/libfoo.dylib"))
+                .unwrap()
+                .nlink(),
+            1
+        );
+    }
+
+    #[test]
+    fn relocation_does_not_touch_hardlinked_store_file() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = setup_store_entry(&tmp);
+        fs::create_dir_all(store_entry.join("lib/pkgconfig")).unwrap();
+        let store_pc = store_entry.join("lib/pkgconfig/foo.pc");
+        fs::write(&store_pc, "prefix=@@HOMEBREW_PREFIX@@\n").unwrap();
+
+        let cellar = Cellar::new(tmp.path())
+            .unwrap()
+            .with_copy_strategy(CopyStrategy::Hardlink);
+        let keg_path = cellar.materialize("foo", "1.2.3", &store_entry).unwrap();
+
+        let keg_pc = fs::read_to_string(keg_path.join("lib/pkgconfig/foo.pc")).unwrap();
+        assert!(!keg_pc.contains("@@HOMEBREW_PREFIX@@"));
+        assert_eq!(
+            fs::read_to_string(&store_pc).unwrap(),
+            "prefix=@@HOMEBREW_PREFIX@@\n"
+        );
+    }
+
     #[test]
     #[cfg(target_os = "macos")]
     fn clonefile_fallback_works() {
@@ -498,4 +923,82 @@ mod tests {
 
         assert_eq!(fixed3, other_path);
     }
+
+    #[test]
+    fn failure_before_rename_leaves_no_keg_behind() {
+        let tmp = TempDir::new().unwrap();
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let keg_path = cellar.keg_path("foo", "1.2.3");
+        fs::create_dir_all(keg_path.parent().unwrap()).unwrap();
+
+        let err = stage_and_swap(&keg_path, |staging| {
+            fs::create_dir_all(staging.join("bin")).unwrap();
+            fs::write(staging.join("bin/foo"), b"half").unwrap();
+            Err(Error::StoreCorruption {
+                message: "simulated patch failure".to_string(),
+            })
+        })
+        .unwrap_err();
+
+        assert!(err.to_string().contains("simulated patch failure"));
+        assert!(!keg_path.exists());
+        assert_eq!(fs::read_dir(keg_path.parent().unwrap()).unwrap().count(), 0);
+        assert!(cellar.list_kegs().unwrap().is_empty());
+    }
+
+    #[test]
+    fn staged_keg_replaces_existing_keg() {
+        let tmp = TempDir::new().unwrap();
+        let keg_path = tmp.path().join("cellar/foo/1.2.3");
+        fs::create_dir_all(keg_path.join("bin")).unwrap();
+        fs::write(keg_path.join("bin/old"), b"old").unwrap();
+
+        stage_and_swap(&keg_path, |staging| {
+            fs::create_dir_all(staging.join("bin")).unwrap();
+            fs::write(staging.join("bin/new"), b"new").unwrap();
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(keg_path.join("bin/new").exists());
+        assert!(!keg_path.join("bin/old").exists());
+        assert_eq!(fs::read_dir(keg_path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn bottle_top_level_dir_may_differ_from_keg_name() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = tmp.path().join("store/def456");
+        fs::create_dir_all(store_entry.join("terraform/1.10.0/bin")).unwrap();
+        fs::write(
+            store_entry.join("terraform/1.10.0/bin/terraform"),
+            b"#!/bin/sh\necho terraform",
+        )
+        .unwrap();
+
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let keg_path = cellar
+            .materialize("terraform@1", "1.10.0", &store_entry)
+            .unwrap();
+
+        assert_eq!(keg_path, cellar.keg_path("terraform@1", "1.10.0"));
+        assert!(keg_path.join("bin/terraform").exists());
+        assert!(!keg_path.join("1.10.0").exists());
+    }
+
+    #[test]
+    fn ambiguous_bottle_layout_is_rejected() {
+        let tmp = TempDir::new().unwrap();
+        let store_entry = tmp.path().join("store/fed789");
+        fs::create_dir_all(store_entry.join("foo/1.0.0/bin")).unwrap();
+        fs::create_dir_all(store_entry.join("foo/2.0.0/bin")).unwrap();
+
+        let cellar = Cellar::new(tmp.path()).unwrap();
+        let err = cellar
+            .materialize("foo", "3.0.0", &store_entry)
+            .unwrap_err();
+
+        assert!(err.to_string().contains("<name>/<version>"), "{err}");
+        assert!(!cellar.has_keg("foo", "3.0.0"));
+    }
 }
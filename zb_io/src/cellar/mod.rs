@@ -1,5 +1,5 @@
 pub mod link;
 pub mod materialize;
 
-pub use link::{LinkedFile, Linker};
+pub use link::{LinkStatus, LinkedFile, Linker, OwnedLink, PartialLink};
 pub use materialize::{Cellar, CopyStrategy, MaterializedKeg};
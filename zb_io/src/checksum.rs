@@ -1,5 +1,6 @@
+use sha1::Sha1;
 use sha2::{Digest, Sha256};
-use zb_core::Error;
+use zb_core::{Checksum, Error};
 
 /// Verify the SHA-256 checksum of a byte slice.
 ///
@@ -11,7 +12,7 @@ pub fn verify_sha256_bytes(bytes: &[u8], expected_sha256: Option<&str>) -> Resul
         return Ok(());
     };
 
-    let expected = normalize_sha256(expected_sha256)?;
+    let expected = normalize_hex_digest(expected_sha256, "sha256", 64)?;
 
     let mut hasher = Sha256::new();
     hasher.update(bytes);
@@ -24,13 +25,35 @@ pub fn verify_sha256_bytes(bytes: &[u8], expected_sha256: Option<&str>) -> Resul
     Ok(())
 }
 
-fn normalize_sha256(input: &str) -> Result<String, Error> {
+/// Verify a byte slice against a source checksum of whichever algorithm the
+/// formula declared. `Checksum::None` passes; callers decide whether that
+/// deserves a warning.
+pub fn verify_checksum_bytes(bytes: &[u8], expected: &Checksum) -> Result<(), Error> {
+    match expected {
+        Checksum::Sha256(hex) => verify_sha256_bytes(bytes, Some(hex)),
+        Checksum::Sha1(hex) => {
+            let expected = normalize_hex_digest(hex, "sha1", 40)?;
+
+            let mut hasher = Sha1::new();
+            hasher.update(bytes);
+            let actual = format!("{:x}", hasher.finalize());
+
+            if actual != expected {
+                return Err(Error::ChecksumMismatch { expected, actual });
+            }
+            Ok(())
+        }
+        Checksum::None => Ok(()),
+    }
+}
+
+fn normalize_hex_digest(input: &str, algorithm: &str, len: usize) -> Result<String, Error> {
     let normalized = input.trim().to_lowercase();
 
-    if normalized.len() != 64 {
+    if normalized.len() != len {
         return Err(Error::InvalidArgument {
             message: format!(
-                "invalid sha256 checksum: expected 64 hex chars, got {}",
+                "invalid {algorithm} checksum: expected {len} hex chars, got {}",
                 normalized.len()
             ),
         });
@@ -38,7 +61,7 @@ fn normalize_sha256(input: &str) -> Result<String, Error> {
 
     if !normalized.bytes().all(|b| b.is_ascii_hexdigit()) {
         return Err(Error::InvalidArgument {
-            message: "invalid sha256 checksum: must contain only hex characters".to_string(),
+            message: format!("invalid {algorithm} checksum: must contain only hex characters"),
         });
     }
 
@@ -85,4 +108,20 @@ mod tests {
         let err = verify_sha256_bytes(b"hello", Some(&"0".repeat(64))).unwrap_err();
         assert!(matches!(err, Error::ChecksumMismatch { .. }));
     }
+
+    #[test]
+    fn verifies_sha1_checksum() {
+        // SHA-1 of b"hello"
+        let expected = Checksum::Sha1("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d".to_string());
+        assert!(verify_checksum_bytes(b"hello", &expected).is_ok());
+
+        let err = verify_checksum_bytes(b"world", &expected).unwrap_err();
+        assert!(matches!(err, Error::ChecksumMismatch { .. }));
+    }
+
+    #[test]
+    fn rejects_sha1_of_wrong_length() {
+        let err = verify_checksum_bytes(b"hello", &Checksum::Sha1("abc".to_string())).unwrap_err();
+        assert!(err.to_string().contains("sha1"));
+    }
 }
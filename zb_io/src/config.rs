@@ -0,0 +1,438 @@
+use std::fs;
+use std::io;
+use std::num::{NonZeroU64, NonZeroUsize};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use tracing::warn;
+
+use crate::network::{AddressFamily, DownloaderConfig};
+
+/// File under the root that persistent settings are read from.
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+
+const DEFAULT_CONCURRENCY: usize = 20;
+
+/// Persistent settings from `<root>/config.toml`.
+///
+/// Each setting is resolved, highest precedence first, from a command-line
+/// flag where one exists, then its environment variable, then the file, then
+/// the built-in default. [`Config::load`] covers the last three; callers
+/// layer their flags on top.
+///
+/// ```toml
+/// concurrency = 8                  # --concurrency
+/// no_link = true                   # ZEROBREW_NO_LINK
+/// offline = false                  # ZEROBREW_OFFLINE
+/// verify_store = false             # ZEROBREW_VERIFY_STORE
+/// api_url = "https://formulae.example.com/api"   # ZEROBREW_API_URL
+/// ghcr_url = "https://registry.example.com"      # ZEROBREW_GHCR_URL
+/// mirrors = ["primary!mirror.example.com"]       # HOMEBREW_BOTTLE_MIRRORS
+///
+/// [download]
+/// racing_connections = 3
+/// racing_stagger_ms = 200
+/// max_buffered_chunk_bytes = 67108864
+/// reject_html_responses = true
+/// connect_timeout_secs = 30
+/// request_timeout_secs = 300
+/// address_family = "auto"         # or "ipv4", "ipv6"
+/// ```
+///
+/// A file that isn't valid TOML is ignored as a whole; a single key with a
+/// value of the wrong type only loses that key.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Bottles downloaded at once.
+    pub concurrency: usize,
+    /// Skip prefix linking on install unless `--link` is given.
+    pub no_link: bool,
+    /// Never touch the network.
+    pub offline: bool,
+    /// Re-hash store entries against their manifest whenever an install
+    /// reuses one. Slow, since it reads every file of the entry.
+    pub verify_store: bool,
+    /// Formula API base URL instead of the Homebrew one.
+    pub api_url: Option<String>,
+    /// Registry to fetch `ghcr.io` bottles from instead.
+    pub ghcr_url: Option<String>,
+    /// Bottle mirror hosts, in `HOMEBREW_BOTTLE_MIRRORS` entry syntax.
+    pub mirrors: Vec<String>,
+    /// The `[download]` table.
+    pub download: DownloaderConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            concurrency: DEFAULT_CONCURRENCY,
+            no_link: false,
+            offline: false,
+            verify_store: false,
+            api_url: None,
+            ghcr_url: None,
+            mirrors: Vec::new(),
+            download: DownloaderConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path(root: &Path) -> PathBuf {
+        root.join(CONFIG_FILE_NAME)
+    }
+
+    /// Read `<root>/config.toml` and apply environment overrides. Never
+    /// fails: a missing file means defaults, and an unreadable file or a bad
+    /// entry is logged and skipped, keeping the default for that setting.
+    pub fn load(root: &Path) -> Self {
+        let path = Self::path(root);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => Some(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                warn!(
+                    path = %path.display(),
+                    error = %e,
+                    "failed to read config file; using defaults"
+                );
+                None
+            }
+        };
+        Self::from_sources(contents.as_deref(), |name| std::env::var(name).ok())
+    }
+
+    fn from_sources(file: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Self {
+        let mut config = Self::default();
+        if let Some(contents) = file {
+            config.apply_file(contents);
+        }
+        config.apply_env(env);
+        config
+    }
+
+    fn apply_file(&mut self, contents: &str) {
+        let file: FileConfig = match toml::from_str(contents) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("ignoring {CONFIG_FILE_NAME}: {e}");
+                return;
+            }
+        };
+        let download = file.download.unwrap_or_default();
+        let unknown = file.unknown.keys().cloned();
+        let unknown_download = download.unknown.keys().map(|key| format!("download.{key}"));
+        for key in unknown.chain(unknown_download) {
+            warn!("ignoring {CONFIG_FILE_NAME} entry: {key}: unknown setting");
+        }
+
+        if let Some(concurrency) = file.concurrency {
+            self.concurrency = concurrency.get();
+        }
+        if let Some(no_link) = file.no_link {
+            self.no_link = no_link;
+        }
+        if let Some(offline) = file.offline {
+            self.offline = offline;
+        }
+        if let Some(verify_store) = file.verify_store {
+            self.verify_store = verify_store;
+        }
+        if let Some(url) = file.api_url {
+            self.api_url = Some(url);
+        }
+        if let Some(url) = file.ghcr_url {
+            self.ghcr_url = Some(url);
+        }
+        if let Some(mirrors) = file.mirrors {
+            self.mirrors = mirrors;
+        }
+
+        let config = &mut self.download;
+        if let Some(connections) = download.racing_connections {
+            config.racing_connections = connections.get();
+        }
+        if let Some(ms) = download.racing_stagger_ms {
+            config.racing_stagger = Duration::from_millis(ms);
+        }
+        if let Some(bytes) = download.max_buffered_chunk_bytes {
+            config.max_buffered_chunk_bytes = bytes;
+        }
+        if let Some(reject) = download.reject_html_responses {
+            config.reject_html_responses = reject;
+        }
+        if let Some(secs) = download.connect_timeout_secs {
+            config.connect_timeout = Duration::from_secs(secs.get());
+        }
+        if let Some(secs) = download.request_timeout_secs {
+            config.request_timeout = Duration::from_secs(secs.get());
+        }
+        if let Some(family) = download.address_family {
+            config.address_family = family;
+        }
+    }
+
+    fn apply_env(&mut self, env: impl Fn(&str) -> Option<String>) {
+        if let Some(value) = env("ZEROBREW_NO_LINK") {
+            self.no_link = env_flag(&value);
+        }
+        if let Some(value) = env("ZEROBREW_OFFLINE") {
+            self.offline = env_flag(&value);
+        }
+        if let Some(value) = env("ZEROBREW_VERIFY_STORE") {
+            self.verify_store = env_flag(&value);
+        }
+        if let Some(url) = env("ZEROBREW_API_URL") {
+            self.api_url = Some(url);
+        }
+        if let Some(url) = env("ZEROBREW_GHCR_URL") {
+            self.ghcr_url = Some(url);
+        }
+        if let Some(mirrors) = env("HOMEBREW_BOTTLE_MIRRORS") {
+            self.mirrors = mirrors
+                .split(',')
+                .map(str::trim)
+                .filter(|mirror| !mirror.is_empty())
+                .map(String::from)
+                .collect();
+        }
+    }
+}
+
+/// Read a boolean environment variable the way clap's `FalseyValueParser`
+/// does: set and not one of the usual "off" spellings.
+fn env_flag(value: &str) -> bool {
+    !matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "" | "0" | "false" | "no" | "off" | "n" | "f"
+    )
+}
+
+/// `config.toml` as written. Every key is optional, and one whose value has
+/// the wrong type or is out of range is logged and skipped rather than
+/// discarding the rest of the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileConfig {
+    #[serde(deserialize_with = "lenient")]
+    concurrency: Option<NonZeroUsize>,
+    #[serde(deserialize_with = "lenient")]
+    no_link: Option<bool>,
+    #[serde(deserialize_with = "lenient")]
+    offline: Option<bool>,
+    #[serde(deserialize_with = "lenient")]
+    verify_store: Option<bool>,
+    #[serde(deserialize_with = "lenient")]
+    api_url: Option<String>,
+    #[serde(deserialize_with = "lenient")]
+    ghcr_url: Option<String>,
+    #[serde(deserialize_with = "lenient")]
+    mirrors: Option<Vec<String>>,
+    #[serde(deserialize_with = "lenient")]
+    download: Option<FileDownloadConfig>,
+    #[serde(flatten)]
+    unknown: toml::Table,
+}
+
+/// The `[download]` table of [`FileConfig`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FileDownloadConfig {
+    #[serde(deserialize_with = "lenient")]
+    racing_connections: Option<NonZeroUsize>,
+    #[serde(deserialize_with = "lenient")]
+    racing_stagger_ms: Option<u64>,
+    #[serde(deserialize_with = "lenient")]
+    max_buffered_chunk_bytes: Option<u64>,
+    #[serde(deserialize_with = "lenient")]
+    reject_html_responses: Option<bool>,
+    #[serde(deserialize_with = "lenient")]
+    connect_timeout_secs: Option<NonZeroU64>,
+    #[serde(deserialize_with = "lenient")]
+    request_timeout_secs: Option<NonZeroU64>,
+    #[serde(deserialize_with = "lenient")]
+    address_family: Option<AddressFamily>,
+    #[serde(flatten)]
+    unknown: toml::Table,
+}
+
+/// Deserialize one setting, logging a value that doesn't fit and leaving
+/// the setting unset instead of failing the whole file.
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = toml::Value::deserialize(deserializer)?;
+    match value.clone().try_into() {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(e) => {
+            warn!(value = %value, "ignoring {CONFIG_FILE_NAME} entry: {e}");
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn from_file(contents: &str) -> Config {
+        Config::from_sources(Some(contents), |_| None)
+    }
+
+    #[test]
+    fn missing_file_loads_defaults() {
+        let tmp = TempDir::new().unwrap();
+        let config = Config::load(tmp.path());
+
+        assert_eq!(config.concurrency, DEFAULT_CONCURRENCY);
+        assert!(config.mirrors.is_empty());
+        assert_eq!(config.download, DownloaderConfig::default());
+    }
+
+    #[test]
+    fn file_values_seed_every_setting() {
+        let config = from_file(
+            r#"
+            # persistent preferences
+            concurrency = 8
+            no_link = true
+            verify_store = true
+            api_url = "https://formulae.example.com/api"
+            mirrors = ["primary!fast.example.com", 'slow.example.com'] # trailing
+
+            [download]
+            racing_connections = 1
+            racing_stagger_ms = 50
+            max_buffered_chunk_bytes = 1_048_576
+            reject_html_responses = false
+            request_timeout_secs = 900
+            address_family = "ipv4"
+            "#,
+        );
+
+        assert_eq!(config.concurrency, 8);
+        assert!(config.no_link);
+        assert!(!config.offline);
+        assert!(config.verify_store);
+        assert_eq!(
+            config.api_url.as_deref(),
+            Some("https://formulae.example.com/api")
+        );
+        assert_eq!(
+            config.mirrors,
+            ["primary!fast.example.com", "slow.example.com"]
+        );
+        assert_eq!(config.download.racing_connections, 1);
+        assert_eq!(config.download.racing_stagger, Duration::from_millis(50));
+        assert_eq!(config.download.max_buffered_chunk_bytes, 1 << 20);
+        assert!(!config.download.reject_html_responses);
+        assert_eq!(config.download.request_timeout, Duration::from_secs(900));
+        assert_eq!(config.download.address_family, AddressFamily::Ipv4Only);
+        assert_eq!(
+            config.download.connect_timeout,
+            DownloaderConfig::default().connect_timeout
+        );
+    }
+
+    #[test]
+    fn bad_entries_fall_back_to_defaults_without_losing_the_rest() {
+        let config = from_file(
+            r#"
+            concurrency = 0
+            offline = "yes"
+            no_link = true
+            colour = "always"
+            ghcr_url = 42
+            mirrors = ["a.example.com", 7]
+
+            [download]
+            racing_connections = "2"
+            connect_timeout_secs = -5
+            request_timeout_secs = 60
+            address_family = "ipv5"
+            "#,
+        );
+
+        assert_eq!(config.concurrency, DEFAULT_CONCURRENCY);
+        assert!(!config.offline);
+        assert!(config.no_link);
+        assert_eq!(config.ghcr_url, None);
+        assert!(config.mirrors.is_empty());
+        assert_eq!(config.download.address_family, AddressFamily::Auto);
+        assert_eq!(
+            config.download.racing_connections,
+            DownloaderConfig::default().racing_connections
+        );
+        assert_eq!(
+            config.download.connect_timeout,
+            DownloaderConfig::default().connect_timeout
+        );
+        assert_eq!(config.download.request_timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn multi_line_arrays_and_inline_tables_parse() {
+        let config = from_file(
+            r#"
+            mirrors = [
+                "a.example.com",   # closest
+                "b.example.com",
+            ]
+            download = { racing_connections = 2 }
+            "#,
+        );
+
+        assert_eq!(config.mirrors, ["a.example.com", "b.example.com"]);
+        assert_eq!(config.download.racing_connections, 2);
+    }
+
+    #[test]
+    fn invalid_toml_keeps_every_default() {
+        let config = from_file(
+            r#"
+            no_link = true
+            [download
+            racing_connections = 2
+            "#,
+        );
+
+        assert!(!config.no_link);
+        assert_eq!(config.download, DownloaderConfig::default());
+    }
+
+    #[test]
+    fn environment_overrides_file_values() {
+        let file = r#"
+            no_link = true
+            offline = true
+            api_url = "https://file.example.com"
+            mirrors = ["file.example.com"]
+        "#;
+        let config = Config::from_sources(Some(file), |name| match name {
+            "ZEROBREW_NO_LINK" => Some("0".to_string()),
+            "ZEROBREW_API_URL" => Some("https://env.example.com".to_string()),
+            "HOMEBREW_BOTTLE_MIRRORS" => Some("a.example.com, ,b.example.com".to_string()),
+            _ => None,
+        });
+
+        assert!(!config.no_link);
+        assert!(config.offline);
+        assert_eq!(config.api_url.as_deref(), Some("https://env.example.com"));
+        assert_eq!(config.mirrors, ["a.example.com", "b.example.com"]);
+    }
+
+    #[test]
+    fn env_flag_treats_falsey_values_as_unset() {
+        for value in ["", "0", "false", "No", "off"] {
+            assert!(!env_flag(value), "{value:?}");
+        }
+        for value in ["1", "true", "yes", "on"] {
+            assert!(env_flag(value), "{value:?}");
+        }
+    }
+}
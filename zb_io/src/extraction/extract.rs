@@ -1,6 +1,8 @@
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 use flate2::read::GzDecoder;
 use tar::Archive;
@@ -15,9 +17,171 @@ enum CompressionFormat {
     Xz,
     Zstd,
     Zip,
+    /// Recognized so it fails with a clear error instead of through the gzip
+    /// fallback; there is no lzip decoder.
+    Lzip,
     Unknown,
 }
 
+/// How many leading bytes to quote when a file is not a recognizable archive.
+const MAGIC_SNIPPET_LEN: usize = 16;
+
+/// Caps on what a single archive may unpack to, so a decompression bomb fails
+/// instead of filling the disk. The defaults sit well above the largest real
+/// bottles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// Total bytes of file content across all entries.
+    pub max_uncompressed_bytes: u64,
+    /// Entries of any kind: files, directories and links.
+    pub max_entries: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_uncompressed_bytes: 16 * 1024 * 1024 * 1024,
+            max_entries: 1_000_000,
+        }
+    }
+}
+
+/// Running totals checked against [`ExtractLimits`] before each entry is
+/// written.
+struct ExtractBudget {
+    limits: ExtractLimits,
+    bytes: u64,
+    entries: u64,
+}
+
+impl ExtractBudget {
+    fn new(limits: ExtractLimits) -> Self {
+        Self {
+            limits,
+            bytes: 0,
+            entries: 0,
+        }
+    }
+
+    /// Count one more entry and reject it if its declared `size` would
+    /// already go over the byte limit.
+    fn admit(&mut self, size: u64) -> Result<(), Error> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(size_limit_error(format!(
+                "more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+        if size > self.remaining_bytes() {
+            return Err(self.bytes_exceeded());
+        }
+        Ok(())
+    }
+
+    /// Add bytes actually written for the current entry.
+    fn record(&mut self, written: u64) -> Result<(), Error> {
+        self.bytes = self.bytes.saturating_add(written);
+        if self.bytes > self.limits.max_uncompressed_bytes {
+            return Err(self.bytes_exceeded());
+        }
+        Ok(())
+    }
+
+    /// Bytes still allowed before the size limit is hit.
+    fn remaining_bytes(&self) -> u64 {
+        self.limits
+            .max_uncompressed_bytes
+            .saturating_sub(self.bytes)
+    }
+
+    fn bytes_exceeded(&self) -> Error {
+        size_limit_error(format!(
+            "more than {} bytes uncompressed",
+            self.limits.max_uncompressed_bytes
+        ))
+    }
+}
+
+/// Handle onto a running extraction: how far it has read, and a flag that
+/// stops it. Clones share the same state, so one can be given to the
+/// extraction and another kept to watch or cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractProgress {
+    cancelled: Arc<AtomicBool>,
+    compressed_bytes: Arc<AtomicU64>,
+}
+
+impl ExtractProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask the extraction to stop. It fails with [`Error::Cancelled`] at the
+    /// next read or entry boundary, whichever comes first.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Bytes read so far from the archive file, before decompression.
+    pub fn compressed_bytes_read(&self) -> u64 {
+        self.compressed_bytes.load(Ordering::Acquire)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Report cancellation in place of whatever I/O error it caused.
+    fn or_cancelled(&self, err: Error) -> Error {
+        if self.is_cancelled() {
+            Error::Cancelled
+        } else {
+            err
+        }
+    }
+}
+
+/// Sits between the archive file and the decoder, counting compressed bytes
+/// into an [`ExtractProgress`] and failing reads once it is cancelled, so a
+/// large entry does not have to finish before the extraction stops.
+struct ProgressReader<R> {
+    inner: R,
+    progress: ExtractProgress,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.progress.is_cancelled() {
+            return Err(io::Error::other("extraction cancelled"));
+        }
+        let n = self.inner.read(buf)?;
+        self.progress
+            .compressed_bytes
+            .fetch_add(n as u64, Ordering::AcqRel);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ProgressReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn size_limit_error(detail: String) -> Error {
+    Error::StoreCorruption {
+        message: format!("archive exceeds size limit: {detail}"),
+    }
+}
+
 pub fn is_archive(path: &Path) -> Result<bool, Error> {
     detect_compression(path).map(|fmt| !matches!(fmt, CompressionFormat::Unknown))
 }
@@ -54,43 +218,110 @@ fn detect_compression(path: &Path) -> Result<CompressionFormat, Error> {
         return Ok(CompressionFormat::Zip);
     }
 
+    // Lzip: 4c 5a 49 50 ("LZIP")
+    if bytes_read >= 4 && magic[0..4] == [0x4c, 0x5a, 0x49, 0x50] {
+        return Ok(CompressionFormat::Lzip);
+    }
+
     Ok(CompressionFormat::Unknown)
 }
 
 pub fn extract_tarball(tarball_path: &Path, dest_dir: &Path) -> Result<(), Error> {
-    extract_archive(tarball_path, dest_dir)
+    extract_archive(tarball_path, dest_dir, ExtractLimits::default())
+}
+
+pub fn extract_archive(
+    archive_path: &Path,
+    dest_dir: &Path,
+    limits: ExtractLimits,
+) -> Result<(), Error> {
+    extract_archive_with_progress(archive_path, dest_dir, limits, &ExtractProgress::new())
 }
 
-pub fn extract_archive(archive_path: &Path, dest_dir: &Path) -> Result<(), Error> {
+/// Like [`extract_archive`], reporting bytes read through `progress` and
+/// stopping with [`Error::Cancelled`] once it is cancelled. Whatever was
+/// already unpacked into `dest_dir` is left for the caller to remove.
+pub fn extract_archive_with_progress(
+    archive_path: &Path,
+    dest_dir: &Path,
+    limits: ExtractLimits,
+    progress: &ExtractProgress,
+) -> Result<(), Error> {
+    progress.check()?;
     let format = detect_compression(archive_path)?;
 
     let file = File::open(archive_path).map_err(Error::store("failed to open archive"))?;
-    let reader = BufReader::new(file);
+    let reader = BufReader::new(ProgressReader {
+        inner: file,
+        progress: progress.clone(),
+    });
 
-    match format {
+    let result = match format {
         CompressionFormat::Gzip => {
             let decoder = GzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, limits, progress)
         }
         CompressionFormat::Xz => {
             let decoder = XzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, limits, progress)
         }
         CompressionFormat::Zstd => {
             let decoder =
                 ZstdDecoder::new(reader).map_err(Error::store("failed to create zstd decoder"))?;
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, limits, progress)
         }
-        CompressionFormat::Zip => extract_zip_archive(archive_path, dest_dir),
+        CompressionFormat::Zip => extract_zip_archive(archive_path, dest_dir, limits, progress),
+        CompressionFormat::Lzip => Err(Error::UnsupportedArchiveFormat {
+            format: "lzip".to_string(),
+        }),
         CompressionFormat::Unknown => {
             // Try gzip as fallback
             let decoder = GzDecoder::new(reader);
-            extract_tar_archive(decoder, dest_dir)
+            extract_tar_archive(decoder, dest_dir, limits, progress).map_err(|e| match e {
+                Error::StoreCorruption { ref message }
+                    if message.starts_with("archive exceeds size limit") =>
+                {
+                    e
+                }
+                Error::Cancelled => e,
+                _ => unknown_archive_error(archive_path),
+            })
         }
+    };
+    result.map_err(|e| progress.or_cancelled(e))
+}
+
+/// Describe an unrecognized archive by its leading bytes, flagging the common
+/// case of a mirror returning an HTML error page with a 200 status.
+fn unknown_archive_error(path: &Path) -> Error {
+    let mut head = Vec::with_capacity(MAGIC_SNIPPET_LEN);
+    if let Ok(file) = File::open(path) {
+        let _ = file.take(MAGIC_SNIPPET_LEN as u64).read_to_end(&mut head);
     }
+
+    let magic = if head.is_empty() {
+        "<empty>".to_string()
+    } else {
+        head.iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let is_text = !head.is_empty()
+        && head
+            .iter()
+            .all(|&b| b.is_ascii_graphic() || b.is_ascii_whitespace());
+
+    Error::UnknownArchiveFormat { magic, is_text }
 }
 
-fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error> {
+fn extract_tar_archive<R: Read>(
+    reader: R,
+    dest_dir: &Path,
+    limits: ExtractLimits,
+    progress: &ExtractProgress,
+) -> Result<(), Error> {
+    let mut budget = ExtractBudget::new(limits);
     let mut archive = Archive::new(reader);
 
     archive.set_preserve_permissions(true);
@@ -100,6 +331,7 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
         .entries()
         .map_err(Error::store("failed to read archive entries"))?
     {
+        progress.check()?;
         let mut entry = entry.map_err(Error::store("failed to read archive entry"))?;
 
         let entry_path = entry
@@ -112,6 +344,11 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
         // Security check: validate path doesn't escape destination
         validate_path(&entry_path, dest_dir)?;
 
+        // The header size is exactly what `unpack_in` reads and writes, so
+        // checking it up front keeps an oversized entry off the disk.
+        budget.admit(entry.size())?;
+        budget.record(entry.size())?;
+
         let ctx = format!("failed to unpack entry {path_display}");
         entry.unpack_in(dest_dir).map_err(Error::store(&ctx))?;
     }
@@ -119,11 +356,23 @@ fn extract_tar_archive<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error>
     Ok(())
 }
 
-fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
+fn extract_zip_archive(
+    path: &Path,
+    dest_dir: &Path,
+    limits: ExtractLimits,
+    progress: &ExtractProgress,
+) -> Result<(), Error> {
+    let mut budget = ExtractBudget::new(limits);
     let file = File::open(path).map_err(Error::store("failed to open zip archive"))?;
-    let mut zip = zip::ZipArchive::new(file).map_err(Error::store("failed to open zip archive"))?;
+    let reader = ProgressReader {
+        inner: file,
+        progress: progress.clone(),
+    };
+    let mut zip =
+        zip::ZipArchive::new(reader).map_err(Error::store("failed to open zip archive"))?;
 
     for i in 0..zip.len() {
+        progress.check()?;
         let mut entry = zip
             .by_index(i)
             .map_err(Error::store("failed to read zip entry"))?;
@@ -134,6 +383,11 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
         };
 
         validate_path(&raw_path, dest_dir)?;
+        reject_symlinked_parent(&raw_path, dest_dir)?;
+
+        // The declared size comes from the central directory and may lie, so
+        // it only rejects early; the copy below is capped as well.
+        budget.admit(entry.size())?;
 
         let out_path = dest_dir.join(&raw_path);
 
@@ -148,10 +402,27 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
                 .map_err(Error::store("failed to create output parent directory"))?;
         }
 
+        if entry.is_symlink() {
+            let mut target = String::new();
+            entry
+                .read_to_string(&mut target)
+                .map_err(Error::store("failed to read zip symlink target"))?;
+            let target = PathBuf::from(target);
+            validate_symlink_target(&raw_path, &target, &out_path, dest_dir)?;
+            create_symlink(&target, &out_path)?;
+            continue;
+        }
+
         let mut output =
             File::create(&out_path).map_err(Error::store("failed to create extracted file"))?;
-        std::io::copy(&mut entry, &mut output)
+        let mut capped = (&mut entry).take(budget.remaining_bytes().saturating_add(1));
+        let written = std::io::copy(&mut capped, &mut output)
             .map_err(Error::store("failed to extract zip entry"))?;
+        if let Err(e) = budget.record(written) {
+            drop(output);
+            let _ = std::fs::remove_file(&out_path);
+            return Err(e);
+        }
 
         #[cfg(unix)]
         {
@@ -167,6 +438,86 @@ fn extract_zip_archive(path: &Path, dest_dir: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Refuse an entry whose parent directories pass through a symlink the
+/// archive created earlier. Writing through one would land wherever it
+/// points, which the lexical checks on the entry's own path cannot see.
+fn reject_symlinked_parent(entry_path: &Path, dest_dir: &Path) -> Result<(), Error> {
+    let mut current = dest_dir.to_path_buf();
+    let Some(parent) = entry_path.parent() else {
+        return Ok(());
+    };
+    for component in parent.components() {
+        current.push(component);
+        match std::fs::symlink_metadata(&current) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                return Err(Error::StoreCorruption {
+                    message: format!("archive entry {} is inside a symlink", entry_path.display()),
+                });
+            }
+            Ok(_) => {}
+            // Nothing further down exists yet, so nothing can be a symlink.
+            Err(_) => break,
+        }
+    }
+    Ok(())
+}
+
+/// Validate that a symlink stored at `link_path` (relative to `dest_dir`,
+/// to be created at `out_path`) resolves inside `dest_dir`. Absolute targets
+/// are rejected outright; relative ones may use `..` as long as they stay
+/// within the destination as it actually exists on disk.
+fn validate_symlink_target(
+    link_path: &Path,
+    target: &Path,
+    out_path: &Path,
+    dest_dir: &Path,
+) -> Result<(), Error> {
+    if target.as_os_str().is_empty() || target.is_absolute() {
+        return Err(Error::StoreCorruption {
+            message: format!(
+                "symlink {} has unsafe target: {}",
+                link_path.display(),
+                target.display()
+            ),
+        });
+    }
+
+    // Resolve against the on-disk parent, not the entry's name, so links
+    // created by earlier entries are accounted for.
+    let link_dir = out_path
+        .parent()
+        .unwrap_or(dest_dir)
+        .canonicalize()
+        .map_err(Error::store("failed to resolve symlink directory"))?;
+    let root = dest_dir
+        .canonicalize()
+        .map_err(Error::store("failed to resolve extraction directory"))?;
+    let resolved = normalize_path(&link_dir.join(target));
+    if !resolved.starts_with(&root) {
+        return Err(Error::StoreCorruption {
+            message: format!(
+                "symlink escapes destination directory: {} -> {}",
+                link_path.display(),
+                target.display()
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, link).map_err(Error::store("failed to create symlink"))
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_target: &Path, link: &Path) -> Result<(), Error> {
+    Err(Error::StoreCorruption {
+        message: format!("symlinks are not supported here: {}", link.display()),
+    })
+}
+
 /// Validate that a path from a tar entry is safe to extract.
 ///
 /// This function ensures:
@@ -275,7 +626,12 @@ fn normalize_path(path: &Path) -> PathBuf {
 /// For file-based extraction with auto-detection, use `extract_tarball` instead.
 pub fn extract_tarball_from_reader<R: Read>(reader: R, dest_dir: &Path) -> Result<(), Error> {
     let decoder = GzDecoder::new(reader);
-    extract_tar_archive(decoder, dest_dir)
+    extract_tar_archive(
+        decoder,
+        dest_dir,
+        ExtractLimits::default(),
+        &ExtractProgress::new(),
+    )
 }
 
 #[cfg(test)]
@@ -370,7 +726,7 @@ mod tests {
         let dest = tmp.path().join("extracted");
         fs::create_dir(&dest).unwrap();
 
-        extract_archive(&zip_path, &dest).unwrap();
+        extract_archive(&zip_path, &dest, ExtractLimits::default()).unwrap();
 
         let content = fs::read_to_string(dest.join("op")).unwrap();
         assert_eq!(content, "#!/bin/sh\necho op");
@@ -629,4 +985,276 @@ mod tests {
         fs::write(&path, b"\x7fELF raw executable bytes").unwrap();
         assert!(!is_archive(&path).unwrap());
     }
+
+    #[test]
+    fn html_page_is_reported_as_text_not_archive() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("bottle.tar.gz");
+        fs::write(
+            &path,
+            b"<!DOCTYPE html>\n<html><body>Not Found</body></html>",
+        )
+        .unwrap();
+
+        let err =
+            extract_archive(&path, &tmp.path().join("out"), ExtractLimits::default()).unwrap_err();
+
+        match err {
+            Error::UnknownArchiveFormat { magic, is_text } => {
+                assert!(is_text);
+                assert!(magic.starts_with("3c 21 44 4f 43 54 59 50 45"));
+            }
+            other => panic!("expected UnknownArchiveFormat, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn unknown_binary_is_reported_with_magic_bytes() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("blob");
+        fs::write(&path, b"\x7fELF\x02\x01\x01\x00").unwrap();
+
+        let err =
+            extract_archive(&path, &tmp.path().join("out"), ExtractLimits::default()).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::UnknownArchiveFormat {
+                magic: "7f 45 4c 46 02 01 01 00".to_string(),
+                is_text: false,
+            }
+        );
+    }
+
+    #[test]
+    fn lzip_is_detected_and_rejected_clearly() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("foo-1.0.tar.lz");
+        fs::write(&path, b"LZIP\x01\x0c\x00\x00").unwrap();
+
+        assert_eq!(detect_compression(&path).unwrap(), CompressionFormat::Lzip);
+        assert_eq!(
+            extract_archive(&path, &tmp.path().join("out"), ExtractLimits::default()).unwrap_err(),
+            Error::UnsupportedArchiveFormat {
+                format: "lzip".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn aborts_once_uncompressed_size_exceeds_limit() {
+        let tmp = TempDir::new().unwrap();
+        let tarball = create_test_tarball(vec![
+            ("first.bin", &[0u8; 600], None),
+            ("second.bin", &[0u8; 600], None),
+            ("third.bin", &[0u8; 600], None),
+        ]);
+        let path = tmp.path().join("bomb.tar.gz");
+        fs::write(&path, &tarball).unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+
+        let limits = ExtractLimits {
+            max_uncompressed_bytes: 1000,
+            ..ExtractLimits::default()
+        };
+        let err = extract_archive(&path, &dest, limits).unwrap_err();
+
+        match err {
+            Error::StoreCorruption { message } => {
+                assert!(
+                    message.starts_with("archive exceeds size limit"),
+                    "{message}"
+                )
+            }
+            other => panic!("expected StoreCorruption, got {other:?}"),
+        }
+        assert!(dest.join("first.bin").exists());
+        assert!(!dest.join("second.bin").exists());
+        assert!(!dest.join("third.bin").exists());
+    }
+
+    #[test]
+    fn aborts_once_entry_count_exceeds_limit() {
+        let tmp = TempDir::new().unwrap();
+        let zip_data = create_test_zip(vec![("a", b"a"), ("b", b"b"), ("c", b"c")]);
+        let path = tmp.path().join("many.zip");
+        fs::write(&path, &zip_data).unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+
+        let limits = ExtractLimits {
+            max_entries: 2,
+            ..ExtractLimits::default()
+        };
+        let err = extract_archive(&path, &dest, limits).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::StoreCorruption { ref message } if message.starts_with("archive exceeds size limit")
+        ));
+        assert!(dest.join("b").exists());
+        assert!(!dest.join("c").exists());
+    }
+
+    fn create_zip_with_symlink(name: &str, target: &str) -> Vec<u8> {
+        use zip::write::SimpleFileOptions;
+
+        let cursor = std::io::Cursor::new(Vec::new());
+        let mut zip = zip::ZipWriter::new(cursor);
+        zip.start_file("Foo.app/lib/real.txt", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"real").unwrap();
+        zip.add_symlink(name, target, SimpleFileOptions::default())
+            .unwrap();
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn extracts_zip_symlink_as_symlink() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("app.zip");
+        fs::write(
+            &path,
+            create_zip_with_symlink("Foo.app/bin/link.txt", "../lib/real.txt"),
+        )
+        .unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+
+        extract_archive(&path, &dest, ExtractLimits::default()).unwrap();
+
+        let link = dest.join("Foo.app/bin/link.txt");
+        assert!(fs::symlink_metadata(&link).unwrap().is_symlink());
+        assert_eq!(
+            fs::read_link(&link).unwrap(),
+            PathBuf::from("../lib/real.txt")
+        );
+        assert_eq!(fs::read_to_string(&link).unwrap(), "real");
+    }
+
+    #[test]
+    fn rejects_zip_symlink_escaping_destination() {
+        let tmp = TempDir::new().unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+
+        for target in ["../../../etc/passwd", "/etc/passwd"] {
+            let path = tmp.path().join("evil.zip");
+            fs::write(&path, create_zip_with_symlink("Foo.app/evil", target)).unwrap();
+
+            let err = extract_archive(&path, &dest, ExtractLimits::default()).unwrap_err();
+
+            assert!(
+                matches!(err, Error::StoreCorruption { .. }),
+                "{target}: {err:?}"
+            );
+            assert!(fs::symlink_metadata(dest.join("Foo.app/evil")).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_zip_entries_written_through_chained_symlinks() {
+        use zip::write::SimpleFileOptions;
+
+        let cursor = std::io::Cursor::new(Vec::new());
+        let mut zip = zip::ZipWriter::new(cursor);
+        zip.add_symlink("x/l", "..", SimpleFileOptions::default())
+            .unwrap();
+        zip.add_symlink("x/l/m", "../evil", SimpleFileOptions::default())
+            .unwrap();
+        zip.start_file("x/l/m/payload", SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"owned").unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("chain.zip");
+        fs::write(&path, bytes).unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+
+        let err = extract_archive(&path, &dest, ExtractLimits::default()).unwrap_err();
+
+        assert!(matches!(err, Error::StoreCorruption { .. }), "{err:?}");
+        assert!(fs::symlink_metadata(dest.join("m")).is_err());
+        assert!(!tmp.path().join("evil").exists());
+    }
+
+    /// Serves `data` and cancels `progress` once `cancel_after` bytes are out.
+    struct CancelPartway {
+        data: std::io::Cursor<Vec<u8>>,
+        cancel_after: u64,
+        progress: ExtractProgress,
+    }
+
+    impl Read for CancelPartway {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.position() >= self.cancel_after {
+                self.progress.cancel();
+            }
+            let len = buf.len().min(4096);
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn cancelling_mid_extraction_stops_with_cancelled() {
+        let tmp = TempDir::new().unwrap();
+        let files: Vec<(String, Vec<u8>)> = (0..64)
+            .map(|i| {
+                let content = (0..16 * 1024u32)
+                    .map(|n| (n.wrapping_mul(2_654_435_761).wrapping_add(i) >> 24) as u8)
+                    .collect();
+                (format!("file-{i:02}"), content)
+            })
+            .collect();
+        let tarball = create_test_tarball(
+            files
+                .iter()
+                .map(|(path, content)| (path.as_str(), content.as_slice(), None))
+                .collect(),
+        );
+
+        let progress = ExtractProgress::new();
+        let reader = ProgressReader {
+            inner: CancelPartway {
+                cancel_after: tarball.len() as u64 / 2,
+                data: std::io::Cursor::new(tarball),
+                progress: progress.clone(),
+            },
+            progress: progress.clone(),
+        };
+
+        let err = extract_tar_archive(
+            GzDecoder::new(reader),
+            tmp.path(),
+            ExtractLimits::default(),
+            &progress,
+        )
+        .map_err(|e| progress.or_cancelled(e))
+        .unwrap_err();
+
+        assert_eq!(err, Error::Cancelled);
+        assert!(progress.compressed_bytes_read() > 0);
+        assert!(tmp.path().join("file-00").exists());
+        assert!(!tmp.path().join("file-63").exists());
+    }
+
+    #[test]
+    fn cancelled_progress_stops_extraction_before_it_starts() {
+        let tmp = TempDir::new().unwrap();
+        let path = tmp.path().join("test.tar.gz");
+        fs::write(&path, create_test_tarball(vec![("a.txt", b"a", None)])).unwrap();
+        let dest = tmp.path().join("out");
+        fs::create_dir(&dest).unwrap();
+
+        let progress = ExtractProgress::new();
+        progress.cancel();
+        let err = extract_archive_with_progress(&path, &dest, ExtractLimits::default(), &progress)
+            .unwrap_err();
+
+        assert_eq!(err, Error::Cancelled);
+        assert!(!dest.join("a.txt").exists());
+    }
 }
@@ -1,4 +1,7 @@
 pub mod extract;
 pub mod patch;
 
-pub use extract::{extract_archive, extract_tarball, extract_tarball_from_reader, is_archive};
+pub use extract::{
+    ExtractLimits, ExtractProgress, extract_archive, extract_archive_with_progress,
+    extract_tarball, extract_tarball_from_reader, is_archive,
+};
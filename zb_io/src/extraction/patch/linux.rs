@@ -9,6 +9,11 @@ use rayon::prelude::*;
 use tracing::warn;
 use zb_core::Error;
 
+use super::break_hardlink;
+use super::text::relocate_text_files;
+
+pub(crate) const LINUXBREW_PREFIX: &str = "/home/linuxbrew/.linuxbrew";
+
 /// Patch @@HOMEBREW_CELLAR@@ and @@HOMEBREW_PREFIX@@ placeholders in both ELF binaries and text files.
 #[cfg(target_os = "linux")]
 pub fn patch_placeholders(
@@ -186,6 +191,16 @@ fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Erro
         let is_readonly = original_mode & 0o200 == 0;
 
         if is_readonly {
+            // chmod would otherwise land on the store's copy too
+            if let Err(e) = break_hardlink(path) {
+                warn!(
+                    path = %path.display(),
+                    error = %e,
+                    "failed to detach hardlinked ELF for patching"
+                );
+                patch_failures.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
             let mut perms = metadata.permissions();
             perms.set_mode(original_mode | 0o200);
             if let Err(e) = fs::set_permissions(path, perms) {
@@ -298,89 +313,14 @@ fn patch_elf_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Erro
     Ok(())
 }
 
-/// Patch text files containing @@HOMEBREW_...@@ placeholders
+/// Patch text files containing @@HOMEBREW_...@@ placeholders or the default
+/// Linuxbrew build prefix (bottles built with `cellar :any_skip_relocation`
+/// keep the latter verbatim).
 fn patch_text_placeholders(keg_path: &Path, prefix_dir: &Path) -> Result<(), Error> {
     let prefix_str = prefix_dir.to_string_lossy().to_string();
     let cellar_str = prefix_dir.join("Cellar").to_string_lossy().to_string();
 
-    // We search for files that are text and contain the placeholders.
-    // To avoid reading every large file, we might filter by extension or size,
-    // but Homebrew generally patches everything that looks like text.
-    // For safety, we skip anything that looks like a binary (has null bytes in first 8kb).
-
-    let files: Vec<PathBuf> = walkdir::WalkDir::new(keg_path)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .map(|e| e.path().to_path_buf())
-        .collect();
-
-    let patch_failures = AtomicUsize::new(0);
-
-    files.par_iter().for_each(|path| {
-        let result = (|| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-            // Check if file is likely text
-            let mut file = fs::File::open(path)?;
-            let mut buf = [0u8; 8192];
-            let n = file.read(&mut buf)?;
-            if buf[..n].contains(&0) {
-                // Determine if it is ELF - we already handled those, but other binaries should be skipped too
-                return Ok(());
-            }
-
-            // Read full content string
-            let content = match fs::read_to_string(path) {
-                Ok(c) => c,
-                Err(_) => return Ok(()), // Not valid UTF-8, skip
-            };
-
-            if !content.contains("@@HOMEBREW_") {
-                return Ok(());
-            }
-
-            let new_content = content
-                .replace("@@HOMEBREW_PREFIX@@", &prefix_str)
-                .replace("@@HOMEBREW_CELLAR@@", &cellar_str)
-                .replace("@@HOMEBREW_REPOSITORY@@", &prefix_str)
-                .replace("@@HOMEBREW_LIBRARY@@", &format!("{}/Library", prefix_str))
-                .replace("@@HOMEBREW_PERL@@", "/usr/bin/perl")
-                .replace("@@HOMEBREW_JAVA@@", "/usr/bin/java");
-
-            // Write back
-            // Check readonly
-            let metadata = fs::metadata(path)?;
-            let original_mode = metadata.permissions().mode();
-            let is_readonly = original_mode & 0o200 == 0;
-
-            if is_readonly {
-                let mut perms = metadata.permissions();
-                perms.set_mode(original_mode | 0o200);
-                fs::set_permissions(path, perms)?;
-            }
-
-            fs::write(path, new_content)?;
-
-            if is_readonly {
-                let mut perms = metadata.permissions();
-                perms.set_mode(original_mode);
-                fs::set_permissions(path, perms)?;
-            }
-
-            Ok(())
-        })();
-
-        if let Err(e) = result {
-            warn!(
-                path = %path.display(),
-                error = %e,
-                "failed to patch text file"
-            );
-            patch_failures.fetch_add(1, Ordering::Relaxed);
-        }
-    });
-
-    Ok(())
+    relocate_text_files(keg_path, &prefix_str, &cellar_str, &[LINUXBREW_PREFIX])
 }
 
 #[cfg(test)]
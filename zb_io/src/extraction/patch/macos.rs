@@ -3,96 +3,16 @@ use std::path::{Path, PathBuf};
 use tracing::warn;
 use zb_core::Error;
 
-const HOMEBREW_PREFIXES: &[&str] = &[
+use super::break_hardlink;
+use super::text::relocate_text_files;
+
+pub(crate) const HOMEBREW_PREFIXES: &[&str] = &[
     "/opt/homebrew",
     "/usr/local/Homebrew",
     "/usr/local",
     "/home/linuxbrew/.linuxbrew",
 ];
 
-/// Patch hardcoded Homebrew paths in text files.
-fn patch_text_file_strings(path: &Path, new_prefix: &str, new_cellar: &str) -> Result<(), Error> {
-    use std::os::unix::fs::PermissionsExt;
-
-    let mut file = match fs::File::open(path) {
-        Ok(f) => f,
-        Err(_) => return Ok(()),
-    };
-
-    let mut buf = [0u8; 8192];
-    let n = match std::io::Read::read(&mut file, &mut buf) {
-        Ok(n) => n,
-        Err(_) => return Ok(()),
-    };
-
-    if buf[..n].contains(&0) {
-        return Ok(());
-    }
-
-    let content = match fs::read_to_string(path) {
-        Ok(c) => c,
-        Err(_) => return Ok(()),
-    };
-
-    if !content.contains("@@HOMEBREW_")
-        && !content.contains("/opt/homebrew")
-        && !content.contains("/usr/local")
-        && !content.contains("/home/linuxbrew")
-    {
-        return Ok(());
-    }
-
-    let mut new_content = content.clone();
-    let mut changed = false;
-
-    new_content = new_content
-        .replace("@@HOMEBREW_PREFIX@@", new_prefix)
-        .replace("@@HOMEBREW_CELLAR@@", new_cellar)
-        .replace("@@HOMEBREW_REPOSITORY@@", new_prefix)
-        .replace("@@HOMEBREW_LIBRARY@@", &format!("{}/Library", new_prefix))
-        .replace("@@HOMEBREW_PERL@@", "/usr/bin/perl")
-        .replace("@@HOMEBREW_JAVA@@", "/usr/bin/java");
-
-    if new_content != content {
-        changed = true;
-    }
-
-    for old_prefix in HOMEBREW_PREFIXES {
-        if old_prefix == &new_prefix {
-            continue;
-        }
-        let replaced = new_content.replace(old_prefix, new_prefix);
-        if replaced != new_content {
-            new_content = replaced;
-            changed = true;
-        }
-    }
-
-    if !changed {
-        return Ok(());
-    }
-
-    let metadata = fs::metadata(path).map_err(Error::store("failed to read metadata"))?;
-    let original_mode = metadata.permissions().mode();
-    let is_readonly = original_mode & 0o200 == 0;
-
-    if is_readonly {
-        let mut perms = metadata.permissions();
-        perms.set_mode(original_mode | 0o200);
-        fs::set_permissions(path, perms).map_err(Error::store("failed to make writable"))?;
-    }
-
-    fs::write(path, new_content).map_err(Error::store("failed to write file"))?;
-
-    if is_readonly {
-        let mut perms = metadata.permissions();
-        perms.set_mode(original_mode);
-        fs::set_permissions(path, perms).map_err(Error::store("failed to restore permissions"))?;
-    }
-
-    Ok(())
-}
-
 /// Patch hardcoded Homebrew paths in Mach-O binary data sections.
 /// This handles paths like /opt/homebrew/opt/git/libexec/git-core that are baked into binaries.
 fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error> {
@@ -104,6 +24,7 @@ fn patch_macho_binary_strings(path: &Path, new_prefix: &str) -> Result<(), Error
     let is_readonly = original_mode & 0o200 == 0;
 
     if is_readonly {
+        break_hardlink(path).map_err(Error::store("failed to detach hardlinked file"))?;
         let mut perms = metadata.permissions();
         perms.set_mode(original_mode | 0o200);
         fs::set_permissions(path, perms).map_err(Error::store("failed to make writable"))?;
@@ -289,17 +210,7 @@ pub fn patch_homebrew_placeholders(
     }
 
     // Second pass: patch text files
-    let text_files: Vec<PathBuf> = walkdir::WalkDir::new(keg_path)
-        .follow_links(false)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .map(|e| e.path().to_path_buf())
-        .collect();
-
-    text_files.par_iter().for_each(|path| {
-        let _ = patch_text_file_strings(path, &prefix_str, &cellar_str);
-    });
+    relocate_text_files(keg_path, &prefix_str, &cellar_str, HOMEBREW_PREFIXES)?;
 
     // Helper to patch a single path reference
     let patch_path = |old_path: &str| -> Option<String> {
@@ -342,6 +253,12 @@ pub fn patch_homebrew_placeholders(
 
     // Third pass: Process Mach-O files for install_name_tool patching
     macho_files.par_iter().for_each(|path| {
+        // install_name_tool and codesign edit in place
+        if break_hardlink(path).is_err() {
+            patch_failures.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
         // Get file permissions and make writable if needed
         let metadata = match fs::metadata(path) {
             Ok(m) => m,
@@ -507,6 +424,10 @@ pub fn codesign_and_strip_xattrs(keg_path: &Path) -> Result<(), Error> {
         let original_mode = metadata.permissions().mode();
         let is_readonly = original_mode & 0o200 == 0;
 
+        if break_hardlink(path).is_err() {
+            return;
+        }
+
         if is_readonly {
             let mut perms = metadata.permissions();
             perms.set_mode(original_mode | 0o200);
@@ -649,7 +570,7 @@ echo "Hello from $PREFIX"
         let new_prefix = "/opt/zerobrew/prefix";
         let new_cellar = format!("{}/Cellar", new_prefix);
 
-        let result = patch_text_file_strings(&test_file, new_prefix, &new_cellar);
+        let result = relocate_text_files(tmp.path(), new_prefix, &new_cellar, HOMEBREW_PREFIXES);
         assert!(result.is_ok());
 
         let patched = fs::read_to_string(&test_file).unwrap();
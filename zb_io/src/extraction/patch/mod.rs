@@ -4,8 +4,59 @@ pub mod linux;
 #[cfg(target_os = "macos")]
 pub mod macos;
 
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod text;
+
 #[cfg(target_os = "linux")]
 pub use linux::patch_placeholders;
 
 #[cfg(target_os = "macos")]
 pub use macos::{codesign_and_strip_xattrs, patch_homebrew_placeholders};
+
+/// Whether a bottle built with `bottle_cellar` baked in can be poured under
+/// `prefix`. Only Homebrew's own prefixes are rewritten when pouring, and on
+/// macOS a path in a binary can't grow, so `prefix` must be no longer.
+#[cfg_attr(not(target_os = "macos"), allow(unused_variables))]
+pub(crate) fn can_relocate_cellar(
+    bottle_cellar: &std::path::Path,
+    prefix: &std::path::Path,
+) -> bool {
+    let Some(bottle_prefix) = bottle_cellar
+        .file_name()
+        .filter(|name| *name == "Cellar")
+        .and(bottle_cellar.parent())
+    else {
+        return false;
+    };
+
+    #[cfg(target_os = "linux")]
+    return bottle_prefix == std::path::Path::new(linux::LINUXBREW_PREFIX);
+
+    #[cfg(target_os = "macos")]
+    return macos::HOMEBREW_PREFIXES
+        .iter()
+        .any(|known| bottle_prefix == std::path::Path::new(known))
+        && prefix.as_os_str().len() <= bottle_prefix.as_os_str().len();
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    false
+}
+
+/// Give `path` an inode of its own if it is hardlinked (typically to the
+/// store) so that an in-place edit can't reach the other links. The copy
+/// keeps the file's permissions.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub(crate) fn break_hardlink(path: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    if std::fs::symlink_metadata(path)?.nlink() <= 1 {
+        return Ok(());
+    }
+
+    let temp_path = path.with_extension("tmp_unlink");
+    if let Err(e) = std::fs::copy(path, &temp_path) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    std::fs::rename(&temp_path, path)
+}
@@ -0,0 +1,209 @@
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rayon::prelude::*;
+use tracing::warn;
+use zb_core::Error;
+
+use super::break_hardlink;
+
+/// Rewrite Homebrew placeholder tokens and hardcoded build prefixes in every
+/// text file under `keg_path` so scripts, pkg-config files and the like point
+/// at our prefix. Binary files (anything with a NUL in the first 8 KiB) and
+/// non-UTF-8 files are left alone. Failures on individual files are logged
+/// and do not abort the pass.
+pub(crate) fn relocate_text_files(
+    keg_path: &Path,
+    prefix: &str,
+    cellar: &str,
+    build_prefixes: &[&str],
+) -> Result<(), Error> {
+    let files: Vec<PathBuf> = walkdir::WalkDir::new(keg_path)
+        .follow_links(false)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().to_path_buf())
+        .collect();
+
+    let failures = AtomicUsize::new(0);
+
+    files.par_iter().for_each(|path| {
+        if let Err(e) = relocate_text_file(path, prefix, cellar, build_prefixes) {
+            warn!(
+                path = %path.display(),
+                error = %e,
+                "failed to relocate text file"
+            );
+            failures.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    let failures = failures.load(Ordering::Relaxed);
+    if failures > 0 {
+        warn!(
+            failures,
+            keg = %keg_path.display(),
+            "some text files could not be relocated"
+        );
+    }
+
+    Ok(())
+}
+
+fn relocate_text_file(
+    path: &Path,
+    prefix: &str,
+    cellar: &str,
+    build_prefixes: &[&str],
+) -> Result<(), Error> {
+    let mut file = fs::File::open(path).map_err(Error::store("failed to open file"))?;
+    let mut buf = [0u8; 8192];
+    let n = file
+        .read(&mut buf)
+        .map_err(Error::store("failed to read file"))?;
+    if buf[..n].contains(&0) {
+        return Ok(());
+    }
+    drop(file);
+
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(_) => return Ok(()),
+    };
+
+    let Some(new_content) = relocate_text(&content, prefix, cellar, build_prefixes) else {
+        return Ok(());
+    };
+
+    break_hardlink(path).map_err(Error::store("failed to detach hardlinked file"))?;
+
+    let metadata = fs::metadata(path).map_err(Error::store("failed to read metadata"))?;
+    let original_mode = metadata.permissions().mode();
+    let is_readonly = original_mode & 0o200 == 0;
+
+    if is_readonly {
+        let mut perms = metadata.permissions();
+        perms.set_mode(original_mode | 0o200);
+        fs::set_permissions(path, perms).map_err(Error::store("failed to make writable"))?;
+    }
+
+    fs::write(path, new_content).map_err(Error::store("failed to write file"))?;
+
+    if is_readonly {
+        let mut perms = metadata.permissions();
+        perms.set_mode(original_mode);
+        fs::set_permissions(path, perms).map_err(Error::store("failed to restore permissions"))?;
+    }
+
+    Ok(())
+}
+
+/// Return the relocated content, or `None` when nothing needed rewriting.
+fn relocate_text(
+    content: &str,
+    prefix: &str,
+    cellar: &str,
+    build_prefixes: &[&str],
+) -> Option<String> {
+    let needs_work = content.contains("@@HOMEBREW_")
+        || build_prefixes
+            .iter()
+            .any(|old| *old != prefix && content.contains(old));
+    if !needs_work {
+        return None;
+    }
+
+    let mut new_content = content
+        .replace("@@HOMEBREW_PREFIX@@", prefix)
+        .replace("@@HOMEBREW_CELLAR@@", cellar)
+        .replace("@@HOMEBREW_REPOSITORY@@", prefix)
+        .replace("@@HOMEBREW_LIBRARY@@", &format!("{prefix}/Library"))
+        .replace("@@HOMEBREW_PERL@@", "/usr/bin/perl")
+        .replace("@@HOMEBREW_JAVA@@", "/usr/bin/java");
+
+    for old_prefix in build_prefixes {
+        if *old_prefix == prefix {
+            continue;
+        }
+        new_content = new_content.replace(old_prefix, prefix);
+    }
+
+    (new_content != content).then_some(new_content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn relocate_text_replaces_placeholders_and_build_prefix() {
+        let content = "prefix=/home/linuxbrew/.linuxbrew\nlibdir=@@HOMEBREW_CELLAR@@/zlib/lib\n";
+
+        let relocated = relocate_text(
+            content,
+            "/opt/zerobrew/prefix",
+            "/opt/zerobrew/prefix/Cellar",
+            &["/home/linuxbrew/.linuxbrew"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            relocated,
+            "prefix=/opt/zerobrew/prefix\nlibdir=/opt/zerobrew/prefix/Cellar/zlib/lib\n"
+        );
+    }
+
+    #[test]
+    fn relocate_text_skips_content_without_matches() {
+        assert!(
+            relocate_text(
+                "echo hello\n",
+                "/opt/zerobrew/prefix",
+                "/opt/zerobrew/prefix/Cellar",
+                &["/opt/homebrew"],
+            )
+            .is_none()
+        );
+        assert!(
+            relocate_text(
+                "prefix=/opt/zerobrew/prefix\n",
+                "/opt/zerobrew/prefix",
+                "/opt/zerobrew/prefix/Cellar",
+                &["/opt/zerobrew/prefix"],
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn relocate_text_files_patches_readonly_pc_file_and_skips_binaries() {
+        let tmp = TempDir::new().unwrap();
+        let keg = tmp.path().join("zlib/1.3.1");
+        fs::create_dir_all(keg.join("lib/pkgconfig")).unwrap();
+
+        let pc = keg.join("lib/pkgconfig/zlib.pc");
+        fs::write(&pc, "prefix=@@HOMEBREW_PREFIX@@/opt/zlib\n").unwrap();
+        fs::set_permissions(&pc, fs::Permissions::from_mode(0o444)).unwrap();
+
+        let blob = keg.join("lib/libz.a");
+        let binary = b"\0\0@@HOMEBREW_PREFIX@@\0".to_vec();
+        fs::write(&blob, &binary).unwrap();
+
+        relocate_text_files(&keg, "/opt/zb", "/opt/zb/Cellar", &[]).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&pc).unwrap(),
+            "prefix=/opt/zb/opt/zlib\n"
+        );
+        assert_eq!(
+            fs::metadata(&pc).unwrap().permissions().mode() & 0o777,
+            0o444
+        );
+        assert_eq!(fs::read(&blob).unwrap(), binary);
+    }
+}
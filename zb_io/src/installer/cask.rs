@@ -1,3 +1,5 @@
+use std::path::{Component, Path, PathBuf};
+
 use serde_json::Value;
 use zb_core::Error;
 
@@ -15,6 +17,86 @@ pub struct ResolvedCask {
     pub url: String,
     pub sha256: String,
     pub binaries: Vec<CaskBinary>,
+    pub zap: Vec<CaskZap>,
+}
+
+/// A path from a cask's `zap` stanza, as written in the cask JSON
+/// (e.g. `~/.config/ghostty/`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaskZap {
+    pub path: String,
+    /// `rmdir` entries are only removed when empty; `trash` and `delete`
+    /// entries are removed with their contents.
+    pub only_if_empty: bool,
+}
+
+impl ResolvedCask {
+    /// Where a binary artifact lives relative to the unpacked download.
+    /// Sources under `$APPDIR`, absolute paths and `..` are refused.
+    pub fn binary_source_path(&self, source: &str) -> Result<PathBuf, Error> {
+        if source.starts_with("$APPDIR") {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "cask '{}' uses APPDIR artifacts which are not supported yet",
+                    self.token
+                ),
+            });
+        }
+
+        let caskroom_prefix = format!("$HOMEBREW_PREFIX/Caskroom/{}/{}/", self.token, self.version);
+        let source_path = Path::new(source.strip_prefix(&caskroom_prefix).unwrap_or(source));
+        if source_path.is_absolute() {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "cask '{}' binary source '{}' must be a relative path",
+                    self.token, source
+                ),
+            });
+        }
+
+        if source_path
+            .components()
+            .any(|component| matches!(component, Component::ParentDir))
+        {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "cask '{}' binary source '{}' cannot contain '..'",
+                    self.token, source
+                ),
+            });
+        }
+
+        Ok(source_path.to_path_buf())
+    }
+
+    /// Keep only the binaries whose target is in `targets`. Selecting none
+    /// of the declared binaries is an error rather than an empty keg.
+    pub fn retain_binaries(&mut self, targets: &[String]) -> Result<(), Error> {
+        let available: Vec<String> = self.binaries.iter().map(|b| b.target.clone()).collect();
+        self.binaries
+            .retain(|binary| targets.contains(&binary.target));
+        if self.binaries.is_empty() {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "cask '{}' has no binaries matching {} (available: {})",
+                    self.token,
+                    targets.join(", "),
+                    available.join(", ")
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Binary artifacts that would fail to stage, described the way the
+    /// install would report them. Empty when every binary is supported.
+    pub fn staging_warnings(&self) -> Vec<String> {
+        self.binaries
+            .iter()
+            .filter_map(|binary| self.binary_source_path(&binary.source).err())
+            .map(|e| e.to_string())
+            .collect()
+    }
 }
 
 pub fn resolve_cask(token: &str, cask: &Value) -> Result<ResolvedCask, Error> {
@@ -55,6 +137,7 @@ pub fn resolve_cask(token: &str, cask: &Value) -> Result<ResolvedCask, Error> {
         url,
         sha256,
         binaries,
+        zap: parse_zap_artifacts(cask),
     })
 }
 
@@ -180,6 +263,79 @@ fn parse_binary_entry(entry: &Value) -> Result<(String, String), Error> {
     Ok((source.to_string(), target))
 }
 
+/// Collect the `trash`, `delete` and `rmdir` paths of every `zap` artifact.
+/// Other zap directives (`launchctl`, `pkgutil`, `quit`, ...) are ignored.
+pub fn parse_zap_artifacts(cask: &Value) -> Vec<CaskZap> {
+    let mut zap = Vec::new();
+    let artifacts = cask
+        .get("artifacts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+
+    for stanza in artifacts
+        .filter_map(|a| a.get("zap"))
+        .filter_map(Value::as_array)
+        .flatten()
+    {
+        for (directive, only_if_empty) in [("trash", false), ("delete", false), ("rmdir", true)] {
+            let paths = match stanza.get(directive) {
+                Some(Value::String(path)) => vec![path.as_str()],
+                Some(Value::Array(paths)) => paths.iter().filter_map(Value::as_str).collect(),
+                _ => continue,
+            };
+            zap.extend(paths.into_iter().map(|path| CaskZap {
+                path: path.to_string(),
+                only_if_empty,
+            }));
+        }
+    }
+
+    zap
+}
+
+/// Expand a zap path against `home` and refuse anything that could reach
+/// outside the user's own data: globs, variables, `..` components, and
+/// locations other than below the home directory or the app folders.
+pub fn resolve_zap_path(token: &str, raw: &str, home: Option<&Path>) -> Result<PathBuf, Error> {
+    let refuse = |reason: &str| Error::InvalidArgument {
+        message: format!("cask '{token}' zap path '{raw}' {reason}"),
+    };
+
+    if raw.contains(['*', '?', '[', '$']) {
+        return Err(refuse("uses globs or variables which are not supported"));
+    }
+
+    let path = match raw.strip_prefix("~/") {
+        Some(rest) => home
+            .ok_or_else(|| refuse("needs a home directory but HOME is not set"))?
+            .join(rest),
+        None => PathBuf::from(raw),
+    };
+
+    if !path.is_absolute() {
+        return Err(refuse("must be absolute or start with '~/'"));
+    }
+    if path
+        .components()
+        .any(|component| matches!(component, Component::ParentDir))
+    {
+        return Err(refuse("cannot contain '..'"));
+    }
+
+    let allowed = home
+        .into_iter()
+        .chain(ZAP_ROOTS.iter().map(Path::new))
+        .any(|root| path.starts_with(root) && path != root);
+    if !allowed {
+        return Err(refuse("is outside the home and application directories"));
+    }
+
+    Ok(path)
+}
+
+const ZAP_ROOTS: &[&str] = &["/Applications", "/Library"];
+
 fn basename(path: &str) -> Result<String, Error> {
     let name = std::path::Path::new(path)
         .file_name()
@@ -287,4 +443,134 @@ mod tests {
         assert!(msg.contains("app"), "got: {msg}");
         assert!(msg.contains("zap"), "got: {msg}");
     }
+
+    #[test]
+    fn resolve_cask_collects_zap_paths() {
+        let cask = serde_json::json!({
+            "token": "ghostty",
+            "version": "1.0.0",
+            "url": "https://example.com/ghostty",
+            "sha256": "aaaa",
+            "artifacts": [
+                { "binary": ["ghostty"] },
+                { "zap": [
+                    { "trash": ["~/.config/ghostty/", "~/Library/Caches/ghostty"] },
+                    { "rmdir": "~/Library/Application Support/ghostty", "launchctl": "x" }
+                ] }
+            ]
+        });
+
+        let resolved = resolve_cask("ghostty", &cask).unwrap();
+        assert_eq!(
+            resolved.zap,
+            vec![
+                CaskZap {
+                    path: "~/.config/ghostty/".to_string(),
+                    only_if_empty: false,
+                },
+                CaskZap {
+                    path: "~/Library/Caches/ghostty".to_string(),
+                    only_if_empty: false,
+                },
+                CaskZap {
+                    path: "~/Library/Application Support/ghostty".to_string(),
+                    only_if_empty: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_zap_path_refuses_paths_outside_allowed_roots() {
+        let home = Path::new("/home/alice");
+
+        assert_eq!(
+            resolve_zap_path("t", "~/.config/t/", Some(home)).unwrap(),
+            home.join(".config/t/")
+        );
+        assert_eq!(
+            resolve_zap_path("t", "/Applications/T.app", Some(home)).unwrap(),
+            PathBuf::from("/Applications/T.app")
+        );
+
+        for raw in [
+            "~/../bob/.ssh",
+            "~/",
+            "/etc/passwd",
+            "/Applications",
+            "relative/path",
+            "~/.config/t*",
+            "$HOME/.config/t",
+        ] {
+            assert!(
+                resolve_zap_path("t", raw, Some(home)).is_err(),
+                "accepted {raw}"
+            );
+        }
+        assert!(resolve_zap_path("t", "~/.config/t", None).is_err());
+    }
+
+    #[test]
+    fn staging_warnings_flag_unsupported_binary_sources() {
+        let cask = serde_json::json!({
+            "token": "tool",
+            "version": "2.0",
+            "url": "https://example.com/tool.zip",
+            "sha256": "aaaa",
+            "artifacts": [
+                { "binary": [
+                    "$HOMEBREW_PREFIX/Caskroom/tool/2.0/bin/tool",
+                    "$APPDIR/Tool.app/Contents/MacOS/tool",
+                    "/usr/local/bin/other",
+                    "../escape"
+                ] }
+            ]
+        });
+
+        let resolved = resolve_cask("tool", &cask).unwrap();
+        assert_eq!(
+            resolved
+                .binary_source_path(&resolved.binaries[0].source)
+                .unwrap(),
+            PathBuf::from("bin/tool")
+        );
+
+        let warnings = resolved.staging_warnings();
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].contains("APPDIR"), "{warnings:?}");
+        assert!(warnings[1].contains("relative path"), "{warnings:?}");
+        assert!(warnings[2].contains("'..'"), "{warnings:?}");
+    }
+
+    #[test]
+    fn retain_binaries_filters_by_target_and_rejects_empty_selection() {
+        let cask = serde_json::json!({
+            "token": "suite",
+            "version": "1.0",
+            "url": "https://example.com/suite.zip",
+            "sha256": "aaaa",
+            "artifacts": [
+                { "binary": ["bin/alpha", ["bin/beta", { "target": "b" }], "bin/gamma"] }
+            ]
+        });
+        let resolved = resolve_cask("suite", &cask).unwrap();
+
+        let mut selected = resolved.clone();
+        selected
+            .retain_binaries(&["alpha".to_string(), "b".to_string()])
+            .unwrap();
+        let targets: Vec<&str> = selected
+            .binaries
+            .iter()
+            .map(|b| b.target.as_str())
+            .collect();
+        assert_eq!(targets, vec!["alpha", "b"]);
+
+        let mut none = resolved;
+        let err = none
+            .retain_binaries(&["beta".to_string()])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("available: alpha, b, gamma"), "{err}");
+    }
 }
@@ -1,133 +1,103 @@
 use std::fs;
-use std::path::Path;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
+use sha2::{Digest, Sha256};
 use tracing::warn;
-use zb_core::{Error, InstallMethod, formula_token};
+use zb_core::{
+    CellarRequirement, Error, Formula, InstallMethod, LogLevel, LoggerHandle, SelectedBottle,
+    formula_token,
+};
 
-use crate::cellar::link::Linker;
+use crate::cellar::link::{LinkedFile, Linker};
 use crate::cellar::materialize::Cellar;
-use crate::installer::cask::resolve_cask;
-use crate::network::download::{DownloadProgressCallback, DownloadRequest, DownloadResult};
+use crate::installer::cask::{ResolvedCask, resolve_cask};
+use crate::network::download::{
+    DownloadProgressCallback, DownloadRequest, DownloadResult, ParallelDownloader,
+};
 use crate::progress::InstallProgress;
+use crate::storage::db::InstallTransaction;
+use crate::storage::store::Store;
+
+use super::{
+    ExecuteResult, Installer, KegIdentity, MAX_CORRUPTION_RETRIES, OutdatedPackage, PlannedInstall,
+};
+
+/// The extraction half of a bottle install, detached from `&Installer` so
+/// several kegs can unpack on blocking threads while DB commits and linking
+/// stay serialized on the installer.
+#[derive(Clone)]
+pub(super) struct BottleExtractor {
+    store: Store,
+    cellar: Cellar,
+    downloader: ParallelDownloader,
+    logger: LoggerHandle,
+}
 
-use super::{Installer, MAX_CORRUPTION_RETRIES, PlannedInstall};
-
-impl Installer {
-    pub(super) async fn process_bottle_item(
-        &mut self,
-        item: &PlannedInstall,
+impl BottleExtractor {
+    /// Unpack the downloaded bottle into the store (re-downloading on
+    /// corruption) and materialize its keg, returning the keg path.
+    pub(super) async fn extract(
+        &self,
+        formula: &Formula,
+        keg: &KegIdentity,
+        bottle: &SelectedBottle,
         download: &DownloadResult,
-        download_progress: &Option<DownloadProgressCallback>,
-        link: bool,
-        report: &impl Fn(InstallProgress),
-    ) -> Result<(), Error> {
-        let InstallMethod::Bottle(ref bottle) = item.method else {
-            unreachable!()
-        };
-        let install_name = &item.install_name;
-        let formula_name = &item.formula.name;
-        let version = item.formula.effective_version();
-        let store_key = &bottle.sha256;
-
-        report(InstallProgress::UnpackStarted {
-            name: formula_name.clone(),
-        });
-
+        progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
         let store_entry = self
-            .extract_with_retry(download, &item.formula, bottle, download_progress.clone())
+            .extract_with_retry(download, formula, bottle, progress)
             .await?;
 
-        let keg_path = self
-            .cellar
-            .materialize(formula_name, &version, &store_entry)?;
-
-        report(InstallProgress::UnpackCompleted {
-            name: formula_name.clone(),
-        });
-
-        let tx = self.db.transaction().inspect_err(|_| {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-        })?;
-
-        tx.record_install(install_name, &version, store_key)
-            .inspect_err(|_| {
-                Self::cleanup_materialized(&self.cellar, formula_name, &version);
-            })?;
-
-        tx.commit().inspect_err(|_| {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-        })?;
-
-        if let Err(e) = self.linker.link_opt(&keg_path) {
-            warn!(formula = %install_name, error = %e, "failed to create opt link");
-        }
-
-        if link && !item.formula.is_keg_only() {
-            report(InstallProgress::LinkStarted {
-                name: formula_name.clone(),
-            });
-            match self.linker.link_keg(&keg_path) {
-                Ok(linked_files) => {
-                    report(InstallProgress::LinkCompleted {
-                        name: formula_name.clone(),
-                    });
-                    self.record_linked_files(install_name, &version, &linked_files);
-                }
-                Err(e) => {
-                    let _ = self.linker.unlink_keg(&keg_path);
-                    report(InstallProgress::InstallCompleted {
-                        name: formula_name.clone(),
-                    });
-                    return Err(e);
-                }
-            }
-        } else if link && item.formula.is_keg_only() {
-            let reason = match &item.formula.keg_only {
-                zb_core::KegOnly::Reason(s) => s.clone(),
-                _ if formula_name.contains('@') => "versioned formula".to_string(),
-                _ => "keg-only formula".to_string(),
-            };
-            report(InstallProgress::LinkSkipped {
-                name: formula_name.clone(),
-                reason,
-            });
-        }
-
-        report(InstallProgress::InstallCompleted {
-            name: formula_name.clone(),
-        });
-
-        Ok(())
+        let cellar = self.cellar.clone();
+        let name = keg.keg_name.clone();
+        let version = keg.version.clone();
+        run_blocking(move || cellar.materialize(&name, &version, &store_entry)).await
     }
 
     async fn extract_with_retry(
         &self,
         download: &DownloadResult,
-        formula: &zb_core::Formula,
-        bottle: &zb_core::SelectedBottle,
+        formula: &Formula,
+        bottle: &SelectedBottle,
         progress: Option<DownloadProgressCallback>,
-    ) -> Result<std::path::PathBuf, Error> {
+    ) -> Result<PathBuf, Error> {
         let mut blob_path = download.blob_path.clone();
         let mut last_error = None;
 
         for attempt in 0..MAX_CORRUPTION_RETRIES {
-            match self.store.ensure_entry(&bottle.sha256, &blob_path) {
+            let store = self.store.clone();
+            let store_key = bottle.sha256.clone();
+            let attempt_blob = blob_path.clone();
+            match run_blocking(move || store.ensure_entry(&store_key, &attempt_blob)).await {
                 Ok(entry) => return Ok(entry),
                 Err(Error::StoreCorruption { message }) => {
                     self.downloader.remove_blob(&bottle.sha256);
 
                     if attempt + 1 < MAX_CORRUPTION_RETRIES {
-                        warn!(
-                            formula = %formula.name,
-                            attempt = attempt + 2,
-                            max_retries = MAX_CORRUPTION_RETRIES,
-                            "corrupted download detected; retrying"
-                        );
+                        if let Some(cb) = &progress {
+                            cb(InstallProgress::ExtractRetrying {
+                                name: formula.name.clone(),
+                                attempt: attempt as u32 + 2,
+                                max: MAX_CORRUPTION_RETRIES as u32,
+                                reason: message.clone(),
+                            });
+                        }
+                        if self.logger.enabled(LogLevel::Debug) {
+                            warn!(
+                                formula = %formula.name,
+                                attempt = attempt + 2,
+                                max_retries = MAX_CORRUPTION_RETRIES,
+                                "corrupted download detected; retrying"
+                            );
+                        }
 
                         let request = DownloadRequest {
                             url: bottle.url.clone(),
                             sha256: bottle.sha256.clone(),
                             name: formula.name.clone(),
+                            priority: 0,
+                            mirror_urls: bottle.mirror_urls.clone(),
                         };
 
                         match self
@@ -162,32 +132,181 @@ impl Installer {
             message: "extraction failed with unknown error".to_string(),
         }))
     }
+}
+
+async fn run_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(Error::exec("extraction task failed"))?
+}
 
-    fn record_linked_files(
+/// Record `linked_files` for one keg inside `tx`. The rows go through a
+/// savepoint, so either all of them land in the enclosing transaction or
+/// none do.
+fn record_links(
+    tx: &mut InstallTransaction<'_>,
+    name: &str,
+    version: &str,
+    linked_files: &[LinkedFile],
+) -> Result<(), Error> {
+    let savepoint = tx.savepoint()?;
+    for linked in linked_files {
+        savepoint.record_linked_file(
+            name,
+            version,
+            &linked.link_path.to_string_lossy(),
+            &linked.target_path.to_string_lossy(),
+            linked
+                .backup_path
+                .as_ref()
+                .map(|p| p.to_string_lossy())
+                .as_deref(),
+        )?;
+    }
+    savepoint.commit()
+}
+
+impl Installer {
+    pub(super) fn bottle_extractor(&self) -> BottleExtractor {
+        BottleExtractor {
+            store: self.store.clone(),
+            cellar: self.cellar.clone(),
+            downloader: self.downloader.clone(),
+            logger: self.logger.clone(),
+        }
+    }
+
+    /// Handle a planned formula that is already installed: report it, and if
+    /// it was requested by name make sure it is explicit and, unless keg-only,
+    /// linked. Nothing is downloaded or re-materialized.
+    pub(super) fn finish_already_installed(
         &mut self,
-        name: &str,
-        version: &str,
-        linked_files: &[crate::cellar::link::LinkedFile],
-    ) {
-        if let Ok(tx) = self.db.transaction() {
-            let mut ok = true;
-            for linked in linked_files {
-                if tx
-                    .record_linked_file(
-                        name,
-                        version,
-                        &linked.link_path.to_string_lossy(),
-                        &linked.target_path.to_string_lossy(),
-                    )
-                    .is_err()
-                {
-                    ok = false;
-                    break;
+        item: &PlannedInstall,
+        link: bool,
+        report: &impl Fn(InstallProgress),
+    ) -> Result<(), Error> {
+        if !item.explicit {
+            return Ok(());
+        }
+
+        let KegIdentity {
+            keg_name, version, ..
+        } = item.keg_identity();
+        report(InstallProgress::AlreadyInstalled {
+            name: item.formula.name.clone(),
+            version: version.clone(),
+        });
+
+        if let Some(keg) = self.db.get_installed(&item.install_name)
+            && !keg.is_explicit
+        {
+            let tx = self.db.transaction()?;
+            tx.record_install(&item.install_name, &keg.version, &keg.store_key, true)?;
+            tx.commit()?;
+        }
+
+        let keg_path = self.cellar.keg_path(&keg_name, &version);
+        if !link || item.formula.is_keg_only() || self.linker.is_linked(&keg_path) {
+            return Ok(());
+        }
+
+        report(InstallProgress::LinkStarted {
+            name: item.formula.name.clone(),
+        });
+        let linked_files = self.linker.link_keg(&keg_path)?;
+        self.record_linked_files(&item.install_name, &version, &linked_files);
+        report(InstallProgress::LinkCompleted {
+            name: item.formula.name.clone(),
+        });
+
+        Ok(())
+    }
+
+    /// Link a materialized keg, then record the install and every linked
+    /// file in one transaction so the DB never holds half of a formula. If
+    /// the bookkeeping fails the links and keg are removed again. Shared by
+    /// bottle and source installs; runs on the installer itself so commits
+    /// happen one at a time, in completion order.
+    pub(super) fn commit_keg(
+        &mut self,
+        item: &PlannedInstall,
+        keg_path: &Path,
+        link: bool,
+        report: &impl Fn(InstallProgress),
+    ) -> Result<(), Error> {
+        let install_name = &item.install_name;
+        let formula_name = &item.formula.name;
+        let KegIdentity {
+            keg_name,
+            version,
+            store_key,
+        } = item.keg_identity();
+
+        if let Err(e) = self.linker.link_opt(keg_path) {
+            warn!(formula = %install_name, error = %e, "failed to create opt link");
+        }
+
+        let mut linked_files = Vec::new();
+        let mut link_error = None;
+        if link && !item.formula.is_keg_only() {
+            report(InstallProgress::LinkStarted {
+                name: formula_name.clone(),
+            });
+            match self.linker.link_keg(keg_path) {
+                Ok(files) => {
+                    report(InstallProgress::LinkCompleted {
+                        name: formula_name.clone(),
+                    });
+                    linked_files = files;
                 }
+                Err(e) => link_error = Some(e),
             }
-            if ok {
-                let _ = tx.commit();
-            }
+        } else if link && item.formula.is_keg_only() {
+            let reason = match &item.formula.keg_only {
+                zb_core::KegOnly::Reason(s) => s.clone(),
+                _ if formula_name.contains('@') => "versioned formula".to_string(),
+                _ => "keg-only formula".to_string(),
+            };
+            report(InstallProgress::LinkSkipped {
+                name: formula_name.clone(),
+                reason,
+            });
+        }
+
+        let recorded = self.db.transaction().and_then(|mut tx| {
+            tx.record_install(install_name, &version, &store_key, item.explicit)?;
+            record_links(&mut tx, install_name, &version, &linked_files)?;
+            tx.commit()
+        });
+        if let Err(e) = recorded {
+            Self::cleanup_failed_install(
+                &self.linker,
+                &self.cellar,
+                &keg_name,
+                &version,
+                keg_path,
+                true,
+            );
+            return Err(e);
+        }
+
+        report(InstallProgress::InstallCompleted {
+            name: formula_name.clone(),
+        });
+
+        match link_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    fn record_linked_files(&mut self, name: &str, version: &str, linked_files: &[LinkedFile]) {
+        if let Ok(mut tx) = self.db.transaction()
+            && record_links(&mut tx, name, version, linked_files).is_ok()
+        {
+            let _ = tx.commit();
         }
     }
 
@@ -218,13 +337,98 @@ impl Installer {
         }
     }
 
+    /// Install a bottle tarball that is already on disk instead of
+    /// downloading it. The formula is named by the bottle's file name
+    /// (`<name>--<version>.<tag>.bottle.tar.gz`) and its metadata and
+    /// dependencies still come from the API; only the bottle itself is local.
+    pub async fn install_local(
+        &mut self,
+        path: &Path,
+        expected_sha256: &str,
+    ) -> Result<ExecuteResult, Error> {
+        let name = bottle_formula_name(path)?;
+
+        let actual = sha256_file(path)?;
+        if actual != expected_sha256 {
+            return Err(Error::ChecksumMismatch {
+                expected: expected_sha256.to_string(),
+                actual,
+            });
+        }
+
+        let mut plan = self.plan(std::slice::from_ref(&name)).await?;
+        if plan
+            .already_installed
+            .iter()
+            .any(|item| item.install_name == name)
+        {
+            return self.execute(plan, true).await;
+        }
+        let item = plan
+            .items
+            .iter_mut()
+            .find(|item| item.install_name == name)
+            .ok_or_else(|| Error::MissingFormula { name: name.clone() })?;
+
+        let (tag, cellar) = match &item.method {
+            InstallMethod::Bottle(bottle) if bottle.sha256 != expected_sha256 => {
+                return Err(Error::InvalidArgument {
+                    message: format!(
+                        "local bottle {} does not match the {} bottle published for '{name}' (expected sha256 {})",
+                        path.display(),
+                        bottle.tag,
+                        bottle.sha256
+                    ),
+                });
+            }
+            InstallMethod::Bottle(bottle) => (bottle.tag.clone(), bottle.cellar.clone()),
+            InstallMethod::Source(_) => ("local".to_string(), CellarRequirement::default()),
+        };
+        item.method = InstallMethod::Bottle(SelectedBottle {
+            tag,
+            url: format!("file://{}", path.display()),
+            sha256: expected_sha256.to_string(),
+            rebuild: item.formula.bottle.stable.rebuild,
+            mirror_urls: Vec::new(),
+            cellar,
+        });
+
+        let blob_cache = self.downloader.blob_cache();
+        if !blob_cache.has_blob(expected_sha256) {
+            let mut source = fs::File::open(path).map_err(Error::file("failed to open bottle"))?;
+            let mut writer = blob_cache
+                .start_write(expected_sha256)
+                .map_err(Error::store("failed to create blob writer"))?;
+            io::copy(&mut source, &mut writer)
+                .map_err(Error::store("failed to copy bottle into cache"))?;
+            writer
+                .flush()
+                .map_err(Error::store("failed to flush bottle"))?;
+            writer.commit()?;
+        }
+
+        self.execute(plan, true).await
+    }
+
+    /// Fetch and resolve a cask without installing it, e.g. to show what it
+    /// would stage. See [`ResolvedCask::staging_warnings`] for binaries the
+    /// install would reject.
+    pub async fn cask_info(&self, token: &str) -> Result<ResolvedCask, Error> {
+        let token = token.strip_prefix("cask:").unwrap_or(token);
+        let cask_json = self.api_client.get_cask(token).await?;
+        resolve_cask(token, &cask_json)
+    }
+
     pub(super) async fn install_single_cask(
         &mut self,
         token: &str,
         link: bool,
     ) -> Result<(), Error> {
         let cask_json = self.api_client.get_cask(token).await?;
-        let cask = resolve_cask(token, &cask_json)?;
+        let mut cask = resolve_cask(token, &cask_json)?;
+        if let Some(targets) = &self.cask_binaries {
+            cask.retain_binaries(targets)?;
+        }
 
         let blob_path = self
             .downloader
@@ -233,6 +437,8 @@ impl Installer {
                     url: cask.url.clone(),
                     sha256: cask.sha256.clone(),
                     name: cask.install_name.clone(),
+                    priority: 0,
+                    mirror_urls: Vec::new(),
                 },
                 None,
             )
@@ -262,13 +468,14 @@ impl Installer {
         };
 
         let tx = self.db.transaction()?;
-        tx.record_install(&cask.install_name, &cask.version, &cask.sha256)?;
+        tx.record_install(&cask.install_name, &cask.version, &cask.sha256, true)?;
         for linked in &linked_files {
             tx.record_linked_file(
                 &cask.install_name,
                 &cask.version,
                 &linked.link_path.to_string_lossy(),
                 &linked.target_path.to_string_lossy(),
+                None,
             )?;
         }
         tx.commit()?;
@@ -276,6 +483,60 @@ impl Installer {
         cleanup.disarm();
         Ok(())
     }
+
+    /// Upgrade an installed cask to what the cask API currently serves: move
+    /// its links from the old keg to freshly staged binaries, then remove the
+    /// old keg. If the new version fails to install, the old keg is relinked.
+    /// Returns `None` when the cask is already current.
+    pub async fn upgrade_cask(
+        &mut self,
+        name: &str,
+        link: bool,
+    ) -> Result<Option<OutdatedPackage>, Error> {
+        let Some(token) = name.strip_prefix("cask:") else {
+            return Err(Error::InvalidArgument {
+                message: format!("'{name}' is not a cask; expected a cask: prefixed name"),
+            });
+        };
+        let Some(outdated) = self.is_outdated(name).await? else {
+            return Ok(None);
+        };
+
+        let old_version = outdated.installed_version.clone();
+        let old_keg = self.cellar.keg_path(name, &old_version);
+        let same_version = outdated.current_version == old_version;
+
+        self.linker.unlink_keg(&old_keg)?;
+        {
+            let tx = self.db.transaction()?;
+            tx.clear_keg_file_records(name)?;
+            tx.commit()?;
+        }
+        // A re-released cask keeps its version, so the new keg lands on the
+        // same path and the old one cannot be kept around for rollback.
+        if same_version {
+            self.cellar.remove_keg(name, &old_version)?;
+        }
+
+        if let Err(e) = self.install_single_cask(token, link).await {
+            if link
+                && old_keg.exists()
+                && let Ok(linked_files) = self.linker.link_keg(&old_keg)
+            {
+                self.record_linked_files(name, &old_version, &linked_files);
+            }
+            return Err(e);
+        }
+
+        if !same_version {
+            let tx = self.db.transaction()?;
+            tx.delete_version_record(name, &old_version)?;
+            tx.commit()?;
+            self.cellar.remove_keg(name, &old_version)?;
+        }
+
+        Ok(Some(outdated))
+    }
 }
 
 pub(super) fn dependency_cellar_path(
@@ -289,6 +550,27 @@ pub(super) fn dependency_cellar_path(
         .to_string()
 }
 
+fn bottle_formula_name(path: &Path) -> Result<String, Error> {
+    path.file_name()
+        .and_then(|f| f.to_str())
+        .and_then(|f| f.split_once("--"))
+        .map(|(name, _)| name.to_string())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| Error::InvalidArgument {
+            message: format!(
+                "cannot tell which formula {} belongs to; expected a file named <name>--<version>.<tag>.bottle.tar.gz",
+                path.display()
+            ),
+        })
+}
+
+fn sha256_file(path: &Path) -> Result<String, Error> {
+    let mut file = fs::File::open(path).map_err(Error::file("failed to open bottle"))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).map_err(Error::file("failed to read bottle"))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 struct FailedInstallGuard<'a> {
     linker: &'a Linker,
     cellar: &'a Cellar,
@@ -348,7 +630,7 @@ fn stage_cask_binaries(
     fs::create_dir_all(&bin_dir).map_err(Error::store("failed to create cask bin dir"))?;
 
     for binary in &cask.binaries {
-        let source = resolve_cask_source_path(extracted_root, cask, &binary.source)?;
+        let source = extracted_root.join(cask.binary_source_path(&binary.source)?);
         if !source.exists() {
             return Err(Error::InvalidArgument {
                 message: format!(
@@ -423,50 +705,6 @@ fn stage_raw_cask_binary(
     Ok(())
 }
 
-fn resolve_cask_source_path(
-    extracted_root: &Path,
-    cask: &crate::installer::cask::ResolvedCask,
-    source: &str,
-) -> Result<std::path::PathBuf, Error> {
-    if source.starts_with("$APPDIR") {
-        return Err(Error::InvalidArgument {
-            message: format!(
-                "cask '{}' uses APPDIR artifacts which are not supported yet",
-                cask.token
-            ),
-        });
-    }
-
-    let mut normalized = source.to_string();
-    let caskroom_prefix = format!("$HOMEBREW_PREFIX/Caskroom/{}/{}/", cask.token, cask.version);
-    if let Some(stripped) = normalized.strip_prefix(&caskroom_prefix) {
-        normalized = stripped.to_string();
-    }
-
-    let source_path = Path::new(&normalized);
-    if source_path.is_absolute() {
-        return Err(Error::InvalidArgument {
-            message: format!(
-                "cask '{}' binary source '{}' must be a relative path",
-                cask.token, source
-            ),
-        });
-    }
-
-    for component in source_path.components() {
-        if matches!(component, std::path::Component::ParentDir) {
-            return Err(Error::InvalidArgument {
-                message: format!(
-                    "cask '{}' binary source '{}' cannot contain '..'",
-                    cask.token, source
-                ),
-            });
-        }
-    }
-
-    Ok(extracted_root.join(source_path))
-}
-
 #[cfg(test)]
 mod tests {
     use std::fs;
@@ -504,7 +742,7 @@ mod tests {
         let db_path = tmp.path().join("zb.sqlite3");
         let mut db = Database::open(&db_path).unwrap();
         let tx = db.transaction().unwrap();
-        tx.record_install("hashicorp/tap/terraform", "1.10.0", "store-key")
+        tx.record_install("hashicorp/tap/terraform", "1.10.0", "store-key", true)
             .unwrap();
         tx.commit().unwrap();
 
@@ -531,6 +769,7 @@ mod tests {
                 source: "claude".to_string(),
                 target: "claude".to_string(),
             }],
+            zap: Vec::new(),
         };
 
         stage_raw_cask_binary(&blob_path, &keg_path, &cask).unwrap();
@@ -573,6 +812,7 @@ mod tests {
                     target: "b".to_string(),
                 },
             ],
+            zap: Vec::new(),
         };
 
         let err = stage_raw_cask_binary(&blob_path, &keg_path, &cask).unwrap_err();
@@ -1,8 +1,9 @@
 use std::collections::{HashMap, HashSet};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use zb_core::{Error, formula_token};
 
+use crate::path::is_writable;
 use crate::storage::db::StoreRef;
 
 use super::Installer;
@@ -15,6 +16,90 @@ pub struct DiagnosticReport {
     pub stale_store_refs: Vec<StaleStoreRef>,
     pub broken_symlinks: Vec<PathBuf>,
     pub stale_keg_file_records: usize,
+    /// Store entries whose files no longer match their extraction manifest.
+    /// Only populated by [`Installer::doctor_deep`].
+    pub corrupted_store_entries: Vec<CorruptedStoreEntry>,
+    /// Directories zerobrew needs to write to but cannot.
+    pub unwritable_dirs: Vec<PathBuf>,
+}
+
+/// A single problem found by [`Installer::doctor`], in a form the CLI can
+/// print without knowing about each check.
+#[derive(Debug)]
+pub enum DoctorFinding<'a> {
+    OrphanedCellarKeg(&'a OrphanedKeg),
+    MissingCellarKeg(&'a MissingKeg),
+    OrphanedStoreEntry(&'a str),
+    StaleStoreRef(&'a StaleStoreRef),
+    BrokenSymlink(&'a Path),
+    StaleKegFileRecords(usize),
+    CorruptedStoreEntry(&'a CorruptedStoreEntry),
+    UnwritableDirectory(&'a Path),
+}
+
+impl DoctorFinding<'_> {
+    pub fn message(&self) -> String {
+        match self {
+            Self::OrphanedCellarKeg(orphan) => format!(
+                "Orphaned cellar keg: {}/{} (no DB record)",
+                orphan.name, orphan.version
+            ),
+            Self::MissingCellarKeg(missing) => format!(
+                "Missing cellar keg: {}/{} (DB record exists but {} is gone)",
+                missing.name,
+                missing.version,
+                missing.expected_path.display()
+            ),
+            Self::OrphanedStoreEntry(key) => {
+                format!("Orphaned store entry: {} (no DB reference)", short_key(key))
+            }
+            Self::StaleStoreRef(stale) => {
+                let status = if !stale.on_disk {
+                    "not on disk"
+                } else if !stale.referenced_by_any_keg {
+                    "unreferenced"
+                } else {
+                    "refcount mismatch"
+                };
+                format!(
+                    "Stale store ref: {} (refcount={}, {})",
+                    short_key(&stale.store_key),
+                    stale.refcount,
+                    status
+                )
+            }
+            Self::BrokenSymlink(link) => format!("Broken symlink: {}", link.display()),
+            Self::StaleKegFileRecords(count) => {
+                format!("{count} stale keg_files records (referencing uninstalled kegs)")
+            }
+            Self::CorruptedStoreEntry(corrupted) => format!(
+                "Corrupted store entry: {} ({} modified or missing {})",
+                short_key(&corrupted.store_key),
+                corrupted.damaged_files.len(),
+                if corrupted.damaged_files.len() == 1 {
+                    "file"
+                } else {
+                    "files"
+                }
+            ),
+            Self::UnwritableDirectory(dir) => {
+                format!("Directory is not writable: {}", dir.display())
+            }
+        }
+    }
+
+    /// Whether `Installer::repair` can resolve this finding on its own.
+    pub fn is_auto_fixable(&self) -> bool {
+        match self {
+            Self::UnwritableDirectory(_) => false,
+            Self::CorruptedStoreEntry(corrupted) => corrupted.blob_cached,
+            _ => true,
+        }
+    }
+}
+
+fn short_key(key: &str) -> &str {
+    &key[..key.len().min(12)]
 }
 
 #[derive(Debug)]
@@ -31,6 +116,14 @@ pub struct MissingKeg {
     pub expected_path: PathBuf,
 }
 
+#[derive(Debug)]
+pub struct CorruptedStoreEntry {
+    pub store_key: String,
+    pub damaged_files: Vec<String>,
+    /// The blob is still cached, so repair can re-extract the entry.
+    pub blob_cached: bool,
+}
+
 #[derive(Debug)]
 pub struct StaleStoreRef {
     pub store_key: String,
@@ -47,11 +140,60 @@ impl DiagnosticReport {
             && self.stale_store_refs.is_empty()
             && self.broken_symlinks.is_empty()
             && self.stale_keg_file_records == 0
+            && self.corrupted_store_entries.is_empty()
+            && self.unwritable_dirs.is_empty()
+    }
+
+    pub fn findings(&self) -> Vec<DoctorFinding<'_>> {
+        let mut findings = Vec::new();
+        findings.extend(
+            self.orphaned_cellar_kegs
+                .iter()
+                .map(DoctorFinding::OrphanedCellarKeg),
+        );
+        findings.extend(
+            self.missing_cellar_kegs
+                .iter()
+                .map(DoctorFinding::MissingCellarKeg),
+        );
+        findings.extend(
+            self.orphaned_store_entries
+                .iter()
+                .map(|key| DoctorFinding::OrphanedStoreEntry(key)),
+        );
+        findings.extend(
+            self.stale_store_refs
+                .iter()
+                .map(DoctorFinding::StaleStoreRef),
+        );
+        findings.extend(
+            self.broken_symlinks
+                .iter()
+                .map(|link| DoctorFinding::BrokenSymlink(link)),
+        );
+        if self.stale_keg_file_records > 0 {
+            findings.push(DoctorFinding::StaleKegFileRecords(
+                self.stale_keg_file_records,
+            ));
+        }
+        findings.extend(
+            self.corrupted_store_entries
+                .iter()
+                .map(DoctorFinding::CorruptedStoreEntry),
+        );
+        findings.extend(
+            self.unwritable_dirs
+                .iter()
+                .map(|dir| DoctorFinding::UnwritableDirectory(dir)),
+        );
+        findings
     }
 }
 
 impl Installer {
-    pub fn doctor(&mut self) -> Result<DiagnosticReport, Error> {
+    /// Run read-only consistency checks across the DB, cellar, store and
+    /// prefix. Nothing is changed; pass the report to [`Installer::repair`].
+    pub fn doctor(&self) -> Result<DiagnosticReport, Error> {
         let mut report = DiagnosticReport::default();
 
         let installed = self.db.list_installed()?;
@@ -153,8 +295,45 @@ impl Installer {
             }
         }
 
+        for link in self.linker.find_dangling_links() {
+            if !report.broken_symlinks.contains(&link) {
+                report.broken_symlinks.push(link);
+            }
+        }
+
         report.stale_keg_file_records = self.db.count_stale_keg_file_records()?;
 
+        for dir in [
+            self.prefix.clone(),
+            self.prefix.join("bin"),
+            self.cellar.cellar_dir().to_path_buf(),
+            self.locks_dir.clone(),
+        ] {
+            if dir.exists() && !is_writable(&dir) {
+                report.unwritable_dirs.push(dir);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Run [`Installer::doctor`] and additionally re-hash every store entry
+    /// against its manifest. This reads every extracted file, so it is slow.
+    pub fn doctor_deep(&self) -> Result<DiagnosticReport, Error> {
+        let mut report = self.doctor()?;
+
+        for store_key in self.store.list_entries()? {
+            let damaged_files = self.store.verify_entry(&store_key)?;
+            if !damaged_files.is_empty() {
+                let blob_cached = self.downloader.blob_cache().has_blob(&store_key);
+                report.corrupted_store_entries.push(CorruptedStoreEntry {
+                    store_key,
+                    damaged_files,
+                    blob_cached,
+                });
+            }
+        }
+
         Ok(report)
     }
 
@@ -211,8 +390,175 @@ impl Installer {
             summary.pruned_keg_file_records = self.db.prune_stale_keg_file_records()?;
         }
 
+        // Bottle store keys are the blob sha256, so an entry can only be rebuilt
+        // while its blob is still cached. Others are left for a reinstall.
+        for corrupted in &report.corrupted_store_entries {
+            let blob_cache = self.downloader.blob_cache();
+            if !blob_cache.has_blob(&corrupted.store_key) {
+                continue;
+            }
+            let blob_path = blob_cache.blob_path(&corrupted.store_key);
+            self.store.remove_entry(&corrupted.store_key)?;
+            self.store.ensure_entry(&corrupted.store_key, &blob_path)?;
+            summary.reextracted_store_entries += 1;
+        }
+
         Ok(summary)
     }
+
+    /// Reconcile the cellar with the DB after an install was interrupted.
+    ///
+    /// Kegs with no recorded version and nothing linked to them are what a
+    /// killed install leaves behind, and are removed along with abandoned
+    /// staging directories. Unrecorded kegs that are linked into the prefix
+    /// may be in use, so they are only reported. Records whose keg is gone
+    /// are kept; the next install of those formulae reinstalls them.
+    ///
+    /// Takes the install lock, so it never races an install in progress.
+    pub fn recover(&mut self) -> Result<RecoveryReport, Error> {
+        let _lock = self.lock_install()?;
+        let mut report = RecoveryReport::default();
+
+        let recorded: HashSet<(String, String)> = self
+            .db
+            .list_installed_versions()?
+            .into_iter()
+            .map(|v| (formula_token(&v.name).to_string(), v.version))
+            .collect();
+
+        for keg in self.cellar.list_kegs()? {
+            if recorded.contains(&(keg.name.clone(), keg.version.clone())) {
+                continue;
+            }
+            let in_use = self.linker.has_opt_link(&keg.path)
+                || !self.linker.collect_linked_files(&keg.path)?.is_empty();
+            let orphan = OrphanedKeg {
+                name: keg.name,
+                version: keg.version,
+                path: keg.path,
+            };
+            if in_use {
+                report.skipped_kegs.push(orphan);
+                continue;
+            }
+            self.cellar.remove_keg(&orphan.name, &orphan.version)?;
+            report.removed_kegs.push(orphan);
+        }
+
+        for staging in self.cellar.list_staging_dirs()? {
+            std::fs::remove_dir_all(&staging)
+                .map_err(Error::store("failed to remove keg staging directory"))?;
+            report.removed_staging_dirs.push(staging);
+        }
+
+        for keg in self.db.list_installed()? {
+            let expected_path = self.cellar.keg_path(formula_token(&keg.name), &keg.version);
+            if !expected_path.exists() {
+                report.needs_reinstall.push(MissingKeg {
+                    name: keg.name,
+                    version: keg.version,
+                    expected_path,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Recreate prefix links for every installed formula from the recorded
+    /// state, e.g. after the prefix was cleared or copied to a new machine.
+    ///
+    /// Only formulae with recorded links are relinked, so keg-only and
+    /// unlinked installs stay that way (they still get their `opt` link).
+    /// Links that are already correct are left alone. Conflicts from every
+    /// formula are collected into a single [`Error::LinkConflict`] after the
+    /// others have been linked. Returns the number of formulae relinked.
+    pub fn relink_all(&mut self) -> Result<usize, Error> {
+        let records = self.db.list_keg_files()?;
+        let linkable: HashSet<&str> = records.iter().map(|r| r.name.as_str()).collect();
+        let backups: HashMap<(&str, &str), &str> = records
+            .iter()
+            .filter_map(|r| {
+                let backup = r.backup_path.as_deref()?;
+                Some(((r.name.as_str(), r.linked_path.as_str()), backup))
+            })
+            .collect();
+
+        let mut relinked = 0;
+        let mut conflicts = Vec::new();
+
+        for keg in self.db.list_installed()? {
+            let keg_path = self.cellar.keg_path(formula_token(&keg.name), &keg.version);
+            if !keg_path.exists() {
+                continue;
+            }
+            if !linkable.contains(keg.name.as_str()) {
+                self.linker.link_opt(&keg_path)?;
+                continue;
+            }
+
+            let linked = match self.linker.link_keg(&keg_path) {
+                Ok(linked) => linked,
+                Err(Error::LinkConflict { conflicts: found }) => {
+                    conflicts.extend(found);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let tx = self.db.transaction()?;
+            for file in &linked {
+                let link_path = file.link_path.to_string_lossy();
+                let backup_path = file
+                    .backup_path
+                    .as_ref()
+                    .map(|p| p.to_string_lossy())
+                    .or_else(|| {
+                        backups
+                            .get(&(keg.name.as_str(), link_path.as_ref()))
+                            .map(|b| (*b).into())
+                    });
+                tx.record_linked_file(
+                    &keg.name,
+                    &keg.version,
+                    &link_path,
+                    &file.target_path.to_string_lossy(),
+                    backup_path.as_deref(),
+                )?;
+            }
+            tx.commit()?;
+            relinked += 1;
+        }
+
+        if conflicts.is_empty() {
+            Ok(relinked)
+        } else {
+            Err(Error::LinkConflict { conflicts })
+        }
+    }
+}
+
+/// What [`Installer::recover`] found and what it did about it.
+#[derive(Debug, Default)]
+pub struct RecoveryReport {
+    /// Unrecorded, unlinked kegs that were removed.
+    pub removed_kegs: Vec<OrphanedKeg>,
+    /// Staging directories from materializations that never finished.
+    pub removed_staging_dirs: Vec<PathBuf>,
+    /// Unrecorded kegs left in place because the prefix links to them.
+    pub skipped_kegs: Vec<OrphanedKeg>,
+    /// Recorded installs whose keg is missing. Installing them again
+    /// restores the keg.
+    pub needs_reinstall: Vec<MissingKeg>,
+}
+
+impl RecoveryReport {
+    pub fn is_clean(&self) -> bool {
+        self.removed_kegs.is_empty()
+            && self.removed_staging_dirs.is_empty()
+            && self.skipped_kegs.is_empty()
+            && self.needs_reinstall.is_empty()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -223,6 +569,7 @@ pub struct RepairSummary {
     pub removed_orphaned_store_entries: usize,
     pub removed_broken_symlinks: usize,
     pub pruned_keg_file_records: usize,
+    pub reextracted_store_entries: usize,
 }
 
 impl RepairSummary {
@@ -233,5 +580,115 @@ impl RepairSummary {
             + self.removed_orphaned_store_entries
             + self.removed_broken_symlinks
             + self.pruned_keg_file_records
+            + self.reextracted_store_entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use crate::cellar::Cellar;
+    use crate::network::api::ApiClient;
+    use crate::storage::blob::BlobCache;
+    use crate::storage::db::Database;
+    use crate::storage::store::Store;
+    use crate::{Installer, Linker};
+
+    use super::{CorruptedStoreEntry, DoctorFinding};
+
+    #[test]
+    fn corrupted_store_entry_is_only_fixable_with_its_blob() {
+        let entry = |blob_cached| CorruptedStoreEntry {
+            store_key: "abc".to_string(),
+            damaged_files: vec!["bin/tool".to_string()],
+            blob_cached,
+        };
+        assert!(DoctorFinding::CorruptedStoreEntry(&entry(true)).is_auto_fixable());
+        assert!(!DoctorFinding::CorruptedStoreEntry(&entry(false)).is_auto_fixable());
+    }
+
+    #[test]
+    fn recover_removes_leftover_kegs_and_keeps_records_of_missing_ones() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        fs::create_dir_all(root.join("locks")).unwrap();
+
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("kept", "1.0.0", "kept-key", true)
+                .unwrap();
+            tx.record_install("gone", "2.0.0", "gone-key", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let cellar_dir = root.join("cellar");
+        for keg in [
+            "kept/1.0.0",
+            "stale/1.0.0",
+            "linked/1.0.0",
+            "kept/.2.0.0.partial-4242",
+        ] {
+            fs::create_dir_all(cellar_dir.join(keg).join("bin")).unwrap();
+        }
+        fs::create_dir_all(prefix.join("opt")).unwrap();
+        std::os::unix::fs::symlink(cellar_dir.join("linked/1.0.0"), prefix.join("opt/linked"))
+            .unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::new(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let report = installer.recover().unwrap();
+
+        let removed: Vec<_> = report
+            .removed_kegs
+            .iter()
+            .map(|k| k.name.as_str())
+            .collect();
+        assert_eq!(removed, ["stale"]);
+        assert!(!cellar_dir.join("stale").exists());
+
+        assert_eq!(
+            report.removed_staging_dirs,
+            [cellar_dir.join("kept/.2.0.0.partial-4242")]
+        );
+        assert!(cellar_dir.join("kept/1.0.0").exists());
+
+        let skipped: Vec<_> = report
+            .skipped_kegs
+            .iter()
+            .map(|k| k.name.as_str())
+            .collect();
+        assert_eq!(skipped, ["linked"]);
+        assert!(cellar_dir.join("linked/1.0.0").exists());
+
+        let missing: Vec<_> = report
+            .needs_reinstall
+            .iter()
+            .map(|k| (k.name.as_str(), k.version.as_str()))
+            .collect();
+        assert_eq!(missing, [("gone", "2.0.0")]);
+        assert!(installer.is_installed("gone"));
+
+        // A second pass only reports what still needs the user.
+        let again = installer.recover().unwrap();
+        assert!(again.removed_kegs.is_empty());
+        assert!(again.removed_staging_dirs.is_empty());
+        assert_eq!(again.skipped_kegs.len(), 1);
+        assert_eq!(again.needs_reinstall.len(), 1);
     }
 }
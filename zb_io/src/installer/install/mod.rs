@@ -2,31 +2,46 @@ mod bottle;
 pub mod doctor;
 mod outdated;
 mod plan;
+mod search;
 mod source;
 mod uninstall;
 
+pub use uninstall::{UninstallReport, ZapReport};
+
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use fs4::fs_std::FileExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tracing::warn;
 
-use crate::cellar::link::Linker;
+use crate::build::{BuildPhase, BuildProgressCallback};
+use crate::cellar::link::{LinkedFile, Linker, OwnedLink, keg_name_from_symlink};
 use crate::cellar::materialize::Cellar;
+use crate::config::Config;
 use crate::network::api::ApiClient;
 use crate::network::cache::ApiCache;
-use crate::network::download::{DownloadProgressCallback, DownloadRequest, ParallelDownloader};
+use crate::network::download::{
+    DownloadProgressCallback, DownloadRequest, DownloaderConfig, ParallelDownloader,
+};
 use crate::progress::{InstallProgress, ProgressCallback};
+use crate::ssl::TlsOptions;
 use crate::storage::blob::BlobCache;
 use crate::storage::db::Database;
 use crate::storage::store::Store;
 
-use zb_core::{Error, Formula, InstallMethod};
+use zb_core::{
+    BuildPlan, DependencyPolicy, Error, Formula, InstallMethod, LoggerHandle, Paths, formula_token,
+};
 
 use bottle::dependency_cellar_path;
 
 const MAX_CORRUPTION_RETRIES: usize = 3;
+const DEFAULT_EXTRACTION_CONCURRENCY: usize = 4;
 
 pub struct Installer {
     api_client: ApiClient,
@@ -37,6 +52,18 @@ pub struct Installer {
     pub(crate) db: Database,
     prefix: PathBuf,
     locks_dir: PathBuf,
+    logger: LoggerHandle,
+    offline: bool,
+    bottle_corruption_source_fallback: bool,
+    bottle_tag: Option<String>,
+    extraction_concurrency: usize,
+    force_reinstall: bool,
+    keep_going: bool,
+    dependency_policy: DependencyPolicy,
+    build_phase_timeout: Option<Duration>,
+    build_log_dir: PathBuf,
+    cask_binaries: Option<Vec<String>>,
+    config: Config,
 }
 
 #[derive(Debug)]
@@ -44,15 +71,80 @@ pub struct PlannedInstall {
     pub install_name: String,
     pub formula: Formula,
     pub method: InstallMethod,
+    /// Named by the caller rather than pulled in as a dependency.
+    pub explicit: bool,
+}
+
+/// Where a planned formula is recorded: its cellar directory, revisioned
+/// version, and the store key the DB row points at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct KegIdentity {
+    pub keg_name: String,
+    pub version: String,
+    pub store_key: String,
+}
+
+impl PlannedInstall {
+    /// Bottle installs, source builds and the already-installed path all
+    /// derive the keg from here, so `openssl@3` and `user/tap/foo@2` end up
+    /// at `<cellar>/<token>/<version>` whichever way they were installed.
+    pub(super) fn keg_identity(&self) -> KegIdentity {
+        let keg_name = formula_token(&self.install_name).to_string();
+        let version = self.formula.effective_version();
+        let store_key = match &self.method {
+            InstallMethod::Bottle(bottle) => bottle.sha256.clone(),
+            InstallMethod::Source(_) => format!("source:{keg_name}:{version}"),
+        };
+        KegIdentity {
+            keg_name,
+            version,
+            store_key,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct InstallPlan {
     pub items: Vec<PlannedInstall>,
+    /// Closure members already installed at the planned version. They are
+    /// not downloaded again; requested ones are only linked if needed.
+    pub already_installed: Vec<PlannedInstall>,
 }
 
+#[derive(Debug, Default)]
 pub struct ExecuteResult {
     pub installed: usize,
+    /// Bottles fetched over the network, and the bytes that took.
+    pub downloaded: usize,
+    pub downloaded_bytes: u64,
+    /// Bottles already in the blob cache.
+    pub cache_hits: usize,
+    /// Wall-clock time from starting the bottle downloads until the last one
+    /// finished; divide `downloaded_bytes` by it for throughput.
+    pub download_time: Duration,
+}
+
+/// What a bare name refers to when it exists both as a formula and as a
+/// cask. See [`Installer::resolve_package_kinds`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PackageKind {
+    Formula,
+    Cask,
+}
+
+/// How [`Installer::plan_with_options`] chooses between a bottle and a
+/// source build for each formula.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MethodPolicy {
+    /// Pour a bottle, building from source only when there is none.
+    #[default]
+    PreferBottle,
+    /// Build from source, pouring a bottle only when there is no source.
+    PreferSource,
+    /// Pour a bottle or fail with [`Error::UnsupportedBottle`].
+    ForceBottle,
+    /// Build from source or fail with [`Error::UnsupportedFormula`].
+    ForceSource,
 }
 
 /// A package that has a newer version available upstream.
@@ -66,9 +158,36 @@ pub struct OutdatedPackage {
     #[serde(skip)]
     pub current_sha256: String,
     #[serde(skip)]
+    pub current_rebuild: u32,
+    #[serde(skip)]
     pub is_source_build: bool,
 }
 
+/// One installed formula as reported by [`Installer::list_installed_detailed`].
+/// The serialized field names are a stable schema for scripts; add fields,
+/// don't rename or remove them.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct InstalledEntry {
+    pub name: String,
+    pub version: String,
+    pub store_key: String,
+    pub linked: bool,
+    /// zerobrew has no pins yet, so this is always `false`; it is part of
+    /// the schema so scripts need not change once pinning lands.
+    pub pinned: bool,
+    /// Requested by name rather than pulled in as a dependency.
+    pub explicit: bool,
+    /// Bytes the keg occupies in the cellar, `0` if its directory is gone.
+    pub disk_usage: u64,
+}
+
+impl OutdatedPackage {
+    /// Upstream published a rebuilt bottle without bumping the version.
+    pub fn is_rebuild(&self) -> bool {
+        !self.is_source_build && self.installed_version == self.current_version
+    }
+}
+
 impl Installer {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
@@ -81,6 +200,7 @@ impl Installer {
         prefix: PathBuf,
         locks_dir: PathBuf,
     ) -> Self {
+        let build_log_dir = prefix.join("tmp").join("build-logs");
         Self {
             api_client,
             downloader: ParallelDownloader::new(blob_cache),
@@ -90,6 +210,154 @@ impl Installer {
             db,
             prefix,
             locks_dir,
+            logger: LoggerHandle::default(),
+            offline: false,
+            bottle_corruption_source_fallback: false,
+            bottle_tag: None,
+            extraction_concurrency: DEFAULT_EXTRACTION_CONCURRENCY,
+            force_reinstall: false,
+            keep_going: false,
+            dependency_policy: DependencyPolicy::default(),
+            build_phase_timeout: None,
+            build_log_dir,
+            cask_binaries: None,
+            config: Config::default(),
+        }
+    }
+
+    /// Settings loaded from `config.toml` and the environment when this
+    /// installer was created. Defaults for one built with [`Installer::new`].
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Hold the install lock until the returned file is dropped, waiting for
+    /// any install already running.
+    fn lock_install(&self) -> Result<File, Error> {
+        let lock_path = self.locks_dir.join("install.lock");
+        let lock_file =
+            File::create(&lock_path).map_err(Error::store("failed to create install lock"))?;
+        lock_file
+            .lock_exclusive()
+            .map_err(Error::store("failed to acquire install lock"))?;
+        Ok(lock_file)
+    }
+
+    /// Set the verbosity used for warnings, progress events and build output.
+    /// At [`zb_core::LogLevel::Error`] a successful install produces no output
+    /// at all.
+    pub fn with_logger(mut self, logger: LoggerHandle) -> Self {
+        self.logger = logger;
+        self
+    }
+
+    /// Install purely from the API and blob caches. Anything not already cached
+    /// fails with `Error::OfflineCacheMiss` rather than reaching the network.
+    pub fn with_offline(self, offline: bool) -> Self {
+        Self {
+            api_client: self.api_client.with_offline(offline),
+            downloader: self.downloader.with_offline(offline),
+            offline,
+            ..self
+        }
+    }
+
+    /// Trust an extra CA bundle or disable certificate verification for
+    /// bottle downloads. See [`TlsOptions`]. Certificates from this
+    /// installer's prefix are trusted unless `options.prefix` says otherwise.
+    pub fn with_tls_options(self, options: &TlsOptions) -> Result<Self, Error> {
+        let options = TlsOptions {
+            prefix: options.prefix.clone().or_else(|| Some(self.prefix.clone())),
+            ..options.clone()
+        };
+        Ok(Self {
+            downloader: self.downloader.with_tls_options(&options)?,
+            ..self
+        })
+    }
+
+    /// Download `ghcr.io` bottles from a registry that speaks the same token
+    /// and blob protocol, such as an internal pull-through cache.
+    pub fn with_registry_base(self, base: &str) -> Result<Self, Error> {
+        Ok(Self {
+            downloader: self.downloader.with_registry_base(base)?,
+            ..self
+        })
+    }
+
+    /// Tune (or disable) connection racing for bottle downloads. See
+    /// [`DownloaderConfig`].
+    pub fn with_downloader_config(self, config: DownloaderConfig) -> Self {
+        Self {
+            downloader: self.downloader.with_config(config),
+            ..self
+        }
+    }
+
+    /// When a bottle is still corrupt after every re-download, build that one
+    /// formula from source instead of failing the install. Only applies to
+    /// formulae that have a source build plan.
+    pub fn with_bottle_corruption_source_fallback(mut self, enabled: bool) -> Self {
+        self.bottle_corruption_source_fallback = enabled;
+        self
+    }
+
+    /// Plan bottles for another platform (e.g. `x86_64_linux`) instead of the
+    /// host. Only meaningful for planning; the bottles will not run here.
+    pub fn with_bottle_tag(mut self, tag: Option<String>) -> Self {
+        self.bottle_tag = tag;
+        self
+    }
+
+    /// Maximum number of bottles unpacked at once while further downloads
+    /// are still in flight. Clamped to at least one.
+    pub fn with_extraction_concurrency(mut self, concurrency: usize) -> Self {
+        self.extraction_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Reinstall requested formulae even when the planned version is already
+    /// installed. Satisfied dependencies are still skipped.
+    pub fn with_force_reinstall(mut self, force: bool) -> Self {
+        self.force_reinstall = force;
+        self
+    }
+
+    /// Keep installing the rest of a plan after one formula fails, then
+    /// report every failure together. Otherwise nothing new is started once
+    /// a formula has failed; work already under way still finishes.
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Choose which recommended and optional dependencies installs pull in.
+    /// Defaults to required plus recommended, as `brew install` does.
+    pub fn with_dependency_policy(mut self, policy: DependencyPolicy) -> Self {
+        self.dependency_policy = policy;
+        self
+    }
+
+    /// Abort a source build when one of its phases (configure, compile, ...)
+    /// runs longer than `timeout`. Unlimited by default.
+    pub fn with_build_phase_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.build_phase_timeout = timeout;
+        self
+    }
+
+    /// Stage only the cask binaries whose target is listed, instead of every
+    /// binary a cask declares. `None` stages them all.
+    pub fn with_cask_binaries(mut self, targets: Option<Vec<String>>) -> Self {
+        self.cask_binaries = targets;
+        self
+    }
+
+    /// Re-hash store entries against their manifest every time an install
+    /// reuses them, re-extracting any that have been modified on disk.
+    pub fn with_store_verification(self, verify: bool) -> Self {
+        Self {
+            store: self.store.with_verification(verify),
+            ..self
         }
     }
 
@@ -107,13 +375,9 @@ impl Installer {
         link: bool,
         progress: Option<Arc<ProgressCallback>>,
     ) -> Result<ExecuteResult, Error> {
-        let lock_path = self.locks_dir.join("install.lock");
-        let lock_file =
-            File::create(&lock_path).map_err(Error::store("failed to create install lock"))?;
-        lock_file
-            .lock_exclusive()
-            .map_err(Error::store("failed to acquire install lock"))?;
-        let _lock = lock_file;
+        let _lock = self.lock_install()?;
+
+        let progress = progress.filter(|_| !self.logger.is_quiet());
 
         let report = |event: InstallProgress| {
             if let Some(ref cb) = progress {
@@ -121,17 +385,24 @@ impl Installer {
             }
         };
 
+        for item in &plan.already_installed {
+            self.finish_already_installed(item, link, &report)?;
+        }
+
         let (bottle_items, source_items): (Vec<_>, Vec<_>) = plan
             .items
             .into_iter()
             .partition(|item| matches!(item.method, InstallMethod::Bottle(_)));
 
         if bottle_items.is_empty() && source_items.is_empty() {
-            return Ok(ExecuteResult { installed: 0 });
+            return Ok(ExecuteResult::default());
         }
 
+        let mut summary = ExecuteResult::default();
         let mut installed = 0usize;
-        let mut error: Option<Error> = None;
+        let mut failed: Vec<(String, Error)> = Vec::new();
+        let mut offline_misses: Vec<String> = Vec::new();
+        let mut fallback_items: Vec<PlannedInstall> = Vec::new();
 
         if !bottle_items.is_empty() {
             let requests: Vec<DownloadRequest> = bottle_items
@@ -144,6 +415,8 @@ impl Installer {
                         url: bottle.url.clone(),
                         sha256: bottle.sha256.clone(),
                         name: item.formula.name.clone(),
+                        priority: u8::from(item.explicit),
+                        mirror_urls: bottle.mirror_urls.clone(),
                     }
                 })
                 .collect();
@@ -154,35 +427,120 @@ impl Installer {
                 }) as DownloadProgressCallback
             });
 
+            let downloads_started = Instant::now();
             let mut rx = self
                 .downloader
                 .download_streaming(requests, download_progress.clone());
 
-            while let Some(result) = rx.recv().await {
-                match result {
-                    Ok(download) => {
-                        match self
-                            .process_bottle_item(
-                                &bottle_items[download.index],
-                                &download,
-                                &download_progress,
-                                link,
-                                &report,
-                            )
-                            .await
-                        {
+            // Extraction runs on blocking threads as downloads land; DB commits
+            // and linking happen here, one keg at a time.
+            let extractor = self.bottle_extractor();
+            let permits = Arc::new(Semaphore::new(self.extraction_concurrency));
+            let mut extractions = JoinSet::new();
+            let mut extraction_items = HashMap::new();
+            let mut downloads_done = false;
+
+            loop {
+                let halted = !self.keep_going && !failed.is_empty();
+                tokio::select! {
+                    result = rx.recv(), if !downloads_done && !halted => match result {
+                        Some((_, Ok(download))) => {
+                            if download.from_cache {
+                                summary.cache_hits += 1;
+                            } else if download.bytes_transferred > 0 {
+                                summary.downloaded += 1;
+                                summary.downloaded_bytes += download.bytes_transferred;
+                            }
+                            let item = &bottle_items[download.index];
+                            let InstallMethod::Bottle(ref bottle) = item.method else {
+                                unreachable!()
+                            };
+                            let formula = item.formula.clone();
+                            let keg = item.keg_identity();
+                            let bottle = bottle.clone();
+                            let extractor = extractor.clone();
+                            let permits = permits.clone();
+                            let progress = progress.clone();
+                            let download_progress = download_progress.clone();
+                            let index = download.index;
+                            let task = extractions.spawn(async move {
+                                let _permit = permits.acquire_owned().await;
+                                let report = |event: InstallProgress| {
+                                    if let Some(ref cb) = progress {
+                                        cb(event);
+                                    }
+                                };
+                                report(InstallProgress::UnpackStarted {
+                                    name: formula.name.clone(),
+                                });
+                                let result = extractor
+                                    .extract(&formula, &keg, &bottle, &download, download_progress)
+                                    .await;
+                                if result.is_ok() {
+                                    report(InstallProgress::UnpackCompleted {
+                                        name: formula.name.clone(),
+                                    });
+                                }
+                                result
+                            });
+                            extraction_items.insert(task.id(), index);
+                        }
+                        Some((_, Err(Error::OfflineCacheMiss { name }))) => {
+                            offline_misses.push(name)
+                        }
+                        Some((index, Err(e))) => {
+                            failed.push((bottle_items[index].install_name.clone(), e))
+                        }
+                        None => {
+                            downloads_done = true;
+                            summary.download_time = downloads_started.elapsed();
+                        }
+                    },
+                    Some(joined) = extractions.join_next_with_id() => {
+                        let (index, result) = match joined {
+                            Ok((id, result)) => (extraction_items[&id], result),
+                            Err(e) => {
+                                let index = extraction_items[&e.id()];
+                                failed.push((
+                                    bottle_items[index].install_name.clone(),
+                                    Error::ExecutionError {
+                                        message: format!("extraction task failed: {e}"),
+                                    },
+                                ));
+                                continue;
+                            }
+                        };
+                        let item = &bottle_items[index];
+                        let result = result.and_then(|keg_path| {
+                            self.commit_keg(item, &keg_path, link, &report)
+                        });
+                        match result {
                             Ok(()) => installed += 1,
-                            Err(e) => error = Some(e),
+                            Err(
+                                e
+                                @ (Error::StoreCorruption { .. } | Error::ChecksumMismatch { .. }),
+                            ) => match self.source_fallback_for(item, &e) {
+                                Some(fallback) => {
+                                    report(InstallProgress::SourceFallback {
+                                        name: item.formula.name.clone(),
+                                        reason: e.to_string(),
+                                    });
+                                    fallback_items.push(fallback);
+                                }
+                                None => failed.push((item.install_name.clone(), e)),
+                            },
+                            Err(e) => failed.push((item.install_name.clone(), e)),
                         }
                     }
-                    Err(e) => {
-                        error = Some(e);
-                    }
+                    else => break,
                 }
             }
         }
 
-        for item in &source_items {
+        for item in fallback_items.iter().chain(&source_items) {
+            if !self.keep_going && !failed.is_empty() {
+                break;
+            }
             let InstallMethod::Source(ref build_plan) = item.method else {
                 unreachable!()
             };
@@ -191,23 +549,66 @@ impl Installer {
                 name: item.formula.name.clone(),
             });
 
+            let build_progress: Option<BuildProgressCallback> = progress.clone().map(|cb| {
+                let name = item.formula.name.clone();
+                Arc::new(move |phase: BuildPhase| {
+                    cb(InstallProgress::BuildPhase {
+                        name: name.clone(),
+                        phase,
+                    });
+                }) as BuildProgressCallback
+            });
+
             match self
-                .install_from_source(item, build_plan, link, &report)
+                .install_from_source(item, build_plan, link, &report, build_progress)
                 .await
             {
                 Ok(()) => installed += 1,
-                Err(e) => {
-                    error = Some(e);
-                    continue;
-                }
+                Err(Error::OfflineCacheMiss { name }) => offline_misses.push(name),
+                Err(e) => failed.push((item.install_name.clone(), e)),
             }
         }
 
-        if let Some(e) = error {
-            return Err(e);
+        if !offline_misses.is_empty() {
+            return Err(Error::OfflineCacheMiss {
+                name: offline_misses.join(", "),
+            });
+        }
+
+        // A lone failure keeps its own error so callers can still match on
+        // it (a link conflict, say); several are reported together.
+        if failed.len() == 1 {
+            return Err(failed.remove(0).1);
+        }
+        if !failed.is_empty() {
+            return Err(Error::InstallFailed { failed });
+        }
+
+        summary.installed = installed;
+        Ok(summary)
+    }
+
+    /// Re-plan a bottle install that failed with terminal corruption as a
+    /// source build, if fallback is enabled and the formula can be built.
+    fn source_fallback_for(&self, item: &PlannedInstall, error: &Error) -> Option<PlannedInstall> {
+        if !self.bottle_corruption_source_fallback {
+            return None;
         }
 
-        Ok(ExecuteResult { installed })
+        let build_plan = BuildPlan::from_formula(&item.formula, &self.prefix)?;
+
+        warn!(
+            formula = %item.install_name,
+            error = %error,
+            "bottle is corrupt after retries; falling back to building from source"
+        );
+
+        Some(PlannedInstall {
+            install_name: item.install_name.clone(),
+            formula: item.formula.clone(),
+            method: InstallMethod::Source(build_plan),
+            explicit: item.explicit,
+        })
     }
 
     pub async fn install(&mut self, names: &[String], link: bool) -> Result<ExecuteResult, Error> {
@@ -216,18 +617,47 @@ impl Installer {
             .cloned()
             .partition(|name| name.starts_with("cask:"));
 
-        let mut installed = 0usize;
+        let mut result = ExecuteResult::default();
 
         if !formulas.is_empty() {
             let plan = self.plan(&formulas).await?;
-            installed += self.execute(plan, link).await?.installed;
+            result = self.execute(plan, link).await?;
         }
 
         if !casks.is_empty() {
-            installed += self.install_casks(&casks, link).await?.installed;
+            result.installed += self.install_casks(&casks, link).await?.installed;
+        }
+
+        Ok(result)
+    }
+
+    /// Download the bottles for `names` and their dependencies into the blob
+    /// cache without extracting or linking anything, so a later `install` is
+    /// served from the cache. Formulae that would be built from source are
+    /// skipped, as are ones already installed at the planned version.
+    pub async fn prefetch(&self, names: &[String]) -> Result<(), Error> {
+        let plan = self.plan(names).await?;
+
+        let mut requests = Vec::with_capacity(plan.items.len());
+        for item in &plan.items {
+            match item.method {
+                InstallMethod::Bottle(ref bottle) => requests.push(DownloadRequest {
+                    url: bottle.url.clone(),
+                    sha256: bottle.sha256.clone(),
+                    name: item.formula.name.clone(),
+                    priority: u8::from(item.explicit),
+                    mirror_urls: bottle.mirror_urls.clone(),
+                }),
+                InstallMethod::Source(_) => {
+                    warn!(
+                        formula = %item.install_name,
+                        "no bottle to prefetch; formula will be built from source"
+                    );
+                }
+            }
         }
 
-        Ok(ExecuteResult { installed })
+        self.downloader.prefetch(requests).await
     }
 
     pub async fn install_casks(
@@ -243,7 +673,23 @@ impl Installer {
             self.install_single_cask(token, link).await?;
             installed += 1;
         }
-        Ok(ExecuteResult { installed })
+        Ok(ExecuteResult {
+            installed,
+            ..Default::default()
+        })
+    }
+
+    /// The name `name` is installed under: itself, or the canonical name when
+    /// it is an alias (e.g. `python`) of a formula recorded as `python@3.13`.
+    /// Only cached alias data is consulted, so this never hits the network.
+    pub(super) fn installed_name(&self, name: &str) -> String {
+        if self.db.get_installed(name).is_some() {
+            return name.to_string();
+        }
+        self.api_client
+            .cached_alias(name)
+            .filter(|canonical| self.db.get_installed(canonical).is_some())
+            .unwrap_or_else(|| name.to_string())
     }
 
     pub fn is_installed(&self, name: &str) -> bool {
@@ -258,27 +704,187 @@ impl Installer {
         self.db.list_installed()
     }
 
+    /// Links the active version of `name` has recorded in the prefix, each
+    /// checked against what is actually there now.
+    pub fn list_linked(&self, name: &str) -> Result<Vec<OwnedLink>, Error> {
+        let name = self.installed_name(name);
+        let keg = self
+            .db
+            .get_installed(&name)
+            .ok_or_else(|| Error::NotInstalled { name: name.clone() })?;
+
+        let recorded: Vec<(PathBuf, PathBuf)> = self
+            .db
+            .linked_files(&name, &keg.version)?
+            .into_iter()
+            .map(|record| {
+                (
+                    PathBuf::from(record.linked_path),
+                    PathBuf::from(record.target_path),
+                )
+            })
+            .collect();
+        Ok(self.linker.linked_files_for(&recorded))
+    }
+
+    /// Link `name` into the prefix. Conflicts of any kind are reported
+    /// without changing anything; see [`Installer::overwrite_link`].
+    pub fn link(&mut self, name: &str) -> Result<Vec<LinkedFile>, Error> {
+        self.link_installed(name, false)
+    }
+
+    /// Link `name`, taking over any links another installed formula owns at
+    /// the same paths, e.g. to switch which version provides `bin/node`. The
+    /// other formula's records for those links are dropped. Files zerobrew
+    /// does not own still conflict.
+    pub fn overwrite_link(&mut self, name: &str) -> Result<Vec<LinkedFile>, Error> {
+        self.link_installed(name, true)
+    }
+
+    fn link_installed(&mut self, name: &str, overwrite: bool) -> Result<Vec<LinkedFile>, Error> {
+        let name = self.installed_name(name);
+        let keg = self
+            .db
+            .get_installed(&name)
+            .ok_or_else(|| Error::NotInstalled { name: name.clone() })?;
+        let keg_path = self.cellar.keg_path(formula_token(&name), &keg.version);
+
+        let linked = if overwrite {
+            self.linker.link_keg_overwriting(&keg_path, None)?
+        } else {
+            self.linker.link_keg(&keg_path)?
+        };
+
+        let tx = self.db.transaction()?;
+        for file in &linked {
+            let link_path = file.link_path.to_string_lossy();
+            let released_backup = tx.release_linked_path(&link_path, &name)?;
+            let backup_path = file
+                .backup_path
+                .as_ref()
+                .map(|p| p.to_string_lossy().into_owned())
+                .or(released_backup);
+            tx.record_linked_file(
+                &name,
+                &keg.version,
+                &link_path,
+                &file.target_path.to_string_lossy(),
+                backup_path.as_deref(),
+            )?;
+        }
+        tx.commit()?;
+
+        Ok(linked)
+    }
+
+    /// Installed formulae paired with the bytes their kegs occupy, largest
+    /// first. Kegs missing from the cellar count as zero.
+    pub fn list_installed_with_sizes(
+        &self,
+    ) -> Result<Vec<(crate::storage::db::InstalledKeg, u64)>, Error> {
+        let mut sized: Vec<_> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| {
+                let size = self
+                    .cellar
+                    .disk_usage(formula_token(&keg.name), &keg.version)
+                    .unwrap_or(0);
+                (keg, size)
+            })
+            .collect();
+        sized.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name.cmp(&b.0.name)));
+        Ok(sized)
+    }
+
+    /// Installed formulae, by name, with their link state and disk usage.
+    /// Read-only; a keg missing from the cellar reports as unlinked with zero
+    /// usage instead of failing the listing.
+    pub fn list_installed_detailed(&self) -> Result<Vec<InstalledEntry>, Error> {
+        Ok(self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| {
+                let keg_name = formula_token(&keg.name);
+                let keg_path = self.cellar.keg_path(keg_name, &keg.version);
+                InstalledEntry {
+                    linked: self.linker.is_linked(&keg_path),
+                    pinned: false,
+                    explicit: keg.is_explicit,
+                    disk_usage: self.cellar.disk_usage(keg_name, &keg.version).unwrap_or(0),
+                    name: keg.name,
+                    version: keg.version,
+                    store_key: keg.store_key,
+                }
+            })
+            .collect())
+    }
+
+    /// Every recorded version per formula, newest (active) first. Unlike
+    /// `list_installed`, stale versions left by partial upgrades are included.
+    pub fn list_installed_versions(&self) -> Result<Vec<crate::storage::db::KegVersion>, Error> {
+        self.db.list_installed_versions()
+    }
+
     pub fn keg_path(&self, name: &str, version: &str) -> PathBuf {
         self.cellar.keg_path(name, version)
     }
 
-    fn cleanup_materialized(cellar: &Cellar, name: &str, version: &str) {
-        if let Err(e) = cellar.remove_keg(name, version) {
-            warn!(
-                formula = %name,
-                version = %version,
-                error = %e,
-                "failed to remove keg after install error"
-            );
+    /// Resolve `prefix/bin/<executable>` to the installed formula that owns it.
+    /// Returns `None` for plain files, links outside the cellar, or kegs that
+    /// are not recorded as installed.
+    pub fn which(&self, executable: &str) -> Option<String> {
+        if executable.is_empty() || executable.contains('/') {
+            return None;
         }
+
+        let keg_name = keg_name_from_symlink(&self.prefix.join("bin").join(executable))?;
+        let installed = self.db.list_installed().ok()?;
+
+        // Tap formulae are recorded under their full `owner/tap/name` install
+        // name but materialized into a cellar dir named after the token.
+        installed
+            .iter()
+            .find(|keg| keg.name == keg_name)
+            .or_else(|| {
+                installed
+                    .iter()
+                    .find(|keg| formula_token(&keg.name) == keg_name)
+            })
+            .map(|keg| keg.name.clone())
     }
 }
 
+/// Build an installer rooted at `root`. Bottle blobs go to `cache_dir` when
+/// given, otherwise `root/cache`. `paths` is checked with [`Paths::validate`]
+/// first, so a misplaced prefix fails here rather than while linking.
+///
+/// Download, mirror and API settings come from [`Config::load`];
+/// `concurrency`, when given, overrides the configured value.
+///
+/// Blobs are content-addressed and written via atomic rename, so several
+/// roots may share one `cache_dir`. Download dedup is per process, though:
+/// two installers fetching the same bottle at once will both download it.
+/// Pruning from one root only protects that root's installed blobs, so a
+/// shared cache may lose blobs another root would have reused.
 pub fn create_installer(
-    root: &Path,
-    prefix: &Path,
-    concurrency: usize,
+    paths: &Paths,
+    concurrency: Option<usize>,
+    cache_dir: Option<&Path>,
 ) -> Result<Installer, Error> {
+    paths.validate()?;
+    if paths.cellar_outside_prefix() {
+        warn!(
+            cellar = %paths.cellar.display(),
+            prefix = %paths.prefix.display(),
+            "cellar is outside the prefix; bottles expect it under the prefix and may fail to load"
+        );
+    }
+
+    let root = paths.root.as_path();
+    let prefix = paths.prefix.as_path();
     if !root.exists() {
         fs::create_dir_all(root).map_err(|e| {
             if e.kind() == std::io::ErrorKind::PermissionDenied {
@@ -299,34 +905,48 @@ pub fn create_installer(
         })?;
     }
 
-    fs::create_dir_all(root.join("db")).map_err(Error::store("failed to create db directory"))?;
+    if let Some(db_dir) = paths.db.parent() {
+        fs::create_dir_all(db_dir).map_err(Error::store("failed to create db directory"))?;
+    }
 
-    fs::create_dir_all(root.join("cache"))
-        .map_err(Error::store("failed to create cache directory"))?;
+    fs::create_dir_all(&paths.cache).map_err(Error::store("failed to create cache directory"))?;
 
-    let api_cache_path = root.join("cache/api-cache.sqlite");
     let api_cache =
-        ApiCache::open(&api_cache_path).map_err(Error::store("failed to open API cache"))?;
+        ApiCache::open(&paths.api_cache()).map_err(Error::store("failed to open API cache"))?;
 
-    let api_client = match std::env::var("ZEROBREW_API_URL") {
-        Ok(url) => ApiClient::with_base_url(url)?,
-        Err(_) => ApiClient::new(),
+    let mut config = Config::load(root);
+    let api_client = match &config.api_url {
+        Some(url) => ApiClient::with_base_url(url.clone())?,
+        None => ApiClient::new(),
     }
     .with_cache(api_cache);
 
+    let blob_cache_dir = cache_dir.unwrap_or(&paths.cache);
     let blob_cache =
-        BlobCache::new(&root.join("cache")).map_err(Error::store("failed to create blob cache"))?;
-    let store = Store::new(root).map_err(Error::store("failed to create store"))?;
-    // Use prefix/Cellar so bottles' hardcoded rpaths work
+        BlobCache::new(blob_cache_dir).map_err(Error::store("failed to create blob cache"))?;
+    let store = Store::new_at(paths.store.clone(), paths.locks.clone())
+        .map_err(Error::store("failed to create store"))?
+        .with_verification(config.verify_store);
     let cellar =
-        Cellar::new_at(prefix.join("Cellar")).map_err(Error::store("failed to create cellar"))?;
+        Cellar::new_at(paths.cellar.clone()).map_err(Error::store("failed to create cellar"))?;
     let linker = Linker::new(prefix).map_err(Error::store("failed to create linker"))?;
-    let db = Database::open(&root.join("db/zb.sqlite3"))?;
+    let db = Database::open(&paths.db)?;
 
-    let locks_dir = root.join("locks");
+    let locks_dir = paths.locks.clone();
     fs::create_dir_all(&locks_dir).map_err(Error::store("failed to create locks directory"))?;
 
-    let parallel_downloader = ParallelDownloader::with_concurrency(blob_cache, concurrency);
+    config.concurrency = concurrency.unwrap_or(config.concurrency);
+    let mut parallel_downloader =
+        ParallelDownloader::with_concurrency(blob_cache, config.concurrency)
+            .with_config(config.download)
+            .with_mirrors(&config.mirrors)
+            .with_tls_options(&TlsOptions {
+                prefix: Some(prefix.to_path_buf()),
+                ..Default::default()
+            })?;
+    if let Some(base) = &config.ghcr_url {
+        parallel_downloader = parallel_downloader.with_registry_base(base)?;
+    }
 
     Ok(Installer {
         api_client,
@@ -337,6 +957,18 @@ pub fn create_installer(
         db,
         prefix: prefix.to_path_buf(),
         locks_dir,
+        logger: LoggerHandle::default(),
+        offline: false,
+        bottle_corruption_source_fallback: false,
+        bottle_tag: None,
+        extraction_concurrency: DEFAULT_EXTRACTION_CONCURRENCY,
+        force_reinstall: false,
+        keep_going: false,
+        dependency_policy: DependencyPolicy::default(),
+        build_phase_timeout: None,
+        build_log_dir: paths.build_logs(),
+        cask_binaries: None,
+        config,
     })
 }
 
@@ -396,13 +1028,15 @@ mod tests {
     use tempfile::TempDir;
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
+    use zb_core::{Error, InstallMethod, Paths};
 
     use crate::cellar::Cellar;
     use crate::network::api::ApiClient;
+    use crate::progress::{InstallProgress, ProgressCallback};
     use crate::storage::blob::BlobCache;
     use crate::storage::db::Database;
     use crate::storage::store::Store;
-    use crate::{Installer, Linker};
+    use crate::{Installer, Linker, create_installer};
 
     use super::test_support::*;
 
@@ -754,7 +1388,13 @@ mod tests {
         );
 
         let conn = rusqlite::Connection::open(&db_path).unwrap();
-        conn.execute("DROP TABLE installed_kegs", []).unwrap();
+        // Planning still reads installed_kegs, so fail only the write.
+        conn.execute(
+            "CREATE TRIGGER fail_install BEFORE INSERT ON installed_kegs \
+             BEGIN SELECT RAISE(ABORT, 'disk full'); END",
+            [],
+        )
+        .unwrap();
 
         let result = installer.install(&["rollbackme".to_string()], true).await;
         assert!(result.is_err());
@@ -828,7 +1468,13 @@ end
         );
 
         let conn = rusqlite::Connection::open(&db_path).unwrap();
-        conn.execute("DROP TABLE installed_kegs", []).unwrap();
+        // Planning still reads installed_kegs, so fail only the write.
+        conn.execute(
+            "CREATE TRIGGER fail_install BEFORE INSERT ON installed_kegs \
+             BEGIN SELECT RAISE(ABORT, 'disk full'); END",
+            [],
+        )
+        .unwrap();
 
         let result = installer
             .install(&["hashicorp/tap/terraform".to_string()], true)
@@ -1036,6 +1682,158 @@ end
         assert!(prefix.join("bin/slowpkg").exists());
     }
 
+    #[tokio::test]
+    async fn bounded_extraction_installs_every_bottle() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+        let names = ["alpha", "beta", "gamma"];
+
+        for name in names {
+            let bottle = create_bottle_tarball(name);
+            let sha = sha256_hex(&bottle);
+            let json = format!(
+                r#"{{"name":"{name}","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/{name}.tar.gz","sha256":"{sha}"}}}}}}}}}}"#,
+                mock_server.uri(),
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(&json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        )
+        .with_extraction_concurrency(1);
+
+        let requested: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let result = installer.install(&requested, true).await.unwrap();
+
+        assert_eq!(result.installed, names.len());
+        for name in names {
+            assert!(installer.db.get_installed(name).is_some());
+            assert!(root.join(format!("cellar/{name}/1.0.0")).exists());
+            assert!(prefix.join(format!("bin/{name}")).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn install_local_adopts_bottle_without_downloading() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("localpkg");
+        let bottle_sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{"name":"localpkg","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/localpkg.tar.gz","sha256":"{bottle_sha}"}}}}}}}}}}"#,
+            mock_server.uri(),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/localpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/localpkg.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let local = tmp
+            .path()
+            .join(format!("localpkg--1.0.0.{tag}.bottle.tar.gz"));
+        fs::write(&local, &bottle).unwrap();
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let wrong_sha = "0".repeat(64);
+        let result = installer.install_local(&local, &wrong_sha).await;
+        assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
+
+        let result = installer.install_local(&local, &bottle_sha).await.unwrap();
+        assert_eq!(result.installed, 1);
+        assert!(installer.db.get_installed("localpkg").is_some());
+        assert!(prefix.join("bin/localpkg").exists());
+    }
+
+    #[tokio::test]
+    async fn install_local_rejects_bottle_that_differs_from_formula() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("localpkg");
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{"name":"localpkg","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/localpkg.tar.gz","sha256":"{}"}}}}}}}}}}"#,
+            mock_server.uri(),
+            "a".repeat(64)
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/localpkg.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let local = tmp
+            .path()
+            .join(format!("localpkg--1.0.0.{tag}.bottle.tar.gz"));
+        fs::write(&local, &bottle).unwrap();
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let result = installer.install_local(&local, &sha256_hex(&bottle)).await;
+        assert!(matches!(result, Err(Error::InvalidArgument { .. })));
+        assert!(installer.db.get_installed("localpkg").is_none());
+    }
+
     #[tokio::test]
     async fn retries_on_corrupted_download() {
         let mock_server = MockServer::start().await;
@@ -1127,4 +1925,969 @@ end
         // Validates the retry mechanism structure -- proper integration test
         // would need injection of corruption between download and extraction.
     }
+
+    #[tokio::test]
+    async fn source_fallback_replans_corrupt_bottle_only_when_enabled() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "rotten",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "urls": {{
+                    "stable": {{
+                        "url": "https://example.com/rotten-1.0.0.tar.gz",
+                        "checksum": "abc123"
+                    }}
+                }},
+                "ruby_source_path": "Formula/r/rotten.rb",
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/rotten-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+            "a".repeat(64)
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/rotten.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let plan = installer.plan(&["rotten".to_string()]).await.unwrap();
+        let item = &plan.items[0];
+        assert!(matches!(item.method, InstallMethod::Bottle(_)));
+
+        let corruption = Error::StoreCorruption {
+            message: "failed after 3 attempts".to_string(),
+        };
+        assert!(installer.source_fallback_for(item, &corruption).is_none());
+
+        let installer = installer.with_bottle_corruption_source_fallback(true);
+        let fallback = installer.source_fallback_for(item, &corruption).unwrap();
+        assert_eq!(fallback.install_name, "rotten");
+        assert!(matches!(fallback.method, InstallMethod::Source(_)));
+    }
+
+    #[tokio::test]
+    async fn plan_honors_explicit_bottle_tag() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let formula_json = format!(
+            r#"{{
+                "name": "crossy",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "arm64_sonoma": {{
+                                "url": "{0}/bottles/crossy-1.0.0.arm64_sonoma.bottle.tar.gz",
+                                "sha256": "{1}"
+                            }},
+                            "x86_64_linux": {{
+                                "url": "{0}/bottles/crossy-1.0.0.x86_64_linux.bottle.tar.gz",
+                                "sha256": "{1}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+            "b".repeat(64)
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/crossy.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        for tag in ["arm64_sonoma", "x86_64_linux"] {
+            installer = installer.with_bottle_tag(Some(tag.to_string()));
+            let plan = installer.plan(&["crossy".to_string()]).await.unwrap();
+            match &plan.items[0].method {
+                InstallMethod::Bottle(bottle) => assert_eq!(bottle.tag, tag),
+                other => panic!("expected bottle, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn which_maps_executable_to_owning_install_name() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+
+        let keg = root.join("cellar/terraform/1.10.0");
+        fs::create_dir_all(keg.join("bin")).unwrap();
+        fs::write(keg.join("bin/terraform"), b"#!/bin/sh").unwrap();
+        std::os::unix::fs::symlink(keg.join("bin/terraform"), prefix.join("bin/terraform"))
+            .unwrap();
+
+        let outside = tmp.path().join("elsewhere/tool");
+        fs::create_dir_all(outside.parent().unwrap()).unwrap();
+        fs::write(&outside, b"#!/bin/sh").unwrap();
+        std::os::unix::fs::symlink(&outside, prefix.join("bin/tool")).unwrap();
+        fs::write(prefix.join("bin/plain"), b"#!/bin/sh").unwrap();
+
+        let tx = db.transaction().unwrap();
+        tx.record_install("hashicorp/tap/terraform", "1.10.0", "sha", true)
+            .unwrap();
+        tx.commit().unwrap();
+
+        let installer = Installer::new(
+            ApiClient::new(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        assert_eq!(
+            installer.which("terraform").as_deref(),
+            Some("hashicorp/tap/terraform")
+        );
+        assert_eq!(installer.which("tool"), None);
+        assert_eq!(installer.which("plain"), None);
+        assert_eq!(installer.which("missing"), None);
+    }
+
+    #[test]
+    fn relink_all_restores_recorded_links_and_collects_conflicts() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+
+        for (name, version) in [("jq", "1.7.1"), ("openssl@3", "3.4.0"), ("clash", "1.0.0")] {
+            let keg = root.join(format!("cellar/{name}/{version}"));
+            fs::create_dir_all(keg.join("bin")).unwrap();
+            fs::write(keg.join(format!("bin/{name}")), b"#!/bin/sh").unwrap();
+        }
+        fs::write(prefix.join("bin/clash"), b"not ours").unwrap();
+
+        let tx = db.transaction().unwrap();
+        for (name, version) in [("jq", "1.7.1"), ("openssl@3", "3.4.0"), ("clash", "1.0.0")] {
+            tx.record_install(name, version, "sha", true).unwrap();
+        }
+        // openssl@3 is keg-only, so it has no recorded links.
+        for (name, version) in [("jq", "1.7.1"), ("clash", "1.0.0")] {
+            tx.record_linked_file(
+                name,
+                version,
+                &prefix.join(format!("bin/{name}")).to_string_lossy(),
+                &root
+                    .join(format!("cellar/{name}/{version}/bin/{name}"))
+                    .to_string_lossy(),
+                None,
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::new(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let err = installer.relink_all().unwrap_err();
+        let Error::LinkConflict { conflicts } = err else {
+            panic!("expected LinkConflict, got {err:?}");
+        };
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].path, prefix.join("bin/clash"));
+
+        assert!(prefix.join("bin/jq").is_symlink());
+        assert!(!prefix.join("bin/openssl@3").exists());
+        assert!(prefix.join("opt/openssl@3").is_symlink());
+
+        fs::remove_file(prefix.join("bin/clash")).unwrap();
+        assert_eq!(installer.relink_all().unwrap(), 2);
+        assert!(prefix.join("bin/clash").is_symlink());
+        assert!(prefix.join("bin/jq").is_symlink());
+    }
+
+    #[test]
+    fn create_installer_shares_blob_cache_dir_across_roots() {
+        use std::io::Write;
+
+        let tmp = TempDir::new().unwrap();
+        let shared = tmp.path().join("shared-cache");
+
+        let first = create_installer(
+            &Paths::new(tmp.path().join("root-a"), tmp.path().join("prefix-a")),
+            Some(4),
+            Some(&shared),
+        )
+        .unwrap();
+        let second = create_installer(
+            &Paths::new(tmp.path().join("root-b"), tmp.path().join("prefix-b")),
+            Some(4),
+            Some(&shared),
+        )
+        .unwrap();
+
+        let mut writer = first.downloader.blob_cache().start_write("abc123").unwrap();
+        writer.write_all(b"bottle").unwrap();
+        let blob = writer.commit().unwrap();
+
+        assert!(blob.starts_with(&shared));
+        assert!(second.downloader.blob_cache().has_blob("abc123"));
+        assert!(!tmp.path().join("root-a/cache/blobs").exists());
+        assert!(tmp.path().join("root-a/cache/api-cache.sqlite").exists());
+
+        assert!(second.downloader.remove_blob("abc123"));
+        assert!(!first.downloader.remove_blob("abc123"));
+    }
+
+    #[tokio::test]
+    async fn upgrade_cask_moves_links_to_new_version() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let mount_cask = |version: &'static str| {
+            let server = &mock_server;
+            let binary = format!("#!/bin/sh\necho {version}\n").into_bytes();
+            let sha = sha256_hex(&binary);
+            let json = format!(
+                r#"{{"token":"op","version":"{version}","url":"{}/downloads/op-{version}","sha256":"{sha}","artifacts":[{{"binary":[["op"]]}}]}}"#,
+                server.uri(),
+            );
+            async move {
+                Mock::given(method("GET"))
+                    .and(path("/cask/op.json"))
+                    .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                    .mount(server)
+                    .await;
+                Mock::given(method("GET"))
+                    .and(path(format!("/downloads/op-{version}")))
+                    .respond_with(ResponseTemplate::new(200).set_body_bytes(binary))
+                    .mount(server)
+                    .await;
+            }
+        };
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+                .unwrap()
+                .with_cask_base_url(format!("{}/cask", mock_server.uri())),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        mount_cask("1.0.0").await;
+        installer
+            .install(&["cask:op".to_string()], true)
+            .await
+            .unwrap();
+        assert!(
+            installer
+                .upgrade_cask("cask:op", true)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        mock_server.reset().await;
+        mount_cask("2.0.0").await;
+
+        let (outdated, warnings) = installer.check_outdated().await.unwrap();
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(outdated.len(), 1);
+        assert_eq!(outdated[0].name, "cask:op");
+        assert_eq!(outdated[0].current_version, "2.0.0");
+
+        let upgraded = installer
+            .upgrade_cask("cask:op", true)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(upgraded.installed_version, "1.0.0");
+
+        assert_eq!(
+            installer.db.get_installed("cask:op").unwrap().version,
+            "2.0.0"
+        );
+        assert!(!root.join("cellar/cask:op/1.0.0").exists());
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/op")).unwrap(),
+            "#!/bin/sh\necho 2.0.0\n"
+        );
+        assert!(installer.is_outdated("cask:op").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn reinstall_skips_installed_formula_but_links_it() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let bottle = create_bottle_tarball("settled");
+        let sha = sha256_hex(&bottle);
+        let json = format!(
+            r#"{{"name":"settled","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/settled.tar.gz","sha256":"{sha}"}}}}}}}}}}"#,
+            mock_server.uri(),
+        );
+        Mock::given(method("GET"))
+            .and(path("/formula/settled.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/settled.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let names = vec!["settled".to_string()];
+        installer.install(&names, false).await.unwrap();
+        assert!(!prefix.join("bin/settled").exists());
+
+        let plan = installer.plan(&names).await.unwrap();
+        assert!(plan.items.is_empty());
+        assert_eq!(plan.already_installed.len(), 1);
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = events.clone();
+        let callback: Arc<ProgressCallback> = Arc::new(Box::new(move |event| {
+            if let InstallProgress::AlreadyInstalled { name, version } = event {
+                seen.lock().unwrap().push(format!("{name} {version}"));
+            }
+        }));
+        let result = installer
+            .execute_with_progress(plan, true, Some(callback))
+            .await
+            .unwrap();
+
+        assert_eq!(result.installed, 0);
+        assert_eq!(*events.lock().unwrap(), vec!["settled 1.0.0"]);
+        assert!(prefix.join("bin/settled").is_symlink());
+
+        installer = installer.with_force_reinstall(true);
+        let plan = installer.plan(&names).await.unwrap();
+        assert_eq!(plan.items.len(), 1);
+        assert!(plan.already_installed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn prefetch_fills_blob_cache_without_installing() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let bottle = create_bottle_tarball("warmed");
+        let sha = sha256_hex(&bottle);
+        let json = format!(
+            r#"{{"name":"warmed","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/warmed.tar.gz","sha256":"{sha}"}}}}}}}}}}"#,
+            mock_server.uri(),
+        );
+        Mock::given(method("GET"))
+            .and(path("/formula/warmed.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/warmed.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let names = vec!["warmed".to_string()];
+        installer.prefetch(&names).await.unwrap();
+
+        assert!(installer.downloader.blob_cache().has_blob(&sha));
+        assert!(!installer.is_installed("warmed"));
+        assert!(!root.join("cellar/warmed").exists());
+
+        installer.install(&names, true).await.unwrap();
+        assert!(installer.is_installed("warmed"));
+    }
+
+    #[tokio::test]
+    async fn reports_every_failed_package_by_install_name() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let good_bottle = create_bottle_tarball("goodpkg");
+        let good_sha = sha256_hex(&good_bottle);
+        for (name, sha) in [
+            ("goodpkg", good_sha.clone()),
+            ("badpkg", "a".repeat(64)),
+            ("worsepkg", "b".repeat(64)),
+        ] {
+            let json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{tag}": {{
+                                    "url": "{}/bottles/{name}-1.0.0.{tag}.bottle.tar.gz",
+                                    "sha256": "{sha}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                mock_server.uri(),
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/goodpkg-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(good_bottle))
+            .mount(&mock_server)
+            .await;
+        for name in ["badpkg", "worsepkg"] {
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz")))
+                .respond_with(ResponseTemplate::new(404).set_body_string("gone"))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        )
+        .with_keep_going(true);
+
+        let names = ["goodpkg", "badpkg", "worsepkg"].map(String::from);
+        let err = installer.install(&names, false).await.unwrap_err();
+
+        let Error::InstallFailed { failed } = err else {
+            panic!("expected InstallFailed, got {err:?}");
+        };
+        let mut failed_names: Vec<_> = failed.into_iter().map(|(name, _)| name).collect();
+        failed_names.sort();
+        assert_eq!(failed_names, ["badpkg", "worsepkg"]);
+        assert!(installer.db.get_installed("goodpkg").is_some());
+    }
+
+    #[test]
+    fn keg_identity_agrees_for_versioned_tap_formula_across_methods() {
+        let formula: zb_core::Formula = serde_json::from_value(serde_json::json!({
+            "name": "foo@2",
+            "versions": { "stable": "2.1.0" },
+            "revision": 1,
+            "dependencies": [],
+            "urls": { "stable": { "url": "https://example.com/foo-2.1.0.tar.gz" } },
+            "bottle": { "stable": { "files": {} } }
+        }))
+        .unwrap();
+        let planned = |method| super::PlannedInstall {
+            install_name: "user/tap/foo@2".to_string(),
+            formula: formula.clone(),
+            method,
+            explicit: true,
+        };
+
+        let bottle = planned(InstallMethod::Bottle(zb_core::SelectedBottle {
+            tag: "all".to_string(),
+            url: "https://example.com/foo@2-2.1.0_1.all.bottle.tar.gz".to_string(),
+            sha256: "abc".to_string(),
+            rebuild: 0,
+            mirror_urls: Vec::new(),
+            cellar: zb_core::CellarRequirement::Any,
+        }))
+        .keg_identity();
+        let source = planned(InstallMethod::Source(
+            zb_core::BuildPlan::from_formula(&formula, std::path::Path::new("/opt/zb")).unwrap(),
+        ))
+        .keg_identity();
+
+        assert_eq!(bottle.keg_name, "foo@2");
+        assert_eq!(bottle.version, "2.1.0_1");
+        assert_eq!(bottle.store_key, "abc");
+        assert_eq!(source.keg_name, bottle.keg_name);
+        assert_eq!(source.version, bottle.version);
+        assert_eq!(source.store_key, "source:foo@2:2.1.0_1");
+    }
+
+    #[tokio::test]
+    async fn cask_info_resolves_without_installing() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/cask/op.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+                r#"{{"token":"op","version":"1.0.0","url":"{}/downloads/op","sha256":"aaaa","artifacts":[{{"binary":["op","$APPDIR/Op.app/op"]}}]}}"#,
+                mock_server.uri(),
+            )))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+                .unwrap()
+                .with_cask_base_url(format!("{}/cask", mock_server.uri())),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let cask = installer.cask_info("cask:op").await.unwrap();
+        assert_eq!(cask.install_name, "cask:op");
+        assert_eq!(cask.version, "1.0.0");
+        assert_eq!(cask.binaries.len(), 2);
+
+        let warnings = cask.staging_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("APPDIR"), "{warnings:?}");
+        assert!(!installer.is_installed("cask:op"));
+    }
+
+    #[tokio::test]
+    async fn link_record_failure_leaves_no_rows_for_the_formula() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("halflinked");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "halflinked",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/halflinked-1.0.0.{}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            tag,
+            bottle_sha
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/halflinked.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/halflinked-1.0.0.{}.bottle.tar.gz",
+                tag
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let db_path = root.join("db/zb.sqlite3");
+        let api_client =
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
+        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let store = Store::new(&root).unwrap();
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let db = Database::open(&db_path).unwrap();
+
+        let mut installer = Installer::new(
+            api_client,
+            blob_cache,
+            store,
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        // The install row is written first; failing the linked-file rows
+        // afterwards must take it down with them.
+        let conn = rusqlite::Connection::open(&db_path).unwrap();
+        conn.execute(
+            "CREATE TRIGGER fail_links BEFORE INSERT ON keg_files
+             BEGIN SELECT RAISE(ABORT, 'injected failure'); END",
+            [],
+        )
+        .unwrap();
+
+        let result = installer.install(&["halflinked".to_string()], true).await;
+        assert!(result.is_err());
+
+        assert!(installer.db.get_installed("halflinked").is_none());
+        assert!(
+            installer
+                .db
+                .linked_files("halflinked", "1.0.0")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(!root.join("cellar/halflinked/1.0.0").exists());
+        assert!(!prefix.join("bin/halflinked").exists());
+        assert!(!prefix.join("opt/halflinked").exists());
+    }
+
+    #[test]
+    fn list_installed_detailed_tolerates_missing_kegs() {
+        let tmp = TempDir::new().unwrap();
+        let mut installer = create_installer(
+            &Paths::new(tmp.path().join("zerobrew"), tmp.path().join("prefix")),
+            Some(4),
+            None,
+        )
+        .unwrap();
+
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install("present", "1.0.0", "aaa", true).unwrap();
+        tx.record_install("vanished", "2.0.0", "bbb", false)
+            .unwrap();
+        tx.commit().unwrap();
+
+        let keg_bin = installer.keg_path("present", "1.0.0").join("bin");
+        fs::create_dir_all(&keg_bin).unwrap();
+        fs::write(keg_bin.join("present"), b"#!/bin/sh\n").unwrap();
+        installer
+            .linker
+            .link_keg(&installer.keg_path("present", "1.0.0"))
+            .unwrap();
+
+        let entries = installer.list_installed_detailed().unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let present = entries.iter().find(|e| e.name == "present").unwrap();
+        assert!(present.linked);
+        assert!(present.explicit);
+        assert!(present.disk_usage > 0);
+
+        let vanished = entries.iter().find(|e| e.name == "vanished").unwrap();
+        assert!(!vanished.linked);
+        assert!(!vanished.explicit);
+        assert_eq!(vanished.disk_usage, 0);
+
+        let json = serde_json::to_value(vanished).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "vanished",
+                "version": "2.0.0",
+                "store_key": "bbb",
+                "linked": false,
+                "pinned": false,
+                "explicit": false,
+                "disk_usage": 0,
+            })
+        );
+    }
+
+    #[test]
+    fn create_installer_rejects_prefix_inside_root_state() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+
+        let err = create_installer(
+            &Paths::new(root.clone(), root.join("cache/prefix")),
+            Some(4),
+            None,
+        )
+        .err()
+        .unwrap();
+
+        assert!(matches!(err, Error::InvalidArgument { .. }));
+        assert!(!root.exists());
+    }
+
+    #[tokio::test]
+    async fn first_failure_stops_the_batch_unless_keep_going() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        // Source-only formulae without a ruby_source_path fail at once, with
+        // no network or build toolchain involved.
+        for name in ["broken-a", "broken-b"] {
+            let json = format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    "urls": {{ "stable": {{ "url": "https://example.com/{name}.tar.gz" }} }},
+                    "bottle": {{ "stable": {{ "files": {{}} }} }}
+                }}"#
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        let installer = |keep_going: bool| {
+            Installer::new(
+                ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+                BlobCache::new(&root.join("cache")).unwrap(),
+                Store::new(&root).unwrap(),
+                Cellar::new(&root).unwrap(),
+                Linker::new(&prefix).unwrap(),
+                Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+                prefix.clone(),
+                root.join("locks"),
+            )
+            .with_keep_going(keep_going)
+        };
+        let names = ["broken-a", "broken-b"].map(String::from);
+
+        let err = installer(false).install(&names, false).await.unwrap_err();
+        assert!(
+            matches!(err, Error::ExecutionError { ref message } if message.contains("broken-a")),
+            "{err:?}"
+        );
+
+        let err = installer(true).install(&names, false).await.unwrap_err();
+        let Error::InstallFailed { failed } = err else {
+            panic!("expected InstallFailed, got {err:?}");
+        };
+        let failed_names: Vec<_> = failed.into_iter().map(|(name, _)| name).collect();
+        assert_eq!(failed_names, ["broken-a", "broken-b"]);
+    }
+
+    #[test]
+    fn overwrite_link_switches_ownership_of_a_shared_binary() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        fs::create_dir_all(root.join("locks")).unwrap();
+
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("node", "22.0.0", "node-key", true)
+                .unwrap();
+            tx.record_install("node@20", "20.0.0", "node20-key", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        for keg in ["node/22.0.0", "node@20/20.0.0"] {
+            let bin = root.join("cellar").join(keg).join("bin");
+            fs::create_dir_all(&bin).unwrap();
+            fs::write(bin.join("node"), keg).unwrap();
+        }
+
+        let mut installer = Installer::new(
+            ApiClient::new(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+        let node_link = prefix.join("bin/node");
+        let linked_paths = |installer: &Installer, name: &str| -> Vec<std::path::PathBuf> {
+            installer
+                .list_linked(name)
+                .unwrap()
+                .into_iter()
+                .map(|link| link.link_path)
+                .collect()
+        };
+
+        installer.link("node").unwrap();
+        assert_eq!(fs::read_to_string(&node_link).unwrap(), "node/22.0.0");
+
+        let err = installer.link("node@20").unwrap_err();
+        assert!(matches!(err, Error::LinkConflict { .. }));
+        assert_eq!(fs::read_to_string(&node_link).unwrap(), "node/22.0.0");
+
+        installer.overwrite_link("node@20").unwrap();
+        assert_eq!(fs::read_to_string(&node_link).unwrap(), "node@20/20.0.0");
+        assert_eq!(
+            linked_paths(&installer, "node@20"),
+            std::slice::from_ref(&node_link)
+        );
+        assert!(linked_paths(&installer, "node").is_empty());
+
+        installer.overwrite_link("node").unwrap();
+        assert_eq!(fs::read_to_string(&node_link).unwrap(), "node/22.0.0");
+        assert_eq!(
+            linked_paths(&installer, "node"),
+            std::slice::from_ref(&node_link)
+        );
+        assert!(linked_paths(&installer, "node@20").is_empty());
+    }
+
+    #[test]
+    fn overwrite_link_still_refuses_files_zerobrew_does_not_own() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("node", "22.0.0", "node-key", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+        let bin = root.join("cellar/node/22.0.0/bin");
+        fs::create_dir_all(&bin).unwrap();
+        fs::write(bin.join("node"), "zerobrew").unwrap();
+        fs::create_dir_all(prefix.join("bin")).unwrap();
+        fs::write(prefix.join("bin/node"), "user's own").unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::new(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        let err = installer.overwrite_link("node").unwrap_err();
+        assert!(matches!(err, Error::LinkConflict { .. }));
+        assert_eq!(
+            fs::read_to_string(prefix.join("bin/node")).unwrap(),
+            "user's own"
+        );
+    }
 }
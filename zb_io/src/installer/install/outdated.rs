@@ -1,6 +1,9 @@
 use std::collections::HashMap;
 
-use zb_core::{Error, select_bottle};
+use zb_core::{Error, Formula, select_bottle};
+
+use crate::installer::cask::resolve_cask;
+use crate::storage::db::InstalledKeg;
 
 use super::{Installer, OutdatedPackage};
 
@@ -10,44 +13,77 @@ impl Installer {
             name: name.to_string(),
         })?;
 
+        if installed.name.starts_with("cask:") {
+            return self.outdated_cask(installed).await;
+        }
+
         let formula = self.api_client.get_formula(name).await?;
-        let is_source = installed.store_key.starts_with("source:");
+        self.compare_formula(&installed, &formula)
+    }
 
-        if is_source {
-            let current_version = formula.effective_version();
-            if installed.version == current_version {
-                Ok(None)
-            } else {
-                Ok(Some(OutdatedPackage {
-                    name: name.to_string(),
-                    installed_version: installed.version,
-                    installed_sha256: installed.store_key,
-                    current_version,
-                    current_sha256: String::new(),
-                    is_source_build: true,
-                }))
-            }
-        } else {
-            let bottle = select_bottle(&formula)?;
-            if installed.store_key == bottle.sha256 {
-                Ok(None)
-            } else {
-                Ok(Some(OutdatedPackage {
-                    name: name.to_string(),
-                    installed_version: installed.version,
-                    installed_sha256: installed.store_key,
-                    current_version: formula.effective_version(),
-                    current_sha256: bottle.sha256,
-                    is_source_build: false,
-                }))
+    /// Compare an installed keg against the formula upstream. Bottle installs
+    /// are compared by checksum, so a rebuilt bottle at the same version is
+    /// reported too; source builds can only be compared by version.
+    fn compare_formula(
+        &self,
+        keg: &InstalledKeg,
+        formula: &Formula,
+    ) -> Result<Option<OutdatedPackage>, Error> {
+        let current_version = formula.effective_version();
+
+        if keg.store_key.starts_with("source:") {
+            if keg.version == current_version {
+                return Ok(None);
             }
+            return Ok(Some(OutdatedPackage {
+                name: keg.name.clone(),
+                installed_version: keg.version.clone(),
+                installed_sha256: keg.store_key.clone(),
+                current_version,
+                current_sha256: String::new(),
+                current_rebuild: 0,
+                is_source_build: true,
+            }));
         }
+
+        let bottle = select_bottle(formula, self.bottle_tag.as_deref())?;
+        if keg.store_key == bottle.sha256 {
+            return Ok(None);
+        }
+
+        Ok(Some(OutdatedPackage {
+            name: keg.name.clone(),
+            installed_version: keg.version.clone(),
+            installed_sha256: keg.store_key.clone(),
+            current_version,
+            current_sha256: bottle.sha256,
+            current_rebuild: bottle.rebuild,
+            is_source_build: false,
+        }))
     }
 
     pub async fn check_outdated(&self) -> Result<(Vec<OutdatedPackage>, Vec<String>), Error> {
-        let installed = self.db.list_installed()?;
+        let (casks, installed): (Vec<_>, Vec<_>) = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .partition(|keg| keg.name.starts_with("cask:"));
+
+        let mut outdated = Vec::new();
+        let mut warnings = Vec::new();
+
+        for keg in casks {
+            let name = keg.name.clone();
+            match self.outdated_cask(keg).await {
+                Ok(Some(pkg)) => outdated.push(pkg),
+                Ok(None) => {}
+                Err(e) => warnings.push(format!("{name}: {e}")),
+            }
+        }
+
         if installed.is_empty() {
-            return Ok((Vec::new(), Vec::new()));
+            outdated.sort_by(|a, b| a.name.cmp(&b.name));
+            return Ok((outdated, warnings));
         }
 
         let installed_names: std::collections::HashSet<&str> =
@@ -68,9 +104,6 @@ impl Installer {
             }
         }
 
-        let mut outdated = Vec::new();
-        let mut warnings = Vec::new();
-
         for keg in &installed {
             let is_tap = keg.name.contains('/');
 
@@ -86,36 +119,10 @@ impl Installer {
                 bulk_map.remove(&keg.name).unwrap()
             };
 
-            let is_source = keg.store_key.starts_with("source:");
-
-            if is_source {
-                let current_version = formula.effective_version();
-                if keg.version != current_version {
-                    outdated.push(OutdatedPackage {
-                        name: keg.name.clone(),
-                        installed_version: keg.version.clone(),
-                        installed_sha256: keg.store_key.clone(),
-                        current_version,
-                        current_sha256: String::new(),
-                        is_source_build: true,
-                    });
-                }
-            } else {
-                match select_bottle(&formula) {
-                    Ok(bottle) => {
-                        if keg.store_key != bottle.sha256 {
-                            outdated.push(OutdatedPackage {
-                                name: keg.name.clone(),
-                                installed_version: keg.version.clone(),
-                                installed_sha256: keg.store_key.clone(),
-                                current_version: formula.effective_version(),
-                                current_sha256: bottle.sha256,
-                                is_source_build: false,
-                            });
-                        }
-                    }
-                    Err(e) => warnings.push(format!("{}: {}", keg.name, e)),
-                }
+            match self.compare_formula(keg, &formula) {
+                Ok(Some(pkg)) => outdated.push(pkg),
+                Ok(None) => {}
+                Err(e) => warnings.push(format!("{}: {}", keg.name, e)),
             }
         }
 
@@ -123,6 +130,27 @@ impl Installer {
         Ok((outdated, warnings))
     }
 
+    /// Compare an installed cask against the version and checksum the cask
+    /// API currently resolves to for this platform.
+    async fn outdated_cask(&self, keg: InstalledKeg) -> Result<Option<OutdatedPackage>, Error> {
+        let token = keg.name.strip_prefix("cask:").unwrap_or(&keg.name);
+        let cask = resolve_cask(token, &self.api_client.get_cask(token).await?)?;
+
+        if keg.version == cask.version && keg.store_key == cask.sha256 {
+            return Ok(None);
+        }
+
+        Ok(Some(OutdatedPackage {
+            name: keg.name,
+            installed_version: keg.version,
+            installed_sha256: keg.store_key,
+            current_version: cask.version,
+            current_sha256: cask.sha256,
+            current_rebuild: 0,
+            is_source_build: false,
+        }))
+    }
+
     pub async fn suggest_formulas(&self, query: &str, limit: usize) -> Result<Vec<String>, Error> {
         self.api_client.suggest_formulas(query, limit).await
     }
@@ -246,7 +274,7 @@ mod tests {
 
         {
             let tx = installer.db.transaction().unwrap();
-            tx.record_install("jq", "1.7.1", sha).unwrap();
+            tx.record_install("jq", "1.7.1", sha, true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -268,7 +296,8 @@ mod tests {
 
         {
             let tx = installer.db.transaction().unwrap();
-            tx.record_install("jq", "1.7.0", "old_sha256").unwrap();
+            tx.record_install("jq", "1.7.0", "old_sha256", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
@@ -289,6 +318,43 @@ mod tests {
         assert!(!result.is_source_build);
     }
 
+    #[tokio::test]
+    async fn rebuilt_bottle_at_same_version_is_outdated() {
+        let (mut installer, mock_server, _tmp) = test_installer().await;
+
+        {
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install("jq", "1.7.1", "old_sha256", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let rebuilt = formula_json("jq", "1.7.1", "rebuilt_sha256")
+            .replace(r#""files""#, r#""rebuild": 1, "files""#);
+        Mock::given(method("GET"))
+            .and(path("/formula/jq.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&rebuilt))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!("[{rebuilt}]")))
+            .mount(&mock_server)
+            .await;
+
+        let result = installer.is_outdated("jq").await.unwrap().unwrap();
+        assert_eq!(result.installed_version, "1.7.1");
+        assert_eq!(result.current_version, "1.7.1");
+        assert_eq!(result.current_sha256, "rebuilt_sha256");
+        assert_eq!(result.current_rebuild, 1);
+        assert!(result.is_rebuild());
+
+        let (outdated, warnings) = installer.check_outdated().await.unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(outdated.len(), 1);
+        assert!(outdated[0].is_rebuild());
+    }
+
     #[tokio::test]
     async fn is_outdated_errors_for_not_installed() {
         let (installer, _mock_server, _tmp) = test_installer().await;
@@ -303,7 +369,8 @@ mod tests {
 
         {
             let tx = installer.db.transaction().unwrap();
-            tx.record_install("jq", "1.7.1", "source:jq:1.7.1").unwrap();
+            tx.record_install("jq", "1.7.1", "source:jq:1.7.1", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
@@ -327,7 +394,8 @@ mod tests {
 
         {
             let tx = installer.db.transaction().unwrap();
-            tx.record_install("jq", "1.6", "source:jq:1.6").unwrap();
+            tx.record_install("jq", "1.6", "source:jq:1.6", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
@@ -362,8 +430,8 @@ mod tests {
 
         {
             let tx = installer.db.transaction().unwrap();
-            tx.record_install("good", "1.0.0", "old_sha").unwrap();
-            tx.record_install("bad", "1.0.0", "old_sha").unwrap();
+            tx.record_install("good", "1.0.0", "old_sha", true).unwrap();
+            tx.record_install("bad", "1.0.0", "old_sha", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -393,7 +461,8 @@ mod tests {
 
         {
             let tx = installer.db.transaction().unwrap();
-            tx.record_install("nobottle", "1.0.0", "old_sha").unwrap();
+            tx.record_install("nobottle", "1.0.0", "old_sha", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
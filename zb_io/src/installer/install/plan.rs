@@ -1,70 +1,232 @@
 use std::collections::BTreeMap;
+use std::path::Path;
 
 use tracing::warn;
-use zb_core::{BuildPlan, Error, Formula, InstallMethod, select_bottle};
+use zb_core::{
+    BuildPlan, CellarRequirement, Error, Formula, InstallMethod, formula_token, select_bottle,
+};
 
-use super::{InstallPlan, Installer, PlannedInstall};
+use crate::extraction::patch::can_relocate_cellar;
+use crate::network::tap_formula::parse_tap_formula_ref;
+
+use super::{InstallPlan, Installer, KegIdentity, MethodPolicy, PackageKind, PlannedInstall};
 
 impl Installer {
+    /// Prefix bare names with `cask:` when `kind` asks for casks. Without an
+    /// explicit kind, a name that resolves as both a formula and a cask is
+    /// rejected instead of quietly being installed as the formula. Names that
+    /// are already `cask:` prefixed or tap-qualified are left alone.
+    pub async fn resolve_package_kinds(
+        &self,
+        names: &[String],
+        kind: Option<PackageKind>,
+    ) -> Result<Vec<String>, Error> {
+        let mut resolved = Vec::with_capacity(names.len());
+        for name in names {
+            if name.starts_with("cask:") || parse_tap_formula_ref(name).is_some() {
+                resolved.push(name.clone());
+                continue;
+            }
+
+            match kind {
+                Some(PackageKind::Cask) => resolved.push(format!("cask:{name}")),
+                Some(PackageKind::Formula) => resolved.push(name.clone()),
+                None => {
+                    // Casks are never cached, so there is nothing to compare
+                    // against offline; fall back to the formula.
+                    if !self.offline {
+                        let (formula, cask) = futures::join!(
+                            self.api_client.get_formula(name),
+                            self.api_client.get_cask(name)
+                        );
+                        if formula.is_ok() && cask.is_ok() {
+                            return Err(Error::AmbiguousName {
+                                name: name.clone(),
+                                candidates: Vec::new(),
+                            });
+                        }
+                    }
+                    resolved.push(name.clone());
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
     pub async fn plan(&self, names: &[String]) -> Result<InstallPlan, Error> {
-        self.plan_with_options(names, false).await
+        self.plan_with_options(names, MethodPolicy::default(), &self.installed_versions()?)
+            .await
     }
 
+    /// Plan `names` and whatever of their closure is missing. Dependencies in
+    /// `installed` (name to version, see
+    /// [`installed_versions`](Self::installed_versions)) are left out of the
+    /// plan together with everything only they depend on, without fetching
+    /// them. Requested names are always planned, so they can still be
+    /// reinstalled or upgraded.
     pub async fn plan_with_options(
         &self,
         names: &[String],
-        build_from_source: bool,
+        method_policy: MethodPolicy,
+        installed: &BTreeMap<String, String>,
     ) -> Result<InstallPlan, Error> {
-        let formulas = self.fetch_all_formulas(names).await?;
-        let ordered = zb_core::resolve_closure(names, &formulas)?;
+        let (names, formulas) = self.fetch_formulas_pruned(names, installed).await?;
+        let ordered =
+            zb_core::resolve_closure_pruned(&names, &formulas, self.dependency_policy, installed)?;
 
         let mut items = Vec::with_capacity(ordered.len());
+        let mut already_installed = Vec::new();
         for install_name in ordered {
             let formula = formulas.get(&install_name).cloned().unwrap();
-            let method = if build_from_source {
-                match BuildPlan::from_formula(&formula, &self.prefix) {
-                    Some(plan) => InstallMethod::Source(plan),
-                    None => match select_bottle(&formula) {
-                        Ok(bottle) => InstallMethod::Bottle(bottle),
-                        Err(_) => {
-                            return Err(Error::UnsupportedBottle {
-                                name: formula.name.clone(),
-                            });
-                        }
-                    },
-                }
-            } else {
-                match select_bottle(&formula) {
-                    Ok(bottle) => InstallMethod::Bottle(bottle),
-                    Err(_) => match BuildPlan::from_formula(&formula, &self.prefix) {
-                        Some(plan) => InstallMethod::Source(plan),
-                        None => {
-                            return Err(Error::UnsupportedBottle {
-                                name: formula.name.clone(),
-                            });
-                        }
-                    },
-                }
-            };
-            items.push(PlannedInstall {
+            let explicit = names.contains(&install_name);
+            let method = self.select_method(&formula, method_policy)?;
+            let item = PlannedInstall {
                 install_name,
                 formula,
                 method,
-            });
+                explicit,
+            };
+            if self.is_satisfied(&item) {
+                already_installed.push(item);
+            } else {
+                items.push(item);
+            }
+        }
+
+        Ok(InstallPlan {
+            items,
+            already_installed,
+        })
+    }
+
+    /// Pick how to install `formula` under `policy`. The `Prefer` policies
+    /// fall back to the other method; the `Force` ones fail instead.
+    ///
+    /// A bottle built for a fixed cellar other than ours is fine when it is a
+    /// Homebrew cellar that pouring relocates. Any other is only poured when
+    /// nothing else is allowed or available, with a warning, since it may
+    /// reference paths that relocation cannot rewrite.
+    fn select_method(
+        &self,
+        formula: &Formula,
+        policy: MethodPolicy,
+    ) -> Result<InstallMethod, Error> {
+        let selected = select_bottle(formula, self.bottle_tag.as_deref()).ok();
+        let cellar_dir = self.cellar.cellar_dir();
+        let misplaced = selected
+            .as_ref()
+            .and_then(|bottle| match &bottle.cellar {
+                CellarRequirement::Path(path)
+                    if !bottle.cellar.allows(cellar_dir)
+                        && !can_relocate_cellar(Path::new(path), &self.prefix) =>
+                {
+                    Some(path)
+                }
+                _ => None,
+            })
+            .cloned();
+        let bottle = || selected.clone().map(InstallMethod::Bottle);
+        let misplaced_bottle = || {
+            let method = bottle()?;
+            if let Some(path) = &misplaced {
+                warn!(
+                    formula = %formula.name,
+                    bottle_cellar = %path,
+                    cellar = %cellar_dir.display(),
+                    "bottle was built for a different cellar and may not work here"
+                );
+            }
+            Some(method)
+        };
+        let source = || BuildPlan::from_formula(formula, &self.prefix).map(InstallMethod::Source);
+
+        let method = match policy {
+            MethodPolicy::PreferBottle if misplaced.is_none() => bottle().or_else(source),
+            MethodPolicy::PreferBottle | MethodPolicy::PreferSource => {
+                source().or_else(misplaced_bottle)
+            }
+            MethodPolicy::ForceBottle => {
+                if let Some(path) = &misplaced {
+                    return Err(Error::UnsupportedFormula {
+                        name: formula.name.clone(),
+                        reason: format!(
+                            "its bottle only works from {path}, not {}",
+                            cellar_dir.display()
+                        ),
+                    });
+                }
+                bottle()
+            }
+            MethodPolicy::ForceSource => {
+                return source().ok_or_else(|| Error::UnsupportedFormula {
+                    name: formula.name.clone(),
+                    reason: "no source to build from".to_string(),
+                });
+            }
+        };
+        method.ok_or_else(|| Error::UnsupportedBottle {
+            name: formula.name.clone(),
+        })
+    }
+
+    /// Installed formulae by name with their installed version. Records
+    /// whose keg has gone missing are left out, so planning treats them as
+    /// not installed.
+    pub fn installed_versions(&self) -> Result<BTreeMap<String, String>, Error> {
+        Ok(self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|keg| {
+                !keg.name.starts_with("cask:")
+                    && self.cellar.has_keg(formula_token(&keg.name), &keg.version)
+            })
+            .map(|keg| (keg.name, keg.version))
+            .collect())
+    }
+
+    /// Whether `item` is installed at the version being planned. Requested
+    /// formulae are never satisfied when a reinstall is forced; dependencies
+    /// always are, so `--force` only reinstalls what was named. A record
+    /// whose keg has gone missing never counts.
+    fn is_satisfied(&self, item: &PlannedInstall) -> bool {
+        if item.explicit && self.force_reinstall {
+            return false;
         }
+        let KegIdentity {
+            keg_name, version, ..
+        } = item.keg_identity();
+        self.db
+            .get_installed(&item.install_name)
+            .is_some_and(|keg| keg.version == version)
+            && self.cellar.has_keg(&keg_name, &version)
+    }
 
-        Ok(InstallPlan { items })
+    /// Fetch `names` and their dependency closure. Formulae are keyed by
+    /// canonical name, so an alias such as `python` lands under the name it
+    /// resolves to; the returned roots are `names` with aliases translated.
+    pub(super) async fn fetch_all_formulas(
+        &self,
+        names: &[String],
+    ) -> Result<(Vec<String>, BTreeMap<String, Formula>), Error> {
+        self.fetch_formulas_pruned(names, &BTreeMap::new()).await
     }
 
-    async fn fetch_all_formulas(
+    /// Like [`fetch_all_formulas`](Self::fetch_all_formulas), but stop at
+    /// dependencies in `installed`: they and their own dependencies are not
+    /// fetched unless named in `names`.
+    async fn fetch_formulas_pruned(
         &self,
         names: &[String],
-    ) -> Result<BTreeMap<String, Formula>, Error> {
-        use std::collections::HashSet;
+        installed: &BTreeMap<String, String>,
+    ) -> Result<(Vec<String>, BTreeMap<String, Formula>), Error> {
+        use std::collections::{HashMap, HashSet};
 
         let mut formulas = BTreeMap::new();
+        let mut canonical: HashMap<String, String> = HashMap::new();
         let mut fetched: HashSet<String> = HashSet::new();
         let mut to_fetch: Vec<String> = names.to_vec();
+        let mut offline_misses: Vec<String> = Vec::new();
 
         while !to_fetch.is_empty() {
             let batch: Vec<String> = to_fetch
@@ -80,20 +242,21 @@ impl Installer {
                 fetched.insert(n.clone());
             }
 
-            let futures: Vec<_> = batch
-                .iter()
-                .map(|n| self.api_client.get_formula(n))
-                .collect();
-
-            let results = futures::future::join_all(futures).await;
+            let results = self.api_client.fetch_formula_batch(&batch).await;
 
             for (i, result) in results.into_iter().enumerate() {
                 let formula = match result {
                     Ok(f) => f,
+                    Err(Error::OfflineCacheMiss { name }) => {
+                        offline_misses.push(name);
+                        continue;
+                    }
                     Err(e) => return Err(e),
                 };
 
-                if select_bottle(&formula).is_err() && !formula.has_source_url() {
+                if select_bottle(&formula, self.bottle_tag.as_deref()).is_err()
+                    && !formula.has_source_url()
+                {
                     warn!(
                         formula = %formula.name,
                         "skipping formula with no bottle or source available for this platform"
@@ -101,17 +264,45 @@ impl Installer {
                     continue;
                 }
 
-                for dep in &formula.dependencies {
-                    if !fetched.contains(dep) && !to_fetch.contains(dep) {
-                        to_fetch.push(dep.clone());
+                for dep in formula.runtime_dependencies(self.dependency_policy) {
+                    if installed.contains_key(dep) && !names.iter().any(|n| n == dep) {
+                        continue;
+                    }
+                    if !fetched.contains(dep) && !to_fetch.iter().any(|n| n == dep) {
+                        to_fetch.push(dep.to_string());
                     }
                 }
 
-                formulas.insert(batch[i].clone(), formula);
+                let requested = &batch[i];
+                let key =
+                    if parse_tap_formula_ref(requested).is_none() && formula.name != *requested {
+                        fetched.insert(formula.name.clone());
+                        canonical.insert(requested.clone(), formula.name.clone());
+                        formula.name.clone()
+                    } else {
+                        requested.clone()
+                    };
+                formulas.insert(key, formula);
+            }
+        }
+
+        // Report every uncached formula at once rather than just the first, so
+        // an offline install can be fixed in a single online pass.
+        if !offline_misses.is_empty() {
+            return Err(Error::OfflineCacheMiss {
+                name: offline_misses.join(", "),
+            });
+        }
+
+        let mut roots: Vec<String> = Vec::with_capacity(names.len());
+        for name in names {
+            let root = canonical.get(name).unwrap_or(name);
+            if !roots.contains(root) {
+                roots.push(root.clone());
             }
         }
 
-        Ok(formulas)
+        Ok((roots, formulas))
     }
 }
 
@@ -129,7 +320,8 @@ mod tests {
     use crate::storage::blob::BlobCache;
     use crate::storage::db::Database;
     use crate::storage::store::Store;
-    use crate::{Installer, Linker};
+    use crate::{Installer, Linker, MethodPolicy, PackageKind};
+    use zb_core::{CellarRequirement, DependencyPolicy, Error, InstallMethod};
 
     #[tokio::test]
     async fn plans_tapped_formula_with_core_dependency() {
@@ -408,4 +600,483 @@ end
             zb_core::Error::MissingFormula { .. }
         ));
     }
+
+    async fn ambiguous_name_installer(mock_server: &MockServer, tmp: &TempDir) -> Installer {
+        let formula_json = r#"{
+            "name": "docker",
+            "versions": { "stable": "27.0.0" },
+            "dependencies": [],
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        Mock::given(method("GET"))
+            .and(path("/formula/docker.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cask/docker.json"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_string(r#"{"token":"docker","version":"4.0"}"#),
+            )
+            .mount(mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+                .unwrap()
+                .with_cask_base_url(format!("{}/cask", mock_server.uri())),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        )
+    }
+
+    #[tokio::test]
+    async fn name_that_is_formula_and_cask_is_ambiguous() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let installer = ambiguous_name_installer(&mock_server, &tmp).await;
+
+        let err = installer
+            .resolve_package_kinds(&["docker".to_string()], None)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            Error::AmbiguousName {
+                name: "docker".to_string(),
+                candidates: Vec::new(),
+            }
+        );
+
+        // An explicit prefix needs no lookup at all.
+        let resolved = installer
+            .resolve_package_kinds(&["cask:docker".to_string()], None)
+            .await
+            .unwrap();
+        assert_eq!(resolved, vec!["cask:docker"]);
+    }
+
+    #[tokio::test]
+    async fn package_kind_selector_disambiguates() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let installer = ambiguous_name_installer(&mock_server, &tmp).await;
+        let names = vec!["docker".to_string()];
+
+        let as_formula = installer
+            .resolve_package_kinds(&names, Some(PackageKind::Formula))
+            .await
+            .unwrap();
+        assert_eq!(as_formula, vec!["docker"]);
+
+        let as_cask = installer
+            .resolve_package_kinds(&names, Some(PackageKind::Cask))
+            .await
+            .unwrap();
+        assert_eq!(as_cask, vec!["cask:docker"]);
+    }
+
+    #[tokio::test]
+    async fn dependency_policy_controls_recommended_and_optional_deps() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let source_formula = |name: &str, extra: &str| {
+            format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    {extra}
+                    "urls": {{
+                        "stable": {{
+                            "url": "https://example.com/{name}-1.0.0.tar.gz",
+                            "checksum": "abc123"
+                        }}
+                    }},
+                    "bottle": {{ "stable": {{ "files": {{}} }} }}
+                }}"#
+            )
+        };
+        let formulas = [
+            (
+                "app",
+                source_formula(
+                    "app",
+                    r#""recommended_dependencies": ["rec"], "optional_dependencies": ["opt"],"#,
+                ),
+            ),
+            ("rec", source_formula("rec", "")),
+            ("opt", source_formula("opt", "")),
+        ];
+        for (name, json) in formulas {
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let planned = |policy: DependencyPolicy| {
+            let api_client =
+                ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
+            let installer = Installer::new(
+                api_client,
+                BlobCache::new(&root.join("cache")).unwrap(),
+                Store::new(&root).unwrap(),
+                Cellar::new(&root).unwrap(),
+                Linker::new(&prefix).unwrap(),
+                Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+                prefix.clone(),
+                root.join("locks"),
+            )
+            .with_dependency_policy(policy);
+            async move {
+                let plan = installer.plan(&["app".to_string()]).await.unwrap();
+                plan.items
+                    .into_iter()
+                    .map(|item| item.install_name)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(
+            planned(DependencyPolicy::default()).await,
+            vec!["rec", "app"]
+        );
+        assert_eq!(
+            planned(DependencyPolicy {
+                include_optional: true,
+                ..Default::default()
+            })
+            .await,
+            vec!["opt", "rec", "app"]
+        );
+        assert_eq!(
+            planned(DependencyPolicy {
+                include_recommended: false,
+                ..Default::default()
+            })
+            .await,
+            vec!["app"]
+        );
+    }
+
+    #[tokio::test]
+    async fn installed_dependencies_are_neither_fetched_nor_planned() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let app_json = r#"{
+            "name": "app",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": ["lib"],
+            "urls": {
+                "stable": {
+                    "url": "https://example.com/app-1.0.0.tar.gz",
+                    "checksum": "abc123"
+                }
+            },
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        Mock::given(method("GET"))
+            .and(path("/formula/app.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(app_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/lib.json"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        );
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install("lib", "0.9.0", "key", false).unwrap();
+        tx.commit().unwrap();
+        fs::create_dir_all(installer.cellar.keg_path("lib", "0.9.0")).unwrap();
+
+        let installed = installer.installed_versions().unwrap();
+        assert_eq!(installed.get("lib").map(String::as_str), Some("0.9.0"));
+
+        let plan = installer
+            .plan_with_options(&["app".to_string()], MethodPolicy::PreferBottle, &installed)
+            .await
+            .unwrap();
+
+        let planned: Vec<_> = plan
+            .items
+            .iter()
+            .map(|item| item.install_name.as_str())
+            .collect();
+        assert_eq!(planned, vec!["app"]);
+        assert!(plan.already_installed.is_empty());
+    }
+
+    #[tokio::test]
+    async fn forced_method_errors_instead_of_falling_back() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let tag = get_test_bottle_tag();
+        let bottle_only = format!(
+            r#"{{
+                "name": "poured",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/poured-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+            "a".repeat(64)
+        );
+        let source_only = r#"{
+            "name": "built",
+            "versions": { "stable": "1.0.0" },
+            "dependencies": [],
+            "urls": {
+                "stable": {
+                    "url": "https://example.com/built-1.0.0.tar.gz",
+                    "checksum": "abc123"
+                }
+            },
+            "bottle": { "stable": { "files": {} } }
+        }"#;
+        for (name, json) in [("poured", bottle_only.as_str()), ("built", source_only)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        let installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        );
+        let plan = |name: &str, policy| {
+            let names = vec![name.to_string()];
+            let installer = &installer;
+            async move {
+                installer
+                    .plan_with_options(&names, policy, &Default::default())
+                    .await
+            }
+        };
+
+        let err = plan("built", MethodPolicy::ForceBottle).await.unwrap_err();
+        assert_eq!(
+            err,
+            Error::UnsupportedBottle {
+                name: "built".to_string()
+            }
+        );
+        let err = plan("poured", MethodPolicy::ForceSource).await.unwrap_err();
+        assert!(
+            matches!(err, Error::UnsupportedFormula { ref name, .. } if name == "poured"),
+            "{err:?}"
+        );
+
+        // The preferring policies still fall back.
+        let plan_built = plan("built", MethodPolicy::PreferBottle).await.unwrap();
+        assert!(matches!(
+            plan_built.items[0].method,
+            InstallMethod::Source(_)
+        ));
+        let plan_poured = plan("poured", MethodPolicy::PreferSource).await.unwrap();
+        assert!(matches!(
+            plan_poured.items[0].method,
+            InstallMethod::Bottle(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn bottle_for_another_cellar_is_passed_over_for_source() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let tag = get_test_bottle_tag();
+        let formula = |name: &str, urls: &str| {
+            format!(
+                r#"{{
+                    "name": "{name}",
+                    "versions": {{ "stable": "1.0.0" }},
+                    "dependencies": [],
+                    {urls}
+                    "bottle": {{
+                        "stable": {{
+                            "files": {{
+                                "{tag}": {{
+                                    "cellar": "/somewhere/else/Cellar",
+                                    "url": "{}/bottles/{name}-1.0.0.{tag}.bottle.tar.gz",
+                                    "sha256": "{}"
+                                }}
+                            }}
+                        }}
+                    }}
+                }}"#,
+                mock_server.uri(),
+                "a".repeat(64)
+            )
+        };
+        let buildable = formula(
+            "pinned",
+            r#""urls": { "stable": { "url": "https://example.com/pinned-1.0.0.tar.gz", "checksum": "abc123" } },"#,
+        );
+        let bottle_only = formula("stuck", "");
+        for (name, json) in [("pinned", buildable), ("stuck", bottle_only)] {
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        let installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        );
+        let plan = |name: &str, policy| {
+            let names = vec![name.to_string()];
+            let installer = &installer;
+            async move {
+                installer
+                    .plan_with_options(&names, policy, &Default::default())
+                    .await
+            }
+        };
+
+        let pinned = plan("pinned", MethodPolicy::PreferBottle).await.unwrap();
+        assert!(matches!(pinned.items[0].method, InstallMethod::Source(_)));
+
+        // With no source to build, the bottle is still the only way in.
+        let stuck = plan("stuck", MethodPolicy::PreferBottle).await.unwrap();
+        match &stuck.items[0].method {
+            InstallMethod::Bottle(bottle) => assert_eq!(
+                bottle.cellar,
+                CellarRequirement::Path("/somewhere/else/Cellar".to_string())
+            ),
+            other => panic!("expected a bottle, got {other:?}"),
+        }
+
+        let err = plan("stuck", MethodPolicy::ForceBottle).await.unwrap_err();
+        assert!(
+            matches!(err, Error::UnsupportedFormula { ref reason, .. } if reason.contains("/somewhere/else/Cellar")),
+            "{err:?}"
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn bottle_for_the_linuxbrew_cellar_is_relocated_not_built() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let tag = get_test_bottle_tag();
+        let json = format!(
+            r#"{{
+                "name": "relocated",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "urls": {{ "stable": {{ "url": "https://example.com/relocated-1.0.0.tar.gz", "checksum": "abc123" }} }},
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "cellar": "/home/linuxbrew/.linuxbrew/Cellar",
+                                "url": "{}/bottles/relocated-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+            "a".repeat(64)
+        );
+        Mock::given(method("GET"))
+            .and(path("/formula/relocated.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(json))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+        let installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        );
+        let names = vec!["relocated".to_string()];
+
+        for policy in [MethodPolicy::PreferBottle, MethodPolicy::ForceBottle] {
+            let plan = installer
+                .plan_with_options(&names, policy, &Default::default())
+                .await
+                .unwrap();
+            assert!(
+                matches!(plan.items[0].method, InstallMethod::Bottle(_)),
+                "{policy:?}"
+            );
+        }
+    }
 }
@@ -0,0 +1,209 @@
+use zb_core::{Error, formula_token};
+
+use crate::storage::db::InstalledKeg;
+
+use super::Installer;
+
+impl Installer {
+    /// Installed packages whose name matches `pattern`, ignoring case. A
+    /// pattern containing `*` or `?` is a glob over the whole name; anything
+    /// else matches as a substring. Each package is tried under its full
+    /// name (`user/tap/foo`, `cask:foo`) and its short name (`foo`), so
+    /// `foo*` finds tap formulae and casks as well as core formulae.
+    pub fn search_installed(&self, pattern: &str) -> Result<Vec<InstalledKeg>, Error> {
+        let pattern = pattern.trim().to_lowercase();
+        Ok(self
+            .db
+            .list_installed()?
+            .into_iter()
+            .filter(|keg| name_matches(&keg.name, &pattern))
+            .collect())
+    }
+
+    /// Resolve `pattern` to the one installed package a command should act
+    /// on. An installed name or alias is used as is, then an exact short
+    /// name, and only then a [`search_installed`](Self::search_installed)
+    /// match. More than one candidate at the deciding step is an
+    /// [`Error::AmbiguousName`] listing them, never a pick.
+    pub fn resolve_installed(&self, pattern: &str) -> Result<String, Error> {
+        let name = self.installed_name(pattern);
+        if self.is_installed(&name) {
+            return Ok(name);
+        }
+
+        let installed = self.db.list_installed()?;
+        let exact: Vec<String> = installed
+            .iter()
+            .filter(|keg| {
+                keg.name.eq_ignore_ascii_case(pattern)
+                    || short_name(&keg.name).eq_ignore_ascii_case(pattern)
+            })
+            .map(|keg| keg.name.clone())
+            .collect();
+        let mut candidates = if exact.is_empty() {
+            let pattern = pattern.to_lowercase();
+            installed
+                .into_iter()
+                .filter(|keg| name_matches(&keg.name, &pattern))
+                .map(|keg| keg.name)
+                .collect()
+        } else {
+            exact
+        };
+
+        match candidates.len() {
+            0 => Err(Error::NotInstalled {
+                name: pattern.to_string(),
+            }),
+            1 => Ok(candidates.remove(0)),
+            _ => Err(Error::AmbiguousName {
+                name: pattern.to_string(),
+                candidates,
+            }),
+        }
+    }
+}
+
+/// `foo` for `foo`, `user/tap/foo` and `cask:foo` alike.
+fn short_name(name: &str) -> &str {
+    formula_token(name.strip_prefix("cask:").unwrap_or(name))
+}
+
+/// `pattern` must already be lowercase.
+fn name_matches(name: &str, pattern: &str) -> bool {
+    let full = name.to_lowercase();
+    let short = short_name(&full);
+    if pattern.contains(['*', '?']) {
+        glob_matches(pattern, &full) || glob_matches(pattern, short)
+    } else {
+        full.contains(pattern)
+    }
+}
+
+/// Whole-string match where `*` is any run of characters and `?` is one.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: its pattern index and the text
+    // index it currently stretches to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, matched)) => {
+                    p = star + 1;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+    use zb_core::{Error, Paths};
+
+    use super::glob_matches;
+    use crate::Installer;
+    use crate::installer::install::create_installer;
+
+    fn installer_with(tmp: &TempDir, names: &[&str]) -> Installer {
+        let mut installer = create_installer(
+            &Paths::new(tmp.path().join("zerobrew"), tmp.path().join("prefix")),
+            Some(4),
+            None,
+        )
+        .unwrap();
+        let tx = installer.db.transaction().unwrap();
+        for name in names {
+            tx.record_install(name, "1.0.0", "key", true).unwrap();
+        }
+        tx.commit().unwrap();
+        installer
+    }
+
+    fn names(installer: &Installer, pattern: &str) -> Vec<String> {
+        installer
+            .search_installed(pattern)
+            .unwrap()
+            .into_iter()
+            .map(|keg| keg.name)
+            .collect()
+    }
+
+    #[test]
+    fn glob_matches_whole_names() {
+        assert!(glob_matches("py*", "python@3.12"));
+        assert!(glob_matches("*@3.1?", "python@3.12"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "axxbyyc"));
+        assert!(!glob_matches("py*", "cpython"));
+        assert!(!glob_matches("a*b", "abc"));
+    }
+
+    #[test]
+    fn search_matches_full_and_short_names_ignoring_case() {
+        let tmp = TempDir::new().unwrap();
+        let installer = installer_with(
+            &tmp,
+            &[
+                "jq",
+                "libpng",
+                "python@3.12",
+                "user/tap/pyenv",
+                "cask:PyCharm",
+            ],
+        );
+
+        assert_eq!(
+            names(&installer, "PY"),
+            ["cask:PyCharm", "python@3.12", "user/tap/pyenv"]
+        );
+        assert_eq!(names(&installer, "user/tap"), ["user/tap/pyenv"]);
+        assert_eq!(
+            names(&installer, "py*"),
+            ["cask:PyCharm", "python@3.12", "user/tap/pyenv"]
+        );
+        assert_eq!(names(&installer, "?q"), ["jq"]);
+        assert!(names(&installer, "ruby").is_empty());
+    }
+
+    #[test]
+    fn resolve_installed_refuses_to_guess_between_matches() {
+        let tmp = TempDir::new().unwrap();
+        let installer = installer_with(
+            &tmp,
+            &["python@3.11", "python@3.12", "user/tap/jq", "libpng"],
+        );
+
+        assert_eq!(installer.resolve_installed("libpng").unwrap(), "libpng");
+        assert_eq!(installer.resolve_installed("jq").unwrap(), "user/tap/jq");
+        assert_eq!(installer.resolve_installed("png").unwrap(), "libpng");
+        assert_eq!(
+            installer.resolve_installed("python").unwrap_err(),
+            Error::AmbiguousName {
+                name: "python".to_string(),
+                candidates: vec!["python@3.11".to_string(), "python@3.12".to_string()],
+            }
+        );
+        assert!(matches!(
+            installer.resolve_installed("ruby"),
+            Err(Error::NotInstalled { .. })
+        ));
+    }
+}
@@ -1,12 +1,12 @@
 use std::fs;
 use std::path::{Path, PathBuf};
 
-use tracing::warn;
-use zb_core::{BuildPlan, Error};
+use zb_core::{BuildPlan, Error, LogLevel};
 
+use crate::build::BuildProgressCallback;
 use crate::progress::InstallProgress;
 
-use super::{Installer, PlannedInstall, dependency_cellar_path};
+use super::{Installer, KegIdentity, PlannedInstall, dependency_cellar_path};
 
 impl Installer {
     pub(super) async fn install_from_source(
@@ -15,10 +15,19 @@ impl Installer {
         build_plan: &BuildPlan,
         link: bool,
         report: &impl Fn(InstallProgress),
+        build_progress: Option<BuildProgressCallback>,
     ) -> Result<(), Error> {
         let install_name = &item.install_name;
         let formula_name = &item.formula.name;
-        let version = item.formula.effective_version();
+        let KegIdentity {
+            keg_name, version, ..
+        } = item.keg_identity();
+
+        if self.offline {
+            return Err(Error::OfflineCacheMiss {
+                name: format!("{install_name} (source build)"),
+            });
+        }
 
         let ruby_source_path =
             item.formula
@@ -52,113 +61,33 @@ impl Installer {
             }
         }
 
-        let keg_path = self.cellar.keg_path(formula_name, &version);
-        let previous_keg_backup =
-            Self::backup_existing_source_keg(&keg_path, formula_name, &version)?;
+        let keg_path = self.cellar.keg_path(&keg_name, &version);
+        let previous_keg_backup = Self::backup_existing_source_keg(&keg_path, &keg_name, &version)?;
 
-        let executor = crate::build::BuildExecutor::new(self.prefix.clone());
+        let executor = crate::build::BuildExecutor::new(self.prefix.clone())
+            .with_echo_output(self.logger.enabled(LogLevel::Debug))
+            .with_log_dir(self.build_log_dir.clone())
+            .with_phase_timeout(self.build_phase_timeout)
+            .with_source_cache(self.downloader.blob_cache().clone());
         if let Err(build_err) = executor
-            .execute(build_plan, &formula_rb, &installed_deps)
+            .execute_with_progress(build_plan, &formula_rb, &installed_deps, build_progress)
             .await
         {
             if let Some(backup_path) = previous_keg_backup.as_ref() {
-                Self::restore_source_keg_from_backup(
-                    &keg_path,
-                    backup_path,
-                    formula_name,
-                    &version,
-                )?;
+                Self::restore_source_keg_from_backup(&keg_path, backup_path, &keg_name, &version)?;
             }
             return Err(build_err);
         }
 
         if let Some(backup_path) = previous_keg_backup.as_ref() {
-            Self::remove_source_keg_backup(backup_path, formula_name, &version)?;
+            Self::remove_source_keg_backup(backup_path, &keg_name, &version)?;
         }
 
         report(InstallProgress::UnpackCompleted {
             name: formula_name.clone(),
         });
 
-        let store_key = format!("source:{formula_name}:{version}");
-
-        let tx = self.db.transaction().inspect_err(|_| {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-        })?;
-
-        if let Err(e) = tx.record_install(install_name, &version, &store_key) {
-            drop(tx);
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-            return Err(e);
-        }
-
-        if let Err(e) = tx.commit() {
-            Self::cleanup_materialized(&self.cellar, formula_name, &version);
-            return Err(e);
-        }
-
-        if let Err(e) = self.linker.link_opt(&keg_path) {
-            warn!(formula = %install_name, error = %e, "failed to create opt link");
-        }
-
-        let should_link = link && !item.formula.is_keg_only();
-
-        if should_link {
-            report(InstallProgress::LinkStarted {
-                name: formula_name.clone(),
-            });
-            match self.linker.link_keg(&keg_path) {
-                Ok(files) => {
-                    report(InstallProgress::LinkCompleted {
-                        name: formula_name.clone(),
-                    });
-                    if !files.is_empty()
-                        && let Ok(tx) = self.db.transaction()
-                    {
-                        let mut ok = true;
-                        for linked in &files {
-                            if tx
-                                .record_linked_file(
-                                    install_name,
-                                    &version,
-                                    &linked.link_path.to_string_lossy(),
-                                    &linked.target_path.to_string_lossy(),
-                                )
-                                .is_err()
-                            {
-                                ok = false;
-                                break;
-                            }
-                        }
-                        if ok {
-                            let _ = tx.commit();
-                        }
-                    }
-                }
-                Err(e) => {
-                    let _ = self.linker.unlink_keg(&keg_path);
-                    report(InstallProgress::InstallCompleted {
-                        name: formula_name.clone(),
-                    });
-                    return Err(e);
-                }
-            }
-        } else if link && item.formula.is_keg_only() {
-            let reason = match &item.formula.keg_only {
-                zb_core::KegOnly::Reason(s) => s.clone(),
-                _ if item.formula.name.contains('@') => "versioned formula".to_string(),
-                _ => "keg-only formula".to_string(),
-            };
-            report(InstallProgress::LinkSkipped {
-                name: formula_name.clone(),
-                reason,
-            });
-        }
-
-        report(InstallProgress::InstallCompleted {
-            name: formula_name.clone(),
-        });
-        Ok(())
+        self.commit_keg(item, &keg_path, link, report)
     }
 
     fn backup_existing_source_keg(
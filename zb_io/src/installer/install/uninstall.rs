@@ -1,16 +1,45 @@
-use zb_core::{Error, formula_token};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tracing::warn;
+use zb_core::{DependencyKind, Error, formula_token};
+
+use crate::installer::cask::{CaskZap, parse_zap_artifacts, resolve_zap_path};
+use crate::storage::db::{KegFileRecord, StoreRef};
 
 use super::Installer;
 
+/// What `uninstall --zap` removed alongside a cask.
+#[derive(Debug, Default)]
+pub struct ZapReport {
+    pub removed: Vec<PathBuf>,
+    /// Zap paths that were left alone, with the reason.
+    pub skipped: Vec<(String, Error)>,
+}
+
+/// What [`Installer::uninstall_many`] did with each requested name.
+#[derive(Debug, Default)]
+pub struct UninstallReport {
+    /// Uninstalled, in the order they were removed.
+    pub removed: Vec<String>,
+    /// Left in place, with the reason.
+    pub skipped: Vec<(String, Error)>,
+}
+
 impl Installer {
     pub fn uninstall(&mut self, name: &str) -> Result<(), Error> {
+        let name = &self.installed_name(name);
         let installed = self.db.get_installed(name).ok_or(Error::NotInstalled {
             name: name.to_string(),
         })?;
         let keg_name = formula_token(&installed.name);
 
         let keg_path = self.cellar.keg_path(keg_name, &installed.version);
+        let displaced = self.db.list_backed_up_files(name)?;
         self.linker.unlink_keg(&keg_path)?;
+        self.restore_displaced_files(&displaced);
 
         {
             let tx = self.db.transaction()?;
@@ -23,18 +52,337 @@ impl Installer {
         Ok(())
     }
 
+    /// Uninstall several packages at once. Dependents go before the
+    /// formulae they depend on, and unless `force` is set a formula another
+    /// installed formula still depends on is skipped instead of removed. A
+    /// name that fails is recorded in the report and the rest carry on.
+    pub async fn uninstall_many(
+        &mut self,
+        names: &[String],
+        force: bool,
+    ) -> Result<UninstallReport, Error> {
+        let mut report = UninstallReport::default();
+        let mut targets: Vec<String> = Vec::new();
+        for name in names {
+            let name = self.installed_name(name);
+            if !self.is_installed(&name) {
+                report
+                    .skipped
+                    .push((name.clone(), Error::NotInstalled { name }));
+            } else if !targets.contains(&name) {
+                targets.push(name);
+            }
+        }
+
+        let dependents = match self.installed_dependents().await {
+            Ok(dependents) => dependents,
+            Err(e) if force => {
+                warn!(error = %e, "could not resolve dependents; uninstalling in the given order");
+                HashMap::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        for name in order_dependents_first(&targets, &dependents) {
+            if !force {
+                let blocking: Vec<String> = dependents
+                    .get(&name)
+                    .into_iter()
+                    .flatten()
+                    .filter(|dependent| self.is_installed(dependent))
+                    .cloned()
+                    .collect();
+                if !blocking.is_empty() {
+                    report.skipped.push((
+                        name.clone(),
+                        Error::RequiredBy {
+                            name,
+                            dependents: blocking,
+                        },
+                    ));
+                    continue;
+                }
+            }
+
+            match self.uninstall(&name) {
+                Ok(()) => report.removed.push(name),
+                Err(e) => report.skipped.push((name, e)),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Each installed formula mapped to the installed formulae that depend
+    /// on it directly, from current formula metadata.
+    async fn installed_dependents(&self) -> Result<HashMap<String, Vec<String>>, Error> {
+        let installed: Vec<String> = self
+            .db
+            .list_installed()?
+            .into_iter()
+            .map(|keg| keg.name)
+            .filter(|name| !name.starts_with("cask:"))
+            .collect();
+        if installed.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let (_, formulas) = self.fetch_all_formulas(&installed).await?;
+        let installed_set: HashSet<&str> = installed.iter().map(String::as_str).collect();
+        let mut dependents: HashMap<String, Vec<String>> = HashMap::new();
+        for name in &installed {
+            let Some(formula) = formulas.get(name) else {
+                continue;
+            };
+            // Any installed runtime dependency counts, whichever policy
+            // pulled it in.
+            for dep in formula.typed_dependencies() {
+                if dep.kind != DependencyKind::Build && installed_set.contains(dep.name.as_str()) {
+                    dependents.entry(dep.name).or_default().push(name.clone());
+                }
+            }
+        }
+        Ok(dependents)
+    }
+
+    /// Uninstall `name` and, when it is a cask, delete the files its `zap`
+    /// stanza lists. The cask JSON is fetched before anything is removed so
+    /// a network failure leaves the cask installed. Formulas have no zap
+    /// data and are uninstalled as usual.
+    pub async fn uninstall_zap(&mut self, name: &str) -> Result<ZapReport, Error> {
+        let name = self.installed_name(name);
+        let Some(token) = name.strip_prefix("cask:") else {
+            self.uninstall(&name)?;
+            return Ok(ZapReport::default());
+        };
+        if !self.is_installed(&name) {
+            return Err(Error::NotInstalled { name });
+        }
+
+        let cask_json = self.api_client.get_cask(token).await?;
+        let zap = parse_zap_artifacts(&cask_json);
+        self.uninstall(&name)?;
+
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        Ok(remove_zap_paths(token, &zap, home.as_deref()))
+    }
+
+    /// Uninstall formulae that were only installed as dependencies and are no
+    /// longer in the closure of any explicitly installed formula. Returns the
+    /// names removed. Casks have no formula metadata and never count as
+    /// roots.
+    pub async fn autoremove(&mut self) -> Result<Vec<String>, Error> {
+        let installed = self.db.list_installed()?;
+        let explicit: Vec<String> = installed
+            .iter()
+            .filter(|keg| keg.is_explicit && !keg.name.starts_with("cask:"))
+            .map(|keg| keg.name.clone())
+            .collect();
+
+        let required: HashSet<String> = if explicit.is_empty() {
+            HashSet::new()
+        } else {
+            let (roots, formulas) = self.fetch_all_formulas(&explicit).await?;
+            zb_core::resolve_closure_with_policy(&roots, &formulas, self.dependency_policy)?
+                .into_iter()
+                .collect()
+        };
+
+        let orphans: Vec<String> = installed
+            .into_iter()
+            .filter(|keg| !keg.is_explicit && !required.contains(&keg.name))
+            .map(|keg| keg.name)
+            .collect();
+
+        for name in &orphans {
+            self.uninstall(name)?;
+        }
+
+        Ok(orphans)
+    }
+
+    /// Put back files that were moved aside when the keg was force-linked.
+    /// A backup whose original location has since been reused is left where
+    /// it is and reported rather than overwriting the user's change.
+    fn restore_displaced_files(&self, displaced: &[KegFileRecord]) {
+        for record in displaced {
+            let Some(backup_path) = record.backup_path.as_deref() else {
+                continue;
+            };
+            let link_path = Path::new(&record.linked_path);
+            match self
+                .linker
+                .restore_backup(link_path, Path::new(backup_path))
+            {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!(
+                        path = %record.linked_path,
+                        backup = %backup_path,
+                        "not restoring displaced file: path was modified after linking"
+                    );
+                }
+                Err(e) => {
+                    warn!(
+                        path = %record.linked_path,
+                        backup = %backup_path,
+                        error = %e,
+                        "failed to restore displaced file"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Remove store entries no installed keg references and return their
+    /// keys. Holds the install lock throughout, so no install in another
+    /// process can start referencing an entry while it is being removed.
     pub fn gc(&mut self) -> Result<Vec<String>, Error> {
+        let _lock = self.lock_install()?;
         let unreferenced = self.db.get_unreferenced_store_keys()?;
+        self.remove_unreferenced_store_entries(&unreferenced)
+    }
+
+    /// Remove each of `candidates` that is still unreferenced. The check and
+    /// the ref row's deletion share a transaction with the removal, so a
+    /// reference recorded after `candidates` was listed keeps the entry, and
+    /// a failed removal leaves the row in place.
+    fn remove_unreferenced_store_entries(
+        &mut self,
+        candidates: &[String],
+    ) -> Result<Vec<String>, Error> {
         let mut removed = Vec::new();
 
-        for store_key in unreferenced {
-            self.store.remove_entry(&store_key)?;
-            self.db.delete_store_ref(&store_key)?;
-            removed.push(store_key);
+        for store_key in candidates {
+            let tx = self.db.transaction()?;
+            if !tx.delete_unreferenced_store_ref(store_key)? {
+                continue;
+            }
+            self.store.remove_entry(store_key)?;
+            tx.commit()?;
+            removed.push(store_key.clone());
         }
 
         Ok(removed)
     }
+
+    /// Every store ref with the installed kegs (`name version`) that hold it,
+    /// for explaining why an entry survives [`Installer::gc`].
+    pub fn store_references(&self) -> Result<Vec<(StoreRef, Vec<String>)>, Error> {
+        let mut holders: HashMap<String, Vec<String>> = HashMap::new();
+        for keg in self.db.list_installed()? {
+            holders
+                .entry(keg.store_key)
+                .or_default()
+                .push(format!("{} {}", keg.name, keg.version));
+        }
+
+        Ok(self
+            .db
+            .list_store_refs()?
+            .into_iter()
+            .map(|store_ref| {
+                let kegs = holders.remove(&store_ref.store_key).unwrap_or_default();
+                (store_ref, kegs)
+            })
+            .collect())
+    }
+
+    /// Trim the bottle download cache. Blobs backing a referenced store entry
+    /// are kept regardless of the limits.
+    pub fn prune_cache(
+        &self,
+        max_total_bytes: Option<u64>,
+        max_age: Option<Duration>,
+    ) -> Result<Vec<String>, Error> {
+        let live_keys: HashSet<String> = self
+            .db
+            .list_store_refs()?
+            .into_iter()
+            .filter(|store_ref| store_ref.refcount > 0)
+            .map(|store_ref| store_ref.store_key)
+            .collect();
+
+        self.downloader
+            .blob_cache()
+            .prune(max_total_bytes, max_age, &live_keys)
+    }
+
+    /// Re-hash the bottle download cache and return the sha256 of every blob
+    /// whose content no longer matches its name. With `remove`, those blobs
+    /// are deleted so the next install downloads them again.
+    pub fn verify_cache(&self, remove: bool) -> Result<Vec<String>, Error> {
+        let blob_cache = self.downloader.blob_cache();
+        let corrupt = blob_cache.verify_all()?;
+        if remove {
+            for sha256 in &corrupt {
+                blob_cache
+                    .remove_blob(sha256)
+                    .map_err(Error::store("failed to remove cached blob"))?;
+            }
+        }
+        Ok(corrupt)
+    }
+}
+
+/// Order `targets` so each comes before every target it depends on,
+/// otherwise keeping the given order. `dependents` maps a formula to the
+/// formulae that depend on it; a cycle falls back to the given order.
+fn order_dependents_first(
+    targets: &[String],
+    dependents: &HashMap<String, Vec<String>>,
+) -> Vec<String> {
+    let mut pending: Vec<&String> = targets.iter().collect();
+    let mut ordered = Vec::with_capacity(targets.len());
+    while !pending.is_empty() {
+        let ready = pending
+            .iter()
+            .position(|name| {
+                !dependents.get(*name).is_some_and(|deps| {
+                    deps.iter()
+                        .any(|dep| dep != *name && pending.contains(&dep))
+                })
+            })
+            .unwrap_or(0);
+        ordered.push(pending.remove(ready).clone());
+    }
+    ordered
+}
+
+fn remove_zap_paths(token: &str, zap: &[CaskZap], home: Option<&Path>) -> ZapReport {
+    let mut report = ZapReport::default();
+
+    for entry in zap {
+        let path = match resolve_zap_path(token, &entry.path, home) {
+            Ok(path) => path,
+            Err(e) => {
+                report.skipped.push((entry.path.clone(), e));
+                continue;
+            }
+        };
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+
+        let removed = if entry.only_if_empty {
+            fs::remove_dir(&path)
+        } else if metadata.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match removed {
+            Ok(()) => report.removed.push(path),
+            Err(e) => report.skipped.push((
+                entry.path.clone(),
+                Error::FileError {
+                    message: format!("failed to remove '{}': {e}", path.display()),
+                },
+            )),
+        }
+    }
+
+    report
 }
 
 #[cfg(test)]
@@ -44,6 +392,7 @@ mod tests {
     use tempfile::TempDir;
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
+    use zb_core::Error;
 
     use crate::cellar::Cellar;
     use crate::installer::install::test_support::*;
@@ -138,6 +487,212 @@ mod tests {
         assert!(!prefix.join("bin/uninstallme").exists());
     }
 
+    #[tokio::test]
+    async fn uninstall_restores_file_displaced_by_backup_link() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("displacer");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "displacer",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{tag}": {{
+                                "url": "{}/bottles/displacer-1.0.0.{tag}.bottle.tar.gz",
+                                "sha256": "{bottle_sha}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            mock_server.uri(),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/displacer.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/bottles/displacer-1.0.0.{tag}.bottle.tar.gz"
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        installer
+            .install(&["displacer".to_string()], false)
+            .await
+            .unwrap();
+
+        fs::write(prefix.join("bin/displacer"), b"preexisting").unwrap();
+        let keg_path = root.join("cellar/displacer/1.0.0");
+        let linked = installer
+            .linker
+            .link_keg_with_backups(&keg_path, &root.join("backups"))
+            .unwrap();
+        {
+            let tx = installer.db.transaction().unwrap();
+            for file in &linked {
+                tx.record_linked_file(
+                    "displacer",
+                    "1.0.0",
+                    &file.link_path.to_string_lossy(),
+                    &file.target_path.to_string_lossy(),
+                    file.backup_path
+                        .as_ref()
+                        .map(|p| p.to_string_lossy())
+                        .as_deref(),
+                )
+                .unwrap();
+            }
+            tx.commit().unwrap();
+        }
+        assert!(prefix.join("bin/displacer").is_symlink());
+
+        installer.uninstall("displacer").unwrap();
+
+        assert!(!prefix.join("bin/displacer").is_symlink());
+        assert_eq!(
+            fs::read(prefix.join("bin/displacer")).unwrap(),
+            b"preexisting"
+        );
+        assert!(!root.join("backups/bin/displacer").exists());
+    }
+
+    #[tokio::test]
+    async fn autoremove_uninstalls_orphaned_dependencies() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        for (name, deps) in [("orphandep", "[]"), ("needsdep", r#"["orphandep"]"#)] {
+            let bottle = create_bottle_tarball(name);
+            let formula_json = format!(
+                r#"{{"name":"{name}","versions":{{"stable":"1.0.0"}},"dependencies":{deps},"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/{name}-1.0.0.{tag}.bottle.tar.gz","sha256":"{}"}}}}}}}}}}"#,
+                mock_server.uri(),
+                sha256_hex(&bottle)
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        installer
+            .install(&["needsdep".to_string()], true)
+            .await
+            .unwrap();
+        assert!(installer.get_installed("needsdep").unwrap().is_explicit);
+        assert!(!installer.get_installed("orphandep").unwrap().is_explicit);
+
+        assert!(installer.autoremove().await.unwrap().is_empty());
+        assert!(installer.is_installed("orphandep"));
+
+        installer.uninstall("needsdep").unwrap();
+        assert_eq!(installer.autoremove().await.unwrap(), vec!["orphandep"]);
+        assert!(!installer.is_installed("orphandep"));
+        assert!(!root.join("cellar/orphandep/1.0.0").exists());
+    }
+
+    #[tokio::test]
+    async fn autoremove_skips_installed_casks() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+        let tag = get_test_bottle_tag();
+
+        let bottle = create_bottle_tarball("keeper");
+        let formula_json = format!(
+            r#"{{"name":"keeper","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/keeper-1.0.0.{tag}.bottle.tar.gz","sha256":"{}"}}}}}}}}}}"#,
+            mock_server.uri(),
+            sha256_hex(&bottle)
+        );
+        Mock::given(method("GET"))
+            .and(path("/formula/keeper.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/keeper-1.0.0.{tag}.bottle.tar.gz")))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        installer
+            .install(&["keeper".to_string()], true)
+            .await
+            .unwrap();
+        // Recorded the way a cask install records it.
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install("cask:tool", "2.0.0", "cask-sha", true)
+            .unwrap();
+        tx.commit().unwrap();
+
+        assert!(installer.autoremove().await.unwrap().is_empty());
+        assert!(installer.is_installed("keeper"));
+        assert!(installer.is_installed("cask:tool"));
+    }
+
     #[tokio::test]
     async fn gc_removes_unreferenced_store_entries() {
         let mock_server = MockServer::start().await;
@@ -308,6 +863,147 @@ mod tests {
         assert!(root.join("store").join(&bottle_sha).exists());
     }
 
+    #[tokio::test]
+    async fn prune_cache_keeps_blobs_of_installed_formulas() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("keepme");
+        let bottle_sha = sha256_hex(&bottle);
+
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{
+                "name": "keepme",
+                "versions": {{ "stable": "1.0.0" }},
+                "dependencies": [],
+                "bottle": {{
+                    "stable": {{
+                        "files": {{
+                            "{}": {{
+                                "url": "{}/bottles/keepme-1.0.0.{}.bottle.tar.gz",
+                                "sha256": "{}"
+                            }}
+                        }}
+                    }}
+                }}
+            }}"#,
+            tag,
+            mock_server.uri(),
+            tag,
+            bottle_sha
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/keepme.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path(format!("/bottles/keepme-1.0.0.{}.bottle.tar.gz", tag)))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let api_client =
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
+        let blob_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let store = Store::new(&root).unwrap();
+        let cellar = Cellar::new(&root).unwrap();
+        let linker = Linker::new(&prefix).unwrap();
+        let db = Database::open(&root.join("db/zb.sqlite3")).unwrap();
+
+        let mut installer = Installer::new(
+            api_client,
+            blob_cache,
+            store,
+            cellar,
+            linker,
+            db,
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        installer
+            .install(&["keepme".to_string()], true)
+            .await
+            .unwrap();
+
+        let orphan_cache = BlobCache::new(&root.join("cache")).unwrap();
+        let mut writer = orphan_cache.start_write("orphan").unwrap();
+        std::io::Write::write_all(&mut writer, b"stale bottle").unwrap();
+        writer.commit().unwrap();
+
+        let removed = installer.prune_cache(Some(0), None).unwrap();
+
+        assert_eq!(removed, vec!["orphan".to_string()]);
+        assert!(orphan_cache.has_blob(&bottle_sha));
+        assert!(!orphan_cache.has_blob("orphan"));
+    }
+
+    #[tokio::test]
+    async fn store_references_show_every_keg_sharing_an_entry() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("shared");
+        let bottle_sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+
+        for name in ["alpha", "beta"] {
+            let formula_json = format!(
+                r#"{{"name":"{name}","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/shared.tar.gz","sha256":"{bottle_sha}"}}}}}}}}}}"#,
+                mock_server.uri(),
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(&formula_json))
+                .mount(&mock_server)
+                .await;
+        }
+
+        Mock::given(method("GET"))
+            .and(path("/bottles/shared.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+
+        installer
+            .install(&["alpha".to_string(), "beta".to_string()], false)
+            .await
+            .unwrap();
+
+        assert_eq!(installer.db.store_refcount(&bottle_sha).unwrap(), 2);
+
+        let references = installer.store_references().unwrap();
+        assert_eq!(references.len(), 1);
+        let (store_ref, mut kegs) = references.into_iter().next().unwrap();
+        kegs.sort();
+        assert_eq!(store_ref.store_key, bottle_sha);
+        assert_eq!(store_ref.refcount, 2);
+        assert_eq!(kegs, vec!["alpha 1.0.0", "beta 1.0.0"]);
+    }
+
     #[tokio::test]
     async fn uninstall_accepts_full_tap_reference_after_install() {
         let mock_server = MockServer::start().await;
@@ -460,4 +1156,235 @@ end
         assert!(matches!(err, zb_core::Error::NotInstalled { .. }));
         assert!(installer.is_installed("terraform"));
     }
+
+    #[tokio::test]
+    async fn alias_installs_under_canonical_name_and_uninstalls_by_alias() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let bottle = create_bottle_tarball("ripgrep");
+        let sha = sha256_hex(&bottle);
+        let tag = get_test_bottle_tag();
+        let formula_json = format!(
+            r#"{{"name":"ripgrep","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/ripgrep.tar.gz","sha256":"{sha}"}}}}}}}}}}"#,
+            mock_server.uri(),
+        );
+
+        Mock::given(method("GET"))
+            .and(path("/formula/rg.json"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_string(r#"[{"name":"ripgrep","aliases":["rg"]}]"#),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/ripgrep.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bottles/ripgrep.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+            .mount(&mock_server)
+            .await;
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.to_path_buf(),
+            root.join("locks"),
+        );
+
+        installer.install(&["rg".to_string()], true).await.unwrap();
+
+        assert!(installer.is_installed("ripgrep"));
+        assert!(!installer.is_installed("rg"));
+        assert!(installer.get_installed("ripgrep").unwrap().is_explicit);
+
+        installer.uninstall("rg").unwrap();
+        assert!(!installer.is_installed("ripgrep"));
+        assert!(!root.join("cellar/ripgrep/1.0.0").exists());
+    }
+
+    #[test]
+    fn zap_removes_resolved_paths_and_reports_the_rest() {
+        use crate::installer::cask::CaskZap;
+
+        let tmp = TempDir::new().unwrap();
+        let home = tmp.path().join("home");
+        fs::create_dir_all(home.join(".config/ghostty")).unwrap();
+        fs::write(home.join(".config/ghostty/config"), "theme").unwrap();
+        fs::create_dir_all(home.join("Library/Support/ghostty/state")).unwrap();
+        fs::write(tmp.path().join("outside"), "keep").unwrap();
+
+        let zap = |path: &str, only_if_empty: bool| CaskZap {
+            path: path.to_string(),
+            only_if_empty,
+        };
+        let report = super::remove_zap_paths(
+            "ghostty",
+            &[
+                zap("~/.config/ghostty/", false),
+                zap("~/Library/Support/ghostty", true),
+                zap("~/../outside", false),
+                zap("~/.cache/ghostty", false),
+            ],
+            Some(&home),
+        );
+
+        assert_eq!(report.removed, vec![home.join(".config/ghostty/")]);
+        assert!(!home.join(".config/ghostty").exists());
+        assert!(home.join("Library/Support/ghostty/state").exists());
+        assert!(tmp.path().join("outside").exists());
+
+        let skipped: Vec<&str> = report.skipped.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(skipped, vec!["~/Library/Support/ghostty", "~/../outside"]);
+    }
+
+    #[test]
+    fn dependents_are_ordered_before_their_dependencies() {
+        let dependents = std::collections::HashMap::from([
+            ("openssl".to_string(), vec!["curl".to_string()]),
+            (
+                "zlib".to_string(),
+                vec!["openssl".to_string(), "curl".to_string()],
+            ),
+        ]);
+        let targets = ["zlib", "jq", "openssl", "curl"].map(String::from);
+
+        assert_eq!(
+            super::order_dependents_first(&targets, &dependents),
+            ["jq", "curl", "openssl", "zlib"].map(String::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn uninstall_many_keeps_dependencies_of_remaining_formulae() {
+        let mock_server = MockServer::start().await;
+        let tmp = TempDir::new().unwrap();
+
+        let tag = get_test_bottle_tag();
+        for (name, deps) in [("deplib", ""), ("mainpkg", r#""deplib""#)] {
+            let bottle = create_bottle_tarball(name);
+            let formula_json = format!(
+                r#"{{"name":"{name}","versions":{{"stable":"1.0.0"}},"dependencies":[{deps}],"bottle":{{"stable":{{"files":{{"{tag}":{{"url":"{}/bottles/{name}-1.0.0.{tag}.bottle.tar.gz","sha256":"{}"}}}}}}}}}}"#,
+                mock_server.uri(),
+                sha256_hex(&bottle)
+            );
+            Mock::given(method("GET"))
+                .and(path(format!("/formula/{name}.json")))
+                .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+                .mount(&mock_server)
+                .await;
+            Mock::given(method("GET"))
+                .and(path(format!("/bottles/{name}-1.0.0.{tag}.bottle.tar.gz")))
+                .respond_with(ResponseTemplate::new(200).set_body_bytes(bottle))
+                .mount(&mock_server)
+                .await;
+        }
+
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix.clone(),
+            root.join("locks"),
+        );
+        installer
+            .install(&["mainpkg".to_string()], true)
+            .await
+            .unwrap();
+
+        let report = installer
+            .uninstall_many(&["deplib".to_string(), "missing".to_string()], false)
+            .await
+            .unwrap();
+        assert!(report.removed.is_empty());
+        assert_eq!(report.skipped.len(), 2);
+        assert!(matches!(
+            &report.skipped[0],
+            (name, Error::NotInstalled { .. }) if name == "missing"
+        ));
+        assert!(matches!(
+            &report.skipped[1],
+            (name, Error::RequiredBy { dependents, .. })
+                if name == "deplib" && dependents == &["mainpkg".to_string()]
+        ));
+        assert!(installer.is_installed("deplib"));
+
+        let report = installer
+            .uninstall_many(&["deplib".to_string(), "mainpkg".to_string()], false)
+            .await
+            .unwrap();
+        assert_eq!(report.removed, ["mainpkg", "deplib"]);
+        assert!(report.skipped.is_empty());
+        assert!(!root.join("cellar/deplib/1.0.0").exists());
+        assert!(!prefix.join("bin/mainpkg").exists());
+    }
+
+    #[test]
+    fn gc_keeps_entries_referenced_after_they_were_listed() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().join("zerobrew");
+        let prefix = tmp.path().join("homebrew");
+        fs::create_dir_all(root.join("db")).unwrap();
+
+        let mut installer = Installer::new(
+            ApiClient::with_base_url("http://127.0.0.1:9/formula".to_string()).unwrap(),
+            BlobCache::new(&root.join("cache")).unwrap(),
+            Store::new(&root).unwrap(),
+            Cellar::new(&root).unwrap(),
+            Linker::new(&prefix).unwrap(),
+            Database::open(&root.join("db/zb.sqlite3")).unwrap(),
+            prefix,
+            root.join("locks"),
+        );
+
+        for (name, key) in [("old", "reused"), ("gone", "orphan")] {
+            fs::create_dir_all(root.join("store").join(key)).unwrap();
+            let tx = installer.db.transaction().unwrap();
+            tx.record_install(name, "1.0.0", key, true).unwrap();
+            tx.record_uninstall(name).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let candidates = installer.db.get_unreferenced_store_keys().unwrap();
+        assert_eq!(candidates.len(), 2);
+
+        // An install records its reference between listing and removal.
+        let tx = installer.db.transaction().unwrap();
+        tx.record_install("new", "1.0.0", "reused", true).unwrap();
+        tx.commit().unwrap();
+
+        let removed = installer
+            .remove_unreferenced_store_entries(&candidates)
+            .unwrap();
+
+        assert_eq!(removed, vec!["orphan"]);
+        assert!(root.join("store/reused").exists());
+        assert!(!root.join("store/orphan").exists());
+        assert_eq!(installer.db.store_refcount("reused").unwrap(), 1);
+        assert!(installer.gc().unwrap().is_empty());
+    }
 }
@@ -2,9 +2,13 @@ mod cask;
 pub mod homebrew;
 pub mod install;
 
+pub use cask::{CaskBinary, CaskZap, ResolvedCask};
 pub use homebrew::{
     HomebrewMigrationPackages, HomebrewPackage, categorize_packages, get_homebrew_packages,
     parse_casks_from_plain_text, parse_formulas_from_json,
 };
-pub use install::doctor::{DiagnosticReport, RepairSummary};
-pub use install::{ExecuteResult, InstallPlan, Installer, OutdatedPackage, create_installer};
+pub use install::doctor::{DiagnosticReport, DoctorFinding, RecoveryReport, RepairSummary};
+pub use install::{
+    ExecuteResult, InstallPlan, InstalledEntry, Installer, MethodPolicy, OutdatedPackage,
+    PackageKind, UninstallReport, ZapReport, create_installer,
+};
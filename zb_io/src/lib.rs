@@ -1,6 +1,7 @@
 pub mod build;
 pub mod cellar;
 pub(crate) mod checksum;
+pub mod config;
 pub mod extraction;
 pub mod installer;
 pub mod network;
@@ -9,17 +10,21 @@ pub mod progress;
 pub mod ssl;
 pub mod storage;
 
-pub use build::{BuildExecutor, DepInfo};
-pub use cellar::{Cellar, LinkedFile, Linker, MaterializedKeg};
-pub use extraction::extract_tarball;
+pub use build::{BuildExecutor, BuildPhase, BuildProgressCallback, DepInfo};
+pub use cellar::{Cellar, LinkStatus, LinkedFile, Linker, MaterializedKeg, OwnedLink, PartialLink};
+pub use config::Config;
+pub use extraction::{ExtractLimits, ExtractProgress, extract_tarball};
 pub use installer::{
-    DiagnosticReport, ExecuteResult, HomebrewMigrationPackages, HomebrewPackage, InstallPlan,
-    Installer, OutdatedPackage, RepairSummary, create_installer, get_homebrew_packages,
+    CaskBinary, CaskZap, DiagnosticReport, DoctorFinding, ExecuteResult, HomebrewMigrationPackages,
+    HomebrewPackage, InstallPlan, InstalledEntry, Installer, MethodPolicy, OutdatedPackage,
+    PackageKind, RecoveryReport, RepairSummary, ResolvedCask, UninstallReport, ZapReport,
+    create_installer, get_homebrew_packages,
 };
 pub use network::{
-    ApiCache, ApiClient, DownloadProgressCallback, DownloadRequest, Downloader, ParallelDownloader,
+    AddressFamily, ApiCache, ApiClient, DownloadProgressCallback, DownloadRequest, Downloader,
+    DownloaderConfig, ParallelDownloader,
 };
-pub use path::validate_privileged_path;
+pub use path::{is_writable, validate_privileged_path};
 pub use progress::{InstallProgress, ProgressCallback};
-pub use ssl::{find_ca_bundle_from_prefix, find_ca_dir};
-pub use storage::{BlobCache, Database, InstalledKeg, KegFileRecord, Store, StoreRef};
+pub use ssl::{TlsOptions, find_ca_bundle_from_prefix, find_ca_dir, load_ca_certs};
+pub use storage::{BlobCache, Database, InstalledKeg, KegFileRecord, KegVersion, Store, StoreRef};
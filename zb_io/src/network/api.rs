@@ -1,15 +1,31 @@
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use crate::checksum::verify_sha256_bytes;
 use crate::network::cache::{ApiCache, CacheEntry};
 use crate::network::suggest::rank_formula_suggestions;
 use crate::network::tap_formula::{parse_tap_formula_ref, parse_tap_formula_ruby};
 use futures_util::stream::{self, StreamExt};
-use zb_core::{Error, Formula};
+use tokio::sync::Semaphore;
+use tracing::debug;
+use zb_core::{ConcurrencyLimits, Error, Formula};
 
 const HOMEBREW_CORE_RAW_BASE: &str =
     "https://raw.githubusercontent.com/Homebrew/homebrew-core/main";
 
+/// Below this many core formulae, individual requests are cheaper than pulling
+/// the multi-megabyte bulk index.
+const DEFAULT_BATCH_INDEX_THRESHOLD: usize = 16;
+
+/// Retries after the first attempt for connection errors and 5xx responses.
+const API_MAX_RETRIES: u32 = 3;
+
+/// Longest response-body excerpt quoted in an HTTP error.
+const ERROR_BODY_SNIPPET_LEN: usize = 200;
+
+type FormulaIndex = std::collections::HashMap<String, serde_json::Value>;
+type AliasMap = std::collections::HashMap<String, String>;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum RubySourceLocator<'a> {
     CoreRelativePath(&'a str),
@@ -74,7 +90,13 @@ pub struct ApiClient {
     tap_raw_base_url: String,
     client: reqwest::Client,
     cache: Option<ApiCache>,
+    offline: bool,
+    batch_index_threshold: usize,
     formula_candidates: RwLock<Option<Arc<[String]>>>,
+    formula_index: RwLock<Option<Arc<FormulaIndex>>>,
+    formula_aliases: RwLock<Option<Arc<AliasMap>>>,
+    /// Bounds the per-formula requests a batch makes at once.
+    metadata_permits: Semaphore,
 }
 
 impl ApiClient {
@@ -107,8 +129,11 @@ impl ApiClient {
     }
 
     fn build_client(base_url: String) -> Self {
+        // Formula JSON compresses well; reqwest advertises gzip and decodes
+        // transparently, and still accepts identity responses.
         let client = reqwest::Client::builder()
             .user_agent("zerobrew/0.1")
+            .gzip(true)
             .pool_max_idle_per_host(20)
             .build()
             .unwrap_or_else(|_| reqwest::Client::new());
@@ -119,7 +144,12 @@ impl ApiClient {
             tap_raw_base_url: "https://raw.githubusercontent.com".to_string(),
             client,
             cache: None,
+            offline: false,
+            batch_index_threshold: DEFAULT_BATCH_INDEX_THRESHOLD,
             formula_candidates: RwLock::new(None),
+            formula_index: RwLock::new(None),
+            formula_aliases: RwLock::new(None),
+            metadata_permits: Semaphore::new(ConcurrencyLimits::default().metadata),
         }
     }
 
@@ -140,6 +170,27 @@ impl ApiClient {
         self
     }
 
+    /// Serve responses only from the API cache; any miss becomes
+    /// `Error::OfflineCacheMiss` instead of a network request.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Minimum number of core formulae in a batch before `get_formula_batch`
+    /// switches from per-formula requests to the bulk index.
+    pub fn with_batch_index_threshold(mut self, threshold: usize) -> Self {
+        self.batch_index_threshold = threshold;
+        self
+    }
+
+    /// Most formula requests `get_formula_batch` keeps in flight at once, so a
+    /// large dependency closure does not trip the API's rate limiting.
+    pub fn with_metadata_concurrency(mut self, limit: usize) -> Self {
+        self.metadata_permits = Semaphore::new(limit.max(1));
+        self
+    }
+
     /// Clear all cached API responses. Returns the number removed.
     pub fn clear_cache(&self) -> Result<usize, Error> {
         match &self.cache {
@@ -184,6 +235,12 @@ impl ApiClient {
             return Ok(dest);
         }
 
+        if self.offline {
+            return Err(Error::OfflineCacheMiss {
+                name: ruby_source_path.to_string(),
+            });
+        }
+
         let response = self
             .client
             .get(url)
@@ -233,23 +290,66 @@ impl ApiClient {
         }
     }
 
-    async fn cached_get(&self, url: &str) -> Result<CachedGetResult, Error> {
-        let cached_entry = self.cache.as_ref().and_then(|c| c.get(url));
-
-        let mut request = self.client.get(url);
+    /// Send the request built by `build`, retrying connection failures and
+    /// 5xx responses with exponential backoff. Any other response, including
+    /// a 404, is returned as-is on the first attempt.
+    async fn send_with_retry(
+        &self,
+        build: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, Error> {
+        let mut attempt = 0;
+        loop {
+            let result = build().send().await;
+            let retriable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => !e.is_builder() && !e.is_redirect(),
+            };
 
-        if let Some(ref entry) = cached_entry {
-            if let Some(ref etag) = entry.etag {
-                request = request.header("If-None-Match", etag.as_str());
+            if !retriable || attempt >= API_MAX_RETRIES {
+                return result.map_err(|e| Error::NetworkFailure {
+                    message: e.to_string(),
+                });
             }
-            if let Some(ref last_modified) = entry.last_modified {
-                request = request.header("If-Modified-Since", last_modified.as_str());
+
+            match &result {
+                Ok(response) => debug!(
+                    status = %response.status(),
+                    attempt = attempt + 1,
+                    "API request failed; retrying"
+                ),
+                Err(e) => debug!(error = %e, attempt = attempt + 1, "API request failed; retrying"),
             }
+            tokio::time::sleep(Duration::from_millis(100 * (1 << attempt))).await;
+            attempt += 1;
         }
+    }
 
-        let response = request.send().await.map_err(|e| Error::NetworkFailure {
-            message: e.to_string(),
-        })?;
+    async fn cached_get(&self, url: &str, name: &str) -> Result<CachedGetResult, Error> {
+        let cached_entry = self.cache.as_ref().and_then(|c| c.get(url));
+
+        if self.offline {
+            return match cached_entry {
+                Some(entry) => Ok(CachedGetResult::Cached(entry.body)),
+                None => Err(Error::OfflineCacheMiss {
+                    name: name.to_string(),
+                }),
+            };
+        }
+
+        let response = self
+            .send_with_retry(|| {
+                let mut request = self.client.get(url);
+                if let Some(ref entry) = cached_entry {
+                    if let Some(ref etag) = entry.etag {
+                        request = request.header("If-None-Match", etag.as_str());
+                    }
+                    if let Some(ref last_modified) = entry.last_modified {
+                        request = request.header("If-Modified-Since", last_modified.as_str());
+                    }
+                }
+                request
+            })
+            .await?;
 
         if response.status() == reqwest::StatusCode::NOT_MODIFIED
             && let Some(entry) = cached_entry
@@ -277,14 +377,77 @@ impl ApiClient {
         }
     }
 
+    /// Fetch a formula by name. Aliases and old names (`python`, `python3`)
+    /// have no JSON of their own, so a 404 is retried under the canonical name
+    /// from the bulk index; callers should key on the returned `Formula::name`.
     pub async fn get_formula(&self, name: &str) -> Result<Formula, Error> {
         if let Some(spec) = parse_tap_formula_ref(name) {
             return self.get_tap_formula(&spec).await;
         }
 
+        match self.get_core_formula(name).await {
+            Err(Error::MissingFormula { .. }) if !self.offline => {
+                match self.resolve_alias(name).await {
+                    Some(canonical) => self.get_core_formula(&canonical).await,
+                    None => Err(Error::MissingFormula {
+                        name: name.to_string(),
+                    }),
+                }
+            }
+            result => result,
+        }
+    }
+
+    /// Canonical name for a formula alias or old name, if `name` is one.
+    pub async fn resolve_alias(&self, name: &str) -> Option<String> {
+        if parse_tap_formula_ref(name).is_some() {
+            return None;
+        }
+
+        let aliases = match self.formula_aliases().await {
+            Ok(aliases) => aliases,
+            Err(e) => {
+                debug!(error = %e, "formula alias map unavailable");
+                return None;
+            }
+        };
+        aliases
+            .get(name)
+            .filter(|canonical| *canonical != name)
+            .cloned()
+    }
+
+    /// Like `resolve_alias`, but never touches the network: only the alias map
+    /// already in memory or a bulk index in the API cache is consulted.
+    pub fn cached_alias(&self, name: &str) -> Option<String> {
+        if parse_tap_formula_ref(name).is_some() {
+            return None;
+        }
+
+        let aliases = match self.formula_aliases.read().ok().and_then(|a| a.clone()) {
+            Some(aliases) => aliases,
+            None => {
+                let entry = self
+                    .cache
+                    .as_ref()?
+                    .get(&format!("{}.json", self.base_url))?;
+                let aliases = Arc::new(Self::extract_formula_aliases(&entry.body).ok()?);
+                if let Ok(mut cached) = self.formula_aliases.write() {
+                    *cached = Some(Arc::clone(&aliases));
+                }
+                aliases
+            }
+        };
+        aliases
+            .get(name)
+            .filter(|canonical| *canonical != name)
+            .cloned()
+    }
+
+    async fn get_core_formula(&self, name: &str) -> Result<Formula, Error> {
         let url = format!("{}/{}.json", self.base_url, name);
 
-        let body = match self.cached_get(&url).await? {
+        let body = match self.cached_get(&url, name).await? {
             CachedGetResult::Cached(body) => body,
             CachedGetResult::Fresh(response) => {
                 if response.status() == reqwest::StatusCode::NOT_FOUND {
@@ -293,9 +456,7 @@ impl ApiClient {
                     });
                 }
                 if !response.status().is_success() {
-                    return Err(Error::NetworkFailure {
-                        message: format!("HTTP {}", response.status()),
-                    });
+                    return Err(http_error(&format!("formula '{name}' fetch"), response).await);
                 }
 
                 let etag = response
@@ -322,10 +483,116 @@ impl ApiClient {
         serde_json::from_str(&body).map_err(Error::network("failed to parse formula JSON"))
     }
 
+    /// Fetch several formulae, in the order given. Large batches are served from
+    /// the bulk index; tap formulae and names missing from it fall back to
+    /// individual requests.
+    pub async fn get_formula_batch(&self, names: &[String]) -> Result<Vec<Formula>, Error> {
+        self.fetch_formula_batch(names).await.into_iter().collect()
+    }
+
+    pub(crate) async fn fetch_formula_batch(
+        &self,
+        names: &[String],
+    ) -> Vec<Result<Formula, Error>> {
+        let core_count = names
+            .iter()
+            .filter(|name| parse_tap_formula_ref(name).is_none())
+            .count();
+
+        let index = if core_count >= self.batch_index_threshold.max(1) {
+            match self.formula_index().await {
+                Ok(index) => Some(index),
+                Err(e) => {
+                    debug!(error = %e, "bulk formula index unavailable; fetching individually");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let futures = names.iter().map(|name| {
+            let indexed = index
+                .as_ref()
+                .filter(|_| parse_tap_formula_ref(name).is_none())
+                .and_then(|index| index.get(name.as_str()))
+                .cloned();
+            async move {
+                match indexed {
+                    Some(value) => serde_json::from_value(value)
+                        .map_err(Error::network("failed to parse formula from bulk index")),
+                    None => {
+                        let _permit = self
+                            .metadata_permits
+                            .acquire()
+                            .await
+                            .map_err(Error::network("metadata request limit closed"))?;
+                        self.get_formula(name).await
+                    }
+                }
+            }
+        });
+
+        futures::future::join_all(futures).await
+    }
+
+    async fn formula_index(&self) -> Result<Arc<FormulaIndex>, Error> {
+        if let Some(index) = self.formula_index.read().ok().and_then(|i| i.clone()) {
+            return Ok(index);
+        }
+
+        let raw = self.get_all_formulas_raw().await?;
+        let entries: Vec<serde_json::Value> = serde_json::from_str(&raw)
+            .map_err(Error::network("failed to parse bulk formula JSON"))?;
+        let index: Arc<FormulaIndex> = Arc::new(
+            entries
+                .into_iter()
+                .filter_map(|entry| {
+                    let name = entry.get("name")?.as_str()?.to_string();
+                    Some((name, entry))
+                })
+                .collect(),
+        );
+
+        if let Ok(mut cached) = self.formula_index.write() {
+            *cached = Some(Arc::clone(&index));
+        }
+        Ok(index)
+    }
+
+    async fn formula_aliases(&self) -> Result<Arc<AliasMap>, Error> {
+        if let Some(aliases) = self.formula_aliases.read().ok().and_then(|a| a.clone()) {
+            return Ok(aliases);
+        }
+
+        let raw = self.get_all_formulas_raw().await?;
+        let aliases = Arc::new(Self::extract_formula_aliases(&raw)?);
+        if let Ok(mut cached) = self.formula_aliases.write() {
+            *cached = Some(Arc::clone(&aliases));
+        }
+        Ok(aliases)
+    }
+
+    fn extract_formula_aliases(raw: &str) -> Result<AliasMap, Error> {
+        let entries: Vec<FormulaSuggestionEntry> = serde_json::from_str(raw)
+            .map_err(Error::network("failed to parse bulk formula JSON"))?;
+
+        let mut aliases = AliasMap::new();
+        for entry in entries {
+            let Some(name) = entry.name else {
+                continue;
+            };
+            for alias in entry.aliases.into_iter().chain(entry.oldnames) {
+                aliases.entry(alias).or_insert_with(|| name.clone());
+            }
+        }
+        Ok(aliases)
+    }
+
     pub async fn get_all_formulas_raw(&self) -> Result<String, Error> {
         let url = format!("{}.json", self.base_url);
 
-        match self.cached_get(&url).await? {
+        match self.cached_get(&url, "formula index").await? {
             CachedGetResult::Cached(body) => Ok(body),
             CachedGetResult::Fresh(response) => {
                 if !response.status().is_success() {
@@ -425,15 +692,14 @@ impl ApiClient {
     }
 
     pub async fn get_cask(&self, token: &str) -> Result<serde_json::Value, Error> {
+        if self.offline {
+            return Err(Error::OfflineCacheMiss {
+                name: format!("cask:{token}"),
+            });
+        }
+
         let url = format!("{}/{}.json", self.cask_base_url, token);
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| Error::NetworkFailure {
-                message: e.to_string(),
-            })?;
+        let response = self.send_with_retry(|| self.client.get(&url)).await?;
 
         if response.status() == reqwest::StatusCode::NOT_FOUND {
             return Err(Error::MissingFormula {
@@ -442,9 +708,7 @@ impl ApiClient {
         }
 
         if !response.status().is_success() {
-            return Err(Error::NetworkFailure {
-                message: format!("HTTP {}", response.status()),
-            });
+            return Err(http_error(&format!("cask '{token}' fetch"), response).await);
         }
 
         response
@@ -457,6 +721,12 @@ impl ApiClient {
         &self,
         spec: &crate::network::tap_formula::TapFormulaRef,
     ) -> Result<Formula, Error> {
+        if self.offline {
+            return Err(Error::OfflineCacheMiss {
+                name: format!("{}/{}/{}", spec.owner, spec.repo, spec.formula),
+            });
+        }
+
         let candidate_repos = if spec.repo.starts_with("homebrew-") {
             vec![
                 spec.repo.clone(),
@@ -553,6 +823,20 @@ impl ApiClient {
     }
 }
 
+/// Describe a failed response by its status and the start of its body.
+async fn http_error(context: &str, response: reqwest::Response) -> Error {
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let snippet: String = body.trim().chars().take(ERROR_BODY_SNIPPET_LEN).collect();
+
+    let message = if snippet.is_empty() {
+        format!("{context} returned HTTP {status}")
+    } else {
+        format!("{context} returned HTTP {status}: {snippet}")
+    };
+    Error::NetworkFailure { message }
+}
+
 impl Default for ApiClient {
     fn default() -> Self {
         Self::new()
@@ -563,7 +847,7 @@ impl Default for ApiClient {
 mod tests {
     use super::*;
     use tempfile::tempdir;
-    use wiremock::matchers::{header, method, path};
+    use wiremock::matchers::{header, header_regex, method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
@@ -672,6 +956,111 @@ mod tests {
         ));
     }
 
+    #[tokio::test]
+    async fn decodes_gzip_encoded_formula() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../../zb_core/fixtures/formula_foo.json");
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(fixture.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .and(header_regex("accept-encoding", "gzip"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .insert_header("etag", "\"gz-v1\"")
+                    .set_body_bytes(compressed),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let cache = ApiCache::in_memory().unwrap();
+        let client = ApiClient::with_base_url(mock_server.uri())
+            .unwrap()
+            .with_cache(cache);
+        let formula = client.get_formula("foo").await.unwrap();
+
+        assert_eq!(formula.name, "foo");
+        assert_eq!(formula.versions.stable, "1.2.3");
+
+        let url = format!("{}/foo.json", mock_server.uri());
+        let entry = client.cache.as_ref().unwrap().get(&url).unwrap();
+        assert_eq!(entry.etag.as_deref(), Some("\"gz-v1\""));
+        assert_eq!(entry.body, fixture);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_before_succeeding() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri()).unwrap();
+        let formula = client.get_formula("foo").await.unwrap();
+
+        assert_eq!(formula.name, "foo");
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_404() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/nonexistent.json"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri()).unwrap();
+        let err = client.get_formula("nonexistent").await.unwrap_err();
+
+        assert!(matches!(err, Error::MissingFormula { .. }));
+    }
+
+    #[tokio::test]
+    async fn persistent_server_error_reports_status_and_body() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("upstream unavailable"))
+            .expect(u64::from(API_MAX_RETRIES) + 1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(mock_server.uri()).unwrap();
+        let err = client.get_formula("foo").await.unwrap_err();
+
+        let Error::NetworkFailure { message } = err else {
+            panic!("expected NetworkFailure, got {err:?}");
+        };
+        assert!(message.contains("502"), "{message}");
+        assert!(message.contains("upstream unavailable"), "{message}");
+    }
+
     #[tokio::test]
     async fn first_request_stores_etag() {
         let mock_server = MockServer::start().await;
@@ -1207,6 +1596,43 @@ end
         assert!(candidates.contains(&"rg".to_string()));
     }
 
+    #[tokio::test]
+    async fn get_formula_resolves_alias_to_canonical_formula() {
+        let mock_server = MockServer::start().await;
+        let bulk = r#"[
+            {"name":"python@3.13","aliases":["python","python3"]},
+            {"name":"ripgrep","aliases":["rg"]}
+        ]"#;
+        let formula_json = r#"{"name":"python@3.13","versions":{"stable":"3.13.1"},"dependencies":[],"bottle":{"stable":{"files":{}}}}"#;
+
+        Mock::given(method("GET"))
+            .and(path("/formula/python.json"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(bulk))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/python@3.13.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(formula_json))
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri())).unwrap();
+        let formula = client.get_formula("python").await.unwrap();
+
+        assert_eq!(formula.name, "python@3.13");
+        assert_eq!(
+            client.cached_alias("python3").as_deref(),
+            Some("python@3.13")
+        );
+        assert_eq!(client.cached_alias("python@3.13"), None);
+    }
+
     #[tokio::test]
     async fn suggest_formulas_returns_ranked_matches_from_bulk_index() {
         let mock_server = MockServer::start().await;
@@ -1263,4 +1689,138 @@ end
 
         assert!(suggestions.is_empty());
     }
+
+    #[tokio::test]
+    async fn offline_mode_serves_cached_formula_without_network() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let cache = ApiCache::in_memory().unwrap();
+        let client = ApiClient::with_base_url(mock_server.uri())
+            .unwrap()
+            .with_cache(cache);
+        client.get_formula("foo").await.unwrap();
+
+        let client = client.with_offline(true);
+        let formula = client.get_formula("foo").await.unwrap();
+        assert_eq!(formula.name, "foo");
+
+        let err = client.get_formula("bar").await.unwrap_err();
+        assert!(matches!(
+            err,
+            Error::OfflineCacheMiss { name } if name == "bar"
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_formula_batch_uses_bulk_index_above_threshold() {
+        let mock_server = MockServer::start().await;
+        let bulk = r#"[
+            {"name":"foo","versions":{"stable":"1.2.3"},"dependencies":[],"bottle":{"stable":{"files":{}}}},
+            {"name":"bar","versions":{"stable":"2.0.0"},"dependencies":["foo"],"bottle":{"stable":{"files":{}}}}
+        ]"#;
+
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(bulk))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+            .unwrap()
+            .with_batch_index_threshold(2);
+        let formulas = client
+            .get_formula_batch(&["bar".to_string(), "foo".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(formulas[0].name, "bar");
+        assert_eq!(formulas[1].name, "foo");
+        assert_eq!(formulas[0].dependencies, vec!["foo".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn get_formula_batch_falls_back_for_names_missing_from_index() {
+        let mock_server = MockServer::start().await;
+        let fixture = include_str!("../../../zb_core/fixtures/formula_foo.json");
+
+        Mock::given(method("GET"))
+            .and(path("/formula.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/formula/foo.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(fixture))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let client = ApiClient::with_base_url(format!("{}/formula", mock_server.uri()))
+            .unwrap()
+            .with_batch_index_threshold(1);
+        let formulas = client
+            .get_formula_batch(&["foo".to_string()])
+            .await
+            .unwrap();
+
+        assert_eq!(formulas[0].name, "foo");
+    }
+
+    #[tokio::test]
+    async fn formula_batch_stays_within_metadata_concurrency() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mock_server = MockServer::start().await;
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let (in_flight_clone, peak_clone) = (in_flight.clone(), peak.clone());
+        Mock::given(method("GET"))
+            .respond_with(move |request: &wiremock::Request| {
+                let current = in_flight_clone.fetch_add(1, Ordering::SeqCst) + 1;
+                peak_clone.fetch_max(current, Ordering::SeqCst);
+
+                std::thread::sleep(Duration::from_millis(50));
+
+                in_flight_clone.fetch_sub(1, Ordering::SeqCst);
+                let name = request
+                    .url
+                    .path()
+                    .rsplit('/')
+                    .next()
+                    .unwrap()
+                    .trim_end_matches(".json")
+                    .to_string();
+                ResponseTemplate::new(200).set_body_string(format!(
+                    r#"{{"name":"{name}","versions":{{"stable":"1.0.0"}},"dependencies":[],"bottle":{{"stable":{{"files":{{}}}}}}}}"#
+                ))
+            })
+            .mount(&mock_server)
+            .await;
+
+        let limit = 3;
+        let client = ApiClient::with_base_url(mock_server.uri())
+            .unwrap()
+            .with_batch_index_threshold(usize::MAX)
+            .with_metadata_concurrency(limit);
+        let names: Vec<String> = (0..12).map(|i| format!("dep{i}")).collect();
+
+        let formulas = client.get_formula_batch(&names).await.unwrap();
+
+        assert_eq!(formulas.len(), names.len());
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(
+            peak <= limit,
+            "peak concurrent metadata requests was {peak}, expected <= {limit}"
+        );
+    }
 }
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -6,6 +7,7 @@ use reqwest::StatusCode;
 use reqwest::header::{AUTHORIZATION, HeaderValue, WWW_AUTHENTICATE};
 use serde::Deserialize;
 use tokio::sync::RwLock;
+use tracing::debug;
 
 use zb_core::Error;
 
@@ -27,7 +29,56 @@ pub(crate) struct CachedToken {
     pub(crate) expires_at: Instant,
 }
 
-pub(crate) type TokenCache = Arc<RwLock<HashMap<String, CachedToken>>>;
+/// The host bottles are published under in formula metadata.
+const GHCR_BASE: &str = "https://ghcr.io";
+
+/// Registry bearer tokens keyed by scope, plus the registry they are issued
+/// for. Tokens are only ever attached to requests for that registry, never
+/// to mirrors.
+#[derive(Clone, Default)]
+pub(crate) struct TokenCache {
+    tokens: Arc<RwLock<HashMap<String, CachedToken>>>,
+    /// Registry serving `ghcr.io/v2/...` paths in ghcr's place, without a
+    /// trailing slash. `None` talks to ghcr.io itself.
+    registry_base: Option<Arc<str>>,
+}
+
+impl TokenCache {
+    pub(crate) fn with_registry_base(mut self, base: Option<&str>) -> Self {
+        self.registry_base = base.map(|b| Arc::from(b.trim_end_matches('/')));
+        self
+    }
+
+    /// `url` pointed at the configured registry if it is a ghcr.io URL.
+    pub(crate) fn registry_url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        match (&self.registry_base, url.strip_prefix(GHCR_BASE)) {
+            (Some(base), Some(path)) if path.starts_with('/') => {
+                Cow::Owned(format!("{base}{path}"))
+            }
+            _ => Cow::Borrowed(url),
+        }
+    }
+
+    fn scope_for_url(&self, url: &str) -> Option<String> {
+        extract_scope_for_url(url, self.registry_base.as_deref())
+    }
+}
+
+/// Turn a failed request on `url` into an error, keeping timeouts apart
+/// from other failures so a stalled connection does not read like an HTTP
+/// error. `started` is when the timed-out part of the request began.
+pub(crate) fn request_error(url: &str, started: Instant, err: reqwest::Error) -> Error {
+    if err.is_timeout() {
+        Error::NetworkTimeout {
+            url: url.to_string(),
+            after: started.elapsed(),
+        }
+    } else {
+        Error::NetworkFailure {
+            message: err.to_string(),
+        }
+    }
+}
 
 pub(crate) async fn fetch_download_response_internal(
     client: &reqwest::Client,
@@ -41,9 +92,11 @@ pub(crate) async fn fetch_download_response_internal(
         request = request.header(AUTHORIZATION, bearer_header(token)?);
     }
 
-    let response = request.send().await.map_err(|e| Error::NetworkFailure {
-        message: e.to_string(),
-    })?;
+    let started = Instant::now();
+    let response = request
+        .send()
+        .await
+        .map_err(|e| request_error(url, started, e))?;
 
     let response = if response.status() == StatusCode::UNAUTHORIZED {
         handle_auth_challenge_internal(client, token_cache, url, response).await?
@@ -76,6 +129,7 @@ pub(crate) async fn fetch_range_response_internal(
             request = request.header(AUTHORIZATION, bearer_header(token)?);
         }
 
+        let started = Instant::now();
         match request.send().await {
             Ok(response) => {
                 let response = if response.status() == StatusCode::UNAUTHORIZED {
@@ -103,9 +157,7 @@ pub(crate) async fn fetch_range_response_internal(
                 return Ok(response);
             }
             Err(e) => {
-                last_error = Some(Error::NetworkFailure {
-                    message: e.to_string(),
-                });
+                last_error = Some(request_error(url, started, e));
 
                 if attempt < MAX_CHUNK_RETRIES {
                     tokio::time::sleep(Duration::from_millis(100 * (1 << attempt))).await;
@@ -124,8 +176,8 @@ pub(crate) async fn get_cached_token_for_url_internal(
     token_cache: &TokenCache,
     url: &str,
 ) -> Option<String> {
-    let scope = extract_scope_for_url(url)?;
-    let cache = token_cache.read().await;
+    let scope = token_cache.scope_for_url(url)?;
+    let cache = token_cache.tokens.read().await;
     let now = Instant::now();
 
     cache
@@ -155,7 +207,7 @@ pub(crate) async fn handle_auth_challenge_internal(
         }
     };
 
-    let token = fetch_bearer_token_internal(client, token_cache, www_auth).await?;
+    let token = fetch_bearer_token_internal(client, token_cache, url, www_auth).await?;
 
     let response = client
         .get(url)
@@ -178,12 +230,13 @@ pub(crate) async fn handle_auth_challenge_internal(
 pub(crate) async fn fetch_bearer_token_internal(
     client: &reqwest::Client,
     token_cache: &TokenCache,
+    url: &str,
     www_authenticate: &str,
 ) -> Result<String, Error> {
     let (realm, service, scope) = parse_www_authenticate(www_authenticate)?;
 
     {
-        let cache = token_cache.read().await;
+        let cache = token_cache.tokens.read().await;
         if let Some(cached) = cache.get(&scope)
             && cached.expires_at > Instant::now()
         {
@@ -191,9 +244,9 @@ pub(crate) async fn fetch_bearer_token_internal(
         }
     }
 
-    let token_url =
-        reqwest::Url::parse_with_params(&realm, &[("service", &service), ("scope", &scope)])
-            .map_err(Error::network("failed to construct token URL"))?;
+    debug!(scope = %scope, "fetching registry bearer token");
+
+    let token_url = token_url(url, &realm, &service, &scope)?;
 
     let response = client
         .get(token_url)
@@ -213,7 +266,7 @@ pub(crate) async fn fetch_bearer_token_internal(
         .map_err(Error::network("failed to parse token response"))?;
 
     {
-        let mut cache = token_cache.write().await;
+        let mut cache = token_cache.tokens.write().await;
         cache.insert(
             scope,
             CachedToken {
@@ -226,18 +279,48 @@ pub(crate) async fn fetch_bearer_token_internal(
     Ok(token_response.token)
 }
 
-pub(crate) fn extract_scope_for_url(url: &str) -> Option<String> {
+/// Where to ask for a token. Registries other than ghcr may send a realm
+/// relative to the blob they challenged, so it is resolved against `url`.
+fn token_url(url: &str, realm: &str, service: &str, scope: &str) -> Result<reqwest::Url, Error> {
+    let mut token_url = reqwest::Url::parse(url)
+        .and_then(|base| base.join(realm))
+        .map_err(Error::network("failed to construct token URL"))?;
+    token_url
+        .query_pairs_mut()
+        .append_pair("service", service)
+        .append_pair("scope", scope);
+    Ok(token_url)
+}
+
+/// The pull scope for a blob on ghcr.io, or on `registry_base` when one is
+/// configured in its place.
+pub(crate) fn extract_scope_for_url(url: &str, registry_base: Option<&str>) -> Option<String> {
     let marker = "ghcr.io/v2/";
-    let start = url.find(marker)? + marker.len();
-    let remainder = &url[start..];
-    let mut parts = remainder.split('/');
+    let remainder = match registry_base {
+        Some(base) => url.strip_prefix(base)?.strip_prefix("/v2/")?,
+        None => &url[url.find(marker)? + marker.len()..],
+    };
+    // Versioned formulae nest one level deeper (`openssl/3`), so the
+    // repository is everything before the blob or manifest path.
+    let repository = ["/blobs/", "/manifests/"]
+        .iter()
+        .find_map(|marker| {
+            remainder
+                .split_once(marker)
+                .map(|(repository, _)| repository)
+        })
+        .unwrap_or(remainder);
+    let mut parts = repository.split('/');
     let owner = parts.next()?;
     let repo = parts.next()?;
-    let formula = parts.next()?;
-    if owner.is_empty() || repo.is_empty() || formula.is_empty() {
+    let image: Vec<&str> = parts.collect();
+    if owner.is_empty() || repo.is_empty() || image.is_empty() || image.contains(&"") {
         return None;
     }
-    Some(format!("repository:{owner}/{repo}/{formula}:pull"))
+    Some(format!(
+        "repository:{owner}/{repo}/{}:pull",
+        image.join("/")
+    ))
 }
 
 fn parse_www_authenticate(header: &str) -> Result<(String, String, String), Error> {
@@ -283,16 +366,106 @@ mod tests {
 
     #[test]
     fn extract_scope_for_url_supports_core_packages() {
-        let scope =
-            extract_scope_for_url("https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc").unwrap();
+        let scope = extract_scope_for_url(
+            "https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc",
+            None,
+        )
+        .unwrap();
         assert_eq!(scope, "repository:homebrew/core/lz4:pull");
     }
 
     #[test]
     fn extract_scope_for_url_supports_tapped_packages() {
-        let scope =
-            extract_scope_for_url("https://ghcr.io/v2/hashicorp/tap/terraform/blobs/sha256:abc")
-                .unwrap();
+        let scope = extract_scope_for_url(
+            "https://ghcr.io/v2/hashicorp/tap/terraform/blobs/sha256:abc",
+            None,
+        )
+        .unwrap();
         assert_eq!(scope, "repository:hashicorp/tap/terraform:pull");
     }
+
+    #[test]
+    fn extract_scope_for_url_keeps_versioned_image_names() {
+        let scope = extract_scope_for_url(
+            "https://ghcr.io/v2/homebrew/core/openssl/3/blobs/sha256:abc",
+            None,
+        )
+        .unwrap();
+        assert_eq!(scope, "repository:homebrew/core/openssl/3:pull");
+    }
+
+    #[test]
+    fn extract_scope_for_url_follows_the_configured_registry() {
+        let base = Some("https://registry.internal:5000");
+        let scope = extract_scope_for_url(
+            "https://registry.internal:5000/v2/homebrew/core/lz4/blobs/sha256:abc",
+            base,
+        )
+        .unwrap();
+        assert_eq!(scope, "repository:homebrew/core/lz4:pull");
+
+        // Tokens for the registry must never be sent to ghcr or a mirror.
+        assert!(
+            extract_scope_for_url(
+                "https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc",
+                base
+            )
+            .is_none()
+        );
+        assert!(
+            extract_scope_for_url(
+                "https://mirror.example.com/v2/homebrew/core/lz4/blobs/sha256:abc",
+                base
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn registry_url_only_rewrites_ghcr_urls() {
+        let cache = TokenCache::default().with_registry_base(Some("http://127.0.0.1:8080/"));
+        assert_eq!(
+            cache.registry_url("https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc"),
+            "http://127.0.0.1:8080/v2/homebrew/core/lz4/blobs/sha256:abc"
+        );
+        assert_eq!(
+            cache.registry_url("https://ghcr.io.evil.example/v2/x/y/z"),
+            "https://ghcr.io.evil.example/v2/x/y/z"
+        );
+        assert_eq!(
+            cache.registry_url("https://example.com/lz4.tar.gz"),
+            "https://example.com/lz4.tar.gz"
+        );
+
+        let unconfigured = TokenCache::default();
+        assert_eq!(
+            unconfigured.registry_url("https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc"),
+            "https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc"
+        );
+    }
+
+    #[test]
+    fn token_url_resolves_relative_realms_against_the_blob_url() {
+        let url = token_url(
+            "http://127.0.0.1:8080/v2/homebrew/core/lz4/blobs/sha256:abc",
+            "/token",
+            "registry",
+            "repository:homebrew/core/lz4:pull",
+        )
+        .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://127.0.0.1:8080/token?service=registry&scope=repository%3Ahomebrew%2Fcore%2Flz4%3Apull"
+        );
+
+        let url = token_url(
+            "https://ghcr.io/v2/homebrew/core/lz4/blobs/sha256:abc",
+            "https://ghcr.io/token",
+            "ghcr.io",
+            "repository:homebrew/core/lz4:pull",
+        )
+        .unwrap();
+        assert_eq!(url.host_str(), Some("ghcr.io"));
+        assert_eq!(url.path(), "/token");
+    }
 }
@@ -6,19 +6,22 @@ use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 
 use crate::progress::InstallProgress;
-use crate::storage::blob::BlobCache;
+use crate::storage::blob::{BlobCache, BlobWriter};
 use futures_util::StreamExt;
 use reqwest::StatusCode;
 use reqwest::header::{ACCEPT_RANGES, AUTHORIZATION, CONTENT_RANGE};
 use sha2::{Digest, Sha256};
-use tokio::sync::{Mutex, Semaphore, mpsc};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::task::JoinSet;
+use tracing::debug;
 use zb_core::Error;
 
 use super::auth::{
-    TokenCache, bearer_header, fetch_bearer_token_internal, fetch_download_response_internal,
-    fetch_range_response_internal, get_cached_token_for_url_internal,
+    TokenCache, bearer_header, fetch_bearer_token_internal, fetch_range_response_internal,
+    get_cached_token_for_url_internal,
 };
-use super::single::download_response_internal;
+use super::retry::RetryBudget;
+use super::single::reject_html_response;
 use super::{DownloadProgressCallback, MAX_CHUNK_RETRIES, MAX_CONCURRENT_CHUNKS};
 
 const MIN_CHUNK_SIZE: u64 = 5 * 1024 * 1024;
@@ -32,6 +35,9 @@ struct ChunkDownloadContext<'a> {
     name: Option<String>,
     file_size: u64,
     total_downloaded: Arc<AtomicU64>,
+    /// Only checked: a chunked download counts against the budget once, as
+    /// a whole, so one file's chunks failing together can't spend it.
+    retry_budget: &'a RetryBudget,
 }
 
 pub(crate) struct ChunkedDownloadContext<'a> {
@@ -39,11 +45,19 @@ pub(crate) struct ChunkedDownloadContext<'a> {
     pub(crate) client: &'a reqwest::Client,
     pub(crate) token_cache: &'a TokenCache,
     pub(crate) url: &'a str,
+    /// Mirrors tried, in order, for any chunk that exhausts its retries on `url`.
+    pub(crate) alternate_urls: &'a [String],
     pub(crate) expected_sha256: &'a str,
     pub(crate) name: Option<String>,
     pub(crate) progress: Option<DownloadProgressCallback>,
     pub(crate) file_size: u64,
     pub(crate) global_semaphore: &'a Arc<Semaphore>,
+    /// See `DownloaderConfig::max_buffered_chunk_bytes`.
+    pub(crate) max_buffered_bytes: u64,
+    /// See `DownloaderConfig::reject_html_responses`.
+    pub(crate) reject_html: bool,
+    /// Shared with every other download on the same downloader.
+    pub(crate) retry_budget: &'a Arc<RetryBudget>,
 }
 
 struct ChunkRange {
@@ -51,13 +65,37 @@ struct ChunkRange {
     size: u64,
 }
 
-pub(crate) fn server_supports_ranges(response: &reqwest::Response) -> bool {
+/// Whether a HEAD response rules out range requests. `Accept-Ranges` is
+/// only a hint: plenty of servers omit it or disagree with what their GETs
+/// do, so anything short of an explicit `none` is settled by the probe in
+/// [`download_with_chunks`].
+pub(crate) fn server_refuses_ranges(response: &reqwest::Response) -> bool {
     response
         .headers()
         .get(ACCEPT_RANGES)
         .and_then(|v| v.to_str().ok())
-        .map(|v| v == "bytes")
-        .unwrap_or(false)
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("none"))
+}
+
+/// Parse the complete-length from a `Content-Range: bytes x-y/TOTAL` header.
+/// Returns `None` when the total is unknown (`*`) or the header is malformed.
+fn content_range_total(content_range: &str) -> Option<u64> {
+    let (_, total) = content_range.rsplit_once('/')?;
+    total.trim().parse().ok()
+}
+
+/// Chunk ranges are planned from the HEAD `Content-Length`; if the object behind
+/// the URL has since changed, every chunk would be fetched against a stale size.
+fn check_content_range_total(content_range: &str, expected_size: u64) -> Result<(), Error> {
+    match content_range_total(content_range) {
+        Some(total) if total != expected_size => Err(Error::NetworkFailure {
+            message: format!(
+                "object changed during download: HEAD reported {expected_size} bytes \
+                 but range response reports {total} bytes"
+            ),
+        }),
+        _ => Ok(()),
+    }
 }
 
 fn calculate_chunk_size(file_size: u64) -> u64 {
@@ -66,7 +104,14 @@ fn calculate_chunk_size(file_size: u64) -> u64 {
     chunk_size.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE)
 }
 
+/// Split `file_size` bytes into range requests. Chunked mode is only chosen
+/// for files above `CHUNKED_DOWNLOAD_THRESHOLD`, so an empty file never gets
+/// here; zero chunks would otherwise "succeed" with nothing written.
 fn calculate_chunk_ranges(file_size: u64) -> Vec<ChunkRange> {
+    debug_assert!(
+        file_size > 0,
+        "chunked download requested for an empty file"
+    );
     let chunk_size = calculate_chunk_size(file_size);
     let mut chunks = Vec::new();
     let mut offset = 0;
@@ -93,6 +138,7 @@ async fn download_chunk(
     let mut last_error = None;
 
     for attempt in 0..=MAX_CHUNK_RETRIES {
+        ctx.retry_budget.check()?;
         let cached_token = get_cached_token_for_url_internal(ctx.token_cache, ctx.url).await;
 
         let mut request = ctx
@@ -119,8 +165,16 @@ async fn download_chunk(
                         }
                     };
 
-                    match fetch_bearer_token_internal(ctx.client, ctx.token_cache, www_auth).await {
+                    match fetch_bearer_token_internal(
+                        ctx.client,
+                        ctx.token_cache,
+                        ctx.url,
+                        www_auth,
+                    )
+                    .await
+                    {
                         Ok(_new_token) => {
+                            debug!(url = %ctx.url, offset = chunk.offset, "refreshed registry token for chunk");
                             last_error = Some(Error::NetworkFailure {
                                 message: "token expired, retrying with new token".to_string(),
                             });
@@ -148,6 +202,7 @@ async fn download_chunk(
                             ),
                         });
                     }
+                    check_content_range_total(range_str, ctx.file_size)?;
                 }
 
                 if !response.status().is_success() {
@@ -156,6 +211,14 @@ async fn download_chunk(
                     };
 
                     if response.status().is_server_error() && attempt < MAX_CHUNK_RETRIES {
+                        debug!(
+                            url = %ctx.url,
+                            offset = chunk.offset,
+                            attempt = attempt + 1,
+                            status = %response.status(),
+                            "retrying chunk after server error"
+                        );
+                        report_retry(ctx, attempt, &err);
                         last_error = Some(err);
                         tokio::time::sleep(Duration::from_millis(100 * (1 << attempt))).await;
                         continue;
@@ -199,6 +262,15 @@ async fn download_chunk(
                 last_error = Some(Error::network("chunk download failed")(e));
 
                 if attempt < MAX_CHUNK_RETRIES {
+                    debug!(
+                        url = %ctx.url,
+                        offset = chunk.offset,
+                        attempt = attempt + 1,
+                        "retrying chunk after request error"
+                    );
+                    if let Some(err) = &last_error {
+                        report_retry(ctx, attempt, err);
+                    }
                     tokio::time::sleep(Duration::from_millis(100 * (1 << attempt))).await;
                     continue;
                 }
@@ -211,21 +283,87 @@ async fn download_chunk(
     }))
 }
 
+/// Tell the progress callback that attempt `attempt` (zero-based) of a
+/// chunk failed with `reason` and another is about to start.
+fn report_retry(ctx: &ChunkDownloadContext<'_>, attempt: u32, reason: &Error) {
+    if let (Some(cb), Some(name)) = (&ctx.progress, &ctx.name) {
+        cb(InstallProgress::DownloadRetrying {
+            name: name.clone(),
+            attempt: attempt + 2,
+            max: MAX_CHUNK_RETRIES + 1,
+            reason: reason.to_string(),
+        });
+    }
+}
+
+/// Fetch a single chunk, moving on to the next URL only once the current one
+/// has exhausted its retries. Bytes from a mirror are not trusted on their own:
+/// the whole-file sha256 check in `download_with_chunks` still applies.
+async fn download_chunk_with_failover<'a>(
+    urls: &'a [String],
+    chunk: &ChunkRange,
+    make_ctx: impl Fn(&'a str) -> ChunkDownloadContext<'a>,
+) -> Result<Vec<u8>, Error> {
+    let mut last_error = None;
+
+    for url in urls {
+        match download_chunk(&make_ctx(url.as_str()), chunk).await {
+            Ok(data) => return Ok(data),
+            Err(e) => {
+                debug!(
+                    url = %url,
+                    offset = chunk.offset,
+                    error = %e,
+                    "chunk failed on this URL; trying next mirror"
+                );
+                last_error = Some(e);
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| Error::NetworkFailure {
+        message: "no URLs available for chunk download".to_string(),
+    }))
+}
+
+/// Download `ctx.url` in parallel ranges. A `bytes=0-0` probe decides
+/// whether the URL really serves ranges; when it does not, nothing is
+/// downloaded and `Ok(None)` leaves the caller to fetch the whole file.
 pub(crate) async fn download_with_chunks(
     ctx: &ChunkedDownloadContext<'_>,
-) -> Result<PathBuf, Error> {
-    if !validate_range_support(ctx).await? {
-        let response =
-            fetch_download_response_internal(ctx.client, ctx.token_cache, ctx.url).await?;
-        return download_response_internal(
-            ctx.blob_cache,
-            response,
-            ctx.expected_sha256,
-            ctx.name.clone(),
-            ctx.progress.clone(),
+) -> Result<Option<PathBuf>, Error> {
+    // A chunk that fails on `url` is retried against the mirrors, so only
+    // mirrors that serve ranges of the same object qualify. The others are
+    // still tried as whole-file downloads by the caller. Every probe runs at
+    // once, so a slow or dead mirror costs one timeout rather than one each.
+    let (primary, mirrors) = futures::join!(
+        validate_range_support(ctx, ctx.url),
+        futures::future::join_all(
+            ctx.alternate_urls
+                .iter()
+                .map(|mirror| validate_range_support(ctx, mirror))
         )
-        .await;
+    );
+    if !primary? {
+        debug!(url = %ctx.url, "range probe failed; not downloading in chunks");
+        return Ok(None);
+    }
+
+    let mut range_mirrors = Vec::new();
+    for (mirror, supported) in ctx.alternate_urls.iter().zip(mirrors) {
+        match supported {
+            Ok(true) => range_mirrors.push(mirror.clone()),
+            Ok(false) | Err(_) => {
+                debug!(mirror = %mirror, "mirror does not serve ranges; skipping for chunk failover")
+            }
+        }
     }
+    let ctx = &ChunkedDownloadContext {
+        alternate_urls: &range_mirrors,
+        name: ctx.name.clone(),
+        progress: ctx.progress.clone(),
+        ..*ctx
+    };
 
     let chunks = calculate_chunk_ranges(ctx.file_size);
 
@@ -241,103 +379,240 @@ pub(crate) async fn download_with_chunks(
         .start_write(ctx.expected_sha256)
         .map_err(Error::network("failed to create blob writer"))?;
 
-    let expected_chunks: BTreeMap<u64, u64> = chunks.iter().map(|c| (c.offset, c.size)).collect();
-    let total_chunks = chunks.len();
+    let (blob_path, _) = fetch_chunks(ctx, chunks, writer).await?;
 
-    let (chunk_tx, mut chunk_rx) = mpsc::unbounded_channel::<(Vec<u8>, u64)>();
+    if let (Some(cb), Some(n)) = (&ctx.progress, &ctx.name) {
+        cb(InstallProgress::DownloadCompleted {
+            name: n.clone(),
+            total_bytes: ctx.file_size,
+        });
+    }
 
-    let total_downloaded = Arc::new(AtomicU64::new(0));
+    Ok(Some(blob_path))
+}
 
-    let writer = Arc::new(Mutex::new(writer));
+/// Chunk data that arrived ahead of the hash cursor. Chunks are written to
+/// disk as they land, but hashed strictly in order, so anything past a gap
+/// is held here (with its share of the buffer budget) until the gap fills.
+struct ChunkReorder {
+    hasher: Sha256,
+    cursor: u64,
+    pending: BTreeMap<u64, (Vec<u8>, OwnedSemaphorePermit)>,
+}
 
-    let mut handles = Vec::new();
-    for chunk in chunks {
-        let client = ctx.client.clone();
-        let token_cache = ctx.token_cache.clone();
-        let url = ctx.url.to_string();
-        let global_semaphore = ctx.global_semaphore.clone();
-        let total_downloaded = total_downloaded.clone();
-        let progress = ctx.progress.clone();
-        let name = ctx.name.clone();
-        let chunk_tx = chunk_tx.clone();
-        let file_size = ctx.file_size;
-        let writer = writer.clone();
+impl ChunkReorder {
+    fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            cursor: 0,
+            pending: BTreeMap::new(),
+        }
+    }
 
-        let handle = tokio::spawn(async move {
-            let _permit = global_semaphore
-                .acquire()
-                .await
-                .map_err(Error::network("global semaphore error"))?;
-
-            let chunk_ctx = ChunkDownloadContext {
-                client: &client,
-                token_cache: &token_cache,
-                url: &url,
-                progress: progress.clone(),
-                name: name.clone(),
-                file_size,
-                total_downloaded: total_downloaded.clone(),
-            };
+    fn insert(&mut self, offset: u64, data: Vec<u8>, reservation: OwnedSemaphorePermit) {
+        self.pending.insert(offset, (data, reservation));
+        while let Some((data, _reservation)) = self.pending.remove(&self.cursor) {
+            self.hasher.update(&data);
+            self.cursor += data.len() as u64;
+        }
+    }
 
-            let chunk_data = download_chunk(&chunk_ctx, &chunk).await?;
+    fn finish(self, file_size: u64) -> Result<String, Error> {
+        if let Some(offset) = self.pending.keys().next() {
+            return Err(Error::NetworkFailure {
+                message: format!(
+                    "chunk gap detected: expected offset {}, got {}",
+                    self.cursor, offset
+                ),
+            });
+        }
 
-            {
-                let mut writer = writer.lock().await;
-                writer
-                    .seek(std::io::SeekFrom::Start(chunk.offset))
-                    .map_err(|e| Error::NetworkFailure {
-                        message: format!("failed to seek to offset {}: {e}", chunk.offset),
-                    })?;
-                writer
-                    .write_all(&chunk_data)
-                    .map_err(|e| Error::NetworkFailure {
-                        message: format!("failed to write chunk at offset {}: {e}", chunk.offset),
-                    })?;
-            }
+        if self.cursor != file_size {
+            return Err(Error::NetworkFailure {
+                message: format!(
+                    "incomplete write: expected {} bytes, wrote {} bytes",
+                    file_size, self.cursor
+                ),
+            });
+        }
 
-            chunk_tx
-                .send((chunk_data, chunk.offset))
-                .map_err(Error::network("failed to send chunk metadata"))?;
+        Ok(format!("{:x}", self.hasher.finalize()))
+    }
+}
 
-            Ok::<(), Error>(())
-        });
+/// Download every chunk into `writer` and verify the result, returning the
+/// committed blob and the most chunk bytes that were held at once.
+///
+/// Each chunk reserves its size from a byte budget before it starts, and
+/// gives it back once hashed. Reservations are taken in offset order, so the
+/// chunk the hash cursor is waiting for always holds one and the download
+/// can't wedge itself.
+async fn fetch_chunks(
+    ctx: &ChunkedDownloadContext<'_>,
+    chunks: Vec<ChunkRange>,
+    writer: BlobWriter,
+) -> Result<(PathBuf, u64), Error> {
+    let expected_chunks: BTreeMap<u64, u64> = chunks.iter().map(|c| (c.offset, c.size)).collect();
+    let total_chunks = chunks.len();
 
-        handles.push(handle);
-    }
+    let largest_chunk = chunks.iter().map(|c| c.size).max().unwrap_or(0);
+    let budget_bytes = ctx
+        .max_buffered_bytes
+        .max(largest_chunk)
+        .min(Semaphore::MAX_PERMITS as u64);
+    let budget = Arc::new(Semaphore::new(budget_bytes as usize));
 
-    drop(chunk_tx);
+    let (chunk_tx, mut chunk_rx) =
+        mpsc::unbounded_channel::<Result<(Vec<u8>, u64, OwnedSemaphorePermit), Error>>();
 
-    let mut received_chunks = BTreeMap::new();
-    let mut chunks_written = 0u64;
+    let total_downloaded = Arc::new(AtomicU64::new(0));
 
-    while let Some((chunk_data, offset)) = chunk_rx.recv().await {
-        let expected_size = expected_chunks
-            .get(&offset)
-            .ok_or_else(|| Error::NetworkFailure {
-                message: format!("received unexpected chunk at offset {}", offset),
-            })?;
+    let writer = Arc::new(Mutex::new(writer));
+    let mut tasks = JoinSet::new();
+
+    // Every chunk goes through the pooled client rather than an isolated one.
+    // The range probe above has already connected to the host, so on HTTP/2
+    // the chunks become concurrent streams on that connection instead of
+    // separate dials; HTTP/1.1 still needs one connection per in-flight chunk.
+    // Either way the semaphore below bounds how many run at once. Over
+    // HTTP/1.1 with two permits, a 40MB file (HEAD, probe and seven chunks)
+    // takes two connections instead of nine.
+    let schedule = {
+        let budget = budget.clone();
+        let writer = writer.clone();
+        let total_downloaded = total_downloaded.clone();
+        let tasks = &mut tasks;
+        async move {
+            let mut peak_reserved = 0u64;
+            for chunk in chunks {
+                let reservation = budget
+                    .clone()
+                    .acquire_many_owned(chunk.size as u32)
+                    .await
+                    .map_err(Error::network("chunk buffer budget closed"))?;
+                peak_reserved = peak_reserved.max(budget_bytes - budget.available_permits() as u64);
+
+                let client = ctx.client.clone();
+                let token_cache = ctx.token_cache.clone();
+                let urls: Vec<String> = std::iter::once(ctx.url.to_string())
+                    .chain(ctx.alternate_urls.iter().cloned())
+                    .collect();
+                let global_semaphore = ctx.global_semaphore.clone();
+                let retry_budget = ctx.retry_budget.clone();
+                let total_downloaded = total_downloaded.clone();
+                let progress = ctx.progress.clone();
+                let name = ctx.name.clone();
+                let chunk_tx = chunk_tx.clone();
+                let file_size = ctx.file_size;
+                let writer = writer.clone();
+
+                tasks.spawn(async move {
+                    let result = async {
+                        let _permit = global_semaphore
+                            .acquire()
+                            .await
+                            .map_err(Error::network("global semaphore error"))?;
+
+                        let chunk_data = download_chunk_with_failover(&urls, &chunk, |url| {
+                            ChunkDownloadContext {
+                                client: &client,
+                                token_cache: &token_cache,
+                                url,
+                                progress: progress.clone(),
+                                name: name.clone(),
+                                file_size,
+                                total_downloaded: total_downloaded.clone(),
+                                retry_budget: &retry_budget,
+                            }
+                        })
+                        .await?;
+
+                        let mut writer = writer.lock().await;
+                        writer
+                            .seek(std::io::SeekFrom::Start(chunk.offset))
+                            .map_err(|e| Error::NetworkFailure {
+                                message: format!("failed to seek to offset {}: {e}", chunk.offset),
+                            })?;
+                        writer
+                            .write_all(&chunk_data)
+                            .map_err(|e| Error::NetworkFailure {
+                                message: format!(
+                                    "failed to write chunk at offset {}: {e}",
+                                    chunk.offset
+                                ),
+                            })?;
+                        Ok(chunk_data)
+                    }
+                    .await;
 
-        if chunk_data.len() != *expected_size as usize {
-            return Err(Error::NetworkFailure {
-                message: format!(
-                    "chunk size mismatch at offset {}: expected {} bytes, got {} bytes",
-                    offset,
-                    expected_size,
-                    chunk_data.len()
-                ),
-            });
+                    // A failed chunk is reported rather than dropped: later
+                    // chunks keep their reservations until it arrives.
+                    let _ = chunk_tx
+                        .send(result.map(|chunk_data| (chunk_data, chunk.offset, reservation)));
+                });
+            }
+
+            drop(chunk_tx);
+            Ok::<_, Error>(peak_reserved)
         }
+    };
+
+    let receive = async {
+        let mut reorder = ChunkReorder::new();
+        let mut chunks_written = 0u64;
+
+        while let Some(received) = chunk_rx.recv().await {
+            let (chunk_data, offset, reservation) = received?;
+            let expected_size =
+                expected_chunks
+                    .get(&offset)
+                    .ok_or_else(|| Error::NetworkFailure {
+                        message: format!("received unexpected chunk at offset {}", offset),
+                    })?;
 
-        received_chunks.insert(offset, chunk_data);
-        chunks_written += 1;
-    }
+            if chunk_data.len() != *expected_size as usize {
+                return Err(Error::NetworkFailure {
+                    message: format!(
+                        "chunk size mismatch at offset {}: expected {} bytes, got {} bytes",
+                        offset,
+                        expected_size,
+                        chunk_data.len()
+                    ),
+                });
+            }
 
-    for handle in handles {
-        handle
-            .await
-            .map_err(Error::network("chunk download task failed"))??;
+            reorder.insert(offset, chunk_data, reservation);
+            chunks_written += 1;
+        }
+
+        Ok((reorder, chunks_written))
+    };
+
+    // The first failed chunk ends the join right away; chunks still in
+    // flight are not waited on.
+    let mut outcome = tokio::try_join!(schedule, receive);
+    if outcome.is_ok() {
+        while let Some(joined) = tasks.join_next().await {
+            if let Err(e) = joined {
+                outcome = Err(Error::network("chunk download task failed")(e));
+                break;
+            }
+        }
     }
 
+    let (peak_reserved, (reorder, chunks_written)) = match outcome {
+        Ok(done) => done,
+        Err(e) => {
+            // Cancel the other chunks instead of letting them download (or
+            // wait out retries) for nothing, and wait until they are gone:
+            // each holds the writer, and the partial file is only deleted
+            // once the last of them lets go.
+            tasks.shutdown().await;
+            drop(writer);
+            return Err(e);
+        }
+    };
+
     if chunks_written as usize != total_chunks {
         return Err(Error::NetworkFailure {
             message: format!(
@@ -347,31 +622,13 @@ pub(crate) async fn download_with_chunks(
         });
     }
 
-    let mut hasher = Sha256::new();
-    let mut total_size = 0u64;
-    for (offset, chunk_data) in received_chunks {
-        if offset != total_size {
-            return Err(Error::NetworkFailure {
-                message: format!(
-                    "chunk gap detected: expected offset {}, got {}",
-                    total_size, offset
-                ),
-            });
-        }
-        hasher.update(&chunk_data);
-        total_size += chunk_data.len() as u64;
-    }
+    debug!(
+        peak_reserved,
+        budget = budget_bytes,
+        "chunked download finished"
+    );
 
-    if total_size != ctx.file_size {
-        return Err(Error::NetworkFailure {
-            message: format!(
-                "incomplete write: expected {} bytes, wrote {} bytes",
-                ctx.file_size, total_size
-            ),
-        });
-    }
-
-    let actual_hash = format!("{:x}", hasher.finalize());
+    let actual_hash = reorder.finish(ctx.file_size)?;
 
     if actual_hash != ctx.expected_sha256 {
         return Err(Error::ChecksumMismatch {
@@ -390,19 +647,27 @@ pub(crate) async fn download_with_chunks(
         .flush()
         .map_err(Error::network("failed to flush download"))?;
 
-    if let (Some(cb), Some(n)) = (&ctx.progress, &ctx.name) {
-        cb(InstallProgress::DownloadCompleted {
-            name: n.clone(),
-            total_bytes: ctx.file_size,
-        });
-    }
-
-    writer.commit()
+    Ok((writer.commit()?, peak_reserved))
 }
 
-async fn validate_range_support(ctx: &ChunkedDownloadContext<'_>) -> Result<bool, Error> {
+async fn validate_range_support(
+    ctx: &ChunkedDownloadContext<'_>,
+    url: &str,
+) -> Result<bool, Error> {
+    // A server that rejects the range outright (416, 501, ...) just does
+    // not serve ranges; that is no reason to fail the download.
     let response =
-        fetch_range_response_internal(ctx.client, ctx.token_cache, ctx.url, "bytes=0-0").await?;
+        match fetch_range_response_internal(ctx.client, ctx.token_cache, url, "bytes=0-0").await {
+            Ok(response) => response,
+            Err(e) => {
+                debug!(url = %url, error = %e, "range probe request failed");
+                return Ok(false);
+            }
+        };
+
+    if ctx.reject_html {
+        reject_html_response(&response)?;
+    }
 
     if response.status() != StatusCode::PARTIAL_CONTENT {
         return Ok(false);
@@ -414,7 +679,12 @@ async fn validate_range_support(ctx: &ChunkedDownloadContext<'_>) -> Result<bool
         .and_then(|v| v.to_str().ok())
         .unwrap_or("");
 
-    Ok(content_range.contains("0-0"))
+    if !content_range.contains("0-0") {
+        return Ok(false);
+    }
+
+    check_content_range_total(content_range, ctx.file_size)?;
+    Ok(true)
 }
 
 #[cfg(test)]
@@ -430,8 +700,12 @@ mod tests {
     use crate::storage::blob::BlobCache;
 
     use super::super::single::Downloader;
-    use super::MAX_CONCURRENT_CHUNKS;
+    use super::{
+        DownloadProgressCallback, InstallProgress, MAX_CHUNK_RETRIES, MAX_CONCURRENT_CHUNKS,
+        RetryBudget,
+    };
     use std::sync::Arc;
+    use tokio::sync::Semaphore;
 
     #[tokio::test]
     async fn chunked_download_for_large_files() {
@@ -554,6 +828,55 @@ mod tests {
         assert_eq!(downloaded_content, large_content);
     }
 
+    #[tokio::test]
+    async fn head_advertising_ranges_is_overruled_by_the_range_probe() {
+        let mock_server = MockServer::start().await;
+
+        let large_content = vec![0xEFu8; 15 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+
+        Mock::given(method("HEAD"))
+            .and(path("/large.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("Content-Length", large_content.len().to_string())
+                    .append_header("Accept-Ranges", "bytes"),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let body = large_content.clone();
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(move |req: &wiremock::Request| {
+                if req.headers.contains_key("Range") {
+                    ResponseTemplate::new(416)
+                } else {
+                    ResponseTemplate::new(200).set_body_bytes(body.clone())
+                }
+            })
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let downloader = Downloader::new(blob_cache);
+
+        let url = format!("{}/large.tar.gz", mock_server.uri());
+        let blob_path = downloader.download(&url, &actual_sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), large_content);
+
+        // Only the probe asked for a range; no chunk requests followed it.
+        let ranged = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|req| req.headers.contains_key("Range"))
+            .count();
+        assert_eq!(ranged, 1);
+    }
+
     #[tokio::test]
     async fn small_files_dont_use_chunked_download() {
         let mock_server = MockServer::start().await;
@@ -1028,4 +1351,601 @@ mod tests {
         let downloaded_content = std::fs::read(&blob_path).unwrap();
         assert_eq!(downloaded_content, large_content);
     }
+
+    fn serve_ranges(content: Vec<u8>) -> impl Fn(&wiremock::Request) -> ResponseTemplate {
+        move |req: &wiremock::Request| {
+            let Some(range_header) = req.headers.get("Range") else {
+                return ResponseTemplate::new(200).set_body_bytes(content.clone());
+            };
+            let range_part = range_header
+                .to_str()
+                .unwrap()
+                .strip_prefix("bytes=")
+                .unwrap();
+            let (start_str, end_str) = range_part.split_once('-').unwrap();
+            let start: usize = start_str.parse().unwrap();
+            let end: usize = end_str.parse().unwrap();
+
+            ResponseTemplate::new(206)
+                .append_header("Content-Length", (end - start + 1).to_string())
+                .append_header(
+                    "Content-Range",
+                    format!("bytes {}-{}/{}", start, end, content.len()),
+                )
+                .set_body_bytes(content[start..=end].to_vec())
+        }
+    }
+
+    async fn mount_failing_primary(server: &MockServer, content: &[u8]) {
+        let first_byte = content[0];
+        let len = content.len();
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(move |req: &wiremock::Request| {
+                match req.headers.get("Range").map(|h| h.to_str().unwrap()) {
+                    Some("bytes=0-0") => ResponseTemplate::new(206)
+                        .append_header("Content-Length", "1")
+                        .append_header("Content-Range", format!("bytes 0-0/{len}"))
+                        .set_body_bytes(vec![first_byte]),
+                    _ => ResponseTemplate::new(500),
+                }
+            })
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn failed_chunks_fail_over_to_alternate_mirror() {
+        let primary = MockServer::start().await;
+        let mirror = MockServer::start().await;
+
+        let large_content = vec![0x34u8; 15 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+
+        mount_failing_primary(&primary, &large_content).await;
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(serve_ranges(large_content.clone()))
+            .mount(&mirror)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let primary_url = format!("{}/large.tar.gz", primary.uri());
+        let alternates = vec![format!("{}/large.tar.gz", mirror.uri())];
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &primary_url,
+            alternate_urls: &alternates,
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: large_content.len() as u64,
+            global_semaphore: &semaphore,
+            max_buffered_bytes: u64::MAX,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let blob_path = super::download_with_chunks(&ctx).await.unwrap().unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), large_content);
+    }
+
+    #[tokio::test]
+    async fn mirror_serving_different_bytes_still_fails_checksum() {
+        let primary = MockServer::start().await;
+        let mirror = MockServer::start().await;
+
+        let large_content = vec![0x56u8; 15 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+        let mut tampered = large_content.clone();
+        tampered[0] = 0x00;
+
+        mount_failing_primary(&primary, &large_content).await;
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(serve_ranges(tampered))
+            .mount(&mirror)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let primary_url = format!("{}/large.tar.gz", primary.uri());
+        let alternates = vec![format!("{}/large.tar.gz", mirror.uri())];
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &primary_url,
+            alternate_urls: &alternates,
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: large_content.len() as u64,
+            global_semaphore: &semaphore,
+            max_buffered_bytes: u64::MAX,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let err = super::download_with_chunks(&ctx).await.unwrap_err();
+        assert!(matches!(err, zb_core::Error::ChecksumMismatch { .. }));
+        assert!(!blob_cache.has_blob(&actual_sha256));
+    }
+
+    #[test]
+    fn content_range_total_parses_complete_length() {
+        assert_eq!(super::content_range_total("bytes 0-0/1234"), Some(1234));
+        assert_eq!(super::content_range_total("bytes 0-0/*"), None);
+        assert_eq!(super::content_range_total("garbage"), None);
+    }
+
+    #[tokio::test]
+    async fn size_change_between_head_and_get_aborts_chunked_download() {
+        let mock_server = MockServer::start().await;
+
+        let large_content = vec![0x78u8; 15 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+        let mut grown = large_content.clone();
+        grown.extend_from_slice(&[0u8; 1024]);
+
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(serve_ranges(grown))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let url = format!("{}/large.tar.gz", mock_server.uri());
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &url,
+            alternate_urls: &[],
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: large_content.len() as u64,
+            global_semaphore: &semaphore,
+            max_buffered_bytes: u64::MAX,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let err = super::download_with_chunks(&ctx).await.unwrap_err();
+        assert!(
+            err.to_string().contains("object changed during download"),
+            "unexpected error: {err}"
+        );
+    }
+
+    /// Forward every connection accepted on a local port to `upstream`,
+    /// counting them, so a test can see how many connections a client opened.
+    fn counting_proxy(upstream: std::net::SocketAddr) -> (String, Arc<AtomicUsize>) {
+        use std::net::{Shutdown, TcpListener, TcpStream};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = Arc::new(AtomicUsize::new(0));
+        let counter = accepted.clone();
+        std::thread::spawn(move || {
+            for client in listener.incoming() {
+                let Ok(client) = client else { break };
+                counter.fetch_add(1, Ordering::SeqCst);
+                let server = TcpStream::connect(upstream).unwrap();
+                for (mut from, mut to) in [
+                    (client.try_clone().unwrap(), server.try_clone().unwrap()),
+                    (server, client),
+                ] {
+                    std::thread::spawn(move || {
+                        let _ = std::io::copy(&mut from, &mut to);
+                        let _ = to.shutdown(Shutdown::Write);
+                    });
+                }
+            }
+        });
+        (format!("http://{addr}"), accepted)
+    }
+
+    #[tokio::test]
+    async fn chunks_reuse_pooled_connections_within_the_concurrency_limit() {
+        let mock_server = MockServer::start().await;
+
+        let large_content = vec![0xCDu8; 40 * 1024 * 1024];
+        let actual_sha256 = {
+            let mut hasher = Sha256::new();
+            hasher.update(&large_content);
+            format!("{:x}", hasher.finalize())
+        };
+
+        Mock::given(method("HEAD"))
+            .and(path("/large.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("Accept-Ranges", "bytes")
+                    .append_header("Content-Length", large_content.len().to_string()),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let range_requests = Arc::new(AtomicUsize::new(0));
+        let range_requests_clone = range_requests.clone();
+        let content = large_content.clone();
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(move |req: &wiremock::Request| {
+                let Some(range) = req.headers.get("Range") else {
+                    return ResponseTemplate::new(200).set_body_bytes(content.clone());
+                };
+                range_requests_clone.fetch_add(1, Ordering::SeqCst);
+                let (start, end) = range
+                    .to_str()
+                    .unwrap()
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                ResponseTemplate::new(206)
+                    .append_header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, content.len()),
+                    )
+                    .set_body_bytes(content[start..=end].to_vec())
+            })
+            .mount(&mock_server)
+            .await;
+
+        let (proxy_url, connections) = counting_proxy(*mock_server.address());
+
+        let permits = 2;
+        let tmp = TempDir::new().unwrap();
+        let downloader = Downloader::with_semaphore(
+            BlobCache::new(tmp.path()).unwrap(),
+            Some(Arc::new(Semaphore::new(permits))),
+        );
+
+        let url = format!("{proxy_url}/large.tar.gz");
+        let blob_path = downloader.download(&url, &actual_sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), large_content);
+
+        // The HEAD, the range probe and every chunk share the pooled client,
+        // so idle connections are picked up again instead of one per request.
+        // At most one connection per permit is open at once, plus the one
+        // the HEAD and probe left idle.
+        let requests = range_requests.load(Ordering::SeqCst) + 1;
+        let connections = connections.load(Ordering::SeqCst);
+        assert!(requests > permits + 1, "only {requests} requests");
+        assert!(
+            connections <= permits + 1,
+            "{connections} connections for {requests} requests"
+        );
+    }
+
+    #[tokio::test]
+    async fn buffered_chunk_bytes_stay_under_the_cap() {
+        let mock_server = MockServer::start().await;
+        let large_content: Vec<u8> = (0..30 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(serve_ranges(large_content.clone()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let url = format!("{}/large.tar.gz", mock_server.uri());
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &url,
+            alternate_urls: &[],
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: large_content.len() as u64,
+            global_semaphore: &semaphore,
+            // Below one chunk, so the cap is exactly one chunk.
+            max_buffered_bytes: 1,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let chunks = super::calculate_chunk_ranges(ctx.file_size);
+        let chunk_size = chunks[0].size;
+        assert!(chunks.len() > 1);
+
+        let writer = blob_cache.start_write(&actual_sha256).unwrap();
+        let (blob_path, peak) = super::fetch_chunks(&ctx, chunks, writer).await.unwrap();
+
+        assert_eq!(std::fs::read(&blob_path).unwrap(), large_content);
+        assert!(
+            peak <= chunk_size,
+            "held {peak} bytes, cap was {chunk_size}"
+        );
+    }
+
+    #[tokio::test]
+    async fn chunk_failover_skips_mirrors_without_range_support() {
+        let primary = MockServer::start().await;
+        let wholefile = MockServer::start().await;
+        let ranged = MockServer::start().await;
+
+        let large_content = vec![0x9Au8; 15 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+
+        mount_failing_primary(&primary, &large_content).await;
+        // Only the range probe should ever reach this mirror.
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(large_content.clone()))
+            .expect(1)
+            .mount(&wholefile)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(serve_ranges(large_content.clone()))
+            .mount(&ranged)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let primary_url = format!("{}/large.tar.gz", primary.uri());
+        let alternates = vec![
+            format!("{}/large.tar.gz", wholefile.uri()),
+            format!("{}/large.tar.gz", ranged.uri()),
+        ];
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &primary_url,
+            alternate_urls: &alternates,
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: large_content.len() as u64,
+            global_semaphore: &semaphore,
+            max_buffered_bytes: u64::MAX,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let blob_path = super::download_with_chunks(&ctx).await.unwrap().unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), large_content);
+    }
+
+    #[tokio::test]
+    async fn mirror_range_probes_run_concurrently() {
+        let primary = MockServer::start().await;
+        let slow_a = MockServer::start().await;
+        let slow_b = MockServer::start().await;
+
+        let large_content = vec![0x3Cu8; 4 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(serve_ranges(large_content.clone()))
+            .mount(&primary)
+            .await;
+        for mirror in [&slow_a, &slow_b] {
+            Mock::given(method("GET"))
+                .and(path("/large.tar.gz"))
+                .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+                .mount(mirror)
+                .await;
+        }
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let primary_url = format!("{}/large.tar.gz", primary.uri());
+        let alternates = vec![
+            format!("{}/large.tar.gz", slow_a.uri()),
+            format!("{}/large.tar.gz", slow_b.uri()),
+        ];
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &primary_url,
+            alternate_urls: &alternates,
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: large_content.len() as u64,
+            global_semaphore: &semaphore,
+            max_buffered_bytes: u64::MAX,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let started = std::time::Instant::now();
+        let blob_path = super::download_with_chunks(&ctx).await.unwrap().unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), large_content);
+        // One after the other, the two mirror probes alone take two seconds.
+        assert!(
+            started.elapsed() < Duration::from_millis(1800),
+            "took {:?}",
+            started.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn chunk_retry_reports_download_retrying() {
+        let mock_server = MockServer::start().await;
+
+        let large_content = vec![0x5Au8; 15 * 1024 * 1024];
+        let actual_sha256 = {
+            let mut hasher = Sha256::new();
+            hasher.update(&large_content);
+            format!("{:x}", hasher.finalize())
+        };
+
+        Mock::given(method("HEAD"))
+            .and(path("/large.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .append_header("Accept-Ranges", "bytes")
+                    .append_header("Content-Length", large_content.len().to_string()),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let failed_once = Arc::new(AtomicUsize::new(0));
+        let failed_once_clone = failed_once.clone();
+        let content = large_content.clone();
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(move |req: &wiremock::Request| {
+                let Some(range) = req.headers.get("Range") else {
+                    return ResponseTemplate::new(200).set_body_bytes(content.clone());
+                };
+                let range = range.to_str().unwrap();
+                // Let the range probe through so the download goes chunked.
+                if range != "bytes=0-0" && failed_once_clone.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return ResponseTemplate::new(503);
+                }
+                let (start, end) = range
+                    .strip_prefix("bytes=")
+                    .unwrap()
+                    .split_once('-')
+                    .unwrap();
+                let (start, end): (usize, usize) = (start.parse().unwrap(), end.parse().unwrap());
+                ResponseTemplate::new(206)
+                    .append_header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, content.len()),
+                    )
+                    .set_body_bytes(content[start..=end].to_vec())
+            })
+            .mount(&mock_server)
+            .await;
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let events_clone = events.clone();
+        let progress: DownloadProgressCallback = Arc::new(move |event| {
+            if let InstallProgress::DownloadRetrying { .. } = event {
+                events_clone.lock().unwrap().push(event);
+            }
+        });
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap());
+        let url = format!("{}/large.tar.gz", mock_server.uri());
+        downloader
+            .download_with_progress(
+                &url,
+                &actual_sha256,
+                Some("large".to_string()),
+                Some(progress),
+            )
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let InstallProgress::DownloadRetrying {
+            name,
+            attempt,
+            max,
+            reason,
+        } = &events[0]
+        else {
+            unreachable!();
+        };
+        assert_eq!(name, "large");
+        assert_eq!((*attempt, *max), (2, MAX_CHUNK_RETRIES + 1));
+        assert!(reason.contains("503"), "reason: {reason}");
+    }
+
+    #[tokio::test]
+    async fn failed_chunk_cancels_the_rest_and_leaves_no_partial_file() {
+        let mock_server = MockServer::start().await;
+        let large_content = vec![0x9Au8; 30 * 1024 * 1024];
+        let actual_sha256 = format!("{:x}", Sha256::digest(&large_content));
+        let len = large_content.len();
+        let serve = serve_ranges(large_content);
+
+        // The probe and the first chunk answer at once; the first chunk
+        // always with a 500. Every other chunk would take far longer than
+        // the failing one needs to exhaust its retries.
+        Mock::given(method("GET"))
+            .and(path("/large.tar.gz"))
+            .respond_with(move |req: &wiremock::Request| {
+                let range = req.headers.get("Range").map(|h| h.to_str().unwrap());
+                match range {
+                    Some("bytes=0-0") => serve(req),
+                    Some(r) if r.starts_with("bytes=0-") => ResponseTemplate::new(500),
+                    _ => serve(req).set_delay(Duration::from_secs(60)),
+                }
+            })
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let client = reqwest::Client::new();
+        let token_cache: super::TokenCache = Default::default();
+        let semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_CHUNKS));
+        let url = format!("{}/large.tar.gz", mock_server.uri());
+
+        let ctx = super::ChunkedDownloadContext {
+            blob_cache: &blob_cache,
+            client: &client,
+            token_cache: &token_cache,
+            url: &url,
+            alternate_urls: &[],
+            expected_sha256: &actual_sha256,
+            name: None,
+            progress: None,
+            file_size: len as u64,
+            global_semaphore: &semaphore,
+            max_buffered_bytes: u64::MAX,
+            reject_html: true,
+            retry_budget: &Arc::new(RetryBudget::new(u32::MAX)),
+        };
+
+        let result =
+            tokio::time::timeout(Duration::from_secs(20), super::download_with_chunks(&ctx))
+                .await
+                .expect("remaining chunks were waited on instead of cancelled");
+
+        assert!(matches!(result, Err(zb_core::Error::NetworkFailure { .. })));
+        assert!(!blob_cache.has_blob(&actual_sha256));
+        let leftovers: Vec<_> = std::fs::read_dir(tmp.path().join("tmp")).unwrap().collect();
+        assert!(leftovers.is_empty(), "partial files left: {leftovers:?}");
+        assert_eq!(semaphore.available_permits(), MAX_CONCURRENT_CHUNKS);
+    }
 }
@@ -1,10 +1,13 @@
-mod auth;
+pub(crate) mod auth;
 mod chunked;
 mod parallel;
+mod retry;
 mod single;
 
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::progress::InstallProgress;
 
@@ -27,15 +30,138 @@ const GLOBAL_DOWNLOAD_CONCURRENCY: usize = 20;
 /// With 20 global concurrency, we can have 3-4 large files downloading concurrently.
 const MAX_CONCURRENT_CHUNKS: usize = 6;
 
+/// Default ceiling on chunk bytes held in memory per chunked download:
+/// enough for a few maximum-size chunks to land out of order.
+const MAX_BUFFERED_CHUNK_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Default time allowed to establish a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default time allowed for a whole request, body included.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
 /// Maximum retry attempts for failed chunk downloads
 const MAX_CHUNK_RETRIES: u32 = 3;
 
+/// Retries for a plain single-connection download, used when racing is off
+/// and there are no mirrors to fall back on.
+const MAX_SINGLE_DOWNLOAD_RETRIES: u32 = 3;
+
+/// Failed attempts in a row, across every download sharing a downloader,
+/// after which the rest of the batch fails without trying again.
+const MAX_CONSECUTIVE_FAILURES: u32 = 8;
+
+/// Checksum mismatches after which a mirror host is skipped for the rest of
+/// the run. A host with fewer strikes is still tried, but after clean ones.
+const MIRROR_STRIKE_LIMIT: u32 = 2;
+
+/// Which IP family download connections use.
+///
+/// Some networks advertise IPv6 (AAAA records resolve, a default route
+/// exists) without actually carrying it, so every connection first stalls
+/// on an unreachable IPv6 address. Pinning the family avoids that stall.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum AddressFamily {
+    /// Connect to whichever addresses the host resolves to.
+    #[default]
+    Auto,
+    /// Only connect over IPv4.
+    Ipv4Only,
+    /// Only connect over IPv6.
+    Ipv6Only,
+}
+
+impl AddressFamily {
+    /// Parse the `auto`, `ipv4` and `ipv6` spellings used in configuration.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "auto" => Some(Self::Auto),
+            "ipv4" | "ipv4-only" | "4" => Some(Self::Ipv4Only),
+            "ipv6" | "ipv6-only" | "6" => Some(Self::Ipv6Only),
+            _ => None,
+        }
+    }
+
+    /// Unspecified local address to bind, which restricts the client to
+    /// resolved addresses of the same family.
+    fn local_address(self) -> Option<IpAddr> {
+        match self {
+            Self::Auto => None,
+            Self::Ipv4Only => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Self::Ipv6Only => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        }
+    }
+}
+
+impl TryFrom<String> for AddressFamily {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value).ok_or_else(|| format!("unknown address family `{value}`"))
+    }
+}
+
+/// How a non-chunked bottle download is fetched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloaderConfig {
+    /// Isolated connections raced against each other for the primary URL.
+    /// `1` disables racing.
+    pub racing_connections: usize,
+    /// Delay before each successive racing connection (and mirror) starts.
+    pub racing_stagger: Duration,
+    /// Bytes of chunk data a chunked download may hold at once, in flight or
+    /// waiting for earlier chunks. New chunks are not started past this.
+    /// Always at least one chunk.
+    pub max_buffered_chunk_bytes: u64,
+    /// Fail a download served as HTML (a captive portal or error page
+    /// rather than an archive) before any of it reaches the cache. Turn off
+    /// for servers that mislabel their archives.
+    pub reject_html_responses: bool,
+    /// How long to wait for a connection before giving up on a URL.
+    pub connect_timeout: Duration,
+    /// Upper bound on a single request, body included. Large bottles over
+    /// slow links may need more than the default.
+    pub request_timeout: Duration,
+    /// Failed attempts in a row, counted across the whole batch, after which
+    /// remaining downloads fail immediately instead of retrying. Any download
+    /// that completes resets the count.
+    pub max_consecutive_failures: u32,
+    /// IP family to connect over, for every client including the isolated
+    /// ones used for racing.
+    pub address_family: AddressFamily,
+}
+
+impl Default for DownloaderConfig {
+    fn default() -> Self {
+        Self {
+            racing_connections: RACING_CONNECTIONS,
+            racing_stagger: Duration::from_millis(RACING_STAGGER_MS),
+            max_buffered_chunk_bytes: MAX_BUFFERED_CHUNK_BYTES,
+            reject_html_responses: true,
+            connect_timeout: CONNECT_TIMEOUT,
+            request_timeout: REQUEST_TIMEOUT,
+            max_consecutive_failures: MAX_CONSECUTIVE_FAILURES,
+            address_family: AddressFamily::Auto,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DownloadResult {
     pub name: String,
     pub sha256: String,
     pub blob_path: PathBuf,
     pub index: usize,
+    /// Bytes fetched over the network for this request. Zero for a cache
+    /// hit, and for a request that waited on an identical download already
+    /// in flight, so a batch's results add up to what was transferred.
+    pub bytes_transferred: u64,
+    /// The blob was already in the cache; nothing was requested.
+    pub from_cache: bool,
+    /// Time from getting a download slot (or starting to wait on an
+    /// in-flight download) until the blob was ready.
+    pub duration: Duration,
 }
 
 pub use parallel::{DownloadRequest, ParallelDownloader};
@@ -1,23 +1,75 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use tokio::sync::{Mutex, Semaphore, mpsc};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc};
+use tokio::time::Instant;
 
+use crate::ssl::TlsOptions;
 use crate::storage::blob::BlobCache;
 use zb_core::Error;
 
 use super::single::Downloader;
-use super::{DownloadProgressCallback, DownloadResult, GLOBAL_DOWNLOAD_CONCURRENCY};
+use super::{
+    DownloadProgressCallback, DownloadResult, DownloaderConfig, GLOBAL_DOWNLOAD_CONCURRENCY,
+};
 
 pub struct DownloadRequest {
     pub url: String,
     pub sha256: String,
     pub name: String,
+    /// Higher values are handed a download slot first when a batch is
+    /// waiting on the concurrency limit. Requests of equal priority keep
+    /// their original order.
+    pub priority: u8,
+    /// Further URLs serving the same file, raced and failed over to after
+    /// `url` alongside any `HOMEBREW_BOTTLE_MIRRORS` hosts.
+    pub mirror_urls: Vec<String>,
 }
 
 type InflightMap = HashMap<String, Arc<tokio::sync::broadcast::Sender<Result<PathBuf, String>>>>;
 
+/// Owns a blob's entry in the in-flight map for as long as its download runs.
+/// If the download future is dropped before `finish`, because its task was
+/// aborted or panicked, the entry is removed and waiters are told it failed
+/// rather than left waiting on a broadcast that never comes.
+struct InflightGuard {
+    inflight: Arc<Mutex<InflightMap>>,
+    sha256: String,
+    finished: bool,
+}
+
+impl InflightGuard {
+    fn finish(mut self, result: Result<PathBuf, String>) {
+        self.finished = true;
+        self.release(result);
+    }
+
+    fn release(&self, result: Result<PathBuf, String>) {
+        let sender = self
+            .inflight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.sha256);
+        if let Some(sender) = sender {
+            let _ = sender.send(result);
+        }
+    }
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.release(Err(format!(
+                "download of {} was cancelled before it finished",
+                self.sha256
+            )));
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct ParallelDownloader {
     downloader: Arc<Downloader>,
     semaphore: Arc<Semaphore>,
@@ -49,10 +101,75 @@ impl ParallelDownloader {
         }
     }
 
+    /// Put the underlying downloader into offline mode.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.downloader_mut().offline = offline;
+        self
+    }
+
+    /// Apply custom TLS trust settings to the underlying downloader.
+    pub fn with_tls_options(mut self, options: &TlsOptions) -> Result<Self, Error> {
+        self.downloader_mut().configure_tls(options)?;
+        Ok(self)
+    }
+
+    /// Serve ghcr.io bottles from a compatible registry instead.
+    pub fn with_registry_base(mut self, base: &str) -> Result<Self, Error> {
+        self.downloader_mut().configure_registry_base(base)?;
+        Ok(self)
+    }
+
+    /// Fetch bottles from these mirror hosts instead of the ones in
+    /// `HOMEBREW_BOTTLE_MIRRORS`.
+    pub fn with_mirrors(mut self, mirrors: &[String]) -> Self {
+        self.downloader_mut().set_mirrors(mirrors);
+        self
+    }
+
+    /// Set racing connection count and stagger on the underlying downloader.
+    pub fn with_config(mut self, config: DownloaderConfig) -> Self {
+        self.downloader_mut().set_config(config);
+        self
+    }
+
+    /// The downloader, for the `with_*` builders above. While a running
+    /// download or a clone still holds it, this one gets a copy of its own,
+    /// so the setting always applies here and leaves the others as they were.
+    fn downloader_mut(&mut self) -> &mut Downloader {
+        Arc::make_mut(&mut self.downloader)
+    }
+
+    pub(crate) fn blob_cache(&self) -> &BlobCache {
+        &self.downloader.blob_cache
+    }
+
     pub fn remove_blob(&self, sha256: &str) -> bool {
         self.downloader.remove_blob(sha256)
     }
 
+    /// SHA-256 digests of the blobs currently being downloaded, sorted.
+    pub fn inflight_downloads(&self) -> Vec<String> {
+        let map = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+        let mut keys: Vec<String> = map.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    /// Forget every in-flight download. Anyone waiting on one gets an error;
+    /// the downloads themselves keep running, and the next request for the
+    /// same blob starts a fresh one.
+    pub fn clear_inflight(&self) {
+        let senders: Vec<_> = self
+            .inflight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .drain()
+            .collect();
+        for (sha256, sender) in senders {
+            let _ = sender.send(Err(format!("download of {sha256} was cleared")));
+        }
+    }
+
     pub async fn download_single(
         &self,
         request: DownloadRequest,
@@ -64,8 +181,10 @@ impl ParallelDownloader {
             self.inflight.clone(),
             request,
             progress,
+            None,
         )
         .await
+        .map(|result| result.blob_path)
     }
 
     pub async fn download_all(
@@ -80,71 +199,122 @@ impl ParallelDownloader {
         requests: Vec<DownloadRequest>,
         progress: Option<DownloadProgressCallback>,
     ) -> Result<Vec<PathBuf>, Error> {
-        let handles: Vec<_> = requests
+        self.download_all_results_with_progress(requests, progress)
+            .await
             .into_iter()
-            .map(|req| {
-                let downloader = self.downloader.clone();
-                let semaphore = self.semaphore.clone();
-                let inflight = self.inflight.clone();
-                let progress = progress.clone();
+            .collect()
+    }
 
-                tokio::spawn(async move {
-                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress).await
-                })
-            })
-            .collect();
+    /// Download every request into the blob cache and discard the paths. Same
+    /// concurrency limit and in-flight dedup as `download_all`.
+    pub async fn prefetch(&self, requests: Vec<DownloadRequest>) -> Result<(), Error> {
+        self.download_all(requests).await.map(|_| ())
+    }
+
+    /// Download every request and report each outcome separately, in request
+    /// order, instead of failing the whole batch on the first error.
+    pub async fn download_all_results(
+        &self,
+        requests: Vec<DownloadRequest>,
+    ) -> Vec<Result<PathBuf, Error>> {
+        self.download_all_results_with_progress(requests, None)
+            .await
+    }
 
-        let mut results = Vec::with_capacity(handles.len());
-        for handle in handles {
-            let result = handle.await.map_err(Error::network("task join error"))??;
-            results.push(result);
+    pub async fn download_all_results_with_progress(
+        &self,
+        requests: Vec<DownloadRequest>,
+        progress: Option<DownloadProgressCallback>,
+    ) -> Vec<Result<PathBuf, Error>> {
+        let count = requests.len();
+        let mut rx = self.dispatch(requests, progress);
+
+        let mut results: Vec<Option<Result<PathBuf, Error>>> = (0..count).map(|_| None).collect();
+        while let Some((index, result)) = rx.recv().await {
+            results[index] = Some(result.map(|result| result.blob_path));
         }
 
-        Ok(results)
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    Err(Error::NetworkFailure {
+                        message: "task join error: download task ended without a result".into(),
+                    })
+                })
+            })
+            .collect()
     }
 
     pub fn download_streaming(
         &self,
         requests: Vec<DownloadRequest>,
         progress: Option<DownloadProgressCallback>,
-    ) -> mpsc::Receiver<Result<DownloadResult, Error>> {
+    ) -> mpsc::Receiver<(usize, Result<DownloadResult, Error>)> {
+        self.dispatch(requests, progress)
+    }
+
+    /// Start every request, handing out download slots highest priority
+    /// first. Results arrive as `(request index, result)` in completion order.
+    ///
+    /// The batch shares one retry budget: once
+    /// `DownloaderConfig::max_consecutive_failures` attempts have failed in a
+    /// row, the remaining requests fail without touching the network.
+    fn dispatch(
+        &self,
+        requests: Vec<DownloadRequest>,
+        progress: Option<DownloadProgressCallback>,
+    ) -> mpsc::Receiver<(usize, Result<DownloadResult, Error>)> {
         let (tx, rx) = mpsc::channel(requests.len().max(1));
+        self.downloader.retry_budget.reset();
 
-        for (index, req) in requests.into_iter().enumerate() {
-            let downloader = self.downloader.clone();
-            let semaphore = self.semaphore.clone();
-            let inflight = self.inflight.clone();
-            let progress = progress.clone();
-            let tx = tx.clone();
-            let name = req.name.clone();
-            let sha256 = req.sha256.clone();
-
-            tokio::spawn(async move {
-                let result =
-                    Self::download_with_dedup(downloader, semaphore, inflight, req, progress).await;
-                let _ = tx
-                    .send(result.map(|blob_path| DownloadResult {
-                        name,
-                        sha256,
-                        blob_path,
-                        index,
-                    }))
-                    .await;
-            });
-        }
+        let mut queue: Vec<(usize, DownloadRequest)> = requests.into_iter().enumerate().collect();
+        queue.sort_by_key(|(index, req)| (Reverse(req.priority), *index));
+
+        let downloader = self.downloader.clone();
+        let semaphore = self.semaphore.clone();
+        let inflight = self.inflight.clone();
+
+        tokio::spawn(async move {
+            for (index, req) in queue {
+                // Acquiring here, in priority order, is what makes priority
+                // matter: the semaphore wakes waiters first-come first-served.
+                let permit = semaphore.clone().acquire_owned().await.ok();
+
+                let downloader = downloader.clone();
+                let semaphore = semaphore.clone();
+                let inflight = inflight.clone();
+                let progress = progress.clone();
+                let tx = tx.clone();
+
+                tokio::spawn(async move {
+                    let result = Self::download_with_dedup(
+                        downloader, semaphore, inflight, req, progress, permit,
+                    )
+                    .await
+                    .map(|result| DownloadResult { index, ..result });
+                    let _ = tx.send((index, result)).await;
+                });
+            }
+        });
 
         rx
     }
 
+    /// Download `req` unless the same blob is already being fetched, in which
+    /// case wait for that download instead. `permit` is a slot the caller has
+    /// already reserved; without one, a slot is acquired here. The result's
+    /// `index` is left at 0 for the caller to fill in.
     async fn download_with_dedup(
         downloader: Arc<Downloader>,
         semaphore: Arc<Semaphore>,
         inflight: Arc<Mutex<InflightMap>>,
         req: DownloadRequest,
         progress: Option<DownloadProgressCallback>,
-    ) -> Result<PathBuf, Error> {
+        permit: Option<OwnedSemaphorePermit>,
+    ) -> Result<DownloadResult, Error> {
         let mut receiver = {
-            let mut map = inflight.lock().await;
+            let mut map = inflight.lock().unwrap_or_else(|e| e.into_inner());
 
             if let Some(sender) = map.get(&req.sha256) {
                 Some(sender.subscribe())
@@ -156,35 +326,71 @@ impl ParallelDownloader {
         };
 
         if let Some(ref mut rx) = receiver {
-            let result = rx
+            drop(permit);
+            let started = Instant::now();
+            let blob_path = rx
                 .recv()
                 .await
-                .map_err(Error::network("broadcast recv error"))?;
-
-            return result.map_err(|msg| Error::NetworkFailure { message: msg });
+                .map_err(Error::network("broadcast recv error"))?
+                .map_err(|msg| Error::NetworkFailure { message: msg })?;
+
+            return Ok(DownloadResult {
+                name: req.name,
+                sha256: req.sha256,
+                blob_path,
+                index: 0,
+                bytes_transferred: 0,
+                from_cache: false,
+                duration: started.elapsed(),
+            });
         }
 
-        let _permit = semaphore
-            .acquire()
-            .await
-            .map_err(Error::network("semaphore error"))?;
+        let guard = InflightGuard {
+            inflight,
+            sha256: req.sha256.clone(),
+            finished: false,
+        };
 
+        let _permit = match permit {
+            Some(permit) => permit,
+            None => semaphore
+                .acquire_owned()
+                .await
+                .map_err(Error::network("semaphore error"))?,
+        };
+
+        let started = Instant::now();
+        let from_cache = downloader.blob_cache.has_blob(&req.sha256);
         let result = downloader
-            .download_with_progress(&req.url, &req.sha256, Some(req.name), progress)
+            .download_with_mirrors(
+                &req.url,
+                &req.mirror_urls,
+                &req.sha256,
+                Some(req.name.clone()),
+                progress,
+            )
             .await;
 
-        {
-            let mut map = inflight.lock().await;
-            if let Some(sender) = map.remove(&req.sha256) {
-                let broadcast_result = match &result {
-                    Ok(path) => Ok(path.clone()),
-                    Err(e) => Err(e.to_string()),
-                };
-                let _ = sender.send(broadcast_result);
-            }
-        }
+        guard.finish(match &result {
+            Ok(path) => Ok(path.clone()),
+            Err(e) => Err(e.to_string()),
+        });
 
-        result
+        let blob_path = result?;
+        let bytes_transferred = if from_cache {
+            0
+        } else {
+            std::fs::metadata(&blob_path).map_or(0, |meta| meta.len())
+        };
+        Ok(DownloadResult {
+            name: req.name,
+            sha256: req.sha256,
+            blob_path,
+            index: 0,
+            bytes_transferred,
+            from_cache,
+            duration: started.elapsed(),
+        })
     }
 }
 
@@ -238,6 +444,8 @@ mod tests {
                     url: format!("{}/file{i}.tar.gz", mock_server.uri()),
                     sha256,
                     name: format!("pkg{i}"),
+                    priority: 0,
+                    mirror_urls: Vec::new(),
                 }
             })
             .collect();
@@ -282,6 +490,8 @@ mod tests {
                 url: format!("{}/dedup.tar.gz", mock_server.uri()),
                 sha256: actual_sha256.clone(),
                 name: format!("dedup{i}"),
+                priority: 0,
+                mirror_urls: Vec::new(),
             })
             .collect();
 
@@ -292,4 +502,254 @@ mod tests {
             assert!(path.exists());
         }
     }
+
+    #[test]
+    fn configuring_a_shared_downloader_applies_to_the_configured_one() {
+        let tmp = TempDir::new().unwrap();
+        let downloader = ParallelDownloader::new(BlobCache::new(tmp.path()).unwrap());
+        // As a running download would hold it.
+        let shared = downloader.downloader.clone();
+        let downloader = downloader.with_offline(true);
+
+        assert!(downloader.downloader.offline);
+        assert!(!shared.offline);
+    }
+
+    #[tokio::test]
+    async fn higher_priority_requests_start_first() {
+        let mock_server = MockServer::start().await;
+        let started = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let started_clone = started.clone();
+
+        let bodies: Vec<Vec<u8>> = (0..3).map(|i| format!("body {i}").into_bytes()).collect();
+        let served = bodies.clone();
+        Mock::given(method("GET"))
+            .respond_with(move |req: &wiremock::Request| {
+                let file = req.url.path().trim_start_matches('/').to_string();
+                let index: usize = file[4..5].parse().unwrap();
+                started_clone.lock().unwrap().push(index);
+                ResponseTemplate::new(200).set_body_bytes(served[index].clone())
+            })
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            ParallelDownloader::with_concurrency(BlobCache::new(tmp.path()).unwrap(), 1);
+
+        let requests: Vec<_> = bodies
+            .iter()
+            .enumerate()
+            .map(|(i, body)| DownloadRequest {
+                url: format!("{}/file{i}.tar.gz", mock_server.uri()),
+                sha256: format!("{:x}", Sha256::digest(body)),
+                name: format!("pkg{i}"),
+                priority: if i == 2 { 10 } else { 0 },
+                mirror_urls: Vec::new(),
+            })
+            .collect();
+
+        let paths = downloader.download_all(requests).await.unwrap();
+
+        assert_eq!(paths.len(), 3);
+        // Racing may open more than one connection per download; only the
+        // order in which each download first hit the server matters.
+        let mut first_seen = Vec::new();
+        for index in started.lock().unwrap().iter() {
+            if !first_seen.contains(index) {
+                first_seen.push(*index);
+            }
+        }
+        assert_eq!(first_seen, vec![2, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn download_all_results_keeps_successes_alongside_failures() {
+        let mock_server = MockServer::start().await;
+        let content = b"good content";
+        let good_sha = format!("{:x}", Sha256::digest(content));
+
+        Mock::given(method("GET"))
+            .and(path("/good.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(content.to_vec())
+                    .set_delay(Duration::from_millis(50)),
+            )
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/missing.tar.gz"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = ParallelDownloader::new(BlobCache::new(tmp.path()).unwrap());
+
+        let request = |file: &str, sha256: &str, name: &str| DownloadRequest {
+            url: format!("{}/{file}", mock_server.uri()),
+            sha256: sha256.to_string(),
+            name: name.to_string(),
+            priority: 0,
+            mirror_urls: Vec::new(),
+        };
+        let requests = vec![
+            request("good.tar.gz", &good_sha, "good"),
+            request("missing.tar.gz", &"f".repeat(64), "missing"),
+            request("good.tar.gz", &good_sha, "good-again"),
+        ];
+
+        let results = downloader.download_all_results(requests).await;
+
+        assert_eq!(results.len(), 3);
+        let first = results[0].as_ref().unwrap();
+        assert!(first.exists());
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap(), first);
+    }
+
+    #[tokio::test]
+    async fn aborted_primary_download_fails_waiters_instead_of_hanging() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/slow.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(b"slow".to_vec())
+                    .set_delay(Duration::from_secs(30)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = ParallelDownloader::new(BlobCache::new(tmp.path()).unwrap());
+        let sha256 = "a".repeat(64);
+        let request = |name: &str| DownloadRequest {
+            url: format!("{}/slow.tar.gz", mock_server.uri()),
+            sha256: sha256.clone(),
+            name: name.to_string(),
+            priority: 0,
+            mirror_urls: Vec::new(),
+        };
+
+        let primary = {
+            let downloader = downloader.clone();
+            let request = request("primary");
+            tokio::spawn(async move { downloader.download_single(request, None).await })
+        };
+        while downloader.inflight_downloads().is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let waiter = {
+            let downloader = downloader.clone();
+            let request = request("waiter");
+            tokio::spawn(async move { downloader.download_single(request, None).await })
+        };
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        primary.abort();
+        let result = tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .expect("waiter hung after the primary download was aborted")
+            .unwrap();
+
+        let err = result.unwrap_err();
+        assert!(err.to_string().contains("cancelled"), "{err}");
+        assert!(downloader.inflight_downloads().is_empty());
+    }
+
+    #[tokio::test]
+    async fn results_tell_network_fetches_from_cache_hits() {
+        let mock_server = MockServer::start().await;
+        let content = b"timed content";
+        let sha256 = format!("{:x}", Sha256::digest(content));
+
+        Mock::given(method("GET"))
+            .and(path("/timed.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = ParallelDownloader::new(BlobCache::new(tmp.path()).unwrap());
+        let request = |name: &str| DownloadRequest {
+            url: format!("{}/timed.tar.gz", mock_server.uri()),
+            sha256: sha256.clone(),
+            name: name.to_string(),
+            priority: 0,
+            mirror_urls: Vec::new(),
+        };
+
+        let mut rx = downloader.download_streaming(vec![request("first")], None);
+        let (_, fetched) = rx.recv().await.unwrap();
+        let fetched = fetched.unwrap();
+        assert!(!fetched.from_cache);
+        assert_eq!(fetched.bytes_transferred, content.len() as u64);
+        assert_eq!(fetched.name, "first");
+
+        let mut rx = downloader.download_streaming(vec![request("second")], None);
+        let (index, cached) = rx.recv().await.unwrap();
+        let cached = cached.unwrap();
+        assert_eq!(index, 0);
+        assert!(cached.from_cache);
+        assert_eq!(cached.bytes_transferred, 0);
+        assert_eq!(cached.blob_path, fetched.blob_path);
+    }
+
+    #[tokio::test]
+    async fn outage_exhausts_shared_retry_budget_instead_of_retrying_every_request() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+
+        let concurrency = 4;
+        let limit = 6;
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            ParallelDownloader::with_concurrency(BlobCache::new(tmp.path()).unwrap(), concurrency)
+                .with_config(DownloaderConfig {
+                    racing_connections: 1,
+                    max_consecutive_failures: limit,
+                    ..DownloaderConfig::default()
+                });
+
+        let requests = (0..20)
+            .map(|i| DownloadRequest {
+                url: format!("{}/bottle-{i}.tar.gz", mock_server.uri()),
+                sha256: format!("{i:064x}"),
+                name: format!("bottle-{i}"),
+                priority: 0,
+                mirror_urls: Vec::new(),
+            })
+            .collect();
+
+        let results = downloader.download_all_results(requests).await;
+
+        assert!(results.iter().all(Result::is_err));
+        assert!(results.iter().any(|r| {
+            r.as_ref()
+                .unwrap_err()
+                .to_string()
+                .contains("consecutive download failures")
+        }));
+
+        // Without the budget every request would make all of its attempts.
+        let gets = mock_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.method.as_str() == "GET")
+            .count();
+        assert!(
+            gets <= limit as usize + concurrency,
+            "made {gets} attempts during a total outage"
+        );
+    }
 }
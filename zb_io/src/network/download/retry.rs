@@ -0,0 +1,111 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use zb_core::Error;
+
+/// Circuit breaker shared by every download on one downloader. Each failed
+/// attempt counts against it (see [`counts_against_budget`]) and any
+/// completed download resets it, so a
+/// flaky mirror or a single bad bottle keeps its retries while a batch hitting
+/// a dead network stops after `limit` failures in a row instead of retrying
+/// every request on its own.
+#[derive(Debug)]
+pub(crate) struct RetryBudget {
+    consecutive_failures: AtomicU32,
+    limit: u32,
+}
+
+impl RetryBudget {
+    pub(crate) fn new(limit: u32) -> Self {
+        Self {
+            consecutive_failures: AtomicU32::new(0),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Count a failed attempt. Returns whether another one may be made.
+    pub(crate) fn record_failure(&self) -> bool {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::AcqRel) + 1;
+        failures < self.limit
+    }
+
+    pub(crate) fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Release);
+    }
+
+    /// Forget earlier failures, at the start of a new batch.
+    pub(crate) fn reset(&self) {
+        self.record_success();
+    }
+
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.consecutive_failures.load(Ordering::Acquire) >= self.limit
+    }
+
+    /// Fail fast once the budget is spent, before any request goes out.
+    pub(crate) fn check(&self) -> Result<(), Error> {
+        if self.is_exhausted() {
+            return Err(self.exhausted_error());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn exhausted_error(&self) -> Error {
+        Error::NetworkFailure {
+            message: format!(
+                "giving up after {} consecutive download failures; \
+                 the network or download server appears to be unreachable",
+                self.limit
+            ),
+        }
+    }
+}
+
+/// Whether a failed attempt says anything about the network. A checksum
+/// mismatch or an HTTP 4xx is about that one file, so other downloads should
+/// still be tried.
+pub(crate) fn counts_against_budget(err: &Error) -> bool {
+    match err {
+        Error::ChecksumMismatch { .. } => false,
+        Error::NetworkFailure { message } => !message.starts_with("HTTP 4"),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trips_after_limit_and_resets_on_success() {
+        let budget = RetryBudget::new(3);
+        assert!(budget.record_failure());
+        assert!(budget.record_failure());
+        budget.record_success();
+        assert!(budget.check().is_ok());
+
+        assert!(budget.record_failure());
+        assert!(budget.record_failure());
+        assert!(!budget.record_failure());
+        assert!(budget.is_exhausted());
+        assert!(budget.check().is_err());
+
+        budget.reset();
+        assert!(!budget.is_exhausted());
+    }
+
+    #[test]
+    fn only_network_trouble_counts() {
+        let http = |status: &str| Error::NetworkFailure {
+            message: format!("HTTP {status}"),
+        };
+        assert!(!counts_against_budget(&http("404 Not Found")));
+        assert!(counts_against_budget(&http("503 Service Unavailable")));
+        assert!(!counts_against_budget(&Error::ChecksumMismatch {
+            expected: "a".into(),
+            actual: "b".into(),
+        }));
+        assert!(counts_against_budget(&Error::network("connect")(
+            std::io::Error::other("refused")
+        )));
+    }
+}
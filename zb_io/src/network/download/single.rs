@@ -1,45 +1,105 @@
 use std::collections::HashMap;
 use std::io::Write;
 use std::path::PathBuf;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use futures_util::StreamExt;
 use futures_util::future::select_all;
-use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH};
+use reqwest::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE};
+use rustls::pki_types::CertificateDer;
 use sha2::{Digest, Sha256};
-use tokio::sync::{Notify, RwLock, Semaphore};
+use tokio::sync::{Notify, Semaphore};
 use tracing::warn;
 
 use crate::progress::InstallProgress;
+use crate::ssl::{
+    NoCertificateVerification, TlsOptions, find_ca_bundle_from_prefix, find_ca_dir,
+    find_prefix_ca_certs, load_ca_certs,
+};
 use crate::storage::blob::BlobCache;
 use zb_core::Error;
 
 use super::auth::{
     TokenCache, bearer_header, fetch_download_response_internal, get_cached_token_for_url_internal,
+    request_error,
 };
-use super::chunked::{ChunkedDownloadContext, download_with_chunks, server_supports_ranges};
+use super::chunked::{ChunkedDownloadContext, download_with_chunks, server_refuses_ranges};
+use super::retry::{RetryBudget, counts_against_budget};
 use super::{
-    CHUNKED_DOWNLOAD_THRESHOLD, DownloadProgressCallback, GLOBAL_DOWNLOAD_CONCURRENCY,
-    RACING_CONNECTIONS, RACING_STAGGER_MS,
+    CHUNKED_DOWNLOAD_THRESHOLD, DownloadProgressCallback, DownloaderConfig,
+    GLOBAL_DOWNLOAD_CONCURRENCY, MAX_SINGLE_DOWNLOAD_RETRIES, MIRROR_STRIKE_LIMIT,
 };
 
-fn get_alternate_urls(primary_url: &str) -> Vec<String> {
-    let mut alternates = Vec::new();
+/// SHA-256 of zero bytes; the only checksum an empty download can satisfy.
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Marks a `HOMEBREW_BOTTLE_MIRRORS` entry to be tried before the URL the
+/// bottle was published at, e.g. `primary!mirror.example.com`.
+const PREFERRED_MIRROR_FLAG: &str = "primary!";
+
+/// `configured` replaces `HOMEBREW_BOTTLE_MIRRORS` when set; see
+/// [`Downloader::with_mirrors`].
+fn get_download_urls(
+    url: &str,
+    configured: Option<&str>,
+    declared_mirrors: &[String],
+) -> Vec<String> {
+    let mirrors = match configured {
+        Some(mirrors) => mirrors.to_string(),
+        None => std::env::var("HOMEBREW_BOTTLE_MIRRORS").unwrap_or_default(),
+    };
+    let mut urls = order_download_urls(url, &mirrors);
+    append_declared_mirrors(&mut urls, declared_mirrors);
+    urls
+}
+
+/// Formula-declared mirrors go after everything configured through the
+/// environment; they need no ghcr rewriting and are skipped if already listed.
+fn append_declared_mirrors(urls: &mut Vec<String>, declared_mirrors: &[String]) {
+    for mirror in declared_mirrors {
+        if !mirror.is_empty() && !urls.contains(mirror) {
+            urls.push(mirror.clone());
+        }
+    }
+}
 
-    if let Ok(mirrors) = std::env::var("HOMEBREW_BOTTLE_MIRRORS") {
-        for mirror in mirrors.split(',') {
-            let mirror = mirror.trim();
-            if !mirror.is_empty()
-                && let Some(alt) = transform_url_to_mirror(primary_url, mirror)
-            {
-                alternates.push(alt);
+/// Every URL to fetch a bottle from, in the order to try them: preferred
+/// mirrors, then `url` itself, then the remaining mirrors. Chunked and
+/// whole-file downloads both treat the first entry as the primary, so a
+/// mirror preference holds regardless of bottle size.
+fn order_download_urls(url: &str, mirrors: &str) -> Vec<String> {
+    let mut preferred = Vec::new();
+    let mut fallback = Vec::new();
+
+    for mirror in mirrors.split(',') {
+        let mirror = mirror.trim();
+        let (mirror, is_preferred) = match mirror.strip_prefix(PREFERRED_MIRROR_FLAG) {
+            Some(rest) => (rest.trim(), true),
+            None => (mirror, false),
+        };
+        if mirror.is_empty() {
+            continue;
+        }
+        if let Some(alt) = transform_url_to_mirror(url, mirror) {
+            if is_preferred {
+                preferred.push(alt);
+            } else {
+                fallback.push(alt);
             }
         }
     }
 
-    alternates
+    preferred.push(url.to_string());
+    preferred.extend(fallback);
+    preferred
+}
+
+fn url_host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(ToString::to_string))
 }
 
 fn transform_url_to_mirror(url: &str, mirror_domain: &str) -> Option<String> {
@@ -50,11 +110,59 @@ fn transform_url_to_mirror(url: &str, mirror_domain: &str) -> Option<String> {
     }
 }
 
-pub(crate) fn build_rustls_config() -> Option<rustls::ClientConfig> {
-    let provider = rustls::crypto::aws_lc_rs::default_provider();
+pub(crate) fn build_rustls_config(
+    options: &TlsOptions,
+) -> Result<Option<rustls::ClientConfig>, Error> {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
 
-    let mut root_store = rustls::RootCertStore::empty();
+    let mut root_store = collect_root_store(load_native_roots(), options);
 
+    if let Some(ca_bundle) = &options.ca_bundle {
+        for cert in load_ca_certs(ca_bundle)? {
+            root_store.add(cert).map_err(|e| Error::InvalidArgument {
+                message: format!("unusable certificate in {}: {e}", ca_bundle.display()),
+            })?;
+        }
+    }
+
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone());
+    let builder = match builder.with_safe_default_protocol_versions() {
+        Ok(builder) => builder,
+        Err(e) if options.ca_bundle.is_none() && !options.insecure => {
+            warn!(
+                error = %e,
+                "failed to configure rustls protocol versions; falling back to reqwest default TLS"
+            );
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(Error::NetworkFailure {
+                message: format!("failed to configure TLS: {e}"),
+            });
+        }
+    };
+
+    if root_store.is_empty() && !options.insecure && options.prefix.is_some() {
+        warn_no_trusted_roots();
+    }
+
+    if options.insecure {
+        return Ok(Some(
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoCertificateVerification(provider)))
+                .with_no_client_auth(),
+        ));
+    }
+
+    Ok(Some(
+        builder
+            .with_root_certificates(root_store)
+            .with_no_client_auth(),
+    ))
+}
+
+fn load_native_roots() -> Vec<CertificateDer<'static>> {
     let cert_result = rustls_native_certs::load_native_certs();
     if !cert_result.errors.is_empty() {
         let details = cert_result
@@ -70,36 +178,130 @@ pub(crate) fn build_rustls_config() -> Option<rustls::ClientConfig> {
             "failed to load native certificates"
         );
     }
+    cert_result.certs
+}
 
-    for cert in cert_result.certs {
+/// The native roots plus whatever the prefix provides. Failures here are
+/// never fatal: a broken prefix bundle must not take down downloads that
+/// the native roots could still serve.
+fn collect_root_store(
+    native: Vec<CertificateDer<'static>>,
+    options: &TlsOptions,
+) -> rustls::RootCertStore {
+    let mut root_store = rustls::RootCertStore::empty();
+    for cert in native {
         let _ = root_store.add(cert);
     }
 
-    let builder = rustls::ClientConfig::builder_with_provider(provider.into());
-    let builder = match builder.with_safe_default_protocol_versions() {
-        Ok(builder) => builder,
-        Err(e) => {
-            warn!(
+    let Some(prefix) = options.prefix.as_deref() else {
+        return root_store;
+    };
+    let mut prefix_certs = find_prefix_ca_certs(prefix);
+    // `SSL_CERT_FILE` normally outranks the prefix, but if it yielded
+    // nothing the prefix bundle is the only hope left.
+    if prefix_certs.is_none() && root_store.is_empty() {
+        prefix_certs = find_ca_bundle_from_prefix(prefix).or_else(|| find_ca_dir(prefix));
+    }
+    if let Some(path) = prefix_certs {
+        match load_ca_certs(&path) {
+            Ok(certs) => {
+                for cert in certs {
+                    let _ = root_store.add(cert);
+                }
+            }
+            Err(e) => warn!(
+                path = %path.display(),
                 error = %e,
-                "failed to configure rustls protocol versions; falling back to reqwest default TLS"
-            );
-            return None;
+                "failed to load CA certificates from prefix"
+            ),
         }
-    };
+    }
+    root_store
+}
 
-    Some(
-        builder
-            .with_root_certificates(root_store)
-            .with_no_client_auth(),
-    )
+/// Without any trust roots every HTTPS download fails with an opaque
+/// handshake error, so say what is missing up front.
+fn warn_no_trusted_roots() {
+    warn!(
+        "no trusted CA certificates were found, so HTTPS downloads will fail; \
+         install your system's ca-certificates package (e.g. `apt-get install \
+         ca-certificates` or `apk add ca-certificates`), or point SSL_CERT_FILE or \
+         --ca-bundle at a PEM bundle"
+    );
+}
+
+/// Client used, with a warning, when the configured one fails to build
+/// where there is no error to return. It has none of the configured TLS,
+/// timeout or address settings, but like the others it leaves bodies
+/// undecoded, since bottles are hashed as received.
+fn fallback_client(err: Error) -> reqwest::Client {
+    warn!(
+        error = %err,
+        "falling back to a default download client; configured TLS, timeout and address settings are not applied"
+    );
+    reqwest::Client::builder()
+        .user_agent("zerobrew/0.1")
+        .gzip(false)
+        .build()
+        .expect("client with default settings should build")
 }
 
+fn build_client(
+    tls_config: Option<&Arc<rustls::ClientConfig>>,
+    config: &DownloaderConfig,
+) -> Result<reqwest::Client, Error> {
+    // Bottles are verified against the checksum of the bytes on the wire, so
+    // never let the client negotiate a content encoding and decode it.
+    let mut builder = reqwest::Client::builder()
+        .user_agent("zerobrew/0.1")
+        .gzip(false);
+    if let Some(tls_config) = tls_config {
+        // reqwest downcasts to a concrete `ClientConfig`, not an `Arc` of one.
+        builder = builder.use_preconfigured_tls(rustls::ClientConfig::clone(tls_config));
+    }
+
+    // Pooled connections are what let chunked downloads multiplex over one
+    // HTTP/2 connection per host, so keep idle ones alive between the range
+    // probe, the chunk burst and the next bottle from the same registry.
+    builder
+        .pool_max_idle_per_host(10)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_nodelay(true)
+        .tcp_keepalive(Duration::from_secs(60))
+        .connect_timeout(config.connect_timeout)
+        .timeout(config.request_timeout)
+        .local_address(config.address_family.local_address())
+        .http2_keep_alive_interval(Duration::from_secs(20))
+        .http2_keep_alive_timeout(Duration::from_secs(10))
+        .http2_keep_alive_while_idle(true)
+        .http2_adaptive_window(true)
+        .http2_initial_stream_window_size(Some(2 * 1024 * 1024))
+        .http2_initial_connection_window_size(Some(4 * 1024 * 1024))
+        .build()
+        .map_err(Error::network("failed to build download client"))
+}
+
+#[derive(Clone)]
 pub struct Downloader {
     client: reqwest::Client,
     pub(crate) blob_cache: BlobCache,
     pub(crate) token_cache: TokenCache,
     pub(crate) global_semaphore: Option<Arc<Semaphore>>,
     tls_config: Option<Arc<rustls::ClientConfig>>,
+    /// What the shared client was built with: `None` until custom TLS
+    /// options are applied, leaving reqwest's defaults in place.
+    client_tls_config: Option<Arc<rustls::ClientConfig>>,
+    pub(crate) offline: bool,
+    insecure_tls: bool,
+    pub(crate) config: DownloaderConfig,
+    /// Mirror list in `HOMEBREW_BOTTLE_MIRRORS` syntax. `None` reads the
+    /// environment variable at download time.
+    mirrors: Option<String>,
+    /// Checksum mismatches seen per mirror host during this run.
+    mirror_strikes: Arc<Mutex<HashMap<String, u32>>>,
+    /// Shared by every download made through this downloader, so a batch
+    /// stops retrying once the network is plainly down.
+    pub(crate) retry_budget: Arc<RetryBudget>,
 }
 
 impl Downloader {
@@ -108,45 +310,185 @@ impl Downloader {
     }
 
     pub fn with_semaphore(blob_cache: BlobCache, semaphore: Option<Arc<Semaphore>>) -> Self {
-        let tls_config = build_rustls_config().map(Arc::new);
+        let tls_config = build_rustls_config(&TlsOptions::default())
+            .ok()
+            .flatten()
+            .map(Arc::new);
 
+        let config = DownloaderConfig::default();
         Self {
-            client: reqwest::Client::builder()
-                .user_agent("zerobrew/0.1")
-                .pool_max_idle_per_host(10)
-                .tcp_nodelay(true)
-                .tcp_keepalive(Duration::from_secs(60))
-                .connect_timeout(Duration::from_secs(30))
-                .timeout(Duration::from_secs(300))
-                .http2_adaptive_window(true)
-                .http2_initial_stream_window_size(Some(2 * 1024 * 1024))
-                .http2_initial_connection_window_size(Some(4 * 1024 * 1024))
-                .build()
-                .unwrap_or_else(|_| reqwest::Client::new()),
+            client: build_client(None, &config).unwrap_or_else(fallback_client),
             blob_cache,
-            token_cache: Arc::new(RwLock::new(HashMap::new())),
+            token_cache: TokenCache::default(),
             global_semaphore: semaphore,
             tls_config,
+            client_tls_config: None,
+            offline: false,
+            insecure_tls: false,
+            mirrors: None,
+            mirror_strikes: Arc::new(Mutex::new(HashMap::new())),
+            retry_budget: Arc::new(RetryBudget::new(config.max_consecutive_failures)),
+            config,
+        }
+    }
+
+    /// Trust an extra CA bundle and/or disable certificate verification.
+    /// Both the shared client and the isolated racing clients are rebuilt on
+    /// top of the resulting rustls config.
+    pub fn with_tls_options(mut self, options: &TlsOptions) -> Result<Self, Error> {
+        self.configure_tls(options)?;
+        Ok(self)
+    }
+
+    pub(crate) fn configure_tls(&mut self, options: &TlsOptions) -> Result<(), Error> {
+        let prefix_certs = options.prefix.as_deref().and_then(find_prefix_ca_certs);
+        // With no native roots at all, reqwest's defaults cannot verify
+        // anything either; build our own config so the prefix gets a say.
+        if options.ca_bundle.is_none()
+            && !options.insecure
+            && prefix_certs.is_none()
+            && (options.prefix.is_none() || !load_native_roots().is_empty())
+        {
+            return Ok(());
+        }
+
+        let tls_config =
+            build_rustls_config(options)?
+                .map(Arc::new)
+                .ok_or_else(|| Error::NetworkFailure {
+                    message: "failed to build TLS configuration".to_string(),
+                })?;
+
+        self.client = build_client(Some(&tls_config), &self.config)?;
+        self.client_tls_config = Some(tls_config.clone());
+        self.tls_config = Some(tls_config);
+        self.insecure_tls = options.insecure;
+        Ok(())
+    }
+
+    /// Only serve blobs already in the cache; never touch the network.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Racing, buffering and timeout settings. See [`DownloaderConfig`].
+    pub fn with_config(mut self, config: DownloaderConfig) -> Self {
+        self.set_config(config);
+        self
+    }
+
+    /// Use these mirror hosts instead of reading `HOMEBREW_BOTTLE_MIRRORS`.
+    /// Entries take the same form, including the `primary!` prefix.
+    pub fn with_mirrors(mut self, mirrors: &[String]) -> Self {
+        self.set_mirrors(mirrors);
+        self
+    }
+
+    pub(crate) fn set_mirrors(&mut self, mirrors: &[String]) {
+        self.mirrors = Some(mirrors.join(","));
+    }
+
+    /// Fetch `https://ghcr.io/v2/...` bottles from a registry implementing the
+    /// same token and blob protocol, e.g. `https://registry.internal`.
+    /// Registry tokens are scoped to that host alone.
+    pub fn with_registry_base(mut self, base: &str) -> Result<Self, Error> {
+        self.configure_registry_base(base)?;
+        Ok(self)
+    }
+
+    pub(crate) fn configure_registry_base(&mut self, base: &str) -> Result<(), Error> {
+        let parsed = reqwest::Url::parse(base).map_err(|e| Error::InvalidArgument {
+            message: format!("invalid registry base URL: {e}"),
+        })?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err(Error::InvalidArgument {
+                message: format!(
+                    "registry base URL must use http or https scheme, got: {}",
+                    parsed.scheme()
+                ),
+            });
         }
+        if !parsed.username().is_empty() || parsed.password().is_some() {
+            return Err(Error::InvalidArgument {
+                message: "registry base URL must not contain credentials".to_string(),
+            });
+        }
+
+        self.token_cache = std::mem::take(&mut self.token_cache).with_registry_base(Some(base));
+        Ok(())
+    }
+
+    /// Replace the config, rebuilding the shared client so new timeouts
+    /// take effect.
+    pub(crate) fn set_config(&mut self, config: DownloaderConfig) {
+        self.client =
+            build_client(self.client_tls_config.as_ref(), &config).unwrap_or_else(fallback_client);
+        self.retry_budget = Arc::new(RetryBudget::new(config.max_consecutive_failures));
+        self.config = config;
     }
 
-    fn create_isolated_client(&self) -> reqwest::Client {
-        let mut builder = reqwest::Client::builder().user_agent("zerobrew/0.1");
+    fn create_isolated_client(&self) -> Result<reqwest::Client, Error> {
+        let mut builder = reqwest::Client::builder()
+            .user_agent("zerobrew/0.1")
+            .gzip(false);
         if let Some(tls_config) = &self.tls_config {
-            builder = builder.use_preconfigured_tls(tls_config.clone());
+            builder = builder.use_preconfigured_tls(rustls::ClientConfig::clone(tls_config));
         }
 
         builder
             .pool_max_idle_per_host(0)
             .tcp_nodelay(true)
             .tcp_keepalive(Duration::from_secs(60))
-            .connect_timeout(Duration::from_secs(30))
-            .timeout(Duration::from_secs(300))
+            .connect_timeout(self.config.connect_timeout)
+            .timeout(self.config.request_timeout)
+            .local_address(self.config.address_family.local_address())
             .http2_adaptive_window(true)
             .http2_initial_stream_window_size(Some(2 * 1024 * 1024))
             .http2_initial_connection_window_size(Some(4 * 1024 * 1024))
             .build()
-            .unwrap_or_else(|_| reqwest::Client::new())
+            .map_err(Error::network("failed to build download client"))
+    }
+
+    /// Count a checksum mismatch against the host `url` was served from.
+    /// The canonical host is never penalised: there is nothing to fall
+    /// back to if it is skipped.
+    fn record_checksum_strike(&self, url: &str, canonical_url: &str) {
+        let Some(host) = url_host(url) else {
+            return;
+        };
+        if url_host(canonical_url).as_deref() == Some(host.as_str()) {
+            return;
+        }
+
+        let mut strikes = self.mirror_strikes.lock().unwrap();
+        let count = strikes.entry(host.clone()).or_default();
+        *count += 1;
+        if *count == MIRROR_STRIKE_LIMIT {
+            warn!(host = %host, "mirror served mismatching bottles; skipping it for this run");
+        }
+    }
+
+    /// Drop mirrors that reached [`MIRROR_STRIKE_LIMIT`] and move struck
+    /// ones behind the rest, keeping the configured order otherwise.
+    fn rank_download_urls(&self, canonical_url: &str, urls: Vec<String>) -> Vec<String> {
+        let strikes = self.mirror_strikes.lock().unwrap();
+        let strikes_for = |url: &str| {
+            if url == canonical_url {
+                return 0;
+            }
+            url_host(url)
+                .and_then(|host| strikes.get(&host).copied())
+                .unwrap_or(0)
+        };
+
+        let mut ranked: Vec<(u32, String)> = urls
+            .into_iter()
+            .map(|url| (strikes_for(&url), url))
+            .filter(|(count, _)| *count < MIRROR_STRIKE_LIMIT)
+            .collect();
+        ranked.sort_by_key(|(count, _)| *count);
+        ranked.into_iter().map(|(_, url)| url).collect()
     }
 
     pub fn remove_blob(&self, sha256: &str) -> bool {
@@ -164,6 +506,21 @@ impl Downloader {
         expected_sha256: &str,
         name: Option<String>,
         progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
+        self.download_with_mirrors(url, &[], expected_sha256, name, progress)
+            .await
+    }
+
+    /// Like [`Downloader::download_with_progress`], with `mirror_urls` raced
+    /// and failed over to after `url` in addition to any mirrors configured
+    /// through `HOMEBREW_BOTTLE_MIRRORS`.
+    pub async fn download_with_mirrors(
+        &self,
+        url: &str,
+        mirror_urls: &[String],
+        expected_sha256: &str,
+        name: Option<String>,
+        progress: Option<DownloadProgressCallback>,
     ) -> Result<PathBuf, Error> {
         if self.blob_cache.has_blob(expected_sha256) {
             if let (Some(cb), Some(n)) = (&progress, &name) {
@@ -175,14 +532,51 @@ impl Downloader {
             return Ok(self.blob_cache.blob_path(expected_sha256));
         }
 
-        let alternates = get_alternate_urls(url);
+        if self.offline {
+            return Err(Error::OfflineCacheMiss {
+                name: name.unwrap_or_else(|| url.to_string()),
+            });
+        }
 
-        self.download_with_racing(url, &alternates, expected_sha256, name, progress)
-            .await
+        if self.insecure_tls {
+            warn!(
+                url = %url,
+                "TLS CERTIFICATE VERIFICATION IS DISABLED (insecure_tls); this download is not authenticated"
+            );
+        }
+
+        // Mirrors are derived from the published ghcr URL; only the
+        // canonical entry moves to the configured registry.
+        let canonical = self.token_cache.registry_url(url);
+        let urls = get_download_urls(url, self.mirrors.as_deref(), mirror_urls)
+            .into_iter()
+            .map(|u| if u == url { canonical.to_string() } else { u })
+            .collect();
+        let urls = self.rank_download_urls(&canonical, urls);
+        let (primary, alternates) = urls
+            .split_first()
+            .expect("the canonical URL is never dropped");
+
+        self.retry_budget.check()?;
+        let result = self
+            .download_with_racing(
+                &canonical,
+                primary,
+                alternates,
+                expected_sha256,
+                name,
+                progress,
+            )
+            .await;
+        if result.is_ok() {
+            self.retry_budget.record_success();
+        }
+        result
     }
 
     async fn download_with_racing(
         &self,
+        canonical_url: &str,
         primary_url: &str,
         alternate_urls: &[String],
         expected_sha256: &str,
@@ -206,11 +600,11 @@ impl Downloader {
                         .and_then(|v| v.to_str().ok())
                         .and_then(|s| s.parse::<u64>().ok());
 
-                    let supports_ranges = server_supports_ranges(&response);
+                    let refuses_ranges = server_refuses_ranges(&response);
 
                     if let Some(size) = content_length {
                         (
-                            supports_ranges && size >= CHUNKED_DOWNLOAD_THRESHOLD,
+                            !refuses_ranges && size >= CHUNKED_DOWNLOAD_THRESHOLD,
                             Some(size),
                         )
                     } else {
@@ -227,37 +621,49 @@ impl Downloader {
                 .clone()
                 .unwrap_or_else(|| Arc::new(Semaphore::new(GLOBAL_DOWNLOAD_CONCURRENCY)));
 
-            let mut all_urls = Vec::new();
-            all_urls.push(primary_url.to_string());
-            all_urls.extend(alternate_urls.iter().cloned());
-
-            let mut last_error = None;
-            for url in &all_urls {
-                let ctx = ChunkedDownloadContext {
-                    blob_cache: &self.blob_cache,
-                    client: &self.client,
-                    token_cache: &self.token_cache,
-                    url: url.as_str(),
-                    expected_sha256,
-                    name: name.clone(),
-                    progress: progress.clone(),
-                    file_size: size,
-                    global_semaphore: &semaphore,
-                };
+            // Mirrors are tried per chunk inside `download_with_chunks`, so
+            // the chunked download runs once rather than once per URL.
+            let ctx = ChunkedDownloadContext {
+                blob_cache: &self.blob_cache,
+                client: &self.client,
+                token_cache: &self.token_cache,
+                url: primary_url,
+                alternate_urls,
+                expected_sha256,
+                name: name.clone(),
+                progress: progress.clone(),
+                file_size: size,
+                global_semaphore: &semaphore,
+                max_buffered_bytes: self.config.max_buffered_chunk_bytes,
+                reject_html: self.config.reject_html_responses,
+                retry_budget: &self.retry_budget,
+            };
 
-                match download_with_chunks(&ctx).await {
-                    Ok(path) => return Ok(path),
-                    Err(err) => last_error = Some(err),
+            match download_with_chunks(&ctx).await {
+                Ok(Some(path)) => return Ok(path),
+                // The primary does not really serve ranges: fetch the whole
+                // file below, racing and mirrors included.
+                Ok(None) => {}
+                Err(err) => {
+                    if matches!(err, Error::ChecksumMismatch { .. }) {
+                        self.record_checksum_strike(primary_url, canonical_url);
+                    } else if counts_against_budget(&err) {
+                        self.retry_budget.record_failure();
+                    }
+                    warn!(
+                        error = %err,
+                        "chunked download failed; falling back to single-connection download"
+                    );
                 }
             }
+        }
 
-            warn!(
-                error = %last_error
-                    .as_ref()
-                    .map(|e| e.to_string())
-                    .unwrap_or_else(|| "unknown error".to_string()),
-                "chunked download failed; falling back to single-connection download"
-            );
+        self.retry_budget.check()?;
+        let racing_connections = self.config.racing_connections.max(1);
+        if racing_connections == 1 && alternate_urls.is_empty() {
+            return self
+                .download_plain(primary_url, expected_sha256, name, progress)
+                .await;
         }
 
         let done = Arc::new(AtomicBool::new(false));
@@ -266,19 +672,25 @@ impl Downloader {
 
         let mut all_urls: Vec<String> = Vec::new();
 
-        for _ in 0..RACING_CONNECTIONS {
+        for _ in 0..racing_connections {
             all_urls.push(primary_url.to_string());
         }
 
         all_urls.extend(alternate_urls.iter().cloned());
 
+        // Built up front so a failure leaves no racer already running.
+        let mut isolated_clients = (0..racing_connections)
+            .map(|_| self.create_isolated_client())
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+
         let mut handles = Vec::new();
+        let mut attempts = Vec::new();
         for (idx, url) in all_urls.into_iter().enumerate() {
-            let downloader_client = if idx < RACING_CONNECTIONS {
-                self.create_isolated_client()
-            } else {
-                self.client.clone()
-            };
+            attempts.push((idx, url.clone()));
+            let downloader_client = isolated_clients
+                .next()
+                .unwrap_or_else(|| self.client.clone());
             let blob_cache = self.blob_cache.clone();
             let token_cache = self.token_cache.clone();
             let expected_sha256 = expected_sha256.to_string();
@@ -287,8 +699,9 @@ impl Downloader {
             let done = done.clone();
             let done_notify = done_notify.clone();
             let body_download_gate = body_download_gate.clone();
+            let reject_html = self.config.reject_html_responses;
 
-            let delay = Duration::from_millis(idx as u64 * RACING_STAGGER_MS);
+            let delay = self.config.racing_stagger * idx as u32;
 
             let handle = tokio::spawn(async move {
                 tokio::time::sleep(delay).await;
@@ -352,6 +765,7 @@ impl Downloader {
                     &expected_sha256,
                     name,
                     progress,
+                    reject_html,
                 )
                 .await;
 
@@ -368,27 +782,129 @@ impl Downloader {
 
         let mut pending = handles;
         let mut last_error = None;
+        // The racers are one attempt at this download, so a lost race costs
+        // the shared budget at most one failure, not one per connection.
+        let mut network_failed = false;
 
         while !pending.is_empty() {
-            let (result, _index, remaining) = select_all(pending).await;
+            let (result, index, remaining) = select_all(pending).await;
             pending = remaining;
+            // `select_all` swap-removes the finished future; mirror that.
+            let (attempt, url) = attempts.swap_remove(index);
 
             match result {
                 Ok(Ok(path)) => {
+                    let mut aborted = 0;
                     for handle in &pending {
+                        if !handle.is_finished() {
+                            aborted += 1;
+                        }
                         handle.abort();
                     }
+                    if let (Some(cb), Some(n)) = (&progress, &name) {
+                        cb(InstallProgress::DownloadRaceWon {
+                            name: n.clone(),
+                            url,
+                            attempt,
+                            aborted,
+                        });
+                    }
                     return Ok(path);
                 }
-                Ok(Err(e)) => last_error = Some(e),
+                Ok(Err(e)) => {
+                    if matches!(e, Error::ChecksumMismatch { .. }) {
+                        self.record_checksum_strike(&url, canonical_url);
+                    }
+                    network_failed |= counts_against_budget(&e);
+                    last_error = Some(e);
+                }
                 Err(e) => last_error = Some(Error::network("task join error")(e)),
             }
         }
 
+        if network_failed {
+            self.retry_budget.record_failure();
+        }
+
         Err(last_error.unwrap_or_else(|| Error::NetworkFailure {
             message: "all download attempts failed".to_string(),
         }))
     }
+
+    /// One connection on the shared client, retried with backoff. With racing
+    /// off there is no second connection to cover a dropped one.
+    async fn download_plain(
+        &self,
+        url: &str,
+        expected_sha256: &str,
+        name: Option<String>,
+        progress: Option<DownloadProgressCallback>,
+    ) -> Result<PathBuf, Error> {
+        let mut attempt = 0;
+        loop {
+            let result = async {
+                let response =
+                    fetch_download_response_internal(&self.client, &self.token_cache, url).await?;
+                download_response_internal(
+                    &self.blob_cache,
+                    response,
+                    expected_sha256,
+                    name.clone(),
+                    progress.clone(),
+                    self.config.reject_html_responses,
+                )
+                .await
+            }
+            .await;
+
+            match result {
+                Err(e) if !matches!(e, Error::ChecksumMismatch { .. }) => {
+                    let may_retry =
+                        !counts_against_budget(&e) || self.retry_budget.record_failure();
+                    if attempt >= MAX_SINGLE_DOWNLOAD_RETRIES {
+                        return Err(e);
+                    }
+                    if !may_retry {
+                        warn!(url = %url, error = %e, "download failed; retry budget exhausted");
+                        return Err(self.retry_budget.exhausted_error());
+                    }
+                    warn!(url = %url, error = %e, attempt = attempt + 1, "download failed; retrying");
+                    tokio::time::sleep(Duration::from_millis(100 * (1 << attempt))).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Refuse a response whose `Content-Type` marks it as an HTML page. A bottle
+/// or source archive is never served that way, so it is almost certainly a
+/// captive portal or an error page that would only fail the checksum later.
+pub(crate) fn reject_html_response(response: &reqwest::Response) -> Result<(), Error> {
+    let Some(content_type) = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return Ok(());
+    };
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if mime == "text/html" || mime == "application/xhtml+xml" {
+        return Err(Error::NetworkFailure {
+            message: format!(
+                "{} returned an HTML page ({content_type}) instead of an archive; \
+                 a captive portal or proxy may be intercepting downloads",
+                response.url()
+            ),
+        });
+    }
+    Ok(())
 }
 
 pub(crate) async fn download_response_internal(
@@ -397,7 +913,12 @@ pub(crate) async fn download_response_internal(
     expected_sha256: &str,
     name: Option<String>,
     progress: Option<DownloadProgressCallback>,
+    reject_html: bool,
 ) -> Result<PathBuf, Error> {
+    if reject_html {
+        reject_html_response(&response)?;
+    }
+
     let total_bytes = response
         .headers()
         .get(CONTENT_LENGTH)
@@ -415,12 +936,19 @@ pub(crate) async fn download_response_internal(
         .start_write(expected_sha256)
         .map_err(Error::network("failed to create blob writer"))?;
 
+    let url = response.url().to_string();
+    let started = Instant::now();
     let mut hasher = Sha256::new();
     let mut stream = response.bytes_stream();
     let mut downloaded: u64 = 0;
 
     while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(Error::network("failed to read chunk"))?;
+        let chunk = chunk.map_err(|e| match request_error(&url, started, e) {
+            Error::NetworkFailure { message } => Error::NetworkFailure {
+                message: format!("failed to read chunk: {message}"),
+            },
+            timeout => timeout,
+        })?;
 
         downloaded += chunk.len() as u64;
         hasher.update(&chunk);
@@ -437,6 +965,12 @@ pub(crate) async fn download_response_internal(
         }
     }
 
+    if downloaded == 0 && expected_sha256 != EMPTY_SHA256 {
+        return Err(Error::NetworkFailure {
+            message: "server returned empty body".to_string(),
+        });
+    }
+
     let actual_hash = format!("{:x}", hasher.finalize());
 
     if actual_hash != expected_sha256 {
@@ -463,13 +997,87 @@ pub(crate) async fn download_response_internal(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::network::download::AddressFamily;
     use tempfile::TempDir;
     use wiremock::matchers::{method, path};
     use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[test]
     fn build_rustls_config_does_not_panic() {
-        let _ = build_rustls_config();
+        let _ = build_rustls_config(&TlsOptions::default());
+    }
+
+    #[test]
+    fn empty_native_store_with_empty_prefix_degrades_to_no_roots() {
+        let tmp = TempDir::new().unwrap();
+        let options = TlsOptions {
+            prefix: Some(tmp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        let root_store = collect_root_store(Vec::new(), &options);
+
+        assert!(root_store.is_empty());
+        assert!(build_rustls_config(&options).is_ok());
+    }
+
+    #[test]
+    fn unreadable_prefix_bundle_is_not_fatal() {
+        let tmp = TempDir::new().unwrap();
+        let bundle = tmp.path().join("etc/ca-certificates/cacert.pem");
+        std::fs::create_dir_all(bundle.parent().unwrap()).unwrap();
+        std::fs::write(&bundle, "not a certificate").unwrap();
+        let options = TlsOptions {
+            prefix: Some(tmp.path().to_path_buf()),
+            ..Default::default()
+        };
+
+        assert!(collect_root_store(Vec::new(), &options).is_empty());
+    }
+
+    #[test]
+    fn insecure_tls_builds_config_without_roots() {
+        let tmp = TempDir::new().unwrap();
+        let options = TlsOptions {
+            insecure: true,
+            ..Default::default()
+        };
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap())
+            .with_tls_options(&options)
+            .unwrap();
+
+        assert!(downloader.insecure_tls);
+        assert!(downloader.tls_config.is_some());
+    }
+
+    #[test]
+    fn unreadable_ca_bundle_is_an_error() {
+        let tmp = TempDir::new().unwrap();
+        let options = TlsOptions {
+            ca_bundle: Some(tmp.path().join("corp-ca.pem")),
+            ..Default::default()
+        };
+
+        let result =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_tls_options(&options);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn unusable_prefix_ca_bundle_is_not_an_error() {
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        std::fs::create_dir_all(prefix.join("etc/ca-certificates")).unwrap();
+        std::fs::write(prefix.join("etc/ca-certificates/cacert.pem"), b"garbage").unwrap();
+        let options = TlsOptions {
+            prefix: Some(prefix),
+            ..Default::default()
+        };
+
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap())
+            .with_tls_options(&options)
+            .unwrap();
+        assert!(!downloader.insecure_tls);
     }
 
     #[tokio::test]
@@ -497,6 +1105,93 @@ mod tests {
         assert_eq!(std::fs::read(&blob_path).unwrap(), content);
     }
 
+    #[tokio::test]
+    async fn empty_body_is_reported_as_network_failure() {
+        let mock_server = MockServer::start().await;
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/empty.tar.gz"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap());
+
+        let url = format!("{}/empty.tar.gz", mock_server.uri());
+        let err = downloader.download(&url, sha256).await.unwrap_err();
+
+        assert!(
+            matches!(&err, Error::NetworkFailure { message } if message.contains("empty body")),
+            "unexpected error: {err}"
+        );
+        assert!(
+            !tmp.path()
+                .join("blobs")
+                .join(format!("{sha256}.tar.gz"))
+                .exists()
+        );
+    }
+
+    #[tokio::test]
+    async fn racing_disabled_issues_a_single_get() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                ..DownloaderConfig::default()
+            });
+
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+        let blob_path = downloader.download(&url, sha256).await.unwrap();
+
+        assert_eq!(std::fs::read(&blob_path).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn racing_disabled_still_retries_server_errors() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                ..DownloaderConfig::default()
+            });
+
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+        let blob_path = downloader.download(&url, sha256).await.unwrap();
+
+        assert_eq!(std::fs::read(&blob_path).unwrap(), content);
+    }
+
     #[tokio::test]
     async fn mismatch_deletes_blob_and_errors() {
         let mock_server = MockServer::start().await;
@@ -559,4 +1254,446 @@ mod tests {
 
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn offline_downloader_serves_cached_blob_and_rejects_misses() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let blob_cache = BlobCache::new(tmp.path()).unwrap();
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+
+        Downloader::new(blob_cache.clone())
+            .download(&url, sha256)
+            .await
+            .unwrap();
+
+        let offline = Downloader::new(blob_cache).with_offline(true);
+        assert!(offline.download(&url, sha256).await.is_ok());
+
+        let err = offline
+            .download_with_progress(&url, &"0".repeat(64), Some("missing".into()), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::OfflineCacheMiss { name } if name == "missing"));
+    }
+
+    #[test]
+    fn preferred_mirrors_come_before_the_primary_url() {
+        let url = "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc";
+
+        assert_eq!(
+            order_download_urls(url, "plain.example.com, primary!fast.example.com,,"),
+            vec![
+                "https://fast.example.com/v2/homebrew/core/jq/blobs/sha256:abc".to_string(),
+                url.to_string(),
+                "https://plain.example.com/v2/homebrew/core/jq/blobs/sha256:abc".to_string(),
+            ]
+        );
+        assert_eq!(order_download_urls(url, ""), vec![url.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn racing_reports_the_winning_connection() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 2,
+                ..DownloaderConfig::default()
+            });
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let progress: DownloadProgressCallback = Arc::new(move |event| {
+            if let InstallProgress::DownloadRaceWon { .. } = event {
+                sink.lock().unwrap().push(event);
+            }
+        });
+
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+        downloader
+            .download_with_progress(&url, sha256, Some("hello".into()), Some(progress))
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        let InstallProgress::DownloadRaceWon {
+            ref name,
+            url: ref won_url,
+            attempt,
+            aborted,
+        } = events[0]
+        else {
+            unreachable!()
+        };
+        assert_eq!(name, "hello");
+        assert_eq!(won_url, &url);
+        assert!(attempt < 2);
+        assert!(aborted <= 1);
+    }
+
+    #[test]
+    fn struck_mirrors_are_demoted_then_skipped() {
+        let tmp = TempDir::new().unwrap();
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap());
+
+        let canonical = "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc".to_string();
+        let bad = "https://bad.example.com/v2/homebrew/core/jq/blobs/sha256:abc".to_string();
+        let good = "https://good.example.com/v2/homebrew/core/jq/blobs/sha256:abc".to_string();
+        let urls = vec![bad.clone(), canonical.clone(), good.clone()];
+
+        downloader.record_checksum_strike(&canonical, &canonical);
+        assert_eq!(
+            downloader.rank_download_urls(&canonical, urls.clone()),
+            urls
+        );
+
+        downloader.record_checksum_strike(&bad, &canonical);
+        assert_eq!(
+            downloader.rank_download_urls(&canonical, urls.clone()),
+            vec![canonical.clone(), good.clone(), bad.clone()]
+        );
+
+        downloader.record_checksum_strike(&bad, &canonical);
+        assert_eq!(
+            downloader.rank_download_urls(&canonical, urls),
+            vec![canonical, good]
+        );
+    }
+
+    #[tokio::test]
+    async fn racing_strikes_mirrors_that_serve_mismatching_content() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/good.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(content.to_vec())
+                    .set_delay(Duration::from_millis(200)),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/bad.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(b"poisoned".to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                racing_stagger: Duration::ZERO,
+                ..DownloaderConfig::default()
+            });
+
+        let port = mock_server.address().port();
+        let canonical = format!("http://localhost:{port}/good.tar.gz");
+        let mirror = format!("http://127.0.0.1:{port}/bad.tar.gz");
+        downloader
+            .download_with_racing(
+                &canonical,
+                &canonical,
+                std::slice::from_ref(&mirror),
+                sha256,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let strikes = downloader.mirror_strikes.lock().unwrap();
+        assert_eq!(strikes.get("127.0.0.1"), Some(&1));
+        assert!(!strikes.contains_key("localhost"));
+    }
+
+    #[test]
+    fn declared_mirrors_follow_configured_ones_without_duplicates() {
+        let url = "https://ghcr.io/v2/homebrew/core/jq/blobs/sha256:abc";
+        let mut urls = order_download_urls(url, "mirror.example.com");
+        append_declared_mirrors(
+            &mut urls,
+            &[
+                "https://cdn.example.com/jq.tar.gz".to_string(),
+                url.to_string(),
+                String::new(),
+            ],
+        );
+
+        assert_eq!(
+            urls,
+            vec![
+                url.to_string(),
+                "https://mirror.example.com/v2/homebrew/core/jq/blobs/sha256:abc".to_string(),
+                "https://cdn.example.com/jq.tar.gz".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn declared_mirror_serves_when_primary_fails() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/release/asset.tar.gz"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/cdn/asset.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                racing_stagger: Duration::ZERO,
+                ..DownloaderConfig::default()
+            });
+
+        let url = format!("{}/release/asset.tar.gz", mock_server.uri());
+        let mirror = format!("{}/cdn/asset.tar.gz", mock_server.uri());
+        let blob = downloader
+            .download_with_mirrors(&url, &[mirror], sha256, None, None)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(blob).unwrap(), content.to_vec());
+    }
+
+    #[tokio::test]
+    async fn html_responses_are_rejected_unless_disabled() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_raw(content.to_vec(), "text/html; charset=utf-8"),
+            )
+            .mount(&mock_server)
+            .await;
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+
+        let tmp = TempDir::new().unwrap();
+        let strict =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                ..DownloaderConfig::default()
+            });
+        let err = strict.download(&url, sha256).await.unwrap_err();
+        assert!(
+            matches!(&err, Error::NetworkFailure { message } if message.contains("HTML page")),
+            "unexpected error: {err}"
+        );
+        assert!(!strict.blob_cache.has_blob(sha256));
+
+        // A server that mislabels its archives still works with the check off.
+        let lenient =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                reject_html_responses: false,
+                ..DownloaderConfig::default()
+            });
+        let blob_path = lenient.download(&url, sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), content);
+    }
+
+    #[tokio::test]
+    async fn stalled_response_reports_timeout_with_url() {
+        let mock_server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/slow.tar.gz"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_bytes(b"late".to_vec())
+                    .set_delay(Duration::from_secs(5)),
+            )
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 1,
+                request_timeout: Duration::from_millis(100),
+                ..DownloaderConfig::default()
+            });
+
+        let url = format!("{}/slow.tar.gz", mock_server.uri());
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let err = downloader.download(&url, sha256).await.unwrap_err();
+        match err {
+            Error::NetworkTimeout {
+                url: timed_out,
+                after,
+            } => {
+                assert_eq!(timed_out, url);
+                assert!(after >= Duration::from_millis(100), "after: {after:?}");
+            }
+            other => panic!("expected a timeout, got {other}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn ghcr_downloads_follow_the_configured_registry() {
+        use wiremock::matchers::header;
+
+        let registry = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let blob_path = format!("/v2/homebrew/core/lz4/blobs/sha256:{sha256}");
+
+        Mock::given(method("GET"))
+            .and(path(blob_path.as_str()))
+            .and(header("authorization", "Bearer registry-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .with_priority(1)
+            .mount(&registry)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(blob_path.as_str()))
+            .respond_with(ResponseTemplate::new(401).insert_header(
+                "www-authenticate",
+                r#"Bearer realm="/token",service="registry",scope="repository:homebrew/core/lz4:pull""#,
+            ))
+            .mount(&registry)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "token": "registry-token" })),
+            )
+            .mount(&registry)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader = Downloader::new(BlobCache::new(tmp.path()).unwrap())
+            .with_registry_base(&registry.uri())
+            .unwrap();
+
+        let url = format!("https://ghcr.io{blob_path}");
+        let path = downloader.download(&url, sha256).await.unwrap();
+        assert_eq!(std::fs::read(path).unwrap(), content);
+    }
+
+    #[test]
+    fn registry_base_must_be_a_plain_http_url() {
+        let tmp = TempDir::new().unwrap();
+        for base in [
+            "ftp://registry.internal",
+            "https://user:pw@registry.internal",
+            "nope",
+        ] {
+            let result =
+                Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_registry_base(base);
+            assert!(
+                matches!(result, Err(Error::InvalidArgument { .. })),
+                "{base} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn pinned_address_family_applies_to_racing_clients() {
+        let mock_server = MockServer::start().await;
+        let content = b"hello world";
+        let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+        Mock::given(method("GET"))
+            .and(path("/test.tar.gz"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(content.to_vec()))
+            .mount(&mock_server)
+            .await;
+
+        // The mock server only listens on 127.0.0.1, so it is reachable when
+        // pinned to IPv4 and never when pinned to IPv6.
+        let url = format!("{}/test.tar.gz", mock_server.uri());
+        let tmp = TempDir::new().unwrap();
+        let ipv4 =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                address_family: AddressFamily::Ipv4Only,
+                ..DownloaderConfig::default()
+            });
+        let blob_path = ipv4.download(&url, sha256).await.unwrap();
+        assert_eq!(std::fs::read(&blob_path).unwrap(), content);
+
+        let tmp = TempDir::new().unwrap();
+        let ipv6 =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                address_family: AddressFamily::Ipv6Only,
+                max_consecutive_failures: 1,
+                ..DownloaderConfig::default()
+            });
+        assert!(ipv6.download(&url, sha256).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_lost_race_costs_the_retry_budget_one_failure() {
+        let mock_server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/down.tar.gz"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/missing.tar.gz"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&mock_server)
+            .await;
+
+        let tmp = TempDir::new().unwrap();
+        let downloader =
+            Downloader::new(BlobCache::new(tmp.path()).unwrap()).with_config(DownloaderConfig {
+                racing_connections: 3,
+                racing_stagger: Duration::ZERO,
+                max_consecutive_failures: 2,
+                ..DownloaderConfig::default()
+            });
+        let sha256 = "0".repeat(64);
+
+        // A missing file says nothing about the network.
+        let missing = format!("{}/missing.tar.gz", mock_server.uri());
+        for _ in 0..3 {
+            assert!(downloader.download(&missing, &sha256).await.is_err());
+        }
+        assert!(!downloader.retry_budget.is_exhausted());
+
+        let down = format!("{}/down.tar.gz", mock_server.uri());
+        assert!(downloader.download(&down, &sha256).await.is_err());
+        assert!(!downloader.retry_budget.is_exhausted());
+        assert!(downloader.download(&down, &sha256).await.is_err());
+        assert!(downloader.retry_budget.is_exhausted());
+    }
 }
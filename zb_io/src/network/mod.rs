@@ -7,5 +7,6 @@ pub mod tap_formula;
 pub use api::ApiClient;
 pub use cache::{ApiCache, CacheEntry};
 pub use download::{
-    DownloadProgressCallback, DownloadRequest, DownloadResult, Downloader, ParallelDownloader,
+    AddressFamily, DownloadProgressCallback, DownloadRequest, DownloadResult, Downloader,
+    DownloaderConfig, ParallelDownloader,
 };
@@ -4,7 +4,7 @@ use std::sync::LazyLock;
 use zb_core::formula::{
     Bottle, BottleFile, BottleStable, FormulaUrls, KegOnly, SourceUrl, Versions,
 };
-use zb_core::{Error, Formula};
+use zb_core::{Error, Formula, revisioned_version};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TapFormulaRef {
@@ -50,13 +50,20 @@ static KEYWORD_START_RE: LazyLock<Regex> = LazyLock::new(|| {
         .expect("KEYWORD_START_RE must compile")
 });
 static ROOT_URL_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"root_url\s+["']([^"']+)["']"#).expect("ROOT_URL_RE must compile")
+    Regex::new(r#"root_url\s+(?:["']([^"']+)["']|:default\b)"#).expect("ROOT_URL_RE must compile")
 });
+/// Homebrew's default bottle domain.
+const GHCR_BOTTLE_DOMAIN: &str = "https://ghcr.io/v2";
+
 static REBUILD_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"(?m)^\s*rebuild\s+(\d+)\s*$"#).expect("REBUILD_RE must compile")
 });
+/// `sha256 cellar: :any, arm64_sonoma: "..."`. The optional `cellar` is
+/// either a symbol (group 1) or a quoted path (group 2); the tag and
+/// checksum are groups 3 and 4.
 static BOTTLE_SHA_RE: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r#"([a-z0-9_]+):\s*"([0-9a-f]{64})""#).expect("BOTTLE_SHA_RE must compile")
+    Regex::new(r#"(?:cellar:\s*(?::([a-z_]+)|"([^"]*)")\s*,\s*)?([a-z0-9_]+):\s*"([0-9a-f]{64})""#)
+        .expect("BOTTLE_SHA_RE must compile")
 });
 static ON_PLATFORM_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r#"^\s*on_(macos|linux|arm|intel)\s+do\b"#).expect("ON_PLATFORM_RE must compile")
@@ -342,7 +349,9 @@ pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Form
     let stable = parse_version(&source).unwrap_or_else(|| "0".to_string());
     let revision = parse_revision(&source).unwrap_or(0);
     let dependencies = parse_runtime_dependencies(&source);
-    let build_dependencies = parse_build_dependencies(&source);
+    let build_dependencies = parse_tagged_dependencies(&source, ":build");
+    let recommended_dependencies = parse_tagged_dependencies(&source, ":recommended");
+    let optional_dependencies = parse_tagged_dependencies(&source, ":optional");
     let parsed_source_url = parse_source_url(&source);
     let bottle = parse_bottle(spec, &source, &stable, revision);
 
@@ -376,6 +385,8 @@ pub fn parse_tap_formula_ruby(spec: &TapFormulaRef, source: &str) -> Result<Form
         keg_only: KegOnly::default(),
         keg_only_reason: None,
         build_dependencies,
+        recommended_dependencies,
+        optional_dependencies,
         urls: source_url.map(|stable| FormulaUrls {
             stable: Some(stable),
             head: None,
@@ -432,8 +443,9 @@ fn parse_runtime_dependencies(source: &str) -> Vec<String> {
             && let Some(cap) = DEPENDS_ON_RE.captures(trimmed)
         {
             let options = cap.get(2).map(|m| m.as_str()).unwrap_or("");
-            if !options.contains(":build")
-                && !options.contains(":test")
+            if ![":build", ":test", ":recommended", ":optional"]
+                .iter()
+                .any(|tag| options.contains(tag))
                 && let Some(dep) = cap.get(1)
             {
                 deps.push(dep.as_str().to_string());
@@ -447,7 +459,8 @@ fn parse_runtime_dependencies(source: &str) -> Vec<String> {
     deps
 }
 
-fn parse_build_dependencies(source: &str) -> Vec<String> {
+/// Dependencies whose `depends_on` options include `tag`, e.g. `:build`.
+fn parse_tagged_dependencies(source: &str, tag: &str) -> Vec<String> {
     let mut deps = Vec::new();
     let body = extract_formula_class_body(source).unwrap_or(source);
     let mut depth = 0usize;
@@ -458,7 +471,7 @@ fn parse_build_dependencies(source: &str) -> Vec<String> {
             && let Some(cap) = DEPENDS_ON_RE.captures(trimmed)
         {
             let options = cap.get(2).map(|m| m.as_str()).unwrap_or("");
-            if options.contains(":build")
+            if options.contains(tag)
                 && let Some(dep) = cap.get(1)
             {
                 deps.push(dep.as_str().to_string());
@@ -563,8 +576,10 @@ fn extract_formula_class_body(source: &str) -> Option<&str> {
 fn parse_bottle(spec: &TapFormulaRef, source: &str, stable: &str, revision: u32) -> Option<Bottle> {
     let block = extract_bottle_block(source)?;
 
-    let root_url = parse_root_url(block)
-        .unwrap_or_else(|| format!("https://ghcr.io/v2/{}/{}", spec.owner, spec.repo));
+    let root_url = match parse_root_url(block) {
+        Some(root_url) => canonical_root_url(spec, &root_url),
+        None => default_root_url(spec),
+    };
     let rebuild = parse_rebuild(block).unwrap_or(0);
     let files = parse_bottle_files(spec, &root_url, stable, revision, rebuild, block);
 
@@ -614,6 +629,7 @@ fn extract_bottle_block(source: &str) -> Option<&str> {
     None
 }
 
+/// `None` when the block names no `root_url`, or asks for `root_url :default`.
 fn parse_root_url(block: &str) -> Option<String> {
     ROOT_URL_RE
         .captures(block)
@@ -621,6 +637,41 @@ fn parse_root_url(block: &str) -> Option<String> {
         .map(|m| m.as_str().to_string())
 }
 
+/// Where Homebrew bottles a tap when it names no other `root_url`.
+fn default_root_url(spec: &TapFormulaRef) -> String {
+    format!(
+        "{GHCR_BOTTLE_DOMAIN}/{}/{}",
+        spec.owner.to_lowercase(),
+        spec.repo.to_lowercase()
+    )
+}
+
+/// Spell any `root_url` on Homebrew's default ghcr domain the way core
+/// formulae do, `https://ghcr.io/v2/<owner>/<repo>`, so tap bottles get the
+/// same blob URLs and registry scopes. Taps write the bare domain, with or
+/// without `/v2`, or leave `/v2` out of the repository path. Other hosts
+/// are left alone.
+fn canonical_root_url(spec: &TapFormulaRef, root_url: &str) -> String {
+    let trimmed = root_url.trim_end_matches('/');
+    let Some(path) = trimmed.strip_prefix("https://ghcr.io") else {
+        return trimmed.to_string();
+    };
+    let path = match path.strip_prefix("/v2") {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+        _ => path,
+    };
+    match path.trim_start_matches('/') {
+        "" => default_root_url(spec),
+        repository => format!("{GHCR_BOTTLE_DOMAIN}/{repository}"),
+    }
+}
+
+/// The registry image a formula's bottles are pushed to: `@` and `+` are
+/// not valid there, so `python@3.12` lives at `python/3.12`.
+fn ghcr_image_name(formula: &str) -> String {
+    formula.replace('@', "/").replace('+', "x")
+}
+
 fn parse_rebuild(block: &str) -> Option<u32> {
     REBUILD_RE
         .captures(block)
@@ -639,21 +690,28 @@ fn parse_bottle_files(
     let mut files = BTreeMap::new();
 
     for cap in BOTTLE_SHA_RE.captures_iter(block) {
-        let Some(tag) = cap.get(1).map(|m| m.as_str()) else {
+        let Some(tag) = cap.get(3).map(|m| m.as_str()) else {
             continue;
         };
-        let Some(sha) = cap.get(2).map(|m| m.as_str()) else {
+        let Some(sha) = cap.get(4).map(|m| m.as_str()) else {
             continue;
         };
         if tag == "cellar" {
             continue;
         }
+        let cellar = match (cap.get(1), cap.get(2)) {
+            (Some(symbol), _) => Some(format!(":{}", symbol.as_str())),
+            (None, Some(path)) => Some(path.as_str().to_string()),
+            (None, None) => None,
+        };
         let url = build_bottle_url(spec, root_url, stable, revision, rebuild, tag, sha);
         files.insert(
             tag.to_string(),
             BottleFile {
                 url,
                 sha256: sha.to_string(),
+                mirrors: Vec::new(),
+                cellar,
             },
         );
     }
@@ -672,14 +730,15 @@ fn build_bottle_url(
 ) -> String {
     let normalized = root_url.trim_end_matches('/');
     if normalized.contains("/v2/") {
-        return format!("{}/{}/blobs/sha256:{}", normalized, spec.formula, sha);
+        return format!(
+            "{}/{}/blobs/sha256:{}",
+            normalized,
+            ghcr_image_name(&spec.formula),
+            sha
+        );
     }
 
-    let effective_version = if revision > 0 {
-        format!("{stable}_{revision}")
-    } else {
-        stable.to_string()
-    };
+    let effective_version = revisioned_version(stable, revision);
 
     if rebuild > 0 {
         format!(
@@ -746,6 +805,68 @@ end
         assert_eq!(formula.build_dependencies, vec!["go".to_string()]);
         assert!(formula.bottle.stable.files.contains_key("arm64_sonoma"));
         assert!(formula.bottle.stable.files.contains_key("x86_64_linux"));
+        assert_eq!(
+            formula.bottle.stable.files["arm64_sonoma"]
+                .cellar
+                .as_deref(),
+            Some(":any_skip_relocation")
+        );
+    }
+
+    #[test]
+    fn captures_quoted_cellar_paths_and_bare_sha_lines() {
+        let source = r#"
+class Pinned < Formula
+  version "2.0.0"
+
+  bottle do
+    root_url "https://example.com/bottles"
+    sha256 cellar: "/opt/homebrew/Cellar", arm64_sonoma: "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+    sha256 x86_64_linux: "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+  end
+end
+"#;
+        let spec = TapFormulaRef {
+            owner: "acme".to_string(),
+            repo: "tap".to_string(),
+            formula: "pinned".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        let files = &formula.bottle.stable.files;
+
+        assert_eq!(
+            files["arm64_sonoma"].cellar.as_deref(),
+            Some("/opt/homebrew/Cellar")
+        );
+        assert_eq!(files["x86_64_linux"].cellar, None);
+        assert!(!files.contains_key("cellar"));
+    }
+
+    #[test]
+    fn separates_recommended_and_optional_dependencies() {
+        let source = r#"
+class Ffmpeg < Formula
+  url "https://example.com/ffmpeg-7.1.tar.gz"
+  sha256 "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+  depends_on "x264"
+  depends_on "lame" => :recommended
+  depends_on "rav1e" => :optional
+  depends_on "pkgconf" => :build
+end
+"#;
+
+        let spec = TapFormulaRef {
+            owner: "user".to_string(),
+            repo: "media".to_string(),
+            formula: "ffmpeg".to_string(),
+        };
+
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        assert_eq!(formula.dependencies, vec!["x264".to_string()]);
+        assert_eq!(formula.recommended_dependencies, vec!["lame".to_string()]);
+        assert_eq!(formula.optional_dependencies, vec!["rav1e".to_string()]);
+        assert_eq!(formula.build_dependencies, vec!["pkgconf".to_string()]);
     }
 
     #[test]
@@ -772,6 +893,36 @@ end
         );
     }
 
+    #[test]
+    fn default_ghcr_root_urls_yield_core_style_blob_urls() {
+        let spec = TapFormulaRef {
+            owner: "Acme".to_string(),
+            repo: "tools".to_string(),
+            formula: "widget@2".to_string(),
+        };
+        let sha = "cccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccccc";
+        let expected = format!("https://ghcr.io/v2/acme/tools/widget/2/blobs/sha256:{sha}");
+
+        for root_url in [
+            ":default",
+            "\"https://ghcr.io\"",
+            "\"https://ghcr.io/v2/\"",
+            "\"https://ghcr.io/acme/tools\"",
+            "\"https://ghcr.io/v2/acme/tools\"",
+        ] {
+            let source = format!(
+                "class WidgetAT2 < Formula\n  bottle do\n    root_url {root_url}\n    sha256 arm64_sonoma: \"{sha}\"\n  end\nend\n"
+            );
+            let formula = parse_tap_formula_ruby(&spec, &source).unwrap();
+            let url = &formula.bottle.stable.files["arm64_sonoma"].url;
+            assert_eq!(url, &expected, "root_url {root_url}");
+            assert_eq!(
+                crate::network::download::auth::extract_scope_for_url(url, None).as_deref(),
+                Some("repository:acme/tools/widget/2:pull")
+            );
+        }
+    }
+
     #[test]
     fn builds_release_style_bottle_url() {
         let source = r#"
@@ -796,6 +947,39 @@ end
         );
     }
 
+    #[test]
+    fn revision_agrees_across_bottle_url_and_keg_path() {
+        let source = r#"
+class Ttfb < Formula
+  version "1.10.0"
+  revision 2
+  bottle do
+    root_url "https://github.com/messense/homebrew-tap/releases/download/ttfb"
+    sha256 x86_64_linux: "054859a821b01d3dd7236e71fbf106f7a694ded54ae6aaaed221b59d3b554c42"
+  end
+end
+"#;
+        let spec = TapFormulaRef {
+            owner: "messense".to_string(),
+            repo: "tap".to_string(),
+            formula: "ttfb".to_string(),
+        };
+        let formula = parse_tap_formula_ruby(&spec, source).unwrap();
+        let version = formula.effective_version();
+        assert_eq!(version, "1.10.0_2");
+
+        let url = &formula.bottle.stable.files["x86_64_linux"].url;
+        assert!(url.ends_with(&format!("/ttfb-{version}.x86_64_linux.bottle.tar.gz")));
+
+        let tmp = tempfile::TempDir::new().unwrap();
+        let cellar = crate::cellar::Cellar::new(tmp.path()).unwrap();
+        assert!(
+            cellar
+                .keg_path(&formula.name, &version)
+                .ends_with("cellar/ttfb/1.10.0_2")
+        );
+    }
+
     #[test]
     fn infers_version_from_url_when_version_field_missing() {
         let source = r#"
@@ -39,6 +39,22 @@ pub fn validate_privileged_path(path: &Path) -> Result<(), Error> {
     Ok(())
 }
 
+/// Probe whether files can be created in `path` by writing and removing a
+/// marker file.
+pub fn is_writable(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    let test_file = path.join(".zb_write_test");
+    match std::fs::write(&test_file, b"test") {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&test_file);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
@@ -1,3 +1,5 @@
+use crate::build::BuildPhase;
+
 /// Progress events during installation
 #[derive(Debug, Clone)]
 pub enum InstallProgress {
@@ -14,18 +16,49 @@ pub enum InstallProgress {
     },
     /// Download completed for a package
     DownloadCompleted { name: String, total_bytes: u64 },
+    /// A raced download finished: which URL won, its staggered-start index
+    /// (racing connections to the primary first, then mirrors), and how many
+    /// other connections were still running and got cancelled
+    DownloadRaceWon {
+        name: String,
+        url: String,
+        attempt: usize,
+        aborted: usize,
+    },
+    /// A download request failed and is being retried. `attempt` is the
+    /// attempt about to start, out of `max` in total
+    DownloadRetrying {
+        name: String,
+        attempt: u32,
+        max: u32,
+        reason: String,
+    },
     /// Starting to unpack/materialize a package
     UnpackStarted { name: String },
     /// Unpacking completed for a package
     UnpackCompleted { name: String },
+    /// The downloaded bottle was corrupt; it is being fetched again and
+    /// re-extracted. `attempt` is the attempt about to start, out of `max`
+    ExtractRetrying {
+        name: String,
+        attempt: u32,
+        max: u32,
+        reason: String,
+    },
     /// Starting to link a package
     LinkStarted { name: String },
     /// Linking completed for a package
     LinkCompleted { name: String },
+    /// Bottle stayed corrupt after retries; building from source instead
+    SourceFallback { name: String, reason: String },
+    /// Source build moved into a new phase
+    BuildPhase { name: String, phase: BuildPhase },
     /// Linking skipped (keg-only or conflict)
     LinkSkipped { name: String, reason: String },
     /// Installation completed for a package (final state)
     InstallCompleted { name: String },
+    /// Requested package is already installed at this version; nothing to do
+    AlreadyInstalled { name: String, version: String },
 }
 
 /// Callback type for progress reporting
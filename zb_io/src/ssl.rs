@@ -1,5 +1,29 @@
 use crate::storage::db::Database;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::DigitallySignedStruct;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use zb_core::Error;
+
+/// Extra trust configuration for bottle downloads.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM bundle, or a directory of `.pem`/`.crt` files, trusted in addition
+    /// to the native roots. Meant for corporate proxies that re-sign TLS.
+    pub ca_bundle: Option<PathBuf>,
+    /// Skip certificate verification entirely. Only for testing against a
+    /// self-signed mirror; every download logs a warning while this is set.
+    pub insecure: bool,
+    /// Active prefix. A `ca-certificates` bundle installed there is trusted
+    /// alongside the native roots, so downloads work right after `zb init`
+    /// on hosts without system certificates. See [`find_prefix_ca_certs`].
+    pub prefix: Option<PathBuf>,
+}
 
 pub fn find_ca_bundle(prefix: &Path, db: &Database) -> Option<PathBuf> {
     let installed = db.get_installed("ca-certificates")?;
@@ -40,6 +64,106 @@ pub fn find_ca_dir(prefix: &Path) -> Option<PathBuf> {
     candidates.into_iter().find(|p| p.exists() && p.is_dir())
 }
 
+/// Bundle or certificate directory from the prefix to trust in addition to
+/// the native roots. Returns `None` when `SSL_CERT_FILE` is set, since the
+/// native loader already honours it and it should win over the prefix.
+pub fn find_prefix_ca_certs(prefix: &Path) -> Option<PathBuf> {
+    if std::env::var_os("SSL_CERT_FILE").is_some() {
+        return None;
+    }
+    find_ca_bundle_from_prefix(prefix).or_else(|| find_ca_dir(prefix))
+}
+
+/// Load every certificate from a PEM bundle, or from each `.pem`/`.crt` file
+/// in a directory laid out like the ones `find_ca_dir` returns.
+pub fn load_ca_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let files = if path.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(Error::file("failed to read CA directory"))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.extension()
+                    .is_some_and(|ext| ext == "pem" || ext == "crt")
+            })
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut certs = Vec::new();
+    for file in &files {
+        let parsed = CertificateDer::pem_file_iter(file)
+            .map_err(|e| Error::FileError {
+                message: format!("failed to read CA bundle {}: {e}", file.display()),
+            })?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| Error::FileError {
+                message: format!("invalid certificate in {}: {e}", file.display()),
+            })?;
+        certs.extend(parsed);
+    }
+
+    if certs.is_empty() {
+        return Err(Error::InvalidArgument {
+            message: format!("no certificates found in {}", path.display()),
+        });
+    }
+
+    Ok(certs)
+}
+
+/// Accepts any server certificate. Handshake signatures are still checked so
+/// the connection is at least consistent with the certificate presented.
+#[derive(Debug)]
+pub(crate) struct NoCertificateVerification(pub(crate) Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<rustls::SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,4 +216,44 @@ mod tests {
         assert!(found.is_some());
         assert_eq!(found.unwrap(), ca_dir);
     }
+
+    #[test]
+    fn load_ca_certs_rejects_file_without_certificates() {
+        let tmp = TempDir::new().unwrap();
+        let bundle = tmp.path().join("empty.pem");
+        fs::write(&bundle, b"not a certificate").unwrap();
+
+        let err = load_ca_certs(&bundle).unwrap_err();
+        assert!(err.to_string().contains("no certificates found"));
+    }
+
+    #[test]
+    fn load_ca_certs_reports_missing_file() {
+        let tmp = TempDir::new().unwrap();
+
+        let err = load_ca_certs(&tmp.path().join("missing.pem")).unwrap_err();
+        assert!(matches!(err, Error::FileError { .. }));
+    }
+
+    #[test]
+    fn prefix_ca_certs_prefers_bundle_over_directory() {
+        if std::env::var_os("SSL_CERT_FILE").is_some() {
+            return;
+        }
+        let tmp = TempDir::new().unwrap();
+        let prefix = tmp.path().join("prefix");
+        fs::create_dir_all(prefix.join("etc/openssl/certs")).unwrap();
+        assert_eq!(
+            find_prefix_ca_certs(&prefix),
+            Some(prefix.join("etc/openssl/certs"))
+        );
+
+        let bundle = prefix.join("opt/ca-certificates/share/ca-certificates");
+        fs::create_dir_all(&bundle).unwrap();
+        fs::write(bundle.join("cacert.pem"), b"cert").unwrap();
+        assert_eq!(
+            find_prefix_ca_certs(&prefix),
+            Some(bundle.join("cacert.pem"))
+        );
+    }
 }
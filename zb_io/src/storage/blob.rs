@@ -1,7 +1,10 @@
+use std::collections::HashSet;
 use std::fs;
 use std::io::{self, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+use sha2::{Digest, Sha256};
 use tempfile::NamedTempFile;
 use zb_core::Error;
 
@@ -30,17 +33,138 @@ impl BlobCache {
         self.blob_path(sha256).exists()
     }
 
-    /// Remove a blob from the cache (used when extraction fails due to corruption)
+    /// Remove a blob from the cache (used when extraction fails due to corruption).
+    /// Another process sharing the cache may remove it first; that counts as
+    /// already gone rather than an error.
     pub fn remove_blob(&self, sha256: &str) -> io::Result<bool> {
-        let path = self.blob_path(sha256);
-        if path.exists() {
-            fs::remove_file(&path)?;
-            Ok(true)
-        } else {
-            Ok(false)
+        match fs::remove_file(self.blob_path(sha256)) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
         }
     }
 
+    /// Delete blobs older than `max_age`, then evict least-recently-accessed
+    /// blobs until the cache fits in `max_total_bytes`. Blobs whose sha256 is in
+    /// `live_keys` are never removed. Returns the sha256 of every removed blob.
+    pub fn prune(
+        &self,
+        max_total_bytes: Option<u64>,
+        max_age: Option<Duration>,
+        live_keys: &HashSet<String>,
+    ) -> Result<Vec<String>, Error> {
+        let now = SystemTime::now();
+        let mut removed = Vec::new();
+        let mut candidates = Vec::new();
+        let mut total_bytes = 0u64;
+
+        let entries =
+            fs::read_dir(&self.blobs_dir).map_err(Error::store("failed to read blob cache"))?;
+        for entry in entries {
+            let entry = entry.map_err(Error::store("failed to read blob cache entry"))?;
+            let file_name = entry.file_name();
+            let Some(sha256) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".tar.gz"))
+            else {
+                continue;
+            };
+            let metadata = entry
+                .metadata()
+                .map_err(Error::store("failed to stat cached blob"))?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let size = metadata.len();
+            total_bytes += size;
+            if live_keys.contains(sha256) {
+                continue;
+            }
+
+            let modified = metadata.modified().unwrap_or(now);
+            // Many filesystems are mounted noatime/relatime; fall back to mtime.
+            let accessed = metadata.accessed().unwrap_or(modified).max(modified);
+
+            let expired = max_age
+                .is_some_and(|max_age| now.duration_since(modified).is_ok_and(|age| age > max_age));
+            if expired {
+                self.remove_pruned(sha256, &mut removed)?;
+                total_bytes -= size;
+                continue;
+            }
+
+            candidates.push((accessed, size, sha256.to_string()));
+        }
+
+        if let Some(limit) = max_total_bytes {
+            candidates.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(&b.2)));
+            for (_, size, sha256) in candidates {
+                if total_bytes <= limit {
+                    break;
+                }
+                self.remove_pruned(&sha256, &mut removed)?;
+                total_bytes -= size;
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Re-hash every blob named by a sha256 and return, sorted, the sha256 of
+    /// each one whose content no longer matches its filename.
+    pub fn verify_all(&self) -> Result<Vec<String>, Error> {
+        let mut mismatched = Vec::new();
+
+        let entries =
+            fs::read_dir(&self.blobs_dir).map_err(Error::store("failed to read blob cache"))?;
+        for entry in entries {
+            let entry = entry.map_err(Error::store("failed to read blob cache entry"))?;
+            let file_name = entry.file_name();
+            // Source archives without a sha256 are keyed by URL; there is
+            // nothing to check those against.
+            let Some(sha256) = file_name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".tar.gz"))
+                .filter(|name| name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit()))
+            else {
+                continue;
+            };
+
+            let mut file = match fs::File::open(entry.path()) {
+                Ok(file) => file,
+                // Removed by another process since the directory was read.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(Error::store("failed to open cached blob")(e)),
+            };
+            if !file
+                .metadata()
+                .map_err(Error::store("failed to stat cached blob"))?
+                .is_file()
+            {
+                continue;
+            }
+            let mut hasher = Sha256::new();
+            io::copy(&mut file, &mut hasher).map_err(Error::store("failed to read cached blob"))?;
+            if format!("{:x}", hasher.finalize()) != sha256.to_ascii_lowercase() {
+                mismatched.push(sha256.to_string());
+            }
+        }
+
+        mismatched.sort();
+        Ok(mismatched)
+    }
+
+    fn remove_pruned(&self, sha256: &str, removed: &mut Vec<String>) -> Result<(), Error> {
+        if self
+            .remove_blob(sha256)
+            .map_err(Error::store("failed to remove cached blob"))?
+        {
+            removed.push(sha256.to_string());
+        }
+        Ok(())
+    }
+
     pub fn start_write(&self, sha256: &str) -> io::Result<BlobWriter> {
         let final_path = self.blob_path(sha256);
         let temp_file = NamedTempFile::new_in(&self.tmp_dir)?;
@@ -61,17 +185,42 @@ impl BlobWriter {
         self.temp_file.seek(pos)
     }
 
-    pub fn commit(self) -> Result<PathBuf, Error> {
+    pub fn commit(mut self) -> Result<PathBuf, Error> {
+        // Data must be on disk before the rename, or a power loss can leave a
+        // renamed blob that is empty or truncated yet passes `has_blob`.
+        self.temp_file
+            .flush()
+            .map_err(Error::store("failed to flush blob"))?;
+        self.temp_file
+            .as_file()
+            .sync_all()
+            .map_err(Error::store("failed to sync blob"))?;
+
         // Content-addressed: same sha256 = identical content, so overwrite is safe.
         // NamedTempFile::persist does an atomic rename(2) on Unix.
         // On drop (e.g. if persist is never called), the temp file is auto-deleted.
         self.temp_file
             .persist(&self.final_path)
             .map_err(Error::store("failed to persist blob"))?;
+
+        if let Some(parent) = self.final_path.parent() {
+            sync_dir(parent).map_err(Error::store("failed to sync blob directory"))?;
+        }
         Ok(self.final_path)
     }
 }
 
+/// Persist a rename into `dir` by syncing the directory entry itself.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
 impl Write for BlobWriter {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.temp_file.write(buf)
@@ -103,6 +252,27 @@ mod tests {
         assert_eq!(fs::read_to_string(&final_path).unwrap(), "hello world");
     }
 
+    #[test]
+    fn committed_blob_survives_reopening_the_cache() {
+        let tmp = TempDir::new().unwrap();
+        let sha = "feed42";
+
+        {
+            let cache = BlobCache::new(tmp.path()).unwrap();
+            let mut writer = cache.start_write(sha).unwrap();
+            writer.write_all(b"durable bytes").unwrap();
+            writer.commit().unwrap();
+        }
+
+        let reopened = BlobCache::new(tmp.path()).unwrap();
+        assert!(reopened.has_blob(sha));
+        assert_eq!(
+            fs::read(reopened.blob_path(sha)).unwrap(),
+            b"durable bytes".to_vec()
+        );
+        assert_eq!(fs::read_dir(tmp.path().join("tmp")).unwrap().count(), 0);
+    }
+
     #[test]
     fn interrupted_write_leaves_no_final_blob() {
         let tmp = TempDir::new().unwrap();
@@ -161,4 +331,84 @@ mod tests {
         let removed = cache.remove_blob("nonexistent").unwrap();
         assert!(!removed);
     }
+
+    fn write_blob(cache: &BlobCache, sha: &str, len: usize) {
+        let mut writer = cache.start_write(sha).unwrap();
+        writer.write_all(&vec![0u8; len]).unwrap();
+        writer.commit().unwrap();
+    }
+
+    #[test]
+    fn prune_by_age_keeps_live_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        write_blob(&cache, "live", 10);
+        write_blob(&cache, "stale", 10);
+        std::thread::sleep(Duration::from_millis(20));
+
+        let live: HashSet<String> = ["live".to_string()].into_iter().collect();
+        let removed = cache
+            .prune(None, Some(Duration::from_millis(1)), &live)
+            .unwrap();
+
+        assert_eq!(removed, vec!["stale".to_string()]);
+        assert!(cache.has_blob("live"));
+        assert!(!cache.has_blob("stale"));
+    }
+
+    #[test]
+    fn prune_by_size_evicts_until_under_limit() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        write_blob(&cache, "aaa", 100);
+        write_blob(&cache, "bbb", 100);
+        write_blob(&cache, "ccc", 100);
+
+        let removed = cache.prune(Some(150), None, &HashSet::new()).unwrap();
+
+        assert_eq!(removed.len(), 2);
+        let remaining = ["aaa", "bbb", "ccc"]
+            .iter()
+            .filter(|sha| cache.has_blob(sha))
+            .count();
+        assert_eq!(remaining, 1);
+    }
+
+    #[test]
+    fn prune_by_size_never_removes_live_blobs() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        write_blob(&cache, "live", 100);
+        write_blob(&cache, "dead", 100);
+
+        let live: HashSet<String> = ["live".to_string()].into_iter().collect();
+        let removed = cache.prune(Some(0), None, &live).unwrap();
+
+        assert_eq!(removed, vec!["dead".to_string()]);
+        assert!(cache.has_blob("live"));
+    }
+
+    #[test]
+    fn verify_all_reports_blobs_whose_content_changed() {
+        let tmp = TempDir::new().unwrap();
+        let cache = BlobCache::new(tmp.path()).unwrap();
+
+        let store = |content: &[u8]| {
+            let sha = format!("{:x}", Sha256::digest(content));
+            let mut writer = cache.start_write(&sha).unwrap();
+            writer.write_all(content).unwrap();
+            writer.commit().unwrap();
+            sha
+        };
+        store(b"good bottle");
+        let bad = store(b"bad bottle");
+        assert!(cache.verify_all().unwrap().is_empty());
+
+        fs::write(cache.blob_path(&bad), b"bit rot").unwrap();
+
+        assert_eq!(cache.verify_all().unwrap(), vec![bad]);
+    }
 }
@@ -1,6 +1,7 @@
+use std::ops::Deref;
 use std::path::Path;
 
-use rusqlite::{Connection, OptionalExtension, Transaction, params};
+use rusqlite::{Connection, OptionalExtension, Savepoint, Transaction, params};
 
 use zb_core::Error;
 
@@ -14,6 +15,19 @@ pub struct InstalledKeg {
     pub version: String,
     pub store_key: String,
     pub installed_at: i64,
+    /// Requested by name rather than pulled in as a dependency.
+    pub is_explicit: bool,
+}
+
+/// One recorded version of a formula. Several can exist after partial
+/// upgrades; the newest by [`zb_core::compare_versions`] is the active one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KegVersion {
+    pub name: String,
+    pub version: String,
+    pub store_key: String,
+    pub installed_at: i64,
+    pub is_active: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -28,10 +42,11 @@ pub struct KegFileRecord {
     pub version: String,
     pub linked_path: String,
     pub target_path: String,
+    pub backup_path: Option<String>,
 }
 
 impl Database {
-    const SCHEMA_VERSION: u32 = 1;
+    const SCHEMA_VERSION: u32 = 4;
 
     pub fn open(path: &Path) -> Result<Self, Error> {
         let conn = Connection::open(path).map_err(Error::store("failed to open database"))?;
@@ -89,6 +104,9 @@ impl Database {
     fn migrate_to_version(conn: &Connection, version: u32) -> Result<(), Error> {
         match version {
             1 => Self::migrate_to_v1(conn),
+            2 => Self::migrate_to_v2(conn),
+            3 => Self::migrate_to_v3(conn),
+            4 => Self::migrate_to_v4(conn),
             _ => Err(Error::StoreCorruption {
                 message: format!("unknown migration version {}", version),
             }),
@@ -124,19 +142,68 @@ impl Database {
         Ok(())
     }
 
+    fn migrate_to_v2(conn: &Connection) -> Result<(), Error> {
+        conn.execute_batch(
+            "
+            ALTER TABLE keg_files ADD COLUMN backup_path TEXT;
+            ",
+        )
+        .map_err(Error::store("failed to add keg_files.backup_path"))?;
+
+        Ok(())
+    }
+
+    fn migrate_to_v3(conn: &Connection) -> Result<(), Error> {
+        // Existing rows predate the distinction; treat them as explicitly
+        // requested so autoremove never takes out something the user chose.
+        conn.execute_batch(
+            "
+            ALTER TABLE installed_kegs ADD COLUMN explicit INTEGER NOT NULL DEFAULT 1;
+            ",
+        )
+        .map_err(Error::store("failed to add installed_kegs.explicit"))?;
+
+        Ok(())
+    }
+
+    fn migrate_to_v4(conn: &Connection) -> Result<(), Error> {
+        // installed_kegs stays one row per name (the linked version); every
+        // version that has been recorded for a name is tracked here.
+        conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS keg_versions (
+                name TEXT NOT NULL,
+                version TEXT NOT NULL,
+                store_key TEXT NOT NULL,
+                installed_at INTEGER NOT NULL,
+                PRIMARY KEY (name, version)
+            );
+
+            INSERT OR IGNORE INTO keg_versions (name, version, store_key, installed_at)
+                SELECT name, version, store_key, installed_at FROM installed_kegs;
+            ",
+        )
+        .map_err(Error::store("failed to create keg_versions"))?;
+
+        Ok(())
+    }
+
     pub fn transaction(&mut self) -> Result<InstallTransaction<'_>, Error> {
         let tx = self
             .conn
             .transaction()
             .map_err(Error::store("failed to start transaction"))?;
 
-        Ok(InstallTransaction { tx })
+        Ok(InstallTransaction {
+            tx: Scope::Transaction(tx),
+        })
     }
 
     pub fn get_installed(&self, name: &str) -> Option<InstalledKeg> {
         self.conn
             .query_row(
-                "SELECT name, version, store_key, installed_at FROM installed_kegs WHERE name = ?1",
+                "SELECT name, version, store_key, installed_at, explicit
+                 FROM installed_kegs WHERE name = ?1",
                 params![name],
                 |row| {
                     Ok(InstalledKeg {
@@ -144,6 +211,7 @@ impl Database {
                         version: row.get(1)?,
                         store_key: row.get(2)?,
                         installed_at: row.get(3)?,
+                        is_explicit: row.get(4)?,
                     })
                 },
             )
@@ -151,11 +219,22 @@ impl Database {
     }
 
     pub fn list_installed(&self) -> Result<Vec<InstalledKeg>, Error> {
+        self.query_installed("ORDER BY name")
+    }
+
+    /// Installed kegs, most recently installed or reinstalled first. Kegs
+    /// installed in the same second are ordered by name.
+    pub fn list_installed_sorted_by_date(&self) -> Result<Vec<InstalledKeg>, Error> {
+        self.query_installed("ORDER BY installed_at DESC, name")
+    }
+
+    fn query_installed(&self, order_by: &str) -> Result<Vec<InstalledKeg>, Error> {
         let mut stmt = self
             .conn
-            .prepare(
-                "SELECT name, version, store_key, installed_at FROM installed_kegs ORDER BY name",
-            )
+            .prepare(&format!(
+                "SELECT name, version, store_key, installed_at, explicit
+                 FROM installed_kegs {order_by}"
+            ))
             .map_err(Error::store("failed to prepare statement"))?;
 
         let kegs = stmt
@@ -165,6 +244,7 @@ impl Database {
                     version: row.get(1)?,
                     store_key: row.get(2)?,
                     installed_at: row.get(3)?,
+                    is_explicit: row.get(4)?,
                 })
             })
             .map_err(Error::store("failed to query installed kegs"))?
@@ -174,14 +254,70 @@ impl Database {
         Ok(kegs)
     }
 
+    /// Every recorded version of `name`, newest first. The first entry is
+    /// the active version.
+    pub fn list_all_versions(&self, name: &str) -> Result<Vec<KegVersion>, Error> {
+        Ok(self
+            .list_installed_versions()?
+            .into_iter()
+            .filter(|v| v.name == name)
+            .collect())
+    }
+
+    /// Every recorded version of every formula, grouped by name and sorted
+    /// newest first within each name.
+    pub fn list_installed_versions(&self) -> Result<Vec<KegVersion>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, version, store_key, installed_at FROM keg_versions ORDER BY name",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let mut versions = stmt
+            .query_map([], |row| {
+                Ok(KegVersion {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    store_key: row.get(2)?,
+                    installed_at: row.get(3)?,
+                    is_active: false,
+                })
+            })
+            .map_err(Error::store("failed to query keg versions"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        versions.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| zb_core::compare_versions(&b.version, &a.version))
+        });
+        for i in 0..versions.len() {
+            versions[i].is_active = i == 0 || versions[i - 1].name != versions[i].name;
+        }
+
+        Ok(versions)
+    }
+
     pub fn get_store_refcount(&self, store_key: &str) -> i64 {
-        self.conn
+        self.store_refcount(store_key).unwrap_or(0)
+    }
+
+    /// Refcount of `store_key`, or 0 when it has no row. Unlike
+    /// [`Database::get_store_refcount`], query failures are surfaced.
+    pub fn store_refcount(&self, store_key: &str) -> Result<i64, Error> {
+        let refcount = self
+            .conn
             .query_row(
                 "SELECT refcount FROM store_refs WHERE store_key = ?1",
                 params![store_key],
                 |row| row.get(0),
             )
-            .unwrap_or(0)
+            .optional()
+            .map_err(Error::store("failed to query store refcount"))?;
+
+        Ok(refcount.unwrap_or(0))
     }
 
     pub fn get_unreferenced_store_keys(&self) -> Result<Vec<String>, Error> {
@@ -233,7 +369,7 @@ impl Database {
         let mut stmt = self
             .conn
             .prepare(
-                "SELECT name, version, linked_path, target_path
+                "SELECT name, version, linked_path, target_path, backup_path
                  FROM keg_files
                  ORDER BY name, version, linked_path",
             )
@@ -246,6 +382,7 @@ impl Database {
                     version: row.get(1)?,
                     linked_path: row.get(2)?,
                     target_path: row.get(3)?,
+                    backup_path: row.get(4)?,
                 })
             })
             .map_err(Error::store("failed to query keg files"))?
@@ -255,6 +392,44 @@ impl Database {
         Ok(records)
     }
 
+    /// Links recorded for one installed version of `name`.
+    pub fn linked_files(&self, name: &str, version: &str) -> Result<Vec<KegFileRecord>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, version, linked_path, target_path, backup_path
+                 FROM keg_files
+                 WHERE name = ?1 AND version = ?2
+                 ORDER BY linked_path",
+            )
+            .map_err(Error::store("failed to prepare statement"))?;
+
+        let records = stmt
+            .query_map(params![name, version], |row| {
+                Ok(KegFileRecord {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    linked_path: row.get(2)?,
+                    target_path: row.get(3)?,
+                    backup_path: row.get(4)?,
+                })
+            })
+            .map_err(Error::store("failed to query keg files"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::store("failed to collect results"))?;
+
+        Ok(records)
+    }
+
+    /// Links recorded for `name` that displaced a pre-existing file.
+    pub fn list_backed_up_files(&self, name: &str) -> Result<Vec<KegFileRecord>, Error> {
+        Ok(self
+            .list_keg_files()?
+            .into_iter()
+            .filter(|record| record.name == name && record.backup_path.is_some())
+            .collect())
+    }
+
     pub fn replace_store_refs(&self, store_refs: &[StoreRef]) -> Result<(), Error> {
         let tx = self
             .conn
@@ -314,11 +489,53 @@ impl Database {
 }
 
 pub struct InstallTransaction<'a> {
-    tx: Transaction<'a>,
+    tx: Scope<'a>,
+}
+
+/// The top-level transaction or a savepoint nested inside it; both are a
+/// connection as far as the record methods are concerned.
+enum Scope<'a> {
+    Transaction(Transaction<'a>),
+    Savepoint(Savepoint<'a>),
+}
+
+impl Deref for Scope<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            Scope::Transaction(tx) => tx,
+            Scope::Savepoint(sp) => sp,
+        }
+    }
 }
 
 impl<'a> InstallTransaction<'a> {
-    pub fn record_install(&self, name: &str, version: &str, store_key: &str) -> Result<(), Error> {
+    /// Open a savepoint inside this transaction. Committing it folds its
+    /// changes into the enclosing transaction; dropping it rolls back only
+    /// what was recorded through it. Nothing is durable until the outermost
+    /// transaction commits.
+    pub fn savepoint(&mut self) -> Result<InstallTransaction<'_>, Error> {
+        let sp = match &mut self.tx {
+            Scope::Transaction(tx) => tx.savepoint(),
+            Scope::Savepoint(sp) => sp.savepoint(),
+        }
+        .map_err(Error::store("failed to start savepoint"))?;
+
+        Ok(InstallTransaction {
+            tx: Scope::Savepoint(sp),
+        })
+    }
+
+    /// Record `name` as installed. `explicit` marks a formula the user asked
+    /// for; reinstalling it later as a dependency keeps it explicit.
+    pub fn record_install(
+        &self,
+        name: &str,
+        version: &str,
+        store_key: &str,
+        explicit: bool,
+    ) -> Result<(), Error> {
         let now = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .map(|d| d.as_secs() as i64)
@@ -336,15 +553,27 @@ impl<'a> InstallTransaction<'a> {
 
         self.tx
             .execute(
-                "INSERT INTO installed_kegs (name, version, store_key, installed_at)
-                 VALUES (?1, ?2, ?3, ?4)
+                "INSERT INTO installed_kegs (name, version, store_key, installed_at, explicit)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
                  ON CONFLICT(name) DO UPDATE SET
                      version = excluded.version,
+                     store_key = excluded.store_key,
+                     installed_at = excluded.installed_at,
+                     explicit = MAX(installed_kegs.explicit, excluded.explicit)",
+                params![name, version, store_key, now, explicit],
+            )
+            .map_err(Error::store("failed to record install"))?;
+
+        self.tx
+            .execute(
+                "INSERT INTO keg_versions (name, version, store_key, installed_at)
+                 VALUES (?1, ?2, ?3, ?4)
+                 ON CONFLICT(name, version) DO UPDATE SET
                      store_key = excluded.store_key,
                      installed_at = excluded.installed_at",
                 params![name, version, store_key, now],
             )
-            .map_err(Error::store("failed to record install"))?;
+            .map_err(Error::store("failed to record keg version"))?;
 
         match previous_store_key.as_deref() {
             Some(previous) if previous == store_key => {}
@@ -371,24 +600,69 @@ impl<'a> InstallTransaction<'a> {
         Ok(())
     }
 
+    /// Drop the row for `store_key` if nothing references it, returning
+    /// whether it was dropped. A reference recorded since the key was listed
+    /// as unreferenced keeps the row.
+    pub fn delete_unreferenced_store_ref(&self, store_key: &str) -> Result<bool, Error> {
+        let deleted = self
+            .tx
+            .execute(
+                "DELETE FROM store_refs WHERE store_key = ?1 AND refcount <= 0",
+                params![store_key],
+            )
+            .map_err(Error::store("failed to delete store ref"))?;
+
+        Ok(deleted > 0)
+    }
+
     pub fn record_linked_file(
         &self,
         name: &str,
         version: &str,
         linked_path: &str,
         target_path: &str,
+        backup_path: Option<&str>,
     ) -> Result<(), Error> {
         self.tx
             .execute(
-                "INSERT OR REPLACE INTO keg_files (name, version, linked_path, target_path)
-                 VALUES (?1, ?2, ?3, ?4)",
-                params![name, version, linked_path, target_path],
+                "INSERT OR REPLACE INTO keg_files (name, version, linked_path, target_path, backup_path)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![name, version, linked_path, target_path, backup_path],
             )
             .map_err(Error::store("failed to record linked file"))?;
 
         Ok(())
     }
 
+    /// Drop every other formula's record of `linked_path`, after `name` has
+    /// taken the link over. Returns the backup recorded with the link, if
+    /// any, so it is not lost with the row.
+    pub fn release_linked_path(
+        &self,
+        linked_path: &str,
+        name: &str,
+    ) -> Result<Option<String>, Error> {
+        let backup_path: Option<String> = self
+            .tx
+            .query_row(
+                "SELECT backup_path FROM keg_files
+                 WHERE linked_path = ?1 AND name != ?2 AND backup_path IS NOT NULL",
+                params![linked_path, name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(Error::store("failed to query released link"))?;
+
+        self.tx
+            .execute(
+                "DELETE FROM keg_files WHERE linked_path = ?1 AND name != ?2",
+                params![linked_path, name],
+            )
+            .map_err(Error::store("failed to release linked file"))?;
+
+        Ok(backup_path)
+    }
+
     pub fn record_uninstall(&self, name: &str) -> Result<Option<String>, Error> {
         // Get the store_key before removing
         let store_key: Option<String> = self
@@ -409,6 +683,10 @@ impl<'a> InstallTransaction<'a> {
             .execute("DELETE FROM keg_files WHERE name = ?1", params![name])
             .map_err(Error::store("failed to remove keg files records"))?;
 
+        self.tx
+            .execute("DELETE FROM keg_versions WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove keg version records"))?;
+
         // Decrement store ref if we had one
         if let Some(ref key) = store_key {
             self.tx
@@ -422,11 +700,27 @@ impl<'a> InstallTransaction<'a> {
         Ok(store_key)
     }
 
+    /// Forget a single stale version, e.g. after its keg has been cleaned up.
+    pub fn delete_version_record(&self, name: &str, version: &str) -> Result<(), Error> {
+        self.tx
+            .execute(
+                "DELETE FROM keg_versions WHERE name = ?1 AND version = ?2",
+                params![name, version],
+            )
+            .map_err(Error::store("failed to remove keg version record"))?;
+
+        Ok(())
+    }
+
     pub fn delete_installed_record(&self, name: &str) -> Result<(), Error> {
         self.tx
             .execute("DELETE FROM installed_kegs WHERE name = ?1", params![name])
             .map_err(Error::store("failed to remove install record"))?;
 
+        self.tx
+            .execute("DELETE FROM keg_versions WHERE name = ?1", params![name])
+            .map_err(Error::store("failed to remove keg version records"))?;
+
         self.clear_keg_file_records(name)
     }
 
@@ -439,9 +733,11 @@ impl<'a> InstallTransaction<'a> {
     }
 
     pub fn commit(self) -> Result<(), Error> {
-        self.tx
-            .commit()
-            .map_err(Error::store("failed to commit transaction"))
+        match self.tx {
+            Scope::Transaction(tx) => tx.commit(),
+            Scope::Savepoint(sp) => sp.commit(),
+        }
+        .map_err(Error::store("failed to commit transaction"))
     }
 
     // Transaction is rolled back automatically when dropped without commit
@@ -457,7 +753,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "abc123").unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -474,7 +770,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "abc123").unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
             // Don't commit - transaction will be rolled back when dropped
         }
 
@@ -491,8 +787,10 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "shared123").unwrap();
-            tx.record_install("bar", "2.0.0", "shared123").unwrap();
+            tx.record_install("foo", "1.0.0", "shared123", true)
+                .unwrap();
+            tx.record_install("bar", "2.0.0", "shared123", true)
+                .unwrap();
             tx.commit().unwrap();
         }
 
@@ -515,8 +813,8 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "key1").unwrap();
-            tx.record_install("bar", "2.0.0", "key2").unwrap();
+            tx.record_install("foo", "1.0.0", "key1", true).unwrap();
+            tx.record_install("bar", "2.0.0", "key2", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -540,12 +838,13 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "abc123").unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
             tx.record_linked_file(
                 "foo",
                 "1.0.0",
                 "/opt/homebrew/bin/foo",
                 "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
+                None,
             )
             .unwrap();
             tx.commit().unwrap();
@@ -561,13 +860,207 @@ mod tests {
         assert!(db.get_installed("foo").is_none());
     }
 
+    #[test]
+    fn backed_up_files_are_listed_per_formula() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            tx.record_linked_file(
+                "foo",
+                "1.0.0",
+                "/opt/homebrew/bin/foo",
+                "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
+                Some("/opt/zerobrew/backups/bin/foo"),
+            )
+            .unwrap();
+            tx.record_linked_file(
+                "foo",
+                "1.0.0",
+                "/opt/homebrew/bin/foo-config",
+                "/opt/zerobrew/cellar/foo/1.0.0/bin/foo-config",
+                None,
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let backed_up = db.list_backed_up_files("foo").unwrap();
+        assert_eq!(backed_up.len(), 1);
+        assert_eq!(backed_up[0].linked_path, "/opt/homebrew/bin/foo");
+        assert_eq!(
+            backed_up[0].backup_path.as_deref(),
+            Some("/opt/zerobrew/backups/bin/foo")
+        );
+        assert!(db.list_backed_up_files("bar").unwrap().is_empty());
+    }
+
+    #[test]
+    fn linked_files_are_listed_per_version() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            tx.record_linked_file(
+                "foo",
+                "1.0.0",
+                "/opt/homebrew/bin/foo",
+                "/opt/zerobrew/cellar/foo/1.0.0/bin/foo",
+                None,
+            )
+            .unwrap();
+            tx.record_linked_file(
+                "foo",
+                "0.9.0",
+                "/opt/homebrew/bin/foo-old",
+                "/opt/zerobrew/cellar/foo/0.9.0/bin/foo-old",
+                None,
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let linked = db.linked_files("foo", "1.0.0").unwrap();
+        assert_eq!(linked.len(), 1);
+        assert_eq!(linked[0].linked_path, "/opt/homebrew/bin/foo");
+        assert!(db.linked_files("bar", "1.0.0").unwrap().is_empty());
+    }
+
+    #[test]
+    fn v1_database_gains_backup_column() {
+        let conn = Connection::open_in_memory().expect("failed to open connection");
+        Database::migrate_to_v1(&conn).expect("v1 migration failed");
+        Database::set_schema_version(&conn, 1).expect("failed to set version");
+        conn.execute(
+            "INSERT INTO keg_files VALUES ('foo', '1.0.0', '/p/bin/foo', '/c/foo/1.0.0/bin/foo')",
+            [],
+        )
+        .expect("failed to insert v1 row");
+
+        Database::migrate(&conn).expect("migration failed");
+
+        let backup: Option<String> = conn
+            .query_row("SELECT backup_path FROM keg_files", [], |row| row.get(0))
+            .expect("failed to query backup_path");
+        assert_eq!(backup, None);
+    }
+
+    #[test]
+    fn dependency_reinstall_keeps_explicit_flag() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "fookey", true).unwrap();
+            tx.record_install("libbar", "1.0.0", "barkey", false)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert!(db.get_installed("foo").unwrap().is_explicit);
+        assert!(!db.get_installed("libbar").unwrap().is_explicit);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.1.0", "fookey2", false).unwrap();
+            tx.record_install("libbar", "1.0.0", "barkey", true)
+                .unwrap();
+            tx.commit().unwrap();
+        }
+
+        assert!(db.get_installed("foo").unwrap().is_explicit);
+        assert!(db.get_installed("libbar").unwrap().is_explicit);
+    }
+
+    #[test]
+    fn v2_rows_are_backfilled_as_explicit() {
+        let conn = Connection::open_in_memory().expect("failed to open connection");
+        Database::migrate_to_v1(&conn).expect("v1 migration failed");
+        Database::migrate_to_v2(&conn).expect("v2 migration failed");
+        Database::set_schema_version(&conn, 2).expect("failed to set version");
+        conn.execute(
+            "INSERT INTO installed_kegs VALUES ('foo', '1.0.0', 'key', 0)",
+            [],
+        )
+        .expect("failed to insert v2 row");
+
+        Database::migrate(&conn).expect("migration failed");
+
+        let explicit: bool = conn
+            .query_row("SELECT explicit FROM installed_kegs", [], |row| row.get(0))
+            .expect("failed to query explicit");
+        assert!(explicit);
+    }
+
+    #[test]
+    fn all_recorded_versions_are_listed_newest_first() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.10", "key110", true).unwrap();
+            tx.record_install("foo", "1.9", "key19", true).unwrap();
+            tx.record_install("bar", "2.0.0", "barkey", true).unwrap();
+            tx.commit().unwrap();
+        }
+
+        let versions = db.list_all_versions("foo").unwrap();
+        let listed: Vec<(&str, bool)> = versions
+            .iter()
+            .map(|v| (v.version.as_str(), v.is_active))
+            .collect();
+        assert_eq!(listed, vec![("1.10", true), ("1.9", false)]);
+        assert_eq!(db.list_installed_versions().unwrap().len(), 3);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.delete_version_record("foo", "1.9").unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(db.list_all_versions("foo").unwrap().len(), 1);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_uninstall("foo").unwrap();
+            tx.commit().unwrap();
+        }
+        assert!(db.list_all_versions("foo").unwrap().is_empty());
+    }
+
+    #[test]
+    fn v3_rows_are_backfilled_into_keg_versions() {
+        let conn = Connection::open_in_memory().expect("failed to open connection");
+        for version in 1..=3 {
+            Database::migrate_to_version(&conn, version).expect("migration failed");
+        }
+        Database::set_schema_version(&conn, 3).expect("failed to set version");
+        conn.execute(
+            "INSERT INTO installed_kegs VALUES ('foo', '1.0.0', 'key', 0, 1)",
+            [],
+        )
+        .expect("failed to insert v3 row");
+
+        Database::migrate(&conn).expect("migration failed");
+
+        let version: String = conn
+            .query_row(
+                "SELECT version FROM keg_versions WHERE name = 'foo'",
+                [],
+                |row| row.get(0),
+            )
+            .expect("failed to query keg_versions");
+        assert_eq!(version, "1.0.0");
+    }
+
     #[test]
     fn reinstall_with_same_store_key_does_not_leak_refcount() {
         let mut db = Database::in_memory().unwrap();
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "samekey").unwrap();
+            tx.record_install("foo", "1.0.0", "samekey", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -575,7 +1068,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "samekey").unwrap();
+            tx.record_install("foo", "1.0.0", "samekey", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -588,7 +1081,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "oldkey").unwrap();
+            tx.record_install("foo", "1.0.0", "oldkey", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -596,7 +1089,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.1.0", "newkey").unwrap();
+            tx.record_install("foo", "1.1.0", "newkey", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -614,7 +1107,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "gc_key").unwrap();
+            tx.record_install("foo", "1.0.0", "gc_key", true).unwrap();
             tx.record_uninstall("foo").unwrap();
             tx.commit().unwrap();
         }
@@ -630,7 +1123,7 @@ mod tests {
 
         {
             let tx = db.transaction().unwrap();
-            tx.record_install("foo", "1.0.0", "oldkey").unwrap();
+            tx.record_install("foo", "1.0.0", "oldkey", true).unwrap();
             tx.commit().unwrap();
         }
 
@@ -644,7 +1137,9 @@ mod tests {
             .unwrap();
 
         let tx = db.transaction().unwrap();
-        let err = tx.record_install("foo", "1.1.0", "newkey").unwrap_err();
+        let err = tx
+            .record_install("foo", "1.1.0", "newkey", true)
+            .unwrap_err();
         assert!(matches!(err, Error::StoreCorruption { .. }));
         assert!(
             err.to_string()
@@ -653,10 +1148,10 @@ mod tests {
     }
 
     #[test]
-    fn new_database_starts_at_version_1() {
+    fn new_database_starts_at_version_4() {
         let db = Database::in_memory().expect("failed to create database");
         let version = Database::get_schema_version(&db.conn).expect("failed to get version");
-        assert_eq!(version, 1);
+        assert_eq!(version, 4);
     }
 
     #[test]
@@ -665,7 +1160,7 @@ mod tests {
         Database::migrate(&db.conn).expect("first migration failed");
         Database::migrate(&db.conn).expect("second migration failed");
         let version = Database::get_schema_version(&db.conn).expect("failed to get version");
-        assert_eq!(version, 1);
+        assert_eq!(version, 4);
     }
 
     #[test]
@@ -704,4 +1199,72 @@ mod tests {
             .expect("failed to query data");
         assert_eq!(name, "test");
     }
+
+    #[test]
+    fn savepoint_rolls_back_without_touching_the_outer_transaction() {
+        let mut db = Database::in_memory().unwrap();
+
+        {
+            let mut tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "abc123", true).unwrap();
+            {
+                let sp = tx.savepoint().unwrap();
+                sp.record_linked_file("foo", "1.0.0", "/p/bin/foo", "/c/foo/bin/foo", None)
+                    .unwrap();
+                // dropped: only the linked file is rolled back
+            }
+            {
+                let sp = tx.savepoint().unwrap();
+                sp.record_linked_file("foo", "1.0.0", "/p/bin/bar", "/c/foo/bin/bar", None)
+                    .unwrap();
+                sp.commit().unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        assert!(db.get_installed("foo").is_some());
+        let linked: Vec<String> = db
+            .linked_files("foo", "1.0.0")
+            .unwrap()
+            .into_iter()
+            .map(|record| record.linked_path)
+            .collect();
+        assert_eq!(linked, vec!["/p/bin/bar".to_string()]);
+    }
+
+    #[test]
+    fn installed_kegs_sort_by_date_and_reinstall_moves_to_front() {
+        let mut db = Database::in_memory().unwrap();
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "k1", true).unwrap();
+            tx.record_install("bar", "1.0.0", "k2", true).unwrap();
+            tx.record_install("baz", "1.0.0", "k3", true).unwrap();
+            tx.commit().unwrap();
+        }
+        db.conn
+            .execute_batch(
+                "UPDATE installed_kegs SET installed_at = 100 WHERE name = 'foo';
+                 UPDATE installed_kegs SET installed_at = 300 WHERE name = 'bar';
+                 UPDATE installed_kegs SET installed_at = 200 WHERE name = 'baz';",
+            )
+            .unwrap();
+
+        let names = |db: &Database| -> Vec<String> {
+            db.list_installed_sorted_by_date()
+                .unwrap()
+                .into_iter()
+                .map(|k| k.name)
+                .collect()
+        };
+        assert_eq!(names(&db), ["bar", "baz", "foo"]);
+
+        {
+            let tx = db.transaction().unwrap();
+            tx.record_install("foo", "1.0.0", "k1", true).unwrap();
+            tx.commit().unwrap();
+        }
+        assert_eq!(names(&db), ["foo", "bar", "baz"]);
+        assert!(db.get_installed("foo").unwrap().installed_at > 300);
+    }
 }
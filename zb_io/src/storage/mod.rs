@@ -3,5 +3,5 @@ pub mod db;
 pub mod store;
 
 pub use blob::{BlobCache, BlobWriter};
-pub use db::{Database, InstallTransaction, InstalledKeg, KegFileRecord, StoreRef};
+pub use db::{Database, InstallTransaction, InstalledKeg, KegFileRecord, KegVersion, StoreRef};
 pub use store::Store;
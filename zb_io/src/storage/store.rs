@@ -1,35 +1,87 @@
+use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io;
 use std::path::{Path, PathBuf};
 
 use fs4::fs_std::FileExt;
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
 
-use crate::extraction::extract::extract_archive;
+use crate::extraction::extract::{ExtractLimits, ExtractProgress, extract_archive_with_progress};
 use zb_core::Error;
 
+const MANIFEST_EXTENSION: &str = "manifest";
+
+#[derive(Clone)]
 pub struct Store {
     store_dir: PathBuf,
     locks_dir: PathBuf,
+    verify_on_hit: bool,
+    extract_limits: ExtractLimits,
+    extract_progress: ExtractProgress,
+    content_dedup: bool,
 }
 
 impl Store {
     pub fn new(root: &Path) -> io::Result<Self> {
-        let store_dir = root.join("store");
-        let locks_dir = root.join("locks");
+        Self::new_at(root.join("store"), root.join("locks"))
+    }
 
+    pub fn new_at(store_dir: PathBuf, locks_dir: PathBuf) -> io::Result<Self> {
         fs::create_dir_all(&store_dir)?;
         fs::create_dir_all(&locks_dir)?;
 
         Ok(Self {
             store_dir,
             locks_dir,
+            verify_on_hit: false,
+            extract_limits: ExtractLimits::default(),
+            extract_progress: ExtractProgress::new(),
+            content_dedup: false,
         })
     }
 
+    /// Re-hash existing entries against their manifest before handing them out
+    /// from `ensure_entry`. Entries that fail are re-extracted from the blob.
+    /// Off by default since it reads every file of the entry on each hit.
+    pub fn with_verification(mut self, verify_on_hit: bool) -> Self {
+        self.verify_on_hit = verify_on_hit;
+        self
+    }
+
+    /// Size and entry caps applied when unpacking blobs into the store.
+    pub fn with_extract_limits(mut self, limits: ExtractLimits) -> Self {
+        self.extract_limits = limits;
+        self
+    }
+
+    /// Share `progress` with every extraction into the store, so cancelling
+    /// it stops the one in flight. A cancelled extraction leaves no entry.
+    pub fn with_extract_progress(mut self, progress: ExtractProgress) -> Self {
+        self.extract_progress = progress;
+        self
+    }
+
+    /// Hardlink each newly extracted file to an identical one already in
+    /// another entry instead of keeping a second copy, as happens when a
+    /// bottle rebuild unpacks to the same tree under a new sha. Files match
+    /// when their manifest digests (the sha256 of the contents) and
+    /// permissions agree. Removing an entry only drops its own links, so
+    /// content another entry still uses stays on disk.
+    pub fn with_content_dedup(mut self, content_dedup: bool) -> Self {
+        self.content_dedup = content_dedup;
+        self
+    }
+
     pub fn entry_path(&self, store_key: &str) -> PathBuf {
         self.store_dir.join(store_key)
     }
 
+    fn manifest_path(&self, store_key: &str) -> PathBuf {
+        self.store_dir
+            .join(format!("{store_key}.{MANIFEST_EXTENSION}"))
+    }
+
     pub fn has_entry(&self, store_key: &str) -> bool {
         self.entry_path(store_key).exists()
     }
@@ -57,7 +109,7 @@ impl Store {
         let entry_path = self.entry_path(store_key);
 
         // Fast path: already exists
-        if entry_path.exists() {
+        if entry_path.exists() && !self.verify_on_hit {
             return Ok(entry_path);
         }
 
@@ -72,19 +124,52 @@ impl Store {
 
         // Double-check after acquiring lock (another process may have created it)
         if entry_path.exists() {
-            return Ok(entry_path);
+            if !self.verify_on_hit {
+                return Ok(entry_path);
+            }
+
+            let damaged = self.verify_entry(store_key)?;
+            if damaged.is_empty() {
+                return Ok(entry_path);
+            }
+
+            warn!(
+                store_key = %store_key,
+                files = damaged.len(),
+                "store entry failed integrity check; re-extracting"
+            );
+            fs::remove_dir_all(&entry_path)
+                .map_err(Error::store("failed to remove corrupted store entry"))?;
         }
 
         let tmp_dir = tempfile::tempdir_in(&self.store_dir)
             .map_err(Error::store("failed to create temp directory"))?;
 
-        extract_archive(blob_path, tmp_dir.path())?;
+        extract_archive_with_progress(
+            blob_path,
+            tmp_dir.path(),
+            self.extract_limits,
+            &self.extract_progress,
+        )?;
+
+        let manifest = build_manifest(tmp_dir.path())?;
+        if self.content_dedup {
+            let linked = self.link_duplicate_files(tmp_dir.path(), &manifest);
+            debug!(store_key = %store_key, files = linked, "deduplicated store entry files");
+        }
+
+        // The manifest goes down first so an entry is never visible without
+        // one; a crash before the rename only leaves a stray manifest that
+        // the next extraction overwrites.
+        fs::write(self.manifest_path(store_key), manifest)
+            .map_err(Error::store("failed to write store manifest"))?;
 
         // Persist the temp dir by converting it into a permanent path.
         // into_path() prevents auto-cleanup so rename failure still needs manual handling.
         let tmp_path = tmp_dir.keep();
         if let Err(e) = fs::rename(&tmp_path, &entry_path) {
             let _ = fs::remove_dir_all(&tmp_path);
+            let _ = fs::remove_file(self.manifest_path(store_key));
             return Err(Error::StoreCorruption {
                 message: format!("failed to rename store entry: {e}"),
             });
@@ -94,6 +179,87 @@ impl Store {
         Ok(entry_path)
     }
 
+    /// Re-hash a store entry against the manifest written when it was extracted.
+    ///
+    /// Returns the relative paths that are missing or no longer match; an empty
+    /// list means the entry is intact. An entry without a manifest cannot be
+    /// checked, so it is reported as damaged under the manifest's file name.
+    pub fn verify_entry(&self, store_key: &str) -> Result<Vec<String>, Error> {
+        let manifest = match fs::read_to_string(self.manifest_path(store_key)) {
+            Ok(manifest) => manifest,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(vec![format!("{store_key}.{MANIFEST_EXTENSION}")]);
+            }
+            Err(e) => return Err(Error::store("failed to read store manifest")(e)),
+        };
+
+        let entry_path = self.entry_path(store_key);
+        let mut damaged = Vec::new();
+
+        for line in manifest.lines().filter(|line| !line.is_empty()) {
+            let Some((expected, rel_path)) = parse_manifest_line(line) else {
+                return Err(Error::StoreCorruption {
+                    message: format!("malformed manifest line for {store_key}: {line}"),
+                });
+            };
+
+            match describe_path(&entry_path.join(rel_path)) {
+                Ok(Some(actual)) if actual == expected => {}
+                Ok(_) | Err(_) => damaged.push(rel_path.to_string()),
+            }
+        }
+
+        Ok(damaged)
+    }
+
+    /// First file in an existing entry for each manifest digest, read from the
+    /// manifests of every entry still present.
+    fn content_index(&self) -> HashMap<String, PathBuf> {
+        let mut index = HashMap::new();
+        let Ok(dir) = fs::read_dir(&self.store_dir) else {
+            return index;
+        };
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(MANIFEST_EXTENSION) {
+                continue;
+            }
+            let Some(store_key) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let entry_path = self.entry_path(store_key);
+            let Ok(manifest) = fs::read_to_string(&path) else {
+                continue;
+            };
+            for (description, rel_path) in manifest.lines().filter_map(parse_manifest_line) {
+                if description.starts_with("sha256:") {
+                    index
+                        .entry(description.to_string())
+                        .or_insert_with(|| entry_path.join(rel_path));
+                }
+            }
+        }
+        index
+    }
+
+    /// Swap files under `root` for hardlinks to matching files elsewhere in
+    /// the store. Best effort: a file that cannot be linked keeps its copy.
+    /// Returns how many files were linked.
+    fn link_duplicate_files(&self, root: &Path, manifest: &str) -> usize {
+        let index = self.content_index();
+        let mut linked = 0;
+        for (description, rel_path) in manifest.lines().filter_map(parse_manifest_line) {
+            let Some(existing) = index.get(description) else {
+                continue;
+            };
+            let path = root.join(rel_path);
+            if link_identical_file(existing, &path, description).is_ok_and(|done| done) {
+                linked += 1;
+            }
+        }
+        linked
+    }
+
     /// Remove a store entry. This should only be called when the refcount is 0.
     pub fn remove_entry(&self, store_key: &str) -> Result<(), Error> {
         let entry_path = self.entry_path(store_key);
@@ -115,6 +281,7 @@ impl Store {
             fs::remove_dir_all(&entry_path)
                 .map_err(Error::store("failed to remove store entry"))?;
         }
+        let _ = fs::remove_file(self.manifest_path(store_key));
 
         // Clean up the lock file
         let _ = fs::remove_file(&lock_path);
@@ -123,6 +290,93 @@ impl Store {
     }
 }
 
+/// One line per regular file or symlink, sorted by path:
+/// `sha256:<hex>\t<path>` for files and `link:<target>\t<path>` for symlinks.
+fn build_manifest(root: &Path) -> Result<String, Error> {
+    let mut records = Vec::new();
+
+    for entry in walkdir::WalkDir::new(root).follow_links(false) {
+        let entry = entry.map_err(Error::store("failed to walk store entry"))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let Some(rel_path) = entry.path().strip_prefix(root).ok().and_then(Path::to_str) else {
+            continue;
+        };
+        if rel_path.contains(['\t', '\n']) {
+            continue;
+        }
+
+        if let Some(description) =
+            describe_path(entry.path()).map_err(Error::store("failed to hash store entry file"))?
+        {
+            records.push((rel_path.to_string(), description));
+        }
+    }
+
+    records.sort();
+    Ok(records
+        .into_iter()
+        .map(|(rel_path, description)| format!("{description}\t{rel_path}\n"))
+        .collect())
+}
+
+/// Replace `path` with a hardlink to `existing` if both are regular files
+/// with the same permissions and `existing` still hashes to `description`.
+fn link_identical_file(existing: &Path, path: &Path, description: &str) -> io::Result<bool> {
+    let ours = fs::symlink_metadata(path)?;
+    let theirs = fs::symlink_metadata(existing)?;
+    if !ours.is_file()
+        || !theirs.is_file()
+        || ours.len() != theirs.len()
+        || ours.permissions() != theirs.permissions()
+    {
+        return Ok(false);
+    }
+    // The other entry may have been tampered with since its manifest was
+    // written; never link to content that no longer matches.
+    if describe_path(existing)?.as_deref() != Some(description) {
+        return Ok(false);
+    }
+
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(".dedup");
+    let staged = PathBuf::from(staged);
+    fs::hard_link(existing, &staged)?;
+    if let Err(e) = fs::rename(&staged, path) {
+        let _ = fs::remove_file(&staged);
+        return Err(e);
+    }
+    Ok(true)
+}
+
+fn parse_manifest_line(line: &str) -> Option<(&str, &str)> {
+    let (description, rel_path) = line.split_once('\t')?;
+    if rel_path.is_empty() {
+        return None;
+    }
+    Some((description, rel_path))
+}
+
+fn describe_path(path: &Path) -> io::Result<Option<String>> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)?;
+        return Ok(Some(format!("link:{}", target.display())));
+    }
+
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(Some(format!("sha256:{:x}", hasher.finalize())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -247,4 +501,147 @@ mod tests {
 
         assert!(store.has_entry(store_key));
     }
+
+    #[test]
+    fn ensure_entry_writes_manifest_and_verifies_clean_entry() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let tarball = create_test_tarball(b"manifest me");
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, &tarball).unwrap();
+
+        store.ensure_entry("manifested", &blob_path).unwrap();
+
+        let manifest = fs::read_to_string(store.manifest_path("manifested")).unwrap();
+        assert!(manifest.contains("\ttest.txt"));
+        assert!(store.verify_entry("manifested").unwrap().is_empty());
+        assert_eq!(
+            store.list_entries().unwrap(),
+            vec!["manifested".to_string()]
+        );
+    }
+
+    #[test]
+    fn verify_entry_reports_tampered_and_missing_files() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let tarball = create_test_tarball(b"original");
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, &tarball).unwrap();
+
+        let entry = store.ensure_entry("tampered", &blob_path).unwrap();
+        fs::write(entry.join("test.txt"), "modified").unwrap();
+        assert_eq!(store.verify_entry("tampered").unwrap(), vec!["test.txt"]);
+
+        fs::remove_file(entry.join("test.txt")).unwrap();
+        assert_eq!(store.verify_entry("tampered").unwrap(), vec!["test.txt"]);
+    }
+
+    #[test]
+    fn verification_on_hit_re_extracts_corrupted_entry() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap().with_verification(true);
+
+        let tarball = create_test_tarball(b"pristine");
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, &tarball).unwrap();
+
+        let entry = store.ensure_entry("rehash", &blob_path).unwrap();
+        fs::write(entry.join("test.txt"), "bit rot").unwrap();
+
+        let entry = store.ensure_entry("rehash", &blob_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(entry.join("test.txt")).unwrap(),
+            "pristine"
+        );
+        assert!(store.verify_entry("rehash").unwrap().is_empty());
+    }
+
+    #[test]
+    fn entry_without_manifest_is_unverified_and_re_extracted() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap().with_verification(true);
+
+        let tarball = create_test_tarball(b"unchecked");
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, &tarball).unwrap();
+
+        let entry = store.ensure_entry("bare", &blob_path).unwrap();
+        fs::remove_file(store.manifest_path("bare")).unwrap();
+        fs::write(entry.join("test.txt"), "bit rot").unwrap();
+        assert_eq!(
+            store.verify_entry("bare").unwrap(),
+            vec!["bare.manifest".to_string()]
+        );
+
+        let entry = store.ensure_entry("bare", &blob_path).unwrap();
+        assert_eq!(
+            fs::read_to_string(entry.join("test.txt")).unwrap(),
+            "unchecked"
+        );
+        assert!(store.verify_entry("bare").unwrap().is_empty());
+    }
+
+    #[test]
+    fn remove_entry_deletes_manifest() {
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap();
+
+        let tarball = create_test_tarball(b"gone");
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, &tarball).unwrap();
+
+        store.ensure_entry("removed", &blob_path).unwrap();
+        store.remove_entry("removed").unwrap();
+
+        assert!(!store.manifest_path("removed").exists());
+    }
+
+    #[test]
+    fn cancelled_extraction_leaves_no_entry_behind() {
+        let tmp = TempDir::new().unwrap();
+        let progress = ExtractProgress::new();
+        let store = Store::new(tmp.path())
+            .unwrap()
+            .with_extract_progress(progress.clone());
+
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, create_test_tarball(b"hello world")).unwrap();
+
+        progress.cancel();
+        let err = store.ensure_entry("abc123", &blob_path).unwrap_err();
+
+        assert_eq!(err, Error::Cancelled);
+        assert!(!store.has_entry("abc123"));
+        assert!(store.list_entries().unwrap().is_empty());
+    }
+
+    #[test]
+    fn identical_content_under_two_keys_is_stored_once() {
+        use std::os::unix::fs::MetadataExt;
+
+        let tmp = TempDir::new().unwrap();
+        let store = Store::new(tmp.path()).unwrap().with_content_dedup(true);
+
+        let blob_path = tmp.path().join("test.tar.gz");
+        fs::write(&blob_path, create_test_tarball(b"same bytes")).unwrap();
+
+        let first = store.ensure_entry("sha-original", &blob_path).unwrap();
+        let second = store.ensure_entry("sha-rebuild", &blob_path).unwrap();
+
+        let a = fs::metadata(first.join("test.txt")).unwrap();
+        let b = fs::metadata(second.join("test.txt")).unwrap();
+        assert_eq!(a.ino(), b.ino());
+        assert_eq!(a.nlink(), 2);
+
+        // Dropping one key must not take the shared content with it.
+        store.remove_entry("sha-original").unwrap();
+        assert_eq!(
+            fs::read(second.join("test.txt")).unwrap(),
+            b"same bytes".to_vec()
+        );
+        assert!(store.verify_entry("sha-rebuild").unwrap().is_empty());
+    }
 }